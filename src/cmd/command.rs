@@ -0,0 +1,60 @@
+use crate::cmd::{lookup, syntax_error, Command, CommandSpec, COMMANDS};
+use crate::db::Db;
+use crate::reply::Reply;
+
+/// `COMMAND [COUNT | LIST | INFO name... | DOCS name...]`
+pub enum CommandCommand {
+    All,
+    Count,
+    List,
+    Info(Vec<Vec<u8>>),
+    Docs,
+}
+
+impl Command for CommandCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let Some(sub) = args.get(1) else {
+            return Ok(CommandCommand::All);
+        };
+        match String::from_utf8_lossy(sub).to_uppercase().as_str() {
+            "COUNT" if args.len() == 2 => Ok(CommandCommand::Count),
+            "LIST" if args.len() == 2 => Ok(CommandCommand::List),
+            "INFO" => Ok(CommandCommand::Info(args[2..].to_vec())),
+            "DOCS" => Ok(CommandCommand::Docs),
+            _ => Err(syntax_error()),
+        }
+    }
+
+    fn execute(&self, _db: &Db) -> Reply {
+        match self {
+            CommandCommand::All => Reply::Array(COMMANDS.iter().map(info).collect()),
+            CommandCommand::Count => Reply::Integer(COMMANDS.len() as i64),
+            CommandCommand::List => {
+                Reply::Array(COMMANDS.iter().map(|spec| Reply::Bulk(spec.name.as_bytes().to_vec())).collect())
+            }
+            CommandCommand::Info(names) if names.is_empty() => Reply::Array(COMMANDS.iter().map(info).collect()),
+            CommandCommand::Info(names) => {
+                Reply::Array(names.iter().map(|name| lookup(name).map_or(Reply::Null, info)).collect())
+            }
+            // Documentation is optional; clients such as redis-cli cope with none.
+            CommandCommand::Docs => Reply::Map(Vec::new()),
+        }
+    }
+}
+
+/// The COMMAND INFO entry for one command.
+fn info(spec: &CommandSpec) -> Reply {
+    let (first, last, step) = spec.keys;
+    Reply::Array(vec![
+        Reply::Bulk(spec.name.as_bytes().to_vec()),
+        Reply::Integer(spec.arity as i64),
+        Reply::Set(spec.flags.iter().map(|flag| Reply::Simple(flag.as_str().to_string())).collect()),
+        Reply::Integer(first as i64),
+        Reply::Integer(last as i64),
+        Reply::Integer(step as i64),
+        Reply::Set(spec.acl_categories().into_iter().map(Reply::Simple).collect()),
+        Reply::Array(Vec::new()),
+        Reply::Array(Vec::new()),
+        Reply::Array(Vec::new()),
+    ])
+}
@@ -0,0 +1,50 @@
+use crate::cmd::{syntax_error, Command};
+use crate::db::Db;
+use crate::reply::Reply;
+
+/// `DEBUG PROTOCOL <type>`: emits a sample of each reply type so client
+/// libraries can verify their RESP2/RESP3 parsing.
+pub struct DebugProtocolCommand {
+    kind: String,
+}
+
+impl DebugProtocolCommand {
+    pub fn new(kind: &str) -> Self {
+        DebugProtocolCommand {
+            kind: kind.to_lowercase(),
+        }
+    }
+}
+
+impl Command for DebugProtocolCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        match args {
+            [_, sub, kind] if sub.eq_ignore_ascii_case(b"PROTOCOL") => {
+                Ok(DebugProtocolCommand::new(&String::from_utf8_lossy(kind)))
+            }
+            _ => Err(syntax_error()),
+        }
+    }
+
+    fn execute(&self, _db: &Db) -> Reply {
+        match self.kind.as_str() {
+            "string" => Reply::Bulk(b"Hello World".to_vec()),
+            "integer" => Reply::Integer(12345),
+            "double" => Reply::Double(3.5),
+            "bignum" => Reply::BigNumber("1234567999999999999999999999999999999".to_string()),
+            "null" => Reply::Null,
+            "array" => Reply::Array((0..3).map(Reply::Integer).collect()),
+            "set" => Reply::Set((0..3).map(Reply::Integer).collect()),
+            "map" => Reply::Map((0..3).map(|i| (Reply::Integer(i), Reply::Boolean(i == 1))).collect()),
+            "push" => Reply::Push(vec![Reply::Bulk(b"server-cpu-usage".to_vec()), Reply::Integer(42)]),
+            "verbatim" => Reply::Verbatim("txt", b"This is a verbatim\nstring".to_vec()),
+            "true" => Reply::Boolean(true),
+            "false" => Reply::Boolean(false),
+            _ => Reply::Error(
+                "ERR Wrong protocol type name. Please use one of the following: \
+                 string|integer|double|bignum|null|array|set|map|push|verbatim|true|false"
+                    .to_string(),
+            ),
+        }
+    }
+}
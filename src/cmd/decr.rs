@@ -1,32 +1,47 @@
-use std::sync::{Arc, Mutex};
-use std::collections::HashMap;
-use std::time::Instant;
-
-type Db = Arc<Mutex<HashMap<String, DbValue>>>;
-type DbValue = (String, Option<Instant>);
+use crate::cmd::Command;
+use crate::db::{Db, Entry, Value};
+use crate::reply::Reply;
 
 pub struct DecrCommand {
-    key: String,
+    key: Vec<u8>,
 }
 
 impl DecrCommand {
-    pub fn new(key: &str) -> Self {
+    pub fn new(key: &[u8]) -> Self {
         DecrCommand {
-            key: key.to_string(),
+            key: key.to_vec(),
         }
     }
+}
+
+impl Command for DecrCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(DecrCommand::new(&args[1]))
+    }
 
-    pub fn execute(&self, db: &Db) -> String {
+    fn execute(&self, db: &Db) -> Reply {
         let mut db = db.lock().unwrap();
-        let entry = db.entry(self.key.clone()).or_insert(("0".to_string(), None));
+        let current = match db.get(&self.key) {
+            Some(Entry { value: Value::String(value), .. }) => {
+                match std::str::from_utf8(value).ok().and_then(|s| s.parse::<i64>().ok()) {
+                    Some(current) => current,
+                    None => return Reply::error("ERR value is not an integer or out of range"),
+                }
+            }
+            Some(_) => return Reply::wrong_type(),
+            None => 0,
+        };
+        let Some(value) = current.checked_sub(1) else {
+            return Reply::error("ERR increment or decrement would overflow");
+        };
 
-        match entry.0.parse::<i64>() {
-            Ok(mut value) => {
-                value -= 1;
-                entry.0 = value.to_string();
-                format!(":{}\r\n", value)
+        let new_value = Value::String(value.to_string().into_bytes());
+        match db.get_mut(&self.key) {
+            Some(entry) => entry.value = new_value,
+            None => {
+                db.insert(self.key.clone(), Entry::new(new_value));
             }
-            Err(_) => "-ERR value is not an integer or out of range\r\n".to_string(),
         }
+        Reply::Integer(value)
     }
-}
\ No newline at end of file
+}
@@ -1,21 +1,24 @@
-use std::sync::{Arc, Mutex};
-use std::collections::HashMap;
-use std::time::Instant;
-
-type Db = Arc<Mutex<HashMap<String, DbValue>>>;
-type DbValue = (String, Option<Instant>);
+use crate::cmd::Command;
+use crate::db::Db;
+use crate::reply::Reply;
 
 pub struct ExistsCommand {
-    keys: Vec<String>,
+    keys: Vec<Vec<u8>>,
 }
 
 impl ExistsCommand {
-    pub fn new(keys: Vec<String>) -> Self {
+    pub fn new(keys: Vec<Vec<u8>>) -> Self {
         ExistsCommand { keys }
     }
+}
+
+impl Command for ExistsCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(ExistsCommand::new(args[1..].to_vec()))
+    }
 
-    pub fn execute(&self, db: &Db) -> String {
-        let db = db.lock().unwrap();
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
         let mut count = 0;
 
         for key in &self.keys {
@@ -24,6 +27,6 @@ impl ExistsCommand {
             }
         }
 
-        format!(":{}\r\n", count)
+        Reply::Integer(count)
     }
 }
\ No newline at end of file
@@ -1,28 +1,31 @@
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
+use crate::cmd::{parse_int, Command};
+use crate::db::Db;
+use crate::reply::Reply;
 
-type Db = Arc<Mutex<HashMap<String, DbValue>>>;
-type DbValue = (String, Option<Instant>);
-
-pub struct ExpireCommand<'a> {
-    key: &'a str,
+pub struct ExpireCommand {
+    key: Vec<u8>,
     seconds: u64,
 }
 
-impl<'a> ExpireCommand<'a> {
-    pub fn new(key: &'a str, seconds: u64) -> Self {
-        ExpireCommand { key, seconds }
+impl ExpireCommand {
+    pub fn new(key: &[u8], seconds: u64) -> Self {
+        ExpireCommand { key: key.to_vec(), seconds }
+    }
+}
+
+impl Command for ExpireCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(ExpireCommand::new(&args[1], parse_int(&args[2])?))
     }
 
-    pub fn execute(&self, db: &Db) -> String {
+    fn execute(&self, db: &Db) -> Reply {
         let mut db = db.lock().unwrap();
-        if let Some((value, _)) = db.get(self.key).cloned() {
-            let expire_time = Instant::now() + Duration::from_secs(self.seconds);
-            db.insert(self.key.to_string(), (value, Some(expire_time)));
-            "+OK\r\n".to_string()
+        if let Some(entry) = db.get_mut(&self.key) {
+            entry.expires_at = Some(Instant::now() + Duration::from_secs(self.seconds));
+            Reply::ok()
         } else {
-            "-ERR no such key\r\n".to_string()
+            Reply::error("ERR no such key")
         }
     }
 }
\ No newline at end of file
@@ -1,31 +1,28 @@
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use std::time::Instant;
+use crate::cmd::Command;
+use crate::db::{Db, Entry, Value};
+use crate::reply::Reply;
 
-type Db = Arc<Mutex<HashMap<String, DbValue>>>;
-type DbValue = (String, Option<Instant>);
+pub struct GetCommand {
+    key: Vec<u8>,
+}
 
-pub struct GetCommand<'a> {
-    key: &'a str,
+impl GetCommand {
+    pub fn new(key: &[u8]) -> Self {
+        GetCommand { key: key.to_vec() }
+    }
 }
 
-impl<'a> GetCommand<'a> {
-    pub fn new(key: &'a str) -> Self {
-        GetCommand { key }
+impl Command for GetCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(GetCommand::new(&args[1]))
     }
 
-    pub fn execute(&self, db: &Db) -> String {
+    fn execute(&self, db: &Db) -> Reply {
         let mut db = db.lock().unwrap();
-        if let Some((value, expire_time)) = db.get(self.key) {
-            if let Some(expire_time) = expire_time {
-                if Instant::now() > *expire_time {
-                    db.remove(self.key);
-                    return "$-1\r\n".to_string();
-                }
-            }
-            format!("${}\r\n{}\r\n", value.len(), value)
-        } else {
-            "$-1\r\n".to_string()
+        match db.get(&self.key) {
+            Some(Entry { value: Value::String(value), .. }) => Reply::Bulk(value.clone()),
+            Some(_) => Reply::wrong_type(),
+            None => Reply::Null,
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,534 @@
+use std::time::{Duration, Instant};
+use crate::cmd::scan::{self, ScanOptions};
+use crate::cmd::{parse_int, Command};
+use crate::db::{Db, Entry, Hash, Keyspace, Value};
+use crate::reply::{format_double, Reply};
+
+/// Returns the hash stored at `key`, or WRONGTYPE if the key holds another type.
+fn get_hash<'a>(db: &'a mut Keyspace, key: &[u8]) -> Result<Option<&'a Hash>, Reply> {
+    match db.get(key) {
+        Some(Entry { value: Value::Hash(hash), .. }) => Ok(Some(hash)),
+        Some(_) => Err(Reply::wrong_type()),
+        None => Ok(None),
+    }
+}
+
+/// Like [`get_hash`], for commands that modify the value; the key counts as
+/// written.
+fn get_hash_mut<'a>(db: &'a mut Keyspace, key: &[u8]) -> Result<Option<&'a mut Hash>, Reply> {
+    match db.get_mut(key) {
+        Some(Entry { value: Value::Hash(hash), .. }) => Ok(Some(hash)),
+        Some(_) => Err(Reply::wrong_type()),
+        None => Ok(None),
+    }
+}
+
+/// Returns the hash stored at `key`, creating an empty one if needed.
+fn get_or_create_hash<'a>(db: &'a mut Keyspace, key: &[u8]) -> Result<&'a mut Hash, Reply> {
+    if get_hash(db, key)?.is_none() {
+        db.insert(key.to_vec(), Entry::new(Value::Hash(Hash::new())));
+    }
+    Ok(get_hash_mut(db, key)?.expect("hash was just inserted"))
+}
+
+/// Stores an incremented value without touching the field's deadline.
+fn update(hash: &mut Hash, field: &[u8], value: Vec<u8>) {
+    match hash.get_mut(field) {
+        Some(current) => *current = value,
+        None => {
+            hash.insert(field.to_vec(), value);
+        }
+    }
+}
+
+fn parse_float(arg: &[u8]) -> Option<f64> {
+    std::str::from_utf8(arg).ok()?.parse().ok().filter(|f: &f64| f.is_finite())
+}
+
+/// `HSET key field value [field value ...]`
+pub struct HSetCommand {
+    key: Vec<u8>,
+    pairs: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl Command for HSetCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        if !args.len().is_multiple_of(2) {
+            return Err(Reply::error("ERR wrong number of arguments for 'hset' command"));
+        }
+        let pairs = args[2..].chunks(2).map(|pair| (pair[0].clone(), pair[1].clone())).collect();
+        Ok(HSetCommand { key: args[1].clone(), pairs })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let hash = match get_or_create_hash(&mut db, &self.key) {
+            Ok(hash) => hash,
+            Err(reply) => return reply,
+        };
+        let added = self.pairs.iter().filter(|(field, value)| hash.insert(field.clone(), value.clone()).is_none()).count();
+        Reply::Integer(added as i64)
+    }
+}
+
+/// `HSETNX key field value`
+pub struct HSetNxCommand {
+    key: Vec<u8>,
+    field: Vec<u8>,
+    value: Vec<u8>,
+}
+
+impl Command for HSetNxCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(HSetNxCommand { key: args[1].clone(), field: args[2].clone(), value: args[3].clone() })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        match get_or_create_hash(&mut db, &self.key) {
+            Ok(hash) if hash.contains_key(&self.field) => Reply::Integer(0),
+            Ok(hash) => {
+                hash.insert(self.field.clone(), self.value.clone());
+                Reply::Integer(1)
+            }
+            Err(reply) => reply,
+        }
+    }
+}
+
+/// `HGET key field`
+pub struct HGetCommand {
+    key: Vec<u8>,
+    field: Vec<u8>,
+}
+
+impl Command for HGetCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(HGetCommand { key: args[1].clone(), field: args[2].clone() })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        match get_hash(&mut db, &self.key) {
+            Ok(hash) => hash.and_then(|hash| hash.get(&self.field)).map_or(Reply::Null, |value| Reply::Bulk(value.clone())),
+            Err(reply) => reply,
+        }
+    }
+}
+
+/// `HMGET key field [field ...]`
+pub struct HMGetCommand {
+    key: Vec<u8>,
+    fields: Vec<Vec<u8>>,
+}
+
+impl Command for HMGetCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(HMGetCommand { key: args[1].clone(), fields: args[2..].to_vec() })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let hash = match get_hash(&mut db, &self.key) {
+            Ok(hash) => hash,
+            Err(reply) => return reply,
+        };
+        Reply::Array(
+            self.fields
+                .iter()
+                .map(|field| hash.as_ref().and_then(|hash| hash.get(field)).map_or(Reply::Null, |value| Reply::Bulk(value.clone())))
+                .collect(),
+        )
+    }
+}
+
+/// `HDEL key field [field ...]`
+pub struct HDelCommand {
+    key: Vec<u8>,
+    fields: Vec<Vec<u8>>,
+}
+
+impl Command for HDelCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(HDelCommand { key: args[1].clone(), fields: args[2..].to_vec() })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let removed = match get_hash_mut(&mut db, &self.key) {
+            Ok(Some(hash)) => self.fields.iter().filter(|field| hash.remove(field).is_some()).count(),
+            Ok(None) => 0,
+            Err(reply) => return reply,
+        };
+        db.remove_if_empty(&self.key);
+        Reply::Integer(removed as i64)
+    }
+}
+
+/// What HGETALL, HKEYS and HVALS return for each field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Part {
+    Pairs,
+    Fields,
+    Values,
+}
+
+/// `HGETALL key`, `HKEYS key` and `HVALS key`
+pub struct HGetAllCommand {
+    key: Vec<u8>,
+    part: Part,
+}
+
+impl Command for HGetAllCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let part = match args[0].to_ascii_lowercase().as_slice() {
+            b"hkeys" => Part::Fields,
+            b"hvals" => Part::Values,
+            _ => Part::Pairs,
+        };
+        Ok(HGetAllCommand { key: args[1].clone(), part })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let hash = match get_hash(&mut db, &self.key) {
+            Ok(Some(hash)) => hash,
+            Ok(None) if self.part == Part::Pairs => return Reply::Map(Vec::new()),
+            Ok(None) => return Reply::Array(Vec::new()),
+            Err(reply) => return reply,
+        };
+        let bulk = |bytes: &Vec<u8>| Reply::Bulk(bytes.clone());
+        match self.part {
+            Part::Pairs => Reply::Map(hash.iter().map(|(field, value)| (bulk(field), bulk(value))).collect()),
+            Part::Fields => Reply::Array(hash.keys().map(bulk).collect()),
+            Part::Values => Reply::Array(hash.values().map(bulk).collect()),
+        }
+    }
+}
+
+/// `HLEN key`
+pub struct HLenCommand {
+    key: Vec<u8>,
+}
+
+impl Command for HLenCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(HLenCommand { key: args[1].clone() })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        match get_hash(&mut db, &self.key) {
+            Ok(hash) => Reply::Integer(hash.map_or(0, |hash| hash.len() as i64)),
+            Err(reply) => reply,
+        }
+    }
+}
+
+/// `HEXISTS key field`
+pub struct HExistsCommand {
+    key: Vec<u8>,
+    field: Vec<u8>,
+}
+
+impl Command for HExistsCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(HExistsCommand { key: args[1].clone(), field: args[2].clone() })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        match get_hash(&mut db, &self.key) {
+            Ok(hash) => Reply::Integer(hash.is_some_and(|hash| hash.contains_key(&self.field)) as i64),
+            Err(reply) => reply,
+        }
+    }
+}
+
+/// `HINCRBY key field increment`
+pub struct HIncrByCommand {
+    key: Vec<u8>,
+    field: Vec<u8>,
+    increment: i64,
+}
+
+impl Command for HIncrByCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(HIncrByCommand { key: args[1].clone(), field: args[2].clone(), increment: parse_int(&args[3])? })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let hash = match get_or_create_hash(&mut db, &self.key) {
+            Ok(hash) => hash,
+            Err(reply) => return reply,
+        };
+        let current = match hash.get(&self.field) {
+            Some(value) => match std::str::from_utf8(value).ok().and_then(|s| s.parse::<i64>().ok()) {
+                Some(current) => current,
+                None => return Reply::error("ERR hash value is not an integer"),
+            },
+            None => 0,
+        };
+        let Some(value) = current.checked_add(self.increment) else {
+            return Reply::error("ERR increment or decrement would overflow");
+        };
+        update(hash, &self.field, value.to_string().into_bytes());
+        Reply::Integer(value)
+    }
+}
+
+/// `HINCRBYFLOAT key field increment`
+pub struct HIncrByFloatCommand {
+    key: Vec<u8>,
+    field: Vec<u8>,
+    increment: f64,
+}
+
+impl Command for HIncrByFloatCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let increment = parse_float(&args[3]).ok_or_else(|| Reply::error("ERR value is not a valid float"))?;
+        Ok(HIncrByFloatCommand { key: args[1].clone(), field: args[2].clone(), increment })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let hash = match get_or_create_hash(&mut db, &self.key) {
+            Ok(hash) => hash,
+            Err(reply) => return reply,
+        };
+        let current = match hash.get(&self.field) {
+            Some(value) => match parse_float(value) {
+                Some(current) => current,
+                None => return Reply::error("ERR hash value is not a float"),
+            },
+            None => 0.0,
+        };
+        let value = current + self.increment;
+        if !value.is_finite() {
+            return Reply::error("ERR increment would produce NaN or Infinity");
+        }
+        let value = format_double(value).into_bytes();
+        update(hash, &self.field, value.clone());
+        Reply::Bulk(value)
+    }
+}
+
+/// `HSCAN key cursor [MATCH pattern] [COUNT count] [NOVALUES]`
+pub struct HScanCommand {
+    key: Vec<u8>,
+    options: ScanOptions,
+}
+
+impl Command for HScanCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(HScanCommand { key: args[1].clone(), options: ScanOptions::parse(&args[2..], &["NOVALUES"])? })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let hash = match get_hash(&mut db, &self.key) {
+            Ok(Some(hash)) => hash,
+            Ok(None) => return scan::reply(0, Vec::new()),
+            Err(reply) => return reply,
+        };
+        let (cursor, page) = hash.scan(self.options.cursor, self.options.count);
+
+        let mut elements = Vec::new();
+        for field in page.into_iter().filter(|field| self.options.matches(field)) {
+            elements.push(Reply::Bulk(field.to_vec()));
+            if !self.options.novalues {
+                elements.push(Reply::Bulk(hash.get(field).expect("scan order lists existing fields").clone()));
+            }
+        }
+        scan::reply(cursor, elements)
+    }
+}
+
+
+/// Parses `FIELDS numfields field [field ...]` at the start of `args`.
+fn parse_fields(args: &[Vec<u8>]) -> Result<Vec<Vec<u8>>, Reply> {
+    match args {
+        [keyword, count, fields @ ..] if keyword.eq_ignore_ascii_case(b"FIELDS") => {
+            let count = match parse_int::<i64>(count) {
+                Ok(count) if count > 0 => count as usize,
+                _ => return Err(Reply::error("ERR Parameter `numFields` should be greater than 0")),
+            };
+            if count != fields.len() {
+                return Err(Reply::error("ERR The `numfields` parameter must match the number of arguments"));
+            }
+            Ok(fields.to_vec())
+        }
+        _ => Err(Reply::error("ERR Mandatory argument FIELDS is missing or not at the right position")),
+    }
+}
+
+/// Reply for a field that does not exist, or every field of a missing key.
+const NO_FIELD: i64 = -2;
+
+/// Condition under which HEXPIRE changes a deadline.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Condition {
+    Always,
+    /// NX: only fields without a deadline.
+    Nx,
+    /// XX: only fields with a deadline.
+    Xx,
+    /// GT: only if the new deadline is later; no deadline counts as infinite.
+    Gt,
+    /// LT: only if the new deadline is earlier.
+    Lt,
+}
+
+impl Condition {
+    fn allows(self, current: Option<Instant>, new: Instant) -> bool {
+        match self {
+            Condition::Always => true,
+            Condition::Nx => current.is_none(),
+            Condition::Xx => current.is_some(),
+            Condition::Gt => current.is_some_and(|current| new > current),
+            Condition::Lt => current.is_none_or(|current| new < current),
+        }
+    }
+}
+
+/// `HEXPIRE key seconds [NX|XX|GT|LT] FIELDS numfields field [field ...]`
+/// and `HPEXPIRE key milliseconds ...`
+pub struct HExpireCommand {
+    /// The command name, lowercase, for error messages.
+    name: String,
+    key: Vec<u8>,
+    ttl: Duration,
+    condition: Condition,
+    fields: Vec<Vec<u8>>,
+}
+
+impl Command for HExpireCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let amount = match parse_int::<i64>(&args[2])? {
+            amount if amount >= 0 => amount as u64,
+            _ => return Err(Reply::error("ERR invalid expire time, must be >= 0")),
+        };
+        let ttl = if args[0].eq_ignore_ascii_case(b"hpexpire") {
+            Duration::from_millis(amount)
+        } else {
+            Duration::from_secs(amount)
+        };
+
+        let mut rest = &args[3..];
+        let condition = match rest.first().map(|arg| arg.to_ascii_uppercase()).as_deref() {
+            Some(b"NX") => Condition::Nx,
+            Some(b"XX") => Condition::Xx,
+            Some(b"GT") => Condition::Gt,
+            Some(b"LT") => Condition::Lt,
+            _ => Condition::Always,
+        };
+        if condition != Condition::Always {
+            rest = &rest[1..];
+        }
+        Ok(HExpireCommand {
+            name: String::from_utf8_lossy(&args[0]).to_lowercase(),
+            key: args[1].clone(),
+            ttl,
+            condition,
+            fields: parse_fields(rest)?,
+        })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let hash = match get_hash_mut(&mut db, &self.key) {
+            Ok(Some(hash)) => hash,
+            Ok(None) => return Reply::Array(self.fields.iter().map(|_| Reply::Integer(NO_FIELD)).collect()),
+            Err(reply) => return reply,
+        };
+
+        let Some(deadline) = Instant::now().checked_add(self.ttl) else {
+            return Reply::error(format!("ERR invalid expire time in '{}' command", self.name));
+        };
+        let results = self
+            .fields
+            .iter()
+            .map(|field| {
+                if !hash.contains_key(field) {
+                    NO_FIELD
+                } else if !self.condition.allows(hash.expires_at(field), deadline) {
+                    0
+                } else if self.ttl.is_zero() {
+                    hash.remove(field);
+                    2
+                } else {
+                    hash.set_expiry(field, Some(deadline));
+                    1
+                }
+            })
+            .map(Reply::Integer)
+            .collect();
+        db.remove_if_empty(&self.key);
+        Reply::Array(results)
+    }
+}
+
+/// `HTTL key FIELDS numfields field [field ...]` and `HPTTL ...`
+pub struct HTtlCommand {
+    key: Vec<u8>,
+    millis: bool,
+    fields: Vec<Vec<u8>>,
+}
+
+impl Command for HTtlCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(HTtlCommand {
+            key: args[1].clone(),
+            millis: args[0].eq_ignore_ascii_case(b"hpttl"),
+            fields: parse_fields(&args[2..])?,
+        })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let hash = match get_hash(&mut db, &self.key) {
+            Ok(hash) => hash,
+            Err(reply) => return reply,
+        };
+        let now = Instant::now();
+        let ttl = |field: &Vec<u8>| match hash.as_ref() {
+            Some(hash) if hash.contains_key(field) => match hash.expires_at(field) {
+                Some(deadline) if self.millis => deadline.saturating_duration_since(now).as_millis() as i64,
+                Some(deadline) => deadline.saturating_duration_since(now).as_secs() as i64,
+                None => -1,
+            },
+            _ => NO_FIELD,
+        };
+        Reply::Array(self.fields.iter().map(|field| Reply::Integer(ttl(field))).collect())
+    }
+}
+
+/// `HPERSIST key FIELDS numfields field [field ...]`
+pub struct HPersistCommand {
+    key: Vec<u8>,
+    fields: Vec<Vec<u8>>,
+}
+
+impl Command for HPersistCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(HPersistCommand { key: args[1].clone(), fields: parse_fields(&args[2..])? })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let mut hash = match get_hash_mut(&mut db, &self.key) {
+            Ok(hash) => hash,
+            Err(reply) => return reply,
+        };
+        let mut persist = |field: &Vec<u8>| match hash.as_deref_mut() {
+            Some(hash) if hash.expires_at(field).is_some() => {
+                hash.set_expiry(field, None);
+                1
+            }
+            Some(hash) if hash.contains_key(field) => -1,
+            _ => NO_FIELD,
+        };
+        Reply::Array(self.fields.iter().map(|field| Reply::Integer(persist(field))).collect())
+    }
+}
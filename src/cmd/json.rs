@@ -1,127 +1,983 @@
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use std::time::Instant;
 use serde_json::Value;
-use serde_json::json;
+use crate::cmd::{parse_int, syntax_error, Command};
+use crate::db::{self, Db, Entry, Keyspace};
+use crate::jsonpath::{self, Path};
+use crate::reply::Reply;
 
-type Db = Arc<Mutex<HashMap<String, DbValue>>>;
-type DbValue = (String, Option<Instant>);
+/// Returns the document stored at `key`, or WRONGTYPE if the key holds another type.
+fn get_document<'a>(db: &'a mut Keyspace, key: &[u8]) -> Result<Option<&'a Value>, Reply> {
+    match db.get(key) {
+        Some(Entry { value: db::Value::Json(document), .. }) => Ok(Some(document)),
+        Some(_) => Err(Reply::wrong_type()),
+        None => Ok(None),
+    }
+}
+
+/// Like [`get_document`], for commands that modify the value; the key counts as
+/// written.
+fn get_document_mut<'a>(db: &'a mut Keyspace, key: &[u8]) -> Result<Option<&'a mut Value>, Reply> {
+    match db.get_mut(key) {
+        Some(Entry { value: db::Value::Json(document), .. }) => Ok(Some(document)),
+        Some(_) => Err(Reply::wrong_type()),
+        None => Ok(None),
+    }
+}
+
+fn parse_path(arg: &[u8]) -> Result<Path, Reply> {
+    Path::parse(&String::from_utf8_lossy(arg)).map_err(|error| Reply::error(format!("ERR {error}")))
+}
 
+fn parse_json(arg: &[u8]) -> Result<Value, Reply> {
+    serde_json::from_slice(arg).map_err(|error| Reply::error(format!("ERR {error}")))
+}
+
+fn path_missing(path: &str) -> Reply {
+    Reply::error(format!("ERR Path '{path}' does not exist"))
+}
+
+/// Condition of JSON.SET.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Condition {
+    /// NX: only set paths that don't exist yet.
+    Missing,
+    /// XX: only set paths that already exist.
+    Existing,
+}
+
+/// `JSON.SET key path value [NX | XX]`
 pub struct SetJsonCommand {
-    key: String,
-    value: String,
+    key: Vec<u8>,
+    value: Vec<u8>,
     path: String,
+    condition: Option<Condition>,
 }
 
 impl SetJsonCommand {
-    pub fn new(key: &str, path: &str, value: &str) -> Self {
+    pub fn new(key: &[u8], path: &str, value: &[u8]) -> Self {
         SetJsonCommand {
-            key: key.to_string(),
-            value: value.to_string(),
+            key: key.to_vec(),
+            value: value.to_vec(),
             path: path.to_string(),
+            condition: None,
         }
     }
+}
 
-    pub fn execute(&self, db: &Db) -> String {
+impl Command for SetJsonCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let mut command = SetJsonCommand::new(&args[1], &String::from_utf8_lossy(&args[2]), &args[3]);
+        command.condition = match &args[4..] {
+            [] => None,
+            [option] if option.eq_ignore_ascii_case(b"NX") => Some(Condition::Missing),
+            [option] if option.eq_ignore_ascii_case(b"XX") => Some(Condition::Existing),
+            _ => return Err(syntax_error()),
+        };
+        Ok(command)
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let path = match parse_path(self.path.as_bytes()) {
+            Ok(path) => path,
+            Err(reply) => return reply,
+        };
+        let value = match parse_json(&self.value) {
+            Ok(value) => value,
+            Err(reply) => return reply,
+        };
         let mut db = db.lock().unwrap();
-        if self.path == "$" {
-            db.insert(self.key.clone(), (self.value.clone(), None));
-            "+OK\r\n".to_string()
+        let document = match get_document_mut(&mut db, &self.key) {
+            Ok(Some(document)) => document,
+            Ok(None) if !path.is_root() => return Reply::error("ERR new objects must be created at the root"),
+            Ok(None) if self.condition == Some(Condition::Existing) => return Reply::Null,
+            Ok(None) => {
+                db.insert(self.key.clone(), Entry::new(db::Value::Json(value)));
+                return Reply::ok();
+            }
+            Err(reply) => return reply,
+        };
+
+        let locations = path.locate(document);
+        if !locations.is_empty() {
+            if self.condition == Some(Condition::Missing) {
+                return Reply::Null;
+            }
+            for location in &locations {
+                if let Some(target) = jsonpath::get_mut(document, location) {
+                    *target = value.clone();
+                }
+            }
         } else {
-            "-ERR unsupported JSON path\r\n".to_string()
+            // A path that doesn't match may still name a new member of
+            // existing objects, e.g. `$.a.new` when `$.a` is an object.
+            if self.condition == Some(Condition::Existing) {
+                return Reply::Null;
+            }
+            let Some((parent, name)) = path.split_last_member() else {
+                return Reply::Null;
+            };
+            let mut created = false;
+            for location in parent.locate(document) {
+                if let Some(Value::Object(map)) = jsonpath::get_mut(document, &location) {
+                    map.insert(name.to_string(), value.clone());
+                    created = true;
+                }
+            }
+            if !created {
+                return Reply::Null;
+            }
         }
+        Reply::ok()
     }
-}   
+}
 
+/// Whitespace options of JSON.GET.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Format {
+    pub indent: String,
+    pub newline: String,
+    pub space: String,
+}
+
+impl Format {
+    pub fn render(&self, value: &Value) -> String {
+        if *self == Format::default() {
+            return value.to_string();
+        }
+        let mut out = String::new();
+        self.write(&mut out, value, 0);
+        out
+    }
+
+    fn write(&self, out: &mut String, value: &Value, depth: usize) {
+        let (open, close, items): (char, char, Vec<(Option<&String>, &Value)>) = match value {
+            Value::Array(items) if !items.is_empty() => ('[', ']', items.iter().map(|item| (None, item)).collect()),
+            Value::Object(map) if !map.is_empty() => ('{', '}', map.iter().map(|(name, item)| (Some(name), item)).collect()),
+            _ => {
+                out.push_str(&value.to_string());
+                return;
+            }
+        };
+        out.push(open);
+        for (i, (name, item)) in items.into_iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str(&self.newline);
+            out.push_str(&self.indent.repeat(depth + 1));
+            if let Some(name) = name {
+                out.push_str(&Value::String(name.clone()).to_string());
+                out.push(':');
+                out.push_str(&self.space);
+            }
+            self.write(out, item, depth + 1);
+        }
+        out.push_str(&self.newline);
+        out.push_str(&self.indent.repeat(depth));
+        out.push(close);
+    }
+}
+
+/// `JSON.GET key [INDENT indent] [NEWLINE newline] [SPACE space] [path ...]`
 pub struct GetJsonCommand {
-    key: String,
+    key: Vec<u8>,
     paths: Vec<String>,
+    format: Format,
 }
 
 impl GetJsonCommand {
-    pub fn new(key: &str, paths: &[&str]) -> Self {
+    pub fn new(key: &[u8], paths: &[&str]) -> Self {
         GetJsonCommand {
-            key: key.to_string(),
+            key: key.to_vec(),
             paths: paths.iter().map(|s| s.to_string()).collect(),
+            format: Format::default(),
+        }
+    }
+}
+
+impl Command for GetJsonCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let mut format = Format::default();
+        let mut i = 2;
+        while i + 1 < args.len() {
+            let value = String::from_utf8_lossy(&args[i + 1]).into_owned();
+            match args[i].to_ascii_uppercase().as_slice() {
+                b"INDENT" => format.indent = value,
+                b"NEWLINE" => format.newline = value,
+                b"SPACE" => format.space = value,
+                _ => break,
+            }
+            i += 2;
         }
+        let paths: Vec<String> = args[i..].iter().map(|s| String::from_utf8_lossy(s).into_owned()).collect();
+        let paths: Vec<&str> = paths.iter().map(|s| s.as_str()).collect();
+        let mut command = GetJsonCommand::new(&args[1], &paths);
+        command.format = format;
+        Ok(command)
     }
 
-    pub fn execute(&self, db: &Db) -> String {
-        let db = db.lock().unwrap();
-        if let Some((value, _)) = db.get(&self.key) {
-            let json_value: Value = serde_json::from_str(value).unwrap_or(json!(null));
-            if self.paths.is_empty() {
-                let response = serde_json::to_string(&json_value).unwrap_or("-ERR invalid JSON\r\n".to_string());
-                return format!("${}\r\n{}\r\n", response.len(), response);
-            }
-    
-            let mut flat_result = vec![];
-            let mut path_results = json!({});
-    
-            for path in &self.paths {
-                let mut values = vec![];
-                if path == "$..b" {
-                    find_all_paths(&json_value, "b", &mut values);
-                    if self.paths.len() == 1 {
-                        flat_result = values;
+    fn execute(&self, db: &Db) -> Reply {
+        let paths = match self.paths.iter().map(|path| parse_path(path.as_bytes())).collect::<Result<Vec<_>, _>>() {
+            Ok(paths) => paths,
+            Err(reply) => return reply,
+        };
+        let mut db = db.lock().unwrap();
+        let document = match get_document(&mut db, &self.key) {
+            Ok(Some(document)) => document,
+            Ok(None) => return Reply::Null,
+            Err(reply) => return reply,
+        };
+        if paths.is_empty() {
+            return Reply::Bulk(self.format.render(document).into_bytes());
+        }
+
+        // Legacy paths return the first match itself, JSONPath an array of
+        // all matches; several paths are returned as an object keyed by path.
+        let legacy = paths.iter().all(Path::is_legacy);
+        let result = match paths.as_slice() {
+            [path] if legacy => match path.query(document).first() {
+                Some(value) => (*value).clone(),
+                None => return path_missing(&self.paths[0]),
+            },
+            [path] => Value::Array(path.query(document).into_iter().cloned().collect()),
+            _ => {
+                let mut results = serde_json::Map::new();
+                for (text, path) in self.paths.iter().zip(&paths) {
+                    let matches = path.query(document);
+                    let value = if legacy {
+                        match matches.first() {
+                            Some(value) => (*value).clone(),
+                            None => return path_missing(text),
+                        }
                     } else {
-                        path_results[path] = json!(values);
-                    }
-                } else {
-                    find_all_paths(&json_value, path.trim_start_matches(".."), &mut values);
-                    path_results[path] = json!(values);
+                        Value::Array(matches.into_iter().cloned().collect())
+                    };
+                    results.insert(text.clone(), value);
                 }
+                Value::Object(results)
             }
-    
-            if !flat_result.is_empty() && self.paths.len() == 1 {
-                let response = serde_json::to_string(&flat_result).unwrap_or("-ERR invalid JSON\r\n".to_string());
-                return format!("${}\r\n{}\r\n", response.len(), response);
-            } else {
-                let response = serde_json::to_string(&path_results).unwrap_or("-ERR invalid JSON path\r\n".to_string());
-                return format!("${}\r\n{}\r\n", response.len(), response);
+        };
+        Reply::Bulk(self.format.render(&result).into_bytes())
+    }
+}
+
+/// A path argument with the text error messages quote. Commands whose path
+/// is optional default to the legacy root `.`.
+struct PathArg {
+    path: Path,
+    text: String,
+}
+
+impl PathArg {
+    fn parse(arg: Option<&Vec<u8>>) -> Result<Self, Reply> {
+        let text = arg.map_or_else(|| String::from("."), |arg| String::from_utf8_lossy(arg).into_owned());
+        Ok(PathArg { path: parse_path(text.as_bytes())?, text })
+    }
+}
+
+fn no_such_key() -> Reply {
+    Reply::error("ERR could not perform this operation on a key that doesn't exist")
+}
+
+/// Lets `run` read the document at `key`. `missing` is the reply for a key
+/// that doesn't exist.
+fn with_document(db: &Db, key: &[u8], missing: Reply, run: impl FnOnce(&Value) -> Result<Reply, Reply>) -> Reply {
+    let mut db = db.lock().unwrap();
+    match get_document(&mut db, key) {
+        Ok(Some(document)) => run(document).unwrap_or_else(|reply| reply),
+        Ok(None) => missing,
+        Err(reply) => reply,
+    }
+}
+
+/// Lets `run` work on the document at `key` in place. `missing` is the
+/// reply for a key that doesn't exist.
+fn with_document_mut(db: &Db, key: &[u8], missing: Reply, run: impl FnOnce(&mut Value) -> Result<Reply, Reply>) -> Reply {
+    let mut db = db.lock().unwrap();
+    match get_document_mut(&mut db, key) {
+        Ok(Some(document)) => run(document).unwrap_or_else(|reply| reply),
+        Ok(None) => missing,
+        Err(reply) => reply,
+    }
+}
+
+/// Runs `apply` on every match of `path`. `apply` returns `None` for values
+/// of the wrong type, which legacy paths report as an error naming `expected`.
+fn apply_to_matches<T>(
+    document: &mut Value,
+    path: &PathArg,
+    expected: &str,
+    mut apply: impl FnMut(&mut Value) -> Result<Option<T>, Reply>,
+) -> Result<Vec<Option<T>>, Reply> {
+    let locations = path.path.locate(document);
+    if path.path.is_legacy() && locations.is_empty() {
+        return Err(path_missing(&path.text));
+    }
+    let mut results = Vec::with_capacity(locations.len());
+    for location in &locations {
+        let Some(value) = jsonpath::get_mut(document, location) else {
+            continue;
+        };
+        let found = jsonpath::type_name(value);
+        let result = apply(value)?;
+        if result.is_none() && path.path.is_legacy() {
+            return Err(Reply::error(format!("ERR wrong type of path value - expected {expected} but found {found}")));
+        }
+        results.push(result);
+    }
+    Ok(results)
+}
+
+/// Like [`apply_to_matches`], for commands that only read the matches.
+fn read_matches<T>(
+    document: &Value,
+    path: &PathArg,
+    expected: &str,
+    mut read: impl FnMut(&Value) -> Result<Option<T>, Reply>,
+) -> Result<Vec<Option<T>>, Reply> {
+    let matches = path.path.query(document);
+    if path.path.is_legacy() && matches.is_empty() {
+        return Err(path_missing(&path.text));
+    }
+    let mut results = Vec::with_capacity(matches.len());
+    for value in matches {
+        let result = read(value)?;
+        if result.is_none() && path.path.is_legacy() {
+            let found = jsonpath::type_name(value);
+            return Err(Reply::error(format!("ERR wrong type of path value - expected {expected} but found {found}")));
+        }
+        results.push(result);
+    }
+    Ok(results)
+}
+
+/// The reply of a command that reports one result per match: the first
+/// result for legacy paths, an array with nil for mismatched types otherwise.
+fn per_match<T>(path: &PathArg, results: Vec<Option<T>>, reply: impl Fn(T) -> Reply) -> Reply {
+    if path.path.is_legacy() {
+        return results.into_iter().flatten().next().map_or(Reply::Null, reply);
+    }
+    Reply::Array(results.into_iter().map(|result| result.map_or(Reply::Null, &reply)).collect())
+}
+
+/// Like [`per_match`], but as a single JSON document, as NUMINCRBY replies.
+fn per_match_json(path: &PathArg, results: Vec<Option<Value>>) -> Reply {
+    let value = if path.path.is_legacy() {
+        results.into_iter().flatten().next().unwrap_or(Value::Null)
+    } else {
+        Value::Array(results.into_iter().map(|result| result.unwrap_or(Value::Null)).collect())
+    };
+    Reply::Bulk(value.to_string().into_bytes())
+}
+
+fn integer(value: usize) -> Reply {
+    Reply::Integer(value as i64)
+}
+
+fn json_bulk(value: &Value) -> Reply {
+    Reply::Bulk(value.to_string().into_bytes())
+}
+
+/// `JSON.DEL key [path]` and `JSON.FORGET key [path]`
+pub struct DelJsonCommand {
+    key: Vec<u8>,
+    path: String,
+}
+
+impl DelJsonCommand {
+    pub fn new(key: &[u8]) -> Self {
+        DelJsonCommand {
+            key: key.to_vec(),
+            path: String::from("."),
+        }
+    }
+}
+
+impl Command for DelJsonCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let mut command = DelJsonCommand::new(&args[1]);
+        match &args[2..] {
+            [] => {}
+            [path] => command.path = String::from_utf8_lossy(path).into_owned(),
+            _ => return Err(syntax_error()),
+        }
+        Ok(command)
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let path = match parse_path(self.path.as_bytes()) {
+            Ok(path) => path,
+            Err(reply) => return reply,
+        };
+        if path.is_root() {
+            let mut db = db.lock().unwrap();
+            return match get_document(&mut db, &self.key) {
+                Ok(Some(_)) => {
+                    db.remove(&self.key);
+                    Reply::Integer(1)
+                }
+                Ok(None) => Reply::Integer(0),
+                Err(reply) => reply,
+            };
+        }
+        with_document_mut(db, &self.key, Reply::Integer(0), |document| {
+            // Delete from the back so earlier array indices stay valid, and
+            // skip matches nested in another match that goes anyway. Once
+            // sorted, the matches nested in one follow it directly.
+            let mut locations = path.locate(document);
+            locations.sort();
+            let mut outermost: Vec<Vec<jsonpath::Key>> = Vec::new();
+            for location in locations {
+                if outermost.last().is_none_or(|kept| !location.starts_with(kept)) {
+                    outermost.push(location);
+                }
             }
+            let mut deleted = 0;
+            for location in outermost.iter().rev() {
+                if jsonpath::remove(document, location).is_some() {
+                    deleted += 1;
+                }
+            }
+            Ok(Reply::Integer(deleted))
+        })
+    }
+}
+
+/// `JSON.MGET key [key ...] path`
+pub struct MGetJsonCommand {
+    keys: Vec<Vec<u8>>,
+    path: PathArg,
+}
+
+impl Command for MGetJsonCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let path = PathArg::parse(args.last())?;
+        Ok(MGetJsonCommand { keys: args[1..args.len() - 1].to_vec(), path })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let replies = self
+            .keys
+            .iter()
+            .map(|key| {
+                let Ok(Some(document)) = get_document(&mut db, key) else {
+                    return Reply::Null;
+                };
+                let matches = self.path.path.query(document);
+                if self.path.path.is_legacy() {
+                    matches.first().map_or(Reply::Null, |value| json_bulk(value))
+                } else {
+                    json_bulk(&Value::Array(matches.into_iter().cloned().collect()))
+                }
+            })
+            .collect();
+        Reply::Array(replies)
+    }
+}
+
+/// `JSON.TYPE key [path]`
+pub struct TypeJsonCommand {
+    key: Vec<u8>,
+    path: PathArg,
+}
+
+impl Command for TypeJsonCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        match args.len() {
+            2 | 3 => Ok(TypeJsonCommand { key: args[1].clone(), path: PathArg::parse(args.get(2))? }),
+            _ => Err(syntax_error()),
+        }
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        with_document(db, &self.key, Reply::Null, |document| {
+            let types = self.path.path.query(document).into_iter().map(jsonpath::type_name);
+            let reply = if self.path.path.is_legacy() {
+                types.map(|name| Reply::Simple(name.to_string())).next().unwrap_or(Reply::Null)
+            } else {
+                Reply::Array(types.map(|name| Reply::Bulk(name.as_bytes().to_vec())).collect())
+            };
+            Ok(reply)
+        })
+    }
+}
+
+/// `JSON.NUMINCRBY key path value` and `JSON.NUMMULTBY key path value`
+pub struct NumOpJsonCommand {
+    key: Vec<u8>,
+    path: PathArg,
+    operand: serde_json::Number,
+    multiply: bool,
+}
+
+impl NumOpJsonCommand {
+    /// Integers stay integers unless the result overflows; anything else is
+    /// computed as a double.
+    fn apply(&self, number: &serde_json::Number) -> Result<serde_json::Number, Reply> {
+        let exact = match (number.as_i64(), self.operand.as_i64()) {
+            (Some(left), Some(right)) if self.multiply => left.checked_mul(right),
+            (Some(left), Some(right)) => left.checked_add(right),
+            _ => None,
+        };
+        if let Some(result) = exact {
+            return Ok(result.into());
+        }
+        let (left, right) = (number.as_f64().unwrap_or(0.0), self.operand.as_f64().unwrap_or(0.0));
+        let result = if self.multiply { left * right } else { left + right };
+        serde_json::Number::from_f64(result).ok_or_else(|| Reply::error("ERR result is an infinite or NaN number"))
+    }
+}
+
+impl Command for NumOpJsonCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let operand = match parse_json(&args[3])? {
+            Value::Number(number) => number,
+            _ => return Err(Reply::error("ERR expected a number")),
+        };
+        Ok(NumOpJsonCommand {
+            key: args[1].clone(),
+            path: PathArg::parse(args.get(2))?,
+            operand,
+            multiply: args[0].eq_ignore_ascii_case(b"json.nummultby"),
+        })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        with_document_mut(db, &self.key, no_such_key(), |document| {
+            let results = apply_to_matches(document, &self.path, "number", |value| match value {
+                Value::Number(number) => {
+                    *number = self.apply(number)?;
+                    Ok(Some(value.clone()))
+                }
+                _ => Ok(None),
+            })?;
+            Ok(per_match_json(&self.path, results))
+        })
+    }
+}
+
+/// `JSON.STRAPPEND key [path] value`
+pub struct StrAppendJsonCommand {
+    key: Vec<u8>,
+    path: PathArg,
+    suffix: String,
+}
+
+impl Command for StrAppendJsonCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let (path, value) = match args.len() {
+            3 => (None, &args[2]),
+            4 => (Some(&args[2]), &args[3]),
+            _ => return Err(syntax_error()),
+        };
+        let Value::String(suffix) = parse_json(value)? else {
+            return Err(Reply::error("ERR expected a JSON string"));
+        };
+        Ok(StrAppendJsonCommand { key: args[1].clone(), path: PathArg::parse(path)?, suffix })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        with_document_mut(db, &self.key, no_such_key(), |document| {
+            let results = apply_to_matches(document, &self.path, "string", |value| match value {
+                Value::String(string) => {
+                    string.push_str(&self.suffix);
+                    Ok(Some(string.len()))
+                }
+                _ => Ok(None),
+            })?;
+            Ok(per_match(&self.path, results, integer))
+        })
+    }
+}
+
+/// What a length command measures.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Measure {
+    String,
+    Array,
+    Object,
+}
+
+/// `JSON.STRLEN key [path]`, `JSON.ARRLEN key [path]` and `JSON.OBJLEN key [path]`
+pub struct LenJsonCommand {
+    key: Vec<u8>,
+    path: PathArg,
+    measure: Measure,
+}
+
+impl Command for LenJsonCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        if args.len() > 3 {
+            return Err(syntax_error());
+        }
+        let measure = match args[0].to_ascii_lowercase().as_slice() {
+            b"json.strlen" => Measure::String,
+            b"json.arrlen" => Measure::Array,
+            _ => Measure::Object,
+        };
+        Ok(LenJsonCommand { key: args[1].clone(), path: PathArg::parse(args.get(2))?, measure })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let expected = match self.measure {
+            Measure::String => "string",
+            Measure::Array => "array",
+            Measure::Object => "object",
+        };
+        with_document(db, &self.key, Reply::Null, |document| {
+            let results = read_matches(document, &self.path, expected, |value| {
+                Ok(match (self.measure, value) {
+                    (Measure::String, Value::String(string)) => Some(string.len()),
+                    (Measure::Array, Value::Array(items)) => Some(items.len()),
+                    (Measure::Object, Value::Object(map)) => Some(map.len()),
+                    _ => None,
+                })
+            })?;
+            Ok(per_match(&self.path, results, integer))
+        })
+    }
+}
+
+/// `JSON.ARRAPPEND key path value [value ...]` and `JSON.ARRINSERT key path index value [value ...]`
+pub struct ArrInsertJsonCommand {
+    key: Vec<u8>,
+    path: PathArg,
+    /// `None` appends.
+    index: Option<i64>,
+    values: Vec<Value>,
+}
+
+impl Command for ArrInsertJsonCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let (index, values) = if args[0].eq_ignore_ascii_case(b"json.arrinsert") {
+            (Some(parse_int::<i64>(&args[3])?), &args[4..])
         } else {
-            "-ERR no such key\r\n".to_string()
+            (None, &args[3..])
+        };
+        if values.is_empty() {
+            return Err(Reply::error(format!(
+                "ERR wrong number of arguments for '{}' command",
+                String::from_utf8_lossy(&args[0]).to_lowercase()
+            )));
         }
+        let values = values.iter().map(|value| parse_json(value)).collect::<Result<_, _>>()?;
+        Ok(ArrInsertJsonCommand { key: args[1].clone(), path: PathArg::parse(args.get(2))?, index, values })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        with_document_mut(db, &self.key, no_such_key(), |document| {
+            let results = apply_to_matches(document, &self.path, "array", |value| {
+                let Value::Array(items) = value else {
+                    return Ok(None);
+                };
+                let at = match self.index {
+                    None => items.len(),
+                    Some(index) => {
+                        let at = if index < 0 { items.len() as i64 + index } else { index };
+                        if !(0..=items.len() as i64).contains(&at) {
+                            return Err(Reply::error("ERR index out of bounds"));
+                        }
+                        at as usize
+                    }
+                };
+                items.splice(at..at, self.values.iter().cloned());
+                Ok(Some(items.len()))
+            })?;
+            Ok(per_match(&self.path, results, integer))
+        })
     }
 }
 
-fn find_all_paths<'a>(json_value: &'a Value, key: &str, results: &mut Vec<Value>) {
-    match json_value {
-        Value::Object(map) => {
-            for (k, v) in map {
-                if k == key {
-                    results.push(v.clone());
+/// `JSON.ARRPOP key [path [index]]`
+pub struct ArrPopJsonCommand {
+    key: Vec<u8>,
+    path: PathArg,
+    index: i64,
+}
+
+impl Command for ArrPopJsonCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let index = match args.get(3) {
+            Some(index) => parse_int::<i64>(index)?,
+            None => -1,
+        };
+        if args.len() > 4 {
+            return Err(syntax_error());
+        }
+        Ok(ArrPopJsonCommand { key: args[1].clone(), path: PathArg::parse(args.get(2))?, index })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        with_document_mut(db, &self.key, no_such_key(), |document| {
+            // An empty array pops nothing, which isn't a type mismatch.
+            let results = apply_to_matches(document, &self.path, "array", |value| {
+                let Value::Array(items) = value else {
+                    return Ok(None);
+                };
+                if items.is_empty() {
+                    return Ok(Some(None));
                 }
-                find_all_paths(v, key, results);
-            }
+                // Out-of-range indices are clamped to the array's ends.
+                let len = items.len() as i64;
+                let index = if self.index < 0 { len + self.index } else { self.index };
+                Ok(Some(Some(items.remove(index.clamp(0, len - 1) as usize))))
+            })?;
+            Ok(per_match(&self.path, results, |popped| popped.as_ref().map_or(Reply::Null, json_bulk)))
+        })
+    }
+}
+
+/// `JSON.ARRINDEX key path value [start [stop]]`
+pub struct ArrIndexJsonCommand {
+    key: Vec<u8>,
+    path: PathArg,
+    value: Value,
+    start: i64,
+    stop: i64,
+}
+
+impl Command for ArrIndexJsonCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        if args.len() > 6 {
+            return Err(syntax_error());
+        }
+        let start = args.get(4).map(|start| parse_int::<i64>(start)).transpose()?.unwrap_or(0);
+        let stop = args.get(5).map(|stop| parse_int::<i64>(stop)).transpose()?.unwrap_or(0);
+        Ok(ArrIndexJsonCommand { key: args[1].clone(), path: PathArg::parse(args.get(2))?, value: parse_json(&args[3])?, start, stop })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        with_document(db, &self.key, no_such_key(), |document| {
+            let results = read_matches(document, &self.path, "array", |value| {
+                let Value::Array(items) = value else {
+                    return Ok(None);
+                };
+                // Negative bounds count from the end; a stop of 0 means the end.
+                let len = items.len() as i64;
+                let start = if self.start < 0 { len + self.start } else { self.start }.clamp(0, len);
+                let stop = match self.stop {
+                    0 => len,
+                    stop if stop < 0 => len + stop,
+                    stop => stop,
+                }
+                .clamp(0, len);
+                let position = (start..stop).find(|i| items[*i as usize] == self.value);
+                Ok(Some(position.unwrap_or(-1)))
+            })?;
+            Ok(per_match(&self.path, results, Reply::Integer))
+        })
+    }
+}
+
+/// `JSON.ARRTRIM key path start stop`
+pub struct ArrTrimJsonCommand {
+    key: Vec<u8>,
+    path: PathArg,
+    start: i64,
+    stop: i64,
+}
+
+impl Command for ArrTrimJsonCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        if args.len() != 5 {
+            return Err(syntax_error());
         }
-        Value::Array(arr) => {
-            for v in arr {
-                find_all_paths(v, key, results);
+        Ok(ArrTrimJsonCommand {
+            key: args[1].clone(),
+            path: PathArg::parse(args.get(2))?,
+            start: parse_int(&args[3])?,
+            stop: parse_int(&args[4])?,
+        })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        with_document_mut(db, &self.key, no_such_key(), |document| {
+            let results = apply_to_matches(document, &self.path, "array", |value| {
+                let Value::Array(items) = value else {
+                    return Ok(None);
+                };
+                // Same range rules as LTRIM: `stop` is inclusive.
+                let len = items.len() as i64;
+                let start = if self.start < 0 { len + self.start } else { self.start }.max(0);
+                let stop = if self.stop < 0 { len + self.stop } else { self.stop }.min(len - 1);
+                if start > stop || start >= len {
+                    items.clear();
+                } else {
+                    items.truncate(stop as usize + 1);
+                    items.drain(..start as usize);
+                }
+                Ok(Some(items.len()))
+            })?;
+            Ok(per_match(&self.path, results, integer))
+        })
+    }
+}
+
+/// `JSON.OBJKEYS key [path]`
+pub struct ObjKeysJsonCommand {
+    key: Vec<u8>,
+    path: PathArg,
+}
+
+impl Command for ObjKeysJsonCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        match args.len() {
+            2 | 3 => Ok(ObjKeysJsonCommand { key: args[1].clone(), path: PathArg::parse(args.get(2))? }),
+            _ => Err(syntax_error()),
+        }
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        with_document(db, &self.key, Reply::Null, |document| {
+            let results = read_matches(document, &self.path, "object", |value| {
+                Ok(match value {
+                    Value::Object(map) => Some(map.keys().map(|name| Reply::Bulk(name.as_bytes().to_vec())).collect()),
+                    _ => None,
+                })
+            })?;
+            Ok(per_match(&self.path, results, Reply::Array))
+        })
+    }
+}
+
+/// `JSON.TOGGLE key path`
+pub struct ToggleJsonCommand {
+    key: Vec<u8>,
+    path: PathArg,
+}
+
+impl Command for ToggleJsonCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        match args.len() {
+            2 | 3 => Ok(ToggleJsonCommand { key: args[1].clone(), path: PathArg::parse(args.get(2))? }),
+            _ => Err(syntax_error()),
+        }
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        with_document_mut(db, &self.key, no_such_key(), |document| {
+            let results = apply_to_matches(document, &self.path, "boolean", |value| match value {
+                Value::Bool(flag) => {
+                    *flag = !*flag;
+                    Ok(Some(*flag))
+                }
+                _ => Ok(None),
+            })?;
+            let reply = if self.path.path.is_legacy() {
+                per_match(&self.path, results, |flag| Reply::Bulk(flag.to_string().into_bytes()))
+            } else {
+                per_match(&self.path, results, |flag| Reply::Integer(flag as i64))
+            };
+            Ok(reply)
+        })
+    }
+}
+
+/// `JSON.CLEAR key [path]`
+pub struct ClearJsonCommand {
+    key: Vec<u8>,
+    path: PathArg,
+}
+
+impl Command for ClearJsonCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        match args.len() {
+            2 | 3 => Ok(ClearJsonCommand { key: args[1].clone(), path: PathArg::parse(args.get(2))? }),
+            _ => Err(syntax_error()),
+        }
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        with_document_mut(db, &self.key, no_such_key(), |document| {
+            // Containers are emptied and numbers reset to 0; other values are
+            // left alone and not counted.
+            let mut cleared = 0;
+            for location in self.path.path.locate(document) {
+                match jsonpath::get_mut(document, &location) {
+                    Some(Value::Array(items)) => items.clear(),
+                    Some(Value::Object(map)) => map.clear(),
+                    Some(value @ Value::Number(_)) => *value = Value::from(0),
+                    _ => continue,
+                }
+                cleared += 1;
             }
+            Ok(Reply::Integer(cleared))
+        })
+    }
+}
+
+/// Applies an RFC 7396 merge patch: objects merge member by member, `null`
+/// removes a member and anything else replaces the target.
+fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(serde_json::Map::new());
+    }
+    let Value::Object(map) = target else {
+        unreachable!("target was just made an object");
+    };
+    for (name, value) in patch {
+        if value.is_null() {
+            map.shift_remove(name);
+        } else {
+            merge_patch(map.entry(name.clone()).or_insert(Value::Null), value);
         }
-        _ => (),
     }
 }
 
-pub struct DelJsonCommand {
-    key: String,
+/// `JSON.MERGE key path value`
+pub struct MergeJsonCommand {
+    key: Vec<u8>,
+    path: PathArg,
+    patch: Value,
 }
 
-impl DelJsonCommand {
-    pub fn new(key: &str) -> Self {
-        DelJsonCommand {
-            key: key.to_string(),
+impl Command for MergeJsonCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        if args.len() != 4 {
+            return Err(syntax_error());
         }
+        Ok(MergeJsonCommand { key: args[1].clone(), path: PathArg::parse(args.get(2))?, patch: parse_json(&args[3])? })
     }
 
-    pub fn execute(&self, db: &Db) -> String {
+    fn execute(&self, db: &Db) -> Reply {
+        let path = &self.path.path;
         let mut db = db.lock().unwrap();
-        if db.remove(&self.key).is_some() {
-            "+OK\r\n".to_string()
-        } else {
-            "-ERR no such key\r\n".to_string()
+        if !path.is_root() && matches!(get_document(&mut db, &self.key), Ok(None)) {
+            return Reply::error("ERR new objects must be created at the root");
+        }
+        let document = match get_document_mut(&mut db, &self.key) {
+            Ok(Some(document)) => document,
+            Ok(None) => {
+                db.insert(self.key.clone(), Entry::new(db::Value::Json(Value::Null)));
+                get_document_mut(&mut db, &self.key).unwrap().expect("document was just inserted")
+            }
+            Err(reply) => return reply,
+        };
+
+        let mut locations = path.locate(document);
+        if locations.is_empty() {
+            // Like JSON.SET, a missing member of existing objects is created.
+            if let Some((parent, name)) = path.split_last_member().filter(|_| !self.patch.is_null()) {
+                for location in parent.locate(document) {
+                    if let Some(Value::Object(map)) = jsonpath::get_mut(document, &location) {
+                        map.insert(name.to_string(), Value::Null);
+                    }
+                }
+                locations = path.locate(document);
+            }
         }
+        locations.sort();
+        for location in locations.iter().rev() {
+            if self.patch.is_null() && !location.is_empty() {
+                jsonpath::remove(document, location);
+            } else if let Some(target) = jsonpath::get_mut(document, location) {
+                merge_patch(target, &self.patch);
+            }
+        }
+        Reply::ok()
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,205 @@
+use crate::cmd::scan::{self, glob_match, ScanOptions};
+use crate::cmd::sets::Random;
+use crate::cmd::{parse_int, syntax_error, Command};
+use crate::db::Db;
+use crate::reply::Reply;
+
+/// `DEL key [key ...]` and `UNLINK key [key ...]`; UNLINK frees large
+/// values in the background.
+pub struct DelCommand {
+    keys: Vec<Vec<u8>>,
+    lazy: bool,
+}
+
+impl Command for DelCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(DelCommand { keys: args[1..].to_vec(), lazy: args[0].eq_ignore_ascii_case(b"UNLINK") })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let mut deleted = 0;
+        for key in &self.keys {
+            let existed = if self.lazy { db.unlink(key) } else { db.remove(key).is_some() };
+            if existed {
+                deleted += 1;
+            }
+        }
+        Reply::Integer(deleted)
+    }
+}
+
+/// `TYPE key`
+pub struct TypeCommand {
+    key: Vec<u8>,
+}
+
+impl Command for TypeCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(TypeCommand { key: args[1].clone() })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let name = db.get(&self.key).map_or("none", |entry| entry.value.type_name());
+        Reply::Simple(name.to_string())
+    }
+}
+
+/// `RENAME key newkey` and `RENAMENX key newkey`
+pub struct RenameCommand {
+    from: Vec<u8>,
+    to: Vec<u8>,
+    /// RENAMENX: only rename if `to` doesn't exist.
+    nx: bool,
+}
+
+impl Command for RenameCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(RenameCommand { from: args[1].clone(), to: args[2].clone(), nx: args[0].eq_ignore_ascii_case(b"RENAMENX") })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        if !db.contains_key(&self.from) {
+            return Reply::error("ERR no such key");
+        }
+        if self.nx {
+            if db.contains_key(&self.to) {
+                return Reply::Integer(0);
+            }
+            db.rename(&self.from, &self.to);
+            return Reply::Integer(1);
+        }
+        db.rename(&self.from, &self.to);
+        Reply::ok()
+    }
+}
+
+/// `COPY source destination [DB destination-db] [REPLACE]`
+pub struct CopyCommand {
+    source: Vec<u8>,
+    destination: Vec<u8>,
+    replace: bool,
+}
+
+impl Command for CopyCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let mut command = CopyCommand { source: args[1].clone(), destination: args[2].clone(), replace: false };
+        let mut rest = &args[3..];
+        while let Some((option, tail)) = rest.split_first() {
+            match (option.to_ascii_uppercase().as_slice(), tail) {
+                (b"REPLACE", _) => {
+                    command.replace = true;
+                    rest = tail;
+                }
+                // There is a single database, number 0.
+                (b"DB", [index, tail @ ..]) => {
+                    if parse_int::<i64>(index)? != 0 {
+                        return Err(Reply::error("ERR DB index is out of range"));
+                    }
+                    rest = tail;
+                }
+                _ => return Err(syntax_error()),
+            }
+        }
+        Ok(command)
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        if self.source == self.destination {
+            return Reply::error("ERR source and destination objects are the same");
+        }
+        let mut db = db.lock().unwrap();
+        let Some(entry) = db.get(&self.source).cloned() else {
+            return Reply::Integer(0);
+        };
+        if db.contains_key(&self.destination) {
+            if !self.replace {
+                return Reply::Integer(0);
+            }
+            db.unlink(&self.destination);
+        }
+        db.insert(self.destination.clone(), entry);
+        db.signal_ready(&self.destination);
+        Reply::Integer(1)
+    }
+}
+
+/// `RANDOMKEY`
+pub struct RandomKeyCommand;
+
+impl Command for RandomKeyCommand {
+    fn parse(_args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(RandomKeyCommand)
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut random = Random::new();
+        match db.lock().unwrap().random_key(|n| random.below(n)) {
+            Some(key) => Reply::Bulk(key),
+            None => Reply::Null,
+        }
+    }
+}
+
+/// `DBSIZE`
+pub struct DbSizeCommand;
+
+impl Command for DbSizeCommand {
+    fn parse(_args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(DbSizeCommand)
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        Reply::Integer(db.lock().unwrap().key_count() as i64)
+    }
+}
+
+/// `KEYS pattern`; walks the whole keyspace, so meant for debugging.
+pub struct KeysCommand {
+    pattern: Vec<u8>,
+}
+
+impl Command for KeysCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(KeysCommand { pattern: args[1].clone() })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let db = db.lock().unwrap();
+        Reply::Array(
+            db.live_entries()
+                .filter(|(key, _)| glob_match(&self.pattern, key))
+                .map(|(key, _)| Reply::Bulk(key.to_vec()))
+                .collect(),
+        )
+    }
+}
+
+/// `SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]`
+///
+/// The cursor is a position in an order that depends only on the keys, so
+/// every key present for the whole iteration is returned however the
+/// keyspace grows or shrinks in between. MATCH and TYPE filter each page
+/// after it is taken, so pages may come back short or empty.
+pub struct ScanCommand {
+    options: ScanOptions,
+}
+
+impl Command for ScanCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(ScanCommand { options: ScanOptions::parse(&args[1..], &["TYPE"])? })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let db = db.lock().unwrap();
+        let (cursor, page) = db.scan(self.options.cursor, self.options.count);
+        let keys = page
+            .into_iter()
+            .filter(|(key, entry)| self.options.matches(key) && self.options.matches_type(entry.value.type_name()))
+            .map(|(key, _)| Reply::Bulk(key.to_vec()))
+            .collect();
+        scan::reply(cursor, keys)
+    }
+}
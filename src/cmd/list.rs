@@ -0,0 +1,478 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+use crate::cmd::{parse_int, parse_timeout, syntax_error, BlockingCommand, Command};
+use crate::db::{Db, Entry, Keyspace, Value};
+use crate::reply::Reply;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum End {
+    Left,
+    Right,
+}
+
+impl End {
+    fn parse(arg: &[u8]) -> Result<Self, Reply> {
+        match arg.to_ascii_uppercase().as_slice() {
+            b"LEFT" => Ok(End::Left),
+            b"RIGHT" => Ok(End::Right),
+            _ => Err(syntax_error()),
+        }
+    }
+
+    /// The end an `L*`/`R*` command such as LPUSH or RPOP operates on.
+    fn of_command(name: &[u8]) -> Self {
+        if name.first().is_some_and(|c| c.eq_ignore_ascii_case(&b'l')) {
+            End::Left
+        } else {
+            End::Right
+        }
+    }
+}
+
+/// Returns the list stored at `key`, or WRONGTYPE if the key holds another type.
+fn get_list<'a>(db: &'a mut Keyspace, key: &[u8]) -> Result<Option<&'a VecDeque<Vec<u8>>>, Reply> {
+    match db.get(key) {
+        Some(Entry { value: Value::List(list), .. }) => Ok(Some(list)),
+        Some(_) => Err(Reply::wrong_type()),
+        None => Ok(None),
+    }
+}
+
+/// Like [`get_list`], for commands that modify the value; the key counts as
+/// written.
+fn get_list_mut<'a>(db: &'a mut Keyspace, key: &[u8]) -> Result<Option<&'a mut VecDeque<Vec<u8>>>, Reply> {
+    match db.get_mut(key) {
+        Some(Entry { value: Value::List(list), .. }) => Ok(Some(list)),
+        Some(_) => Err(Reply::wrong_type()),
+        None => Ok(None),
+    }
+}
+
+/// Pushes `elements` one by one at `end`, creating the list if needed.
+/// Returns the new length.
+pub fn push(db: &mut Keyspace, key: &[u8], end: End, elements: &[Vec<u8>]) -> Result<usize, Reply> {
+    let list = match get_list_mut(db, key)? {
+        Some(list) => list,
+        None => {
+            db.insert(key.to_vec(), Entry::new(Value::List(VecDeque::new())));
+            get_list_mut(db, key)?.expect("list was just inserted")
+        }
+    };
+    for element in elements {
+        match end {
+            End::Left => list.push_front(element.clone()),
+            End::Right => list.push_back(element.clone()),
+        }
+    }
+    let len = list.len();
+    db.signal_ready(key);
+    Ok(len)
+}
+
+/// Pops up to `count` elements from `end`, deleting the key once it is empty.
+/// Returns `None` when the key does not exist.
+pub fn pop(db: &mut Keyspace, key: &[u8], end: End, count: usize) -> Result<Option<Vec<Vec<u8>>>, Reply> {
+    let Some(list) = get_list_mut(db, key)? else {
+        return Ok(None);
+    };
+    let mut popped = Vec::with_capacity(count.min(list.len()));
+    while popped.len() < count {
+        let element = match end {
+            End::Left => list.pop_front(),
+            End::Right => list.pop_back(),
+        };
+        match element {
+            Some(element) => popped.push(element),
+            None => break,
+        }
+    }
+    db.remove_if_empty(key);
+    Ok(Some(popped))
+}
+
+/// Atomically moves one element from `source` to `destination`.
+pub fn lmove(db: &mut Keyspace, source: &[u8], destination: &[u8], from: End, to: End) -> Result<Option<Vec<u8>>, Reply> {
+    if get_list(db, source)?.is_none() {
+        return Ok(None);
+    }
+    // Check the destination type before anything is popped.
+    get_list(db, destination)?;
+    let Some(mut popped) = pop(db, source, from, 1)? else {
+        return Ok(None);
+    };
+    let element = popped.pop().expect("source list is never empty");
+    push(db, destination, to, std::slice::from_ref(&element))?;
+    Ok(Some(element))
+}
+
+/// Converts a Redis index (negative counts from the tail) into a position.
+fn index(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 { index + len as i64 } else { index };
+    (0..len as i64).contains(&index).then_some(index as usize)
+}
+
+/// Clamps an inclusive `start..=stop` range with Redis semantics, returning
+/// `None` when it selects nothing.
+pub fn range(start: i64, stop: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 { (start + len).max(0) } else { start };
+    let stop = if stop < 0 { stop + len } else { stop.min(len - 1) };
+    (start <= stop && start < len).then_some((start as usize, stop as usize))
+}
+
+fn parse_count(arg: &[u8]) -> Result<usize, Reply> {
+    match parse_int::<i64>(arg) {
+        Ok(count) if count >= 0 => Ok(count as usize),
+        _ => Err(Reply::error("ERR value is out of range, must be positive")),
+    }
+}
+
+/// `LPUSH key element [element ...]` and `RPUSH key element [element ...]`
+pub struct PushCommand {
+    key: Vec<u8>,
+    end: End,
+    elements: Vec<Vec<u8>>,
+}
+
+impl Command for PushCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(PushCommand { key: args[1].clone(), end: End::of_command(&args[0]), elements: args[2..].to_vec() })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        match push(&mut db, &self.key, self.end, &self.elements) {
+            Ok(len) => Reply::Integer(len as i64),
+            Err(reply) => reply,
+        }
+    }
+}
+
+/// `LPOP key [count]` and `RPOP key [count]`
+pub struct PopCommand {
+    key: Vec<u8>,
+    end: End,
+    count: Option<usize>,
+}
+
+impl Command for PopCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let count = match args.len() {
+            2 => None,
+            3 => Some(parse_count(&args[2])?),
+            _ => return Err(syntax_error()),
+        };
+        Ok(PopCommand { key: args[1].clone(), end: End::of_command(&args[0]), count })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        match (pop(&mut db, &self.key, self.end, self.count.unwrap_or(1)), self.count) {
+            (Err(reply), _) => reply,
+            (Ok(None), None) => Reply::Null,
+            (Ok(None), Some(_)) => Reply::NullArray,
+            (Ok(Some(mut popped)), None) => popped.pop().map_or(Reply::Null, Reply::Bulk),
+            (Ok(Some(popped)), Some(_)) => Reply::Array(popped.into_iter().map(Reply::Bulk).collect()),
+        }
+    }
+}
+
+/// `LLEN key`
+pub struct LLenCommand {
+    key: Vec<u8>,
+}
+
+impl Command for LLenCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(LLenCommand { key: args[1].clone() })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        match get_list(&mut db, &self.key) {
+            Ok(list) => Reply::Integer(list.map_or(0, |list| list.len() as i64)),
+            Err(reply) => reply,
+        }
+    }
+}
+
+/// `LRANGE key start stop`
+pub struct LRangeCommand {
+    key: Vec<u8>,
+    start: i64,
+    stop: i64,
+}
+
+impl Command for LRangeCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(LRangeCommand { key: args[1].clone(), start: parse_int(&args[2])?, stop: parse_int(&args[3])? })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        match get_list(&mut db, &self.key) {
+            Ok(Some(list)) => match range(self.start, self.stop, list.len()) {
+                Some((start, stop)) => Reply::Array(list.range(start..=stop).cloned().map(Reply::Bulk).collect()),
+                None => Reply::Array(Vec::new()),
+            },
+            Ok(None) => Reply::Array(Vec::new()),
+            Err(reply) => reply,
+        }
+    }
+}
+
+/// `LINDEX key index`
+pub struct LIndexCommand {
+    key: Vec<u8>,
+    index: i64,
+}
+
+impl Command for LIndexCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(LIndexCommand { key: args[1].clone(), index: parse_int(&args[2])? })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        match get_list(&mut db, &self.key) {
+            Ok(Some(list)) => match index(self.index, list.len()) {
+                Some(i) => Reply::Bulk(list[i].clone()),
+                None => Reply::Null,
+            },
+            Ok(None) => Reply::Null,
+            Err(reply) => reply,
+        }
+    }
+}
+
+/// `LSET key index element`
+pub struct LSetCommand {
+    key: Vec<u8>,
+    index: i64,
+    element: Vec<u8>,
+}
+
+impl Command for LSetCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(LSetCommand { key: args[1].clone(), index: parse_int(&args[2])?, element: args[3].clone() })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        match get_list_mut(&mut db, &self.key) {
+            Ok(Some(list)) => match index(self.index, list.len()) {
+                Some(i) => {
+                    list[i] = self.element.clone();
+                    Reply::ok()
+                }
+                None => Reply::error("ERR index out of range"),
+            },
+            Ok(None) => Reply::error("ERR no such key"),
+            Err(reply) => reply,
+        }
+    }
+}
+
+/// `LREM key count element`
+pub struct LRemCommand {
+    key: Vec<u8>,
+    count: i64,
+    element: Vec<u8>,
+}
+
+impl Command for LRemCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(LRemCommand { key: args[1].clone(), count: parse_int(&args[2])?, element: args[3].clone() })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let list = match get_list_mut(&mut db, &self.key) {
+            Ok(Some(list)) => list,
+            Ok(None) => return Reply::Integer(0),
+            Err(reply) => return reply,
+        };
+
+        let limit = if self.count == 0 { usize::MAX } else { self.count.unsigned_abs() as usize };
+        let mut removed = 0;
+        if self.count >= 0 {
+            list.retain(|element| {
+                let matches = removed < limit && *element == self.element;
+                removed += matches as usize;
+                !matches
+            });
+        } else {
+            // Only matches from the `limit`-th last one on are removed.
+            let first = list.iter().rposition(|element| {
+                removed += (*element == self.element) as usize;
+                removed == limit
+            });
+            let (first, mut index) = (first.unwrap_or(0), 0);
+            list.retain(|element| {
+                index += 1;
+                index <= first || *element != self.element
+            });
+        }
+        db.remove_if_empty(&self.key);
+        Reply::Integer(removed as i64)
+    }
+}
+
+/// `LTRIM key start stop`
+pub struct LTrimCommand {
+    key: Vec<u8>,
+    start: i64,
+    stop: i64,
+}
+
+impl Command for LTrimCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(LTrimCommand { key: args[1].clone(), start: parse_int(&args[2])?, stop: parse_int(&args[3])? })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let list = match get_list_mut(&mut db, &self.key) {
+            Ok(Some(list)) => list,
+            Ok(None) => return Reply::ok(),
+            Err(reply) => return reply,
+        };
+        match range(self.start, self.stop, list.len()) {
+            Some((start, stop)) => {
+                list.truncate(stop + 1);
+                list.drain(..start);
+            }
+            None => list.clear(),
+        }
+        db.remove_if_empty(&self.key);
+        Reply::ok()
+    }
+}
+
+/// `LINSERT key BEFORE|AFTER pivot element`
+pub struct LInsertCommand {
+    key: Vec<u8>,
+    after: bool,
+    pivot: Vec<u8>,
+    element: Vec<u8>,
+}
+
+impl Command for LInsertCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let after = match args[2].to_ascii_uppercase().as_slice() {
+            b"BEFORE" => false,
+            b"AFTER" => true,
+            _ => return Err(syntax_error()),
+        };
+        Ok(LInsertCommand { key: args[1].clone(), after, pivot: args[3].clone(), element: args[4].clone() })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let list = match get_list_mut(&mut db, &self.key) {
+            Ok(Some(list)) => list,
+            Ok(None) => return Reply::Integer(0),
+            Err(reply) => return reply,
+        };
+        match list.iter().position(|element| *element == self.pivot) {
+            Some(i) => {
+                list.insert(if self.after { i + 1 } else { i }, self.element.clone());
+                Reply::Integer(list.len() as i64)
+            }
+            None => Reply::Integer(-1),
+        }
+    }
+}
+
+/// `BLPOP key [key ...] timeout` and `BRPOP key [key ...] timeout`
+pub struct BPopCommand {
+    keys: Vec<Vec<u8>>,
+    end: End,
+    timeout: Option<Duration>,
+}
+
+impl BlockingCommand for BPopCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let (timeout, keys) = args[1..].split_last().expect("arity is checked");
+        Ok(BPopCommand { keys: keys.to_vec(), end: End::of_command(&args[0][1..]), timeout: parse_timeout(timeout)? })
+    }
+
+    fn keys(&self) -> &[Vec<u8>] {
+        &self.keys
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    fn try_serve(&self, db: &mut Keyspace, key: &[u8]) -> Result<Option<Reply>, Reply> {
+        Ok(pop(db, key, self.end, 1)?
+            .and_then(|mut popped| popped.pop())
+            .map(|element| Reply::Array(vec![Reply::Bulk(key.to_vec()), Reply::Bulk(element)])))
+    }
+}
+
+/// `LMOVE source destination LEFT|RIGHT LEFT|RIGHT`
+pub struct LMoveCommand {
+    source: Vec<u8>,
+    destination: Vec<u8>,
+    from: End,
+    to: End,
+}
+
+impl Command for LMoveCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(LMoveCommand {
+            source: args[1].clone(),
+            destination: args[2].clone(),
+            from: End::parse(&args[3])?,
+            to: End::parse(&args[4])?,
+        })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        match lmove(&mut db, &self.source, &self.destination, self.from, self.to) {
+            Ok(Some(element)) => Reply::Bulk(element),
+            Ok(None) => Reply::Null,
+            Err(reply) => reply,
+        }
+    }
+}
+
+/// `BLMOVE source destination LEFT|RIGHT LEFT|RIGHT timeout`
+pub struct BLMoveCommand {
+    keys: [Vec<u8>; 1],
+    destination: Vec<u8>,
+    from: End,
+    to: End,
+    timeout: Option<Duration>,
+}
+
+impl BlockingCommand for BLMoveCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(BLMoveCommand {
+            keys: [args[1].clone()],
+            destination: args[2].clone(),
+            from: End::parse(&args[3])?,
+            to: End::parse(&args[4])?,
+            timeout: parse_timeout(&args[5])?,
+        })
+    }
+
+    fn keys(&self) -> &[Vec<u8>] {
+        &self.keys
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    fn try_serve(&self, db: &mut Keyspace, key: &[u8]) -> Result<Option<Reply>, Reply> {
+        Ok(lmove(db, key, &self.destination, self.from, self.to)?.map(Reply::Bulk))
+    }
+
+    fn timeout_reply(&self) -> Reply {
+        Reply::Null
+    }
+}
@@ -5,7 +5,378 @@ pub mod ttl;
 pub mod incr;
 pub mod decr;
 pub mod exists;
+pub mod keyspace;
 pub mod json;
+pub mod list;
+pub mod hash;
+pub mod sets;
+pub mod zset;
+pub mod stream;
+pub mod search;
+pub mod scan;
+pub mod debug;
+pub mod ping;
+pub mod command;
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use crate::db::{Db, Keyspace};
+use crate::reply::Reply;
+
+/// A command that runs against the keyspace.
+pub trait Command: Sized {
+    /// Builds the command from its arguments, command name included. The
+    /// registry has already checked the arity, so required positions exist.
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply>;
+
+    fn execute(&self, db: &Db) -> Reply;
+}
+
+/// A command that parks the client until one of its keys can serve it.
+pub trait BlockingCommand: Sized + Send + 'static {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply>;
+
+    /// Keys to wait on, in the order they are tried.
+    fn keys(&self) -> &[Vec<u8>];
+
+    /// How long to wait; `None` blocks indefinitely.
+    fn timeout(&self) -> Option<Duration>;
+
+    /// Tries to serve the command before parking the client. By default the
+    /// keys are tried in order and the first one with data answers.
+    fn try_now(&mut self, db: &mut Keyspace) -> Result<Option<Reply>, Reply> {
+        for key in self.keys() {
+            if let Some(reply) = self.try_serve(db, key)? {
+                return Ok(Some(reply));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Whether to park the client at all; XREAD only blocks with BLOCK.
+    fn blocks(&self) -> bool {
+        true
+    }
+
+    /// Serves the command from `key`. `Ok(None)` means the key has nothing
+    /// to offer yet; an error is returned to the client unless it is
+    /// already parked, in which case it keeps waiting.
+    fn try_serve(&self, db: &mut Keyspace, key: &[u8]) -> Result<Option<Reply>, Reply>;
+
+    /// Sent when the timeout expires.
+    fn timeout_reply(&self) -> Reply {
+        Reply::NullArray
+    }
+}
+
+/// Result of a command that may block.
+pub enum Outcome {
+    Reply(Reply),
+    Blocked(Parked),
+}
+
+/// A client parked on the keyspace, waiting for its reply.
+pub struct Parked {
+    pub id: u64,
+    pub reply: oneshot::Receiver<Reply>,
+    pub timeout: Option<Duration>,
+    pub timeout_reply: Reply,
+}
+
+/// Behaviour flags reported by COMMAND and mapped to ACL categories.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Flag {
+    Write,
+    ReadOnly,
+    Admin,
+    Blocking,
+    Fast,
+}
+
+impl Flag {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Flag::Write => "write",
+            Flag::ReadOnly => "readonly",
+            Flag::Admin => "admin",
+            Flag::Blocking => "blocking",
+            Flag::Fast => "fast",
+        }
+    }
+}
+
+pub enum Handler {
+    /// Parsed and executed against the keyspace through [`Command`].
+    Db(fn(&[Vec<u8>], &Db) -> Reply),
+    /// Parsed through [`BlockingCommand`]; the connection handler waits on
+    /// the parked client.
+    Blocking(fn(&[Vec<u8>], &Db) -> Outcome),
+    /// Needs connection state or async I/O and is executed by the connection
+    /// handler itself.
+    Connection,
+}
+
+pub struct CommandSpec {
+    pub name: &'static str,
+    /// Number of arguments including the command name; `-N` means at least N.
+    pub arity: i32,
+    pub flags: &'static [Flag],
+    /// ACL category of the data type or subsystem, e.g. `string`.
+    pub group: &'static str,
+    /// Positions of the first and last key argument and the step between keys.
+    pub keys: (i32, i32, i32),
+    pub handler: Handler,
+}
+
+impl CommandSpec {
+    pub fn accepts(&self, argc: usize) -> bool {
+        if self.arity >= 0 {
+            argc == self.arity as usize
+        } else {
+            argc >= self.arity.unsigned_abs() as usize
+        }
+    }
+
+    pub fn has_flag(&self, flag: Flag) -> bool {
+        self.flags.contains(&flag)
+    }
+
+    pub fn acl_categories(&self) -> Vec<String> {
+        let mut categories = Vec::new();
+        if self.has_flag(Flag::Write) {
+            categories.push("@write".to_string());
+        }
+        if self.has_flag(Flag::ReadOnly) {
+            categories.push("@read".to_string());
+        }
+        if self.has_flag(Flag::Admin) {
+            categories.push("@admin".to_string());
+            categories.push("@dangerous".to_string());
+        }
+        if self.has_flag(Flag::Blocking) {
+            categories.push("@blocking".to_string());
+        }
+        categories.push(if self.has_flag(Flag::Fast) { "@fast" } else { "@slow" }.to_string());
+        categories.push(format!("@{}", self.group));
+        categories
+    }
+}
+
+fn run<C: Command>(args: &[Vec<u8>], db: &Db) -> Reply {
+    match C::parse(args) {
+        Ok(command) => command.execute(db),
+        Err(reply) => reply,
+    }
+}
+
+fn run_blocking<C: BlockingCommand>(args: &[Vec<u8>], db: &Db) -> Outcome {
+    let mut command = match C::parse(args) {
+        Ok(command) => command,
+        Err(reply) => return Outcome::Reply(reply),
+    };
+    let mut db = db.lock().unwrap();
+    match command.try_now(&mut db) {
+        Ok(Some(reply)) | Err(reply) => return Outcome::Reply(reply),
+        Ok(None) if !command.blocks() => return Outcome::Reply(command.timeout_reply()),
+        Ok(None) => {}
+    }
+
+    // Register under the same lock, so a push right after cannot be missed.
+    let keys = command.keys().to_vec();
+    let timeout = command.timeout();
+    let timeout_reply = command.timeout_reply();
+    let (sender, receiver) = oneshot::channel();
+    let serve = Box::new(move |db: &mut Keyspace, key: &[u8]| command.try_serve(db, key));
+    let id = db.block(keys, serve, sender);
+    Outcome::Blocked(Parked { id, reply: receiver, timeout, timeout_reply })
+}
+
+use Flag::*;
+
+pub static COMMANDS: &[CommandSpec] = &[
+    CommandSpec { name: "ping", arity: -1, flags: &[Fast], group: "connection", keys: (0, 0, 0), handler: Handler::Db(run::<ping::PingCommand>) },
+    CommandSpec { name: "hello", arity: -1, flags: &[Fast], group: "connection", keys: (0, 0, 0), handler: Handler::Connection },
+    CommandSpec { name: "command", arity: -1, flags: &[], group: "connection", keys: (0, 0, 0), handler: Handler::Db(run::<command::CommandCommand>) },
+    CommandSpec { name: "debug", arity: -2, flags: &[Admin], group: "server", keys: (0, 0, 0), handler: Handler::Db(run::<debug::DebugProtocolCommand>) },
+    CommandSpec { name: "users", arity: 1, flags: &[Admin], group: "server", keys: (0, 0, 0), handler: Handler::Connection },
+    CommandSpec { name: "set", arity: -3, flags: &[Write], group: "string", keys: (1, 1, 1), handler: Handler::Db(run::<set::SetCommand>) },
+    CommandSpec { name: "get", arity: 2, flags: &[ReadOnly, Fast], group: "string", keys: (1, 1, 1), handler: Handler::Db(run::<get::GetCommand>) },
+    CommandSpec { name: "incr", arity: 2, flags: &[Write, Fast], group: "string", keys: (1, 1, 1), handler: Handler::Db(run::<incr::IncrCommand>) },
+    CommandSpec { name: "decr", arity: 2, flags: &[Write, Fast], group: "string", keys: (1, 1, 1), handler: Handler::Db(run::<decr::DecrCommand>) },
+    CommandSpec { name: "expire", arity: 3, flags: &[Write, Fast], group: "keyspace", keys: (1, 1, 1), handler: Handler::Db(run::<expire::ExpireCommand>) },
+    CommandSpec { name: "ttl", arity: 2, flags: &[ReadOnly, Fast], group: "keyspace", keys: (1, 1, 1), handler: Handler::Db(run::<ttl::TTLCommand>) },
+    CommandSpec { name: "exists", arity: -2, flags: &[ReadOnly, Fast], group: "keyspace", keys: (1, -1, 1), handler: Handler::Db(run::<exists::ExistsCommand>) },
+    CommandSpec { name: "touch", arity: -2, flags: &[ReadOnly, Fast], group: "keyspace", keys: (1, -1, 1), handler: Handler::Db(run::<exists::ExistsCommand>) },
+    CommandSpec { name: "del", arity: -2, flags: &[Write], group: "keyspace", keys: (1, -1, 1), handler: Handler::Db(run::<keyspace::DelCommand>) },
+    CommandSpec { name: "unlink", arity: -2, flags: &[Write, Fast], group: "keyspace", keys: (1, -1, 1), handler: Handler::Db(run::<keyspace::DelCommand>) },
+    CommandSpec { name: "type", arity: 2, flags: &[ReadOnly, Fast], group: "keyspace", keys: (1, 1, 1), handler: Handler::Db(run::<keyspace::TypeCommand>) },
+    CommandSpec { name: "rename", arity: 3, flags: &[Write], group: "keyspace", keys: (1, 2, 1), handler: Handler::Db(run::<keyspace::RenameCommand>) },
+    CommandSpec { name: "renamenx", arity: 3, flags: &[Write, Fast], group: "keyspace", keys: (1, 2, 1), handler: Handler::Db(run::<keyspace::RenameCommand>) },
+    CommandSpec { name: "copy", arity: -3, flags: &[Write], group: "keyspace", keys: (1, 2, 1), handler: Handler::Db(run::<keyspace::CopyCommand>) },
+    CommandSpec { name: "keys", arity: 2, flags: &[ReadOnly], group: "keyspace", keys: (0, 0, 0), handler: Handler::Db(run::<keyspace::KeysCommand>) },
+    CommandSpec { name: "scan", arity: -2, flags: &[ReadOnly], group: "keyspace", keys: (0, 0, 0), handler: Handler::Db(run::<keyspace::ScanCommand>) },
+    CommandSpec { name: "randomkey", arity: 1, flags: &[ReadOnly], group: "keyspace", keys: (0, 0, 0), handler: Handler::Db(run::<keyspace::RandomKeyCommand>) },
+    CommandSpec { name: "dbsize", arity: 1, flags: &[ReadOnly, Fast], group: "keyspace", keys: (0, 0, 0), handler: Handler::Db(run::<keyspace::DbSizeCommand>) },
+    CommandSpec { name: "lpush", arity: -3, flags: &[Write, Fast], group: "list", keys: (1, 1, 1), handler: Handler::Db(run::<list::PushCommand>) },
+    CommandSpec { name: "rpush", arity: -3, flags: &[Write, Fast], group: "list", keys: (1, 1, 1), handler: Handler::Db(run::<list::PushCommand>) },
+    CommandSpec { name: "lpop", arity: -2, flags: &[Write, Fast], group: "list", keys: (1, 1, 1), handler: Handler::Db(run::<list::PopCommand>) },
+    CommandSpec { name: "rpop", arity: -2, flags: &[Write, Fast], group: "list", keys: (1, 1, 1), handler: Handler::Db(run::<list::PopCommand>) },
+    CommandSpec { name: "llen", arity: 2, flags: &[ReadOnly, Fast], group: "list", keys: (1, 1, 1), handler: Handler::Db(run::<list::LLenCommand>) },
+    CommandSpec { name: "lrange", arity: 4, flags: &[ReadOnly], group: "list", keys: (1, 1, 1), handler: Handler::Db(run::<list::LRangeCommand>) },
+    CommandSpec { name: "lindex", arity: 3, flags: &[ReadOnly], group: "list", keys: (1, 1, 1), handler: Handler::Db(run::<list::LIndexCommand>) },
+    CommandSpec { name: "lset", arity: 4, flags: &[Write], group: "list", keys: (1, 1, 1), handler: Handler::Db(run::<list::LSetCommand>) },
+    CommandSpec { name: "lrem", arity: 4, flags: &[Write], group: "list", keys: (1, 1, 1), handler: Handler::Db(run::<list::LRemCommand>) },
+    CommandSpec { name: "ltrim", arity: 4, flags: &[Write], group: "list", keys: (1, 1, 1), handler: Handler::Db(run::<list::LTrimCommand>) },
+    CommandSpec { name: "linsert", arity: 5, flags: &[Write], group: "list", keys: (1, 1, 1), handler: Handler::Db(run::<list::LInsertCommand>) },
+    CommandSpec { name: "blpop", arity: -3, flags: &[Write, Blocking], group: "list", keys: (1, -2, 1), handler: Handler::Blocking(run_blocking::<list::BPopCommand>) },
+    CommandSpec { name: "brpop", arity: -3, flags: &[Write, Blocking], group: "list", keys: (1, -2, 1), handler: Handler::Blocking(run_blocking::<list::BPopCommand>) },
+    CommandSpec { name: "lmove", arity: 5, flags: &[Write], group: "list", keys: (1, 2, 1), handler: Handler::Db(run::<list::LMoveCommand>) },
+    CommandSpec { name: "blmove", arity: 6, flags: &[Write, Blocking], group: "list", keys: (1, 2, 1), handler: Handler::Blocking(run_blocking::<list::BLMoveCommand>) },
+    CommandSpec { name: "hset", arity: -4, flags: &[Write, Fast], group: "hash", keys: (1, 1, 1), handler: Handler::Db(run::<hash::HSetCommand>) },
+    CommandSpec { name: "hsetnx", arity: 4, flags: &[Write, Fast], group: "hash", keys: (1, 1, 1), handler: Handler::Db(run::<hash::HSetNxCommand>) },
+    CommandSpec { name: "hget", arity: 3, flags: &[ReadOnly, Fast], group: "hash", keys: (1, 1, 1), handler: Handler::Db(run::<hash::HGetCommand>) },
+    CommandSpec { name: "hmget", arity: -3, flags: &[ReadOnly, Fast], group: "hash", keys: (1, 1, 1), handler: Handler::Db(run::<hash::HMGetCommand>) },
+    CommandSpec { name: "hdel", arity: -3, flags: &[Write, Fast], group: "hash", keys: (1, 1, 1), handler: Handler::Db(run::<hash::HDelCommand>) },
+    CommandSpec { name: "hgetall", arity: 2, flags: &[ReadOnly], group: "hash", keys: (1, 1, 1), handler: Handler::Db(run::<hash::HGetAllCommand>) },
+    CommandSpec { name: "hkeys", arity: 2, flags: &[ReadOnly], group: "hash", keys: (1, 1, 1), handler: Handler::Db(run::<hash::HGetAllCommand>) },
+    CommandSpec { name: "hvals", arity: 2, flags: &[ReadOnly], group: "hash", keys: (1, 1, 1), handler: Handler::Db(run::<hash::HGetAllCommand>) },
+    CommandSpec { name: "hlen", arity: 2, flags: &[ReadOnly, Fast], group: "hash", keys: (1, 1, 1), handler: Handler::Db(run::<hash::HLenCommand>) },
+    CommandSpec { name: "hexists", arity: 3, flags: &[ReadOnly, Fast], group: "hash", keys: (1, 1, 1), handler: Handler::Db(run::<hash::HExistsCommand>) },
+    CommandSpec { name: "hincrby", arity: 4, flags: &[Write, Fast], group: "hash", keys: (1, 1, 1), handler: Handler::Db(run::<hash::HIncrByCommand>) },
+    CommandSpec { name: "hincrbyfloat", arity: 4, flags: &[Write, Fast], group: "hash", keys: (1, 1, 1), handler: Handler::Db(run::<hash::HIncrByFloatCommand>) },
+    CommandSpec { name: "hscan", arity: -3, flags: &[ReadOnly], group: "hash", keys: (1, 1, 1), handler: Handler::Db(run::<hash::HScanCommand>) },
+    CommandSpec { name: "hexpire", arity: -6, flags: &[Write, Fast], group: "hash", keys: (1, 1, 1), handler: Handler::Db(run::<hash::HExpireCommand>) },
+    CommandSpec { name: "hpexpire", arity: -6, flags: &[Write, Fast], group: "hash", keys: (1, 1, 1), handler: Handler::Db(run::<hash::HExpireCommand>) },
+    CommandSpec { name: "httl", arity: -5, flags: &[ReadOnly, Fast], group: "hash", keys: (1, 1, 1), handler: Handler::Db(run::<hash::HTtlCommand>) },
+    CommandSpec { name: "hpttl", arity: -5, flags: &[ReadOnly, Fast], group: "hash", keys: (1, 1, 1), handler: Handler::Db(run::<hash::HTtlCommand>) },
+    CommandSpec { name: "hpersist", arity: -5, flags: &[Write, Fast], group: "hash", keys: (1, 1, 1), handler: Handler::Db(run::<hash::HPersistCommand>) },
+    CommandSpec { name: "sadd", arity: -3, flags: &[Write, Fast], group: "set", keys: (1, 1, 1), handler: Handler::Db(run::<sets::SAddCommand>) },
+    CommandSpec { name: "srem", arity: -3, flags: &[Write, Fast], group: "set", keys: (1, 1, 1), handler: Handler::Db(run::<sets::SRemCommand>) },
+    CommandSpec { name: "smembers", arity: 2, flags: &[ReadOnly], group: "set", keys: (1, 1, 1), handler: Handler::Db(run::<sets::SMembersCommand>) },
+    CommandSpec { name: "sismember", arity: 3, flags: &[ReadOnly, Fast], group: "set", keys: (1, 1, 1), handler: Handler::Db(run::<sets::SIsMemberCommand>) },
+    CommandSpec { name: "smismember", arity: -3, flags: &[ReadOnly, Fast], group: "set", keys: (1, 1, 1), handler: Handler::Db(run::<sets::SIsMemberCommand>) },
+    CommandSpec { name: "scard", arity: 2, flags: &[ReadOnly, Fast], group: "set", keys: (1, 1, 1), handler: Handler::Db(run::<sets::SCardCommand>) },
+    CommandSpec { name: "spop", arity: -2, flags: &[Write, Fast], group: "set", keys: (1, 1, 1), handler: Handler::Db(run::<sets::SPopCommand>) },
+    CommandSpec { name: "srandmember", arity: -2, flags: &[ReadOnly], group: "set", keys: (1, 1, 1), handler: Handler::Db(run::<sets::SRandMemberCommand>) },
+    CommandSpec { name: "sinter", arity: -2, flags: &[ReadOnly], group: "set", keys: (1, -1, 1), handler: Handler::Db(run::<sets::SetOpCommand>) },
+    CommandSpec { name: "sunion", arity: -2, flags: &[ReadOnly], group: "set", keys: (1, -1, 1), handler: Handler::Db(run::<sets::SetOpCommand>) },
+    CommandSpec { name: "sdiff", arity: -2, flags: &[ReadOnly], group: "set", keys: (1, -1, 1), handler: Handler::Db(run::<sets::SetOpCommand>) },
+    CommandSpec { name: "sinterstore", arity: -3, flags: &[Write], group: "set", keys: (1, -1, 1), handler: Handler::Db(run::<sets::SetOpCommand>) },
+    CommandSpec { name: "sunionstore", arity: -3, flags: &[Write], group: "set", keys: (1, -1, 1), handler: Handler::Db(run::<sets::SetOpCommand>) },
+    CommandSpec { name: "sdiffstore", arity: -3, flags: &[Write], group: "set", keys: (1, -1, 1), handler: Handler::Db(run::<sets::SetOpCommand>) },
+    CommandSpec { name: "sintercard", arity: -3, flags: &[ReadOnly], group: "set", keys: (2, -1, 1), handler: Handler::Db(run::<sets::SInterCardCommand>) },
+    CommandSpec { name: "sscan", arity: -3, flags: &[ReadOnly], group: "set", keys: (1, 1, 1), handler: Handler::Db(run::<sets::SScanCommand>) },
+    CommandSpec { name: "zadd", arity: -4, flags: &[Write, Fast], group: "sortedset", keys: (1, 1, 1), handler: Handler::Db(run::<zset::ZAddCommand>) },
+    CommandSpec { name: "zincrby", arity: 4, flags: &[Write, Fast], group: "sortedset", keys: (1, 1, 1), handler: Handler::Db(run::<zset::ZIncrByCommand>) },
+    CommandSpec { name: "zrange", arity: -4, flags: &[ReadOnly], group: "sortedset", keys: (1, 1, 1), handler: Handler::Db(run::<zset::ZRangeCommand>) },
+    CommandSpec { name: "zrevrange", arity: -4, flags: &[ReadOnly], group: "sortedset", keys: (1, 1, 1), handler: Handler::Db(run::<zset::ZRangeCommand>) },
+    CommandSpec { name: "zrangebyscore", arity: -4, flags: &[ReadOnly], group: "sortedset", keys: (1, 1, 1), handler: Handler::Db(run::<zset::ZRangeCommand>) },
+    CommandSpec { name: "zrevrangebyscore", arity: -4, flags: &[ReadOnly], group: "sortedset", keys: (1, 1, 1), handler: Handler::Db(run::<zset::ZRangeCommand>) },
+    CommandSpec { name: "zrangebylex", arity: -4, flags: &[ReadOnly], group: "sortedset", keys: (1, 1, 1), handler: Handler::Db(run::<zset::ZRangeCommand>) },
+    CommandSpec { name: "zrevrangebylex", arity: -4, flags: &[ReadOnly], group: "sortedset", keys: (1, 1, 1), handler: Handler::Db(run::<zset::ZRangeCommand>) },
+    CommandSpec { name: "zcount", arity: 4, flags: &[ReadOnly, Fast], group: "sortedset", keys: (1, 1, 1), handler: Handler::Db(run::<zset::ZCountCommand>) },
+    CommandSpec { name: "zlexcount", arity: 4, flags: &[ReadOnly, Fast], group: "sortedset", keys: (1, 1, 1), handler: Handler::Db(run::<zset::ZCountCommand>) },
+    CommandSpec { name: "zremrangebyrank", arity: 4, flags: &[Write], group: "sortedset", keys: (1, 1, 1), handler: Handler::Db(run::<zset::ZRemRangeCommand>) },
+    CommandSpec { name: "zremrangebyscore", arity: 4, flags: &[Write], group: "sortedset", keys: (1, 1, 1), handler: Handler::Db(run::<zset::ZRemRangeCommand>) },
+    CommandSpec { name: "zremrangebylex", arity: 4, flags: &[Write], group: "sortedset", keys: (1, 1, 1), handler: Handler::Db(run::<zset::ZRemRangeCommand>) },
+    CommandSpec { name: "zrank", arity: -3, flags: &[ReadOnly, Fast], group: "sortedset", keys: (1, 1, 1), handler: Handler::Db(run::<zset::ZRankCommand>) },
+    CommandSpec { name: "zrevrank", arity: -3, flags: &[ReadOnly, Fast], group: "sortedset", keys: (1, 1, 1), handler: Handler::Db(run::<zset::ZRankCommand>) },
+    CommandSpec { name: "zscore", arity: 3, flags: &[ReadOnly, Fast], group: "sortedset", keys: (1, 1, 1), handler: Handler::Db(run::<zset::ZScoreCommand>) },
+    CommandSpec { name: "zmscore", arity: -3, flags: &[ReadOnly, Fast], group: "sortedset", keys: (1, 1, 1), handler: Handler::Db(run::<zset::ZScoreCommand>) },
+    CommandSpec { name: "zcard", arity: 2, flags: &[ReadOnly, Fast], group: "sortedset", keys: (1, 1, 1), handler: Handler::Db(run::<zset::ZCardCommand>) },
+    CommandSpec { name: "zrem", arity: -3, flags: &[Write, Fast], group: "sortedset", keys: (1, 1, 1), handler: Handler::Db(run::<zset::ZRemCommand>) },
+    CommandSpec { name: "zpopmin", arity: -2, flags: &[Write, Fast], group: "sortedset", keys: (1, 1, 1), handler: Handler::Db(run::<zset::ZPopCommand>) },
+    CommandSpec { name: "zpopmax", arity: -2, flags: &[Write, Fast], group: "sortedset", keys: (1, 1, 1), handler: Handler::Db(run::<zset::ZPopCommand>) },
+    CommandSpec { name: "zmpop", arity: -4, flags: &[Write], group: "sortedset", keys: (0, 0, 0), handler: Handler::Db(run::<zset::ZMPopCommand>) },
+    CommandSpec { name: "bzpopmin", arity: -3, flags: &[Write, Fast, Blocking], group: "sortedset", keys: (1, -2, 1), handler: Handler::Blocking(run_blocking::<zset::BZPopCommand>) },
+    CommandSpec { name: "bzpopmax", arity: -3, flags: &[Write, Fast, Blocking], group: "sortedset", keys: (1, -2, 1), handler: Handler::Blocking(run_blocking::<zset::BZPopCommand>) },
+    CommandSpec { name: "bzmpop", arity: -5, flags: &[Write, Blocking], group: "sortedset", keys: (0, 0, 0), handler: Handler::Blocking(run_blocking::<zset::BZMPopCommand>) },
+    CommandSpec { name: "zunionstore", arity: -4, flags: &[Write], group: "sortedset", keys: (1, 1, 1), handler: Handler::Db(run::<zset::ZStoreCommand>) },
+    CommandSpec { name: "zinterstore", arity: -4, flags: &[Write], group: "sortedset", keys: (1, 1, 1), handler: Handler::Db(run::<zset::ZStoreCommand>) },
+    CommandSpec { name: "xadd", arity: -5, flags: &[Write, Fast], group: "stream", keys: (1, 1, 1), handler: Handler::Db(run::<stream::XAddCommand>) },
+    CommandSpec { name: "xrange", arity: -4, flags: &[ReadOnly], group: "stream", keys: (1, 1, 1), handler: Handler::Db(run::<stream::XRangeCommand>) },
+    CommandSpec { name: "xrevrange", arity: -4, flags: &[ReadOnly], group: "stream", keys: (1, 1, 1), handler: Handler::Db(run::<stream::XRangeCommand>) },
+    CommandSpec { name: "xlen", arity: 2, flags: &[ReadOnly, Fast], group: "stream", keys: (1, 1, 1), handler: Handler::Db(run::<stream::XLenCommand>) },
+    CommandSpec { name: "xtrim", arity: -4, flags: &[Write], group: "stream", keys: (1, 1, 1), handler: Handler::Db(run::<stream::XTrimCommand>) },
+    CommandSpec { name: "xdel", arity: -3, flags: &[Write, Fast], group: "stream", keys: (1, 1, 1), handler: Handler::Db(run::<stream::XDelCommand>) },
+    CommandSpec { name: "xread", arity: -4, flags: &[ReadOnly, Blocking], group: "stream", keys: (0, 0, 0), handler: Handler::Blocking(run_blocking::<stream::XReadCommand>) },
+    CommandSpec { name: "xgroup", arity: -2, flags: &[Write], group: "stream", keys: (2, 2, 1), handler: Handler::Db(run::<stream::XGroupCommand>) },
+    CommandSpec { name: "xreadgroup", arity: -7, flags: &[Write, Blocking], group: "stream", keys: (0, 0, 0), handler: Handler::Blocking(run_blocking::<stream::XReadGroupCommand>) },
+    CommandSpec { name: "xack", arity: -4, flags: &[Write, Fast], group: "stream", keys: (1, 1, 1), handler: Handler::Db(run::<stream::XAckCommand>) },
+    CommandSpec { name: "xpending", arity: -3, flags: &[ReadOnly], group: "stream", keys: (1, 1, 1), handler: Handler::Db(run::<stream::XPendingCommand>) },
+    CommandSpec { name: "xclaim", arity: -6, flags: &[Write, Fast], group: "stream", keys: (1, 1, 1), handler: Handler::Db(run::<stream::XClaimCommand>) },
+    CommandSpec { name: "xautoclaim", arity: -6, flags: &[Write, Fast], group: "stream", keys: (1, 1, 1), handler: Handler::Db(run::<stream::XAutoClaimCommand>) },
+    CommandSpec { name: "xinfo", arity: -2, flags: &[ReadOnly], group: "stream", keys: (2, 2, 1), handler: Handler::Db(run::<stream::XInfoCommand>) },
+    CommandSpec { name: "json.set", arity: -4, flags: &[Write], group: "json", keys: (1, 1, 1), handler: Handler::Db(run::<json::SetJsonCommand>) },
+    CommandSpec { name: "json.get", arity: -2, flags: &[ReadOnly], group: "json", keys: (1, 1, 1), handler: Handler::Db(run::<json::GetJsonCommand>) },
+    CommandSpec { name: "json.del", arity: -2, flags: &[Write], group: "json", keys: (1, 1, 1), handler: Handler::Db(run::<json::DelJsonCommand>) },
+    CommandSpec { name: "json.forget", arity: -2, flags: &[Write], group: "json", keys: (1, 1, 1), handler: Handler::Db(run::<json::DelJsonCommand>) },
+    CommandSpec { name: "json.mget", arity: -3, flags: &[ReadOnly], group: "json", keys: (1, -2, 1), handler: Handler::Db(run::<json::MGetJsonCommand>) },
+    CommandSpec { name: "json.type", arity: -2, flags: &[ReadOnly, Fast], group: "json", keys: (1, 1, 1), handler: Handler::Db(run::<json::TypeJsonCommand>) },
+    CommandSpec { name: "json.numincrby", arity: 4, flags: &[Write], group: "json", keys: (1, 1, 1), handler: Handler::Db(run::<json::NumOpJsonCommand>) },
+    CommandSpec { name: "json.nummultby", arity: 4, flags: &[Write], group: "json", keys: (1, 1, 1), handler: Handler::Db(run::<json::NumOpJsonCommand>) },
+    CommandSpec { name: "json.strappend", arity: -3, flags: &[Write], group: "json", keys: (1, 1, 1), handler: Handler::Db(run::<json::StrAppendJsonCommand>) },
+    CommandSpec { name: "json.strlen", arity: -2, flags: &[ReadOnly, Fast], group: "json", keys: (1, 1, 1), handler: Handler::Db(run::<json::LenJsonCommand>) },
+    CommandSpec { name: "json.arrappend", arity: -4, flags: &[Write], group: "json", keys: (1, 1, 1), handler: Handler::Db(run::<json::ArrInsertJsonCommand>) },
+    CommandSpec { name: "json.arrinsert", arity: -5, flags: &[Write], group: "json", keys: (1, 1, 1), handler: Handler::Db(run::<json::ArrInsertJsonCommand>) },
+    CommandSpec { name: "json.arrpop", arity: -2, flags: &[Write], group: "json", keys: (1, 1, 1), handler: Handler::Db(run::<json::ArrPopJsonCommand>) },
+    CommandSpec { name: "json.arrlen", arity: -2, flags: &[ReadOnly, Fast], group: "json", keys: (1, 1, 1), handler: Handler::Db(run::<json::LenJsonCommand>) },
+    CommandSpec { name: "json.arrindex", arity: -4, flags: &[ReadOnly], group: "json", keys: (1, 1, 1), handler: Handler::Db(run::<json::ArrIndexJsonCommand>) },
+    CommandSpec { name: "json.arrtrim", arity: 5, flags: &[Write], group: "json", keys: (1, 1, 1), handler: Handler::Db(run::<json::ArrTrimJsonCommand>) },
+    CommandSpec { name: "json.objkeys", arity: -2, flags: &[ReadOnly], group: "json", keys: (1, 1, 1), handler: Handler::Db(run::<json::ObjKeysJsonCommand>) },
+    CommandSpec { name: "json.objlen", arity: -2, flags: &[ReadOnly, Fast], group: "json", keys: (1, 1, 1), handler: Handler::Db(run::<json::LenJsonCommand>) },
+    CommandSpec { name: "json.toggle", arity: -2, flags: &[Write], group: "json", keys: (1, 1, 1), handler: Handler::Db(run::<json::ToggleJsonCommand>) },
+    CommandSpec { name: "json.clear", arity: -2, flags: &[Write], group: "json", keys: (1, 1, 1), handler: Handler::Db(run::<json::ClearJsonCommand>) },
+    CommandSpec { name: "json.merge", arity: 4, flags: &[Write], group: "json", keys: (1, 1, 1), handler: Handler::Db(run::<json::MergeJsonCommand>) },
+    CommandSpec { name: "ft.create", arity: -5, flags: &[Write], group: "search", keys: (0, 0, 0), handler: Handler::Db(run::<search::CreateIndexCommand>) },
+    CommandSpec { name: "ft.search", arity: -3, flags: &[ReadOnly], group: "search", keys: (0, 0, 0), handler: Handler::Db(run::<search::SearchCommand>) },
+    CommandSpec { name: "ft.aggregate", arity: -3, flags: &[ReadOnly], group: "search", keys: (0, 0, 0), handler: Handler::Db(run::<search::AggregateCommand>) },
+    CommandSpec { name: "ft.dropindex", arity: -2, flags: &[Write], group: "search", keys: (0, 0, 0), handler: Handler::Db(run::<search::DropIndexCommand>) },
+    CommandSpec { name: "ft._list", arity: 1, flags: &[ReadOnly], group: "search", keys: (0, 0, 0), handler: Handler::Db(run::<search::ListIndexesCommand>) },
+];
+
+/// Finds a command by name, case-insensitively.
+pub fn lookup(name: &[u8]) -> Option<&'static CommandSpec> {
+    static TABLE: OnceLock<HashMap<&'static str, &'static CommandSpec>> = OnceLock::new();
+    let table = TABLE.get_or_init(|| COMMANDS.iter().map(|spec| (spec.name, spec)).collect());
+    let name = std::str::from_utf8(name).ok()?.to_lowercase();
+    table.get(name.as_str()).copied()
+}
+
+pub fn parse_int<T: FromStr>(arg: &[u8]) -> Result<T, Reply> {
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| Reply::error("ERR value is not an integer or out of range"))
+}
+
+/// The longest blocking timeout; as in Redis, it must fit in an i64 of
+/// milliseconds.
+const MAX_TIMEOUT: Duration = Duration::from_millis(i64::MAX as u64);
+
+/// Parses a blocking timeout in seconds; zero waits forever.
+pub fn parse_timeout(arg: &[u8]) -> Result<Option<Duration>, Reply> {
+    let seconds: f64 = std::str::from_utf8(arg)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .filter(|seconds: &f64| seconds.is_finite())
+        .ok_or_else(|| Reply::error("ERR timeout is not a float or out of range"))?;
+    if seconds < 0.0 {
+        return Err(Reply::error("ERR timeout is negative"));
+    }
+    let timeout = Duration::try_from_secs_f64(seconds)
+        .ok()
+        .filter(|timeout| *timeout <= MAX_TIMEOUT)
+        .ok_or_else(|| Reply::error("ERR timeout is out of range"))?;
+    Ok((!timeout.is_zero()).then_some(timeout))
+}
+
+pub fn syntax_error() -> Reply {
+    Reply::error("ERR syntax error")
+}
 
 #[cfg(test)]
-mod tests;
\ No newline at end of file
+mod tests;
@@ -0,0 +1,24 @@
+use crate::cmd::Command;
+use crate::db::Db;
+use crate::reply::Reply;
+
+pub struct PingCommand {
+    message: Option<Vec<u8>>,
+}
+
+impl Command for PingCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        match args {
+            [_] => Ok(PingCommand { message: None }),
+            [_, message] => Ok(PingCommand { message: Some(message.clone()) }),
+            _ => Err(Reply::error("ERR wrong number of arguments for 'ping' command")),
+        }
+    }
+
+    fn execute(&self, _db: &Db) -> Reply {
+        match &self.message {
+            Some(message) => Reply::Bulk(message.clone()),
+            None => Reply::Simple("PONG".to_string()),
+        }
+    }
+}
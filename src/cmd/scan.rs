@@ -0,0 +1,129 @@
+use crate::cmd::{parse_int, syntax_error};
+use crate::reply::Reply;
+
+/// Options shared by the SCAN family: `cursor [MATCH pattern] [COUNT count]`,
+/// plus the command specific NOVALUES flag of HSCAN and TYPE filter of SCAN.
+#[derive(Debug, PartialEq)]
+pub struct ScanOptions {
+    pub cursor: u64,
+    pub pattern: Option<Vec<u8>>,
+    pub count: usize,
+    pub novalues: bool,
+    pub type_name: Option<Vec<u8>>,
+}
+
+impl ScanOptions {
+    /// Parses the arguments starting at the cursor. Besides MATCH and COUNT
+    /// only the options named in `extra` are accepted, e.g. `["TYPE"]` for SCAN.
+    pub fn parse(args: &[Vec<u8>], extra: &[&str]) -> Result<Self, Reply> {
+        let cursor = std::str::from_utf8(&args[0])
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| Reply::error("ERR invalid cursor"))?;
+        let mut options = ScanOptions { cursor, pattern: None, count: 10, novalues: false, type_name: None };
+
+        let mut rest = &args[1..];
+        while let Some((option, tail)) = rest.split_first() {
+            match (option.to_ascii_uppercase().as_slice(), tail) {
+                (b"MATCH", [pattern, ..]) => {
+                    options.pattern = Some(pattern.clone());
+                    rest = &tail[1..];
+                }
+                (b"COUNT", [count, ..]) => {
+                    options.count = match parse_int::<i64>(count)? {
+                        count if count >= 1 => count as usize,
+                        _ => return Err(syntax_error()),
+                    };
+                    rest = &tail[1..];
+                }
+                (b"TYPE", [type_name, ..]) if extra.contains(&"TYPE") => {
+                    options.type_name = Some(type_name.clone());
+                    rest = &tail[1..];
+                }
+                (b"NOVALUES", _) if extra.contains(&"NOVALUES") => {
+                    options.novalues = true;
+                    rest = tail;
+                }
+                _ => return Err(syntax_error()),
+            }
+        }
+        Ok(options)
+    }
+
+    /// Whether a value of the type TYPE reports as `type_name` passes the
+    /// TYPE filter.
+    pub fn matches_type(&self, type_name: &str) -> bool {
+        self.type_name.as_ref().is_none_or(|wanted| wanted.eq_ignore_ascii_case(type_name.as_bytes()))
+    }
+
+    pub fn matches(&self, name: &[u8]) -> bool {
+        self.pattern.as_ref().is_none_or(|pattern| glob_match(pattern, name))
+    }
+}
+
+/// Builds the `[cursor, [elements...]]` reply of the SCAN family.
+pub fn reply(cursor: u64, elements: Vec<Reply>) -> Reply {
+    Reply::Array(vec![Reply::Bulk(cursor.to_string().into_bytes()), Reply::Array(elements)])
+}
+
+/// Glob-style matching as used by KEYS and MATCH: `*`, `?`, `[abc]`,
+/// `[^a-z]` and `\` escapes.
+pub fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // Where to resume after the last `*` if the rest fails to match.
+    let mut backtrack = None;
+
+    while n < name.len() {
+        let step = match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, n));
+                p += 1;
+                continue;
+            }
+            Some(b'?') => Some(p + 1),
+            Some(b'[') => match_class(pattern, p, name[n]),
+            Some(b'\\') if p + 1 < pattern.len() => (pattern[p + 1] == name[n]).then_some(p + 2),
+            Some(c) => (*c == name[n]).then_some(p + 1),
+            None => None,
+        };
+        match (step, backtrack) {
+            (Some(next), _) => {
+                p = next;
+                n += 1;
+            }
+            (None, Some((star, from))) => {
+                backtrack = Some((star, from + 1));
+                p = star + 1;
+                n = from + 1;
+            }
+            (None, None) => return false,
+        }
+    }
+    pattern[p.min(pattern.len())..].iter().all(|c| *c == b'*')
+}
+
+/// Matches `c` against the class starting at `pattern[start] == b'['`,
+/// returning the position after the class on success.
+fn match_class(pattern: &[u8], start: usize, c: u8) -> Option<usize> {
+    let mut p = start + 1;
+    let negate = pattern.get(p) == Some(&b'^');
+    if negate {
+        p += 1;
+    }
+    let mut matched = false;
+    while p < pattern.len() && pattern[p] != b']' {
+        if pattern[p] == b'\\' && p + 1 < pattern.len() {
+            matched |= pattern[p + 1] == c;
+            p += 2;
+        } else if p + 2 < pattern.len() && pattern[p + 1] == b'-' && pattern[p + 2] != b']' {
+            let (low, high) = (pattern[p].min(pattern[p + 2]), pattern[p].max(pattern[p + 2]));
+            matched |= (low..=high).contains(&c);
+            p += 3;
+        } else {
+            matched |= pattern[p] == c;
+            p += 1;
+        }
+    }
+    // An unterminated class runs to the end of the pattern.
+    (matched != negate).then_some((p + 1).min(pattern.len()))
+}
@@ -0,0 +1,552 @@
+use std::collections::HashMap;
+use crate::cmd::{parse_int, syntax_error, Command};
+use crate::db::{Db, Entry};
+use crate::reply::{format_double, Reply};
+use crate::search::{
+    aggregate, Algorithm, Expression, Field, FieldKind, Index, Match, Metric, Property, Query, ReduceFunction, Reducer,
+    Schema, Source, Step,
+};
+
+fn no_such_index(name: &str) -> Reply {
+    Reply::error(format!("ERR {name}: no such index"))
+}
+
+fn text(arg: &[u8]) -> String {
+    String::from_utf8_lossy(arg).into_owned()
+}
+
+/// A document or result row as a flat name/value array.
+fn properties_reply(properties: Vec<(String, Property)>) -> Reply {
+    Reply::Array(
+        properties
+            .into_iter()
+            .flat_map(|(name, value)| [Reply::Bulk(name.into_bytes()), Reply::Bulk(value.into_bytes())])
+            .collect(),
+    )
+}
+
+/// Largest DIM of a vector field.
+const MAX_VECTOR_DIM: usize = 32768;
+
+/// Largest M of an HNSW field, the limit of the RediSearch vector library.
+const MAX_HNSW_M: usize = u16::MAX as usize / 2;
+
+/// `VECTOR FLAT | HNSW nargs TYPE FLOAT32 DIM dim DISTANCE_METRIC L2 | IP | COSINE
+/// [M m] [EF_CONSTRUCTION ef] [EF_RUNTIME ef] ...`, returning the arguments
+/// after it.
+fn vector_kind(args: &[Vec<u8>]) -> Result<(FieldKind, &[Vec<u8>]), Reply> {
+    let bad_arguments = |what: &str| Reply::error(format!("ERR Bad arguments for vector similarity {what}"));
+    let Some((name, args)) = args.split_first() else {
+        return Err(bad_arguments("algorithm"));
+    };
+    let (algorithm_name, mut algorithm) = match name.to_ascii_uppercase().as_slice() {
+        b"FLAT" => ("FLAT", Algorithm::Flat),
+        b"HNSW" => ("HNSW", Algorithm::Hnsw { m: 16, ef_construction: 200, ef_runtime: 10 }),
+        _ => return Err(bad_arguments("algorithm")),
+    };
+    let attributes = counted(args, 0)?;
+    if attributes.len() % 2 != 0 {
+        return Err(bad_arguments(&format!("{algorithm_name} index parameters")));
+    }
+
+    let (mut dim, mut metric, mut typed) = (None, None, false);
+    for pair in attributes.chunks(2) {
+        let (attribute, value) = (pair[0].to_ascii_uppercase(), &pair[1]);
+        let invalid = || bad_arguments(&format!("{algorithm_name} index {}", text(&attribute)));
+        let at_most = |max: usize| parse_int::<usize>(value).ok().filter(|n| (1..=max).contains(n)).ok_or_else(invalid);
+        let number = || at_most(usize::MAX);
+        match (attribute.as_slice(), &mut algorithm) {
+            (b"TYPE", _) if value.eq_ignore_ascii_case(b"FLOAT32") => typed = true,
+            (b"DIM", _) => dim = Some(at_most(MAX_VECTOR_DIM)?),
+            (b"DISTANCE_METRIC", _) => {
+                metric = Some(match value.to_ascii_uppercase().as_slice() {
+                    b"L2" => Metric::L2,
+                    b"IP" => Metric::Ip,
+                    b"COSINE" => Metric::Cosine,
+                    _ => return Err(invalid()),
+                })
+            }
+            // Storage is allocated as needed, so sizing hints are ignored.
+            (b"INITIAL_CAP" | b"BLOCK_SIZE", _) => {
+                number()?;
+            }
+            (b"M", Algorithm::Hnsw { m, .. }) => *m = at_most(MAX_HNSW_M)?,
+            (b"EF_CONSTRUCTION", Algorithm::Hnsw { ef_construction, .. }) => *ef_construction = number()?,
+            (b"EF_RUNTIME", Algorithm::Hnsw { ef_runtime, .. }) => *ef_runtime = number()?,
+            _ => return Err(invalid()),
+        }
+    }
+    let missing = |attribute: &str| {
+        Reply::error(format!(
+            "ERR Missing mandatory parameter: cannot create {algorithm_name} index without specifying {attribute} argument"
+        ))
+    };
+    if !typed {
+        return Err(missing("TYPE"));
+    }
+    let dim = dim.ok_or_else(|| missing("DIM"))?;
+    let metric = metric.ok_or_else(|| missing("DISTANCE_METRIC"))?;
+    Ok((FieldKind::Vector { algorithm, dim, metric }, &args[1 + attributes.len()..]))
+}
+
+/// `FT.CREATE index [ON HASH | JSON] [PREFIX count prefix ...] SCHEMA field [AS name] type [options] ...`
+///
+/// Field types are `TEXT [WEIGHT weight] [NOSTEM]`, `TAG [SEPARATOR sep]
+/// [CASESENSITIVE]`, `NUMERIC`, each optionally `SORTABLE`, and `VECTOR`
+/// (see [`vector_kind`]). Fields of a JSON index are JSONPaths.
+pub struct CreateIndexCommand {
+    name: String,
+    schema: Schema,
+}
+
+impl Command for CreateIndexCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let mut source = Source::Hash;
+        let mut prefixes = Vec::new();
+        let mut i = 2;
+        loop {
+            let option = args.get(i).ok_or_else(|| Reply::error("ERR Fields arguments are missing"))?.to_ascii_uppercase();
+            match (option.as_slice(), args.get(i + 1)) {
+                (b"SCHEMA", _) => break,
+                (b"ON", Some(kind)) => {
+                    source = match kind.to_ascii_uppercase().as_slice() {
+                        b"HASH" => Source::Hash,
+                        b"JSON" => Source::Json,
+                        _ => return Err(Reply::error(format!("ERR Invalid index type '{}'", text(kind)))),
+                    };
+                    i += 2;
+                }
+                (b"PREFIX", Some(_)) => {
+                    let listed = counted(args, i + 1)?;
+                    prefixes.extend(listed.iter().cloned());
+                    i += 2 + listed.len();
+                }
+                _ => return Err(Reply::error(format!("ERR Unknown argument `{}`", text(&args[i])))),
+            }
+        }
+
+        let mut fields: Vec<Field> = Vec::new();
+        let mut rest = &args[i + 1..];
+        while let Some((identifier, tail)) = rest.split_first() {
+            let identifier = text(identifier);
+            let (name, tail) = match tail {
+                [option, name, tail @ ..] if option.eq_ignore_ascii_case(b"AS") => (Some(text(name)), tail),
+                _ => (None, tail),
+            };
+            let Some((kind, mut tail)) = tail.split_first() else {
+                return Err(Reply::error(format!("ERR Field `{identifier}` does not have a type")));
+            };
+            let mut kind = match kind.to_ascii_uppercase().as_slice() {
+                b"TEXT" => FieldKind::Text { weight: 1.0 },
+                b"TAG" => FieldKind::Tag { separator: ',', case_sensitive: false },
+                b"NUMERIC" => FieldKind::Numeric,
+                b"VECTOR" => {
+                    let (kind, next) = vector_kind(tail)?;
+                    tail = next;
+                    kind
+                }
+                _ => return Err(Reply::error(format!("ERR Invalid field type for field `{identifier}`"))),
+            };
+            // Options of the type; anything else starts the next field.
+            loop {
+                match (&mut kind, tail) {
+                    (_, [option, next @ ..]) if option.eq_ignore_ascii_case(b"SORTABLE") => tail = next,
+                    (FieldKind::Text { .. }, [option, next @ ..]) if option.eq_ignore_ascii_case(b"NOSTEM") => tail = next,
+                    (FieldKind::Text { weight }, [option, value, next @ ..]) if option.eq_ignore_ascii_case(b"WEIGHT") => {
+                        *weight = std::str::from_utf8(value)
+                            .ok()
+                            .and_then(|value| value.parse().ok())
+                            .filter(|weight: &f64| weight.is_finite() && *weight >= 0.0)
+                            .ok_or_else(|| Reply::error("ERR Bad arguments for WEIGHT: Could not convert argument to expected type"))?;
+                        tail = next;
+                    }
+                    (FieldKind::Tag { separator, .. }, [option, value, next @ ..]) if option.eq_ignore_ascii_case(b"SEPARATOR") => {
+                        *separator = match std::str::from_utf8(value).ok().map(|value| value.chars().collect::<Vec<_>>()).as_deref() {
+                            Some([c]) => *c,
+                            _ => return Err(Reply::error("ERR Tag separator must be a single character")),
+                        };
+                        tail = next;
+                    }
+                    (FieldKind::Tag { case_sensitive, .. }, [option, next @ ..]) if option.eq_ignore_ascii_case(b"CASESENSITIVE") => {
+                        *case_sensitive = true;
+                        tail = next;
+                    }
+                    _ => break,
+                }
+            }
+
+            let field = Field::new(source, &identifier, name.as_deref(), kind)
+                .map_err(|error| Reply::error(format!("ERR Invalid JSONPath `{identifier}`: {error}")))?;
+            if fields.iter().any(|other| other.name == field.name) {
+                return Err(Reply::error(format!("ERR Duplicate field in schema - {}", field.name)));
+            }
+            fields.push(field);
+            rest = tail;
+        }
+        if fields.is_empty() {
+            return Err(Reply::error("ERR Fields arguments are missing"));
+        }
+        Ok(CreateIndexCommand { name: text(&args[1]), schema: Schema { source, prefixes, fields } })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        if db.lock().unwrap().create_index(&self.name, Index::new(self.schema.clone())) {
+            Reply::ok()
+        } else {
+            Reply::error("ERR Index already exists")
+        }
+    }
+}
+
+/// `PARAMS nargs name value ...`, starting at the nargs argument `args[i]`.
+fn params(args: &[Vec<u8>], i: usize, params: &mut HashMap<String, Vec<u8>>) -> Result<usize, Reply> {
+    let pairs = counted(args, i)?;
+    if pairs.len() % 2 != 0 {
+        return Err(Reply::error("ERR Bad arguments for PARAMS: Expected name value pairs"));
+    }
+    params.extend(pairs.chunks(2).map(|pair| (text(&pair[0]), pair[1].clone())));
+    Ok(pairs.len())
+}
+
+/// Parses a query, failing with the reply to send.
+fn parse_query(query: &str, schema: &Schema, params: &HashMap<String, Vec<u8>>) -> Result<Query, Reply> {
+    Query::parse(query, schema, params).map_err(|error| Reply::error(format!("ERR {error}")))
+}
+
+/// `FT.SEARCH index query [NOCONTENT] [VERBATIM] [WITHSCORES] [RETURN count field ...]
+/// [SORTBY field [ASC | DESC]] [LIMIT offset count] [PARAMS nargs name value ...] [DIALECT n]`
+///
+/// For a KNN query each document also returns its distance to the query
+/// vector, first.
+pub struct SearchCommand {
+    index: String,
+    query: String,
+    params: HashMap<String, Vec<u8>>,
+    no_content: bool,
+    with_scores: bool,
+    fields: Option<Vec<String>>,
+    sort_by: Option<(String, bool)>,
+    offset: usize,
+    count: usize,
+}
+
+impl Command for SearchCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let mut command = SearchCommand {
+            index: text(&args[1]),
+            query: text(&args[2]),
+            params: HashMap::new(),
+            no_content: false,
+            with_scores: false,
+            fields: None,
+            sort_by: None,
+            offset: 0,
+            count: 10,
+        };
+        let mut i = 3;
+        while i < args.len() {
+            let option = args[i].to_ascii_uppercase();
+            match (option.as_slice(), args.get(i + 1)) {
+                (b"NOCONTENT", _) => command.no_content = true,
+                // Terms are never stemmed, so they are always matched verbatim.
+                (b"VERBATIM", _) => {}
+                (b"WITHSCORES", _) => command.with_scores = true,
+                (b"RETURN", Some(_)) => {
+                    let fields = counted(args, i + 1)?;
+                    command.fields = Some(fields.iter().map(|field| text(field)).collect());
+                    i += 1 + fields.len();
+                }
+                (b"SORTBY", Some(field)) => {
+                    let order = args.get(i + 2).map(|order| order.to_ascii_uppercase());
+                    if matches!(order.as_deref(), Some(b"ASC" | b"DESC")) {
+                        i += 1;
+                    }
+                    command.sort_by = Some((text(field), order.as_deref() == Some(b"DESC")));
+                    i += 1;
+                }
+                (b"LIMIT", Some(offset)) => {
+                    let count = args.get(i + 2).ok_or_else(syntax_error)?;
+                    command.offset = parse_int(offset)?;
+                    command.count = parse_int(count)?;
+                    i += 2;
+                }
+                (b"PARAMS", Some(_)) => i += 1 + params(args, i + 1, &mut command.params)?,
+                // Only one query dialect is spoken.
+                (b"DIALECT", Some(dialect)) => {
+                    parse_int::<u64>(dialect)?;
+                    i += 1;
+                }
+                _ => return Err(Reply::error(format!("ERR Unknown argument `{}`", text(&args[i])))),
+            }
+            i += 1;
+        }
+        if command.fields.as_ref().is_some_and(Vec::is_empty) {
+            command.no_content = true;
+        }
+        Ok(command)
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        db.sync_indexes();
+        let db = &*db;
+        let Some(index) = db.index(&self.index) else {
+            return no_such_index(&self.index);
+        };
+        let query = match parse_query(&self.query, &index.schema, &self.params) {
+            Ok(query) => query,
+            Err(reply) => return reply,
+        };
+        let distance_name = query.knn.as_ref().map(|knn| knn.name.as_str());
+        let sort_by = match &self.sort_by {
+            // KNN matches come ordered by distance already.
+            Some((name, _)) if Some(name.as_str()) == distance_name => None,
+            Some((name, descending)) => match index.schema.field(name) {
+                Some((field, _)) => Some((field, *descending)),
+                None => return Reply::error(format!("ERR Property `{name}` not loaded nor in schema")),
+            },
+            None => None,
+        };
+
+        let mut matches: Vec<_> = index
+            .search(&query, sort_by)
+            .into_iter()
+            .filter_map(|found| db.peek(found.key).map(|entry| (found, entry)))
+            .collect();
+        if matches!(&self.sort_by, Some((name, true)) if Some(name.as_str()) == distance_name) {
+            matches.reverse();
+        }
+        let mut reply = vec![Reply::Integer(matches.len() as i64)];
+        for (found, entry) in matches.into_iter().skip(self.offset).take(self.count) {
+            reply.push(Reply::Bulk(found.key.to_vec()));
+            if self.with_scores {
+                reply.push(Reply::Bulk(format_double(found.score).into_bytes()));
+            }
+            if !self.no_content {
+                reply.push(self.content(&index.schema, entry, &found, distance_name));
+            }
+        }
+        Reply::Array(reply)
+    }
+}
+
+impl SearchCommand {
+    /// The field/value pairs returned for a document: the whole hash or JSON
+    /// document (as `$`), or the RETURN fields it has. A KNN match's distance
+    /// comes first, as `distance_name`.
+    fn content(&self, schema: &Schema, entry: &Entry, found: &Match, distance_name: Option<&str>) -> Reply {
+        let mut properties = match &self.fields {
+            None => schema.load_all(&entry.value),
+            Some(names) => names
+                .iter()
+                .filter(|name| Some(name.as_str()) != distance_name)
+                .filter_map(|name| Some((name.clone(), schema.load(&entry.value, name)?)))
+                .collect(),
+        };
+        if let (Some(distance), Some(name)) = (found.distance, distance_name) {
+            if self.fields.as_ref().is_none_or(|names| names.iter().any(|field| field == name)) {
+                properties.insert(0, (name.to_string(), Property::Text(distance.to_string().into_bytes())));
+            }
+        }
+        properties_reply(properties)
+    }
+}
+
+/// `FT.AGGREGATE index query [VERBATIM] [LOAD count property ...] step ...
+/// [PARAMS nargs name value ...] [DIALECT n]`
+///
+/// The steps run in the order given: `GROUPBY nargs @property ... [REDUCE
+/// function nargs [@property] [AS name] ...]`, `APPLY expression AS name`,
+/// `FILTER expression`, `SORTBY nargs @property [ASC | DESC] ... [MAX count]`
+/// and `LIMIT offset count`. The reply starts with the number of rows.
+pub struct AggregateCommand {
+    index: String,
+    query: String,
+    params: HashMap<String, Vec<u8>>,
+    load: Vec<String>,
+    steps: Vec<Step>,
+}
+
+/// A `@name` argument naming a row property.
+fn property(arg: &[u8], step: &str) -> Result<String, Reply> {
+    match arg.strip_prefix(b"@") {
+        Some(name) if !name.is_empty() => Ok(text(name)),
+        _ => Err(Reply::error(format!("ERR Bad arguments for {step}: Unknown property `{}`. Did you mean `@{0}`?", text(arg)))),
+    }
+}
+
+fn expression(arg: &[u8]) -> Result<Expression, Reply> {
+    Expression::parse(&String::from_utf8_lossy(arg)).map_err(|error| Reply::error(format!("ERR {error}")))
+}
+
+/// The `count` arguments following `args[i]`, which holds the count.
+fn counted(args: &[Vec<u8>], i: usize) -> Result<&[Vec<u8>], Reply> {
+    let count: usize = parse_int(args.get(i).ok_or_else(syntax_error)?)?;
+    args.get(i + 1..).and_then(|rest| rest.get(..count)).ok_or_else(syntax_error)
+}
+
+impl Command for AggregateCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let mut command = AggregateCommand {
+            index: text(&args[1]),
+            query: text(&args[2]),
+            params: HashMap::new(),
+            load: Vec::new(),
+            steps: Vec::new(),
+        };
+        let mut i = 3;
+        while i < args.len() {
+            let option = args[i].to_ascii_uppercase();
+            match (option.as_slice(), args.get(i + 1)) {
+                (b"VERBATIM", _) => i += 1,
+                (b"LOAD", Some(_)) => {
+                    let names = counted(args, i + 1)?;
+                    command.load.extend(names.iter().map(|name| text(name.strip_prefix(b"@").unwrap_or(name))));
+                    i += 2 + names.len();
+                }
+                (b"GROUPBY", Some(_)) => {
+                    let properties = counted(args, i + 1)?
+                        .iter()
+                        .map(|arg| property(arg, "GROUPBY"))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    i += 2 + properties.len();
+                    let mut reducers = Vec::new();
+                    while args.get(i).is_some_and(|arg| arg.eq_ignore_ascii_case(b"REDUCE")) {
+                        let name = args.get(i + 1).ok_or_else(syntax_error)?;
+                        let function = ReduceFunction::by_name(&String::from_utf8_lossy(name))
+                            .ok_or_else(|| Reply::error(format!("ERR Unknown reducer `{}`", text(name))))?;
+                        let arguments = counted(args, i + 2)?;
+                        let reducer_property = match arguments {
+                            [] if !function.takes_property() => None,
+                            [arg] if function.takes_property() => Some(property(arg, "REDUCE")?),
+                            _ => return Err(Reply::error(format!("ERR Bad arguments for {}", text(name).to_uppercase()))),
+                        };
+                        i += 3 + arguments.len();
+                        let alias = match (args.get(i), args.get(i + 1)) {
+                            (Some(option), Some(alias)) if option.eq_ignore_ascii_case(b"AS") => {
+                                i += 2;
+                                text(alias)
+                            }
+                            _ => format!(
+                                "__generated_alias{}{}",
+                                text(name).to_lowercase(),
+                                reducer_property.as_deref().unwrap_or("")
+                            ),
+                        };
+                        reducers.push(Reducer { function, property: reducer_property, name: alias });
+                    }
+                    command.steps.push(Step::GroupBy { properties, reducers });
+                }
+                (b"APPLY", Some(arg)) => {
+                    let name = match (args.get(i + 2), args.get(i + 3)) {
+                        (Some(option), Some(name)) if option.eq_ignore_ascii_case(b"AS") => text(name),
+                        _ => return Err(Reply::error("ERR Missing AS for APPLY")),
+                    };
+                    command.steps.push(Step::Apply { expression: expression(arg)?, name });
+                    i += 4;
+                }
+                (b"FILTER", Some(arg)) => {
+                    command.steps.push(Step::Filter(expression(arg)?));
+                    i += 2;
+                }
+                (b"SORTBY", Some(_)) => {
+                    let arguments = counted(args, i + 1)?;
+                    let mut keys: Vec<(String, bool)> = Vec::new();
+                    for arg in arguments {
+                        match arg.to_ascii_uppercase().as_slice() {
+                            b"ASC" | b"DESC" if !keys.is_empty() => keys.last_mut().unwrap().1 = arg.eq_ignore_ascii_case(b"DESC"),
+                            _ => keys.push((property(arg, "SORTBY")?, false)),
+                        }
+                    }
+                    i += 2 + arguments.len();
+                    let max = match (args.get(i), args.get(i + 1)) {
+                        (Some(option), Some(max)) if option.eq_ignore_ascii_case(b"MAX") => {
+                            i += 2;
+                            Some(parse_int(max)?)
+                        }
+                        _ => None,
+                    };
+                    command.steps.push(Step::SortBy { keys, max });
+                }
+                (b"LIMIT", Some(offset)) => {
+                    let count = args.get(i + 2).ok_or_else(syntax_error)?;
+                    command.steps.push(Step::Limit { offset: parse_int(offset)?, count: parse_int(count)? });
+                    i += 3;
+                }
+                (b"PARAMS", Some(_)) => i += 2 + params(args, i + 1, &mut command.params)?,
+                (b"DIALECT", Some(dialect)) => {
+                    parse_int::<u64>(dialect)?;
+                    i += 2;
+                }
+                _ => return Err(Reply::error(format!("ERR Unknown argument `{}`", text(&args[i])))),
+            }
+        }
+        Ok(command)
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        db.sync_indexes();
+        let db = &*db;
+        let Some(index) = db.index(&self.index) else {
+            return no_such_index(&self.index);
+        };
+        let query = match parse_query(&self.query, &index.schema, &self.params) {
+            Ok(query) => query,
+            Err(reply) => return reply,
+        };
+
+        let matches = index.search(&query, None);
+        let keys = matches.iter().map(|found| found.key).filter(|key| db.peek(key).is_some());
+        let load = |key: &[u8], name: &str| db.peek(key).and_then(|entry| index.schema.load(&entry.value, name));
+        let rows = aggregate(keys, &self.load, &self.steps, &load);
+
+        let mut reply = vec![Reply::Integer(rows.len() as i64)];
+        reply.extend(rows.into_iter().map(properties_reply));
+        Reply::Array(reply)
+    }
+}
+
+/// `FT.DROPINDEX index [DD]`; DD also deletes the indexed documents.
+pub struct DropIndexCommand {
+    name: String,
+    delete_documents: bool,
+}
+
+impl Command for DropIndexCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let delete_documents = match &args[2..] {
+            [] => false,
+            [option] if option.eq_ignore_ascii_case(b"DD") => true,
+            _ => return Err(syntax_error()),
+        };
+        Ok(DropIndexCommand { name: text(&args[1]), delete_documents })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let Some(index) = db.drop_index(&self.name) else {
+            return Reply::error("ERR Unknown Index name");
+        };
+        if self.delete_documents {
+            for key in index.keys() {
+                db.remove(key);
+            }
+        }
+        Reply::ok()
+    }
+}
+
+/// `FT._LIST`
+pub struct ListIndexesCommand;
+
+impl Command for ListIndexesCommand {
+    fn parse(_args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(ListIndexesCommand)
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let db = db.lock().unwrap();
+        Reply::Array(db.index_names().map(|name| Reply::Bulk(name.clone().into_bytes())).collect())
+    }
+}
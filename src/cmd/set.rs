@@ -1,27 +1,54 @@
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
+use crate::cmd::{parse_int, syntax_error, Command};
+use crate::db::{Db, Entry, Value};
+use crate::reply::Reply;
 
-type Db = Arc<Mutex<HashMap<String, DbValue>>>;
-type DbValue = (String, Option<Instant>);
-
-pub struct SetCommand<'a> {
-    key: &'a str,
-    value: &'a str,
+pub struct SetCommand {
+    key: Vec<u8>,
+    value: Vec<u8>,
     expire_seconds: Option<u64>,
     expire_milliseconds: Option<u64>,
 }
 
-impl<'a> SetCommand<'a> {
-    pub fn new(key: &'a str, value: &'a str, expire_seconds: Option<u64>, expire_milliseconds: Option<u64>) -> Self {
-        SetCommand { key, value, expire_seconds, expire_milliseconds }
+impl SetCommand {
+    pub fn new(key: &[u8], value: &[u8], expire_seconds: Option<u64>, expire_milliseconds: Option<u64>) -> Self {
+        SetCommand { key: key.to_vec(), value: value.to_vec(), expire_seconds, expire_milliseconds }
+    }
+}
+
+impl Command for SetCommand {
+    /// `SET key value [EX seconds | PX milliseconds]`
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let mut expire_seconds = None;
+        let mut expire_milliseconds = None;
+
+        let mut i = 3;
+        while i < args.len() {
+            let option = args[i].to_ascii_uppercase();
+            let repeated = expire_seconds.is_some() || expire_milliseconds.is_some();
+            if repeated || (option != b"EX" && option != b"PX") {
+                return Err(syntax_error());
+            }
+            let amount = match parse_int::<u64>(args.get(i + 1).ok_or_else(syntax_error)?)? {
+                0 => return Err(Reply::error("ERR invalid expire time in 'set' command")),
+                amount => amount,
+            };
+            if option == b"EX" {
+                expire_seconds = Some(amount);
+            } else {
+                expire_milliseconds = Some(amount);
+            }
+            i += 2;
+        }
+
+        Ok(SetCommand::new(&args[1], &args[2], expire_seconds, expire_milliseconds))
     }
 
-    pub fn execute(&self, db: &Db) -> String {
+    fn execute(&self, db: &Db) -> Reply {
         let mut db = db.lock().unwrap();
         let expire_time = self.expire_seconds.map(|seconds| Instant::now() + Duration::from_secs(seconds))
             .or_else(|| self.expire_milliseconds.map(|milliseconds| Instant::now() + Duration::from_millis(milliseconds)));
-        db.insert(self.key.to_string(), (self.value.to_string(), expire_time));
-        "+OK\r\n".to_string()
+        db.insert(self.key.clone(), Entry::with_expiry(Value::String(self.value.clone()), expire_time));
+        Reply::ok()
     }
 }
\ No newline at end of file
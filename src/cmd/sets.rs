@@ -0,0 +1,416 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashSet;
+use std::hash::BuildHasher;
+use crate::cmd::scan::{self, ScanOptions};
+use crate::cmd::{parse_int, syntax_error, Command};
+use crate::db::{Db, Entry, Keyspace, Set, Value};
+use crate::reply::Reply;
+
+/// Returns the set stored at `key`, or WRONGTYPE if the key holds another type.
+fn get_set<'a>(db: &'a mut Keyspace, key: &[u8]) -> Result<Option<&'a Set>, Reply> {
+    match db.get(key) {
+        Some(Entry { value: Value::Set(set), .. }) => Ok(Some(set)),
+        Some(_) => Err(Reply::wrong_type()),
+        None => Ok(None),
+    }
+}
+
+/// Like [`get_set`], for commands that modify the value; the key counts as
+/// written.
+fn get_set_mut<'a>(db: &'a mut Keyspace, key: &[u8]) -> Result<Option<&'a mut Set>, Reply> {
+    match db.get_mut(key) {
+        Some(Entry { value: Value::Set(set), .. }) => Ok(Some(set)),
+        Some(_) => Err(Reply::wrong_type()),
+        None => Ok(None),
+    }
+}
+
+fn bulks(members: Vec<Vec<u8>>) -> Vec<Reply> {
+    members.into_iter().map(Reply::Bulk).collect()
+}
+
+/// A xorshift generator seeded from the process' random hasher keys; good
+/// enough for picking random members.
+pub(crate) struct Random(u64);
+
+impl Random {
+    pub(crate) fn new() -> Self {
+        Random(RandomState::new().hash_one(0u8) | 1)
+    }
+
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+
+    /// Moves `count` randomly chosen members to the front of `members`.
+    fn shuffle_prefix(&mut self, members: &mut [Vec<u8>], count: usize) {
+        for i in 0..count.min(members.len()) {
+            let j = i + self.below(members.len() - i);
+            members.swap(i, j);
+        }
+    }
+}
+
+/// `SADD key member [member ...]`
+pub struct SAddCommand {
+    key: Vec<u8>,
+    members: Vec<Vec<u8>>,
+}
+
+impl Command for SAddCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(SAddCommand { key: args[1].clone(), members: args[2..].to_vec() })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let set = match get_set_mut(&mut db, &self.key) {
+            Ok(Some(set)) => set,
+            Ok(None) => {
+                db.insert(self.key.clone(), Entry::new(Value::Set(Set::new())));
+                get_set_mut(&mut db, &self.key).unwrap().expect("set was just inserted")
+            }
+            Err(reply) => return reply,
+        };
+        let added = self.members.iter().filter(|member| set.insert(member)).count();
+        Reply::Integer(added as i64)
+    }
+}
+
+/// `SREM key member [member ...]`
+pub struct SRemCommand {
+    key: Vec<u8>,
+    members: Vec<Vec<u8>>,
+}
+
+impl Command for SRemCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(SRemCommand { key: args[1].clone(), members: args[2..].to_vec() })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let removed = match get_set_mut(&mut db, &self.key) {
+            Ok(Some(set)) => self.members.iter().filter(|member| set.remove(member)).count(),
+            Ok(None) => 0,
+            Err(reply) => return reply,
+        };
+        db.remove_if_empty(&self.key);
+        Reply::Integer(removed as i64)
+    }
+}
+
+/// `SMEMBERS key`
+pub struct SMembersCommand {
+    key: Vec<u8>,
+}
+
+impl Command for SMembersCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(SMembersCommand { key: args[1].clone() })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        match get_set(&mut db, &self.key) {
+            Ok(set) => Reply::Set(set.map_or_else(Vec::new, |set| bulks(set.members()))),
+            Err(reply) => reply,
+        }
+    }
+}
+
+/// `SISMEMBER key member` and `SMISMEMBER key member [member ...]`
+pub struct SIsMemberCommand {
+    key: Vec<u8>,
+    members: Vec<Vec<u8>>,
+    multiple: bool,
+}
+
+impl Command for SIsMemberCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(SIsMemberCommand {
+            key: args[1].clone(),
+            members: args[2..].to_vec(),
+            multiple: args[0].eq_ignore_ascii_case(b"smismember"),
+        })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let set = match get_set(&mut db, &self.key) {
+            Ok(set) => set,
+            Err(reply) => return reply,
+        };
+        let mut found = self
+            .members
+            .iter()
+            .map(|member| Reply::Integer(set.as_ref().is_some_and(|set| set.contains(member)) as i64));
+        if self.multiple {
+            Reply::Array(found.collect())
+        } else {
+            found.next().expect("arity guarantees a member")
+        }
+    }
+}
+
+/// `SCARD key`
+pub struct SCardCommand {
+    key: Vec<u8>,
+}
+
+impl Command for SCardCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(SCardCommand { key: args[1].clone() })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        match get_set(&mut db, &self.key) {
+            Ok(set) => Reply::Integer(set.map_or(0, |set| set.len() as i64)),
+            Err(reply) => reply,
+        }
+    }
+}
+
+fn parse_count(arg: &[u8]) -> Result<usize, Reply> {
+    match parse_int::<i64>(arg) {
+        Ok(count) if count >= 0 => Ok(count as usize),
+        _ => Err(Reply::error("ERR value is out of range, must be positive")),
+    }
+}
+
+/// Up to `count` distinct random members. Small counts are drawn one by one;
+/// counts close to the size of the set shuffle a copy instead, since most
+/// draws would hit members already taken.
+fn distinct_members(set: &Set, count: usize, random: &mut Random) -> Vec<Vec<u8>> {
+    if count >= set.len() {
+        return set.members();
+    }
+    if count * 3 > set.len() {
+        let mut members = set.members();
+        random.shuffle_prefix(&mut members, count);
+        members.truncate(count);
+        return members;
+    }
+    let mut picked = HashSet::new();
+    while picked.len() < count {
+        picked.insert(set.random_member(|n| random.below(n)).expect("set is not empty"));
+    }
+    picked.into_iter().collect()
+}
+
+/// `SPOP key [count]`
+pub struct SPopCommand {
+    key: Vec<u8>,
+    count: Option<usize>,
+}
+
+impl Command for SPopCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let count = match args.len() {
+            2 => None,
+            3 => Some(parse_count(&args[2])?),
+            _ => return Err(syntax_error()),
+        };
+        Ok(SPopCommand { key: args[1].clone(), count })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let set = match get_set_mut(&mut db, &self.key) {
+            Ok(Some(set)) => set,
+            Ok(None) if self.count.is_some() => return Reply::Set(Vec::new()),
+            Ok(None) => return Reply::Null,
+            Err(reply) => return reply,
+        };
+        let mut members = distinct_members(set, self.count.unwrap_or(1), &mut Random::new());
+        for member in &members {
+            set.remove(member);
+        }
+        db.remove_if_empty(&self.key);
+
+        match self.count {
+            Some(_) => Reply::Set(bulks(members)),
+            None => members.pop().map_or(Reply::Null, Reply::Bulk),
+        }
+    }
+}
+
+/// The reply to a negative SRANDMEMBER count is built whole under the lock,
+/// so it may not repeat members more often than this.
+const MAX_REPEATS: u64 = 1024 * 1024;
+
+/// `SRANDMEMBER key [count]`; a negative count may return a member repeatedly.
+pub struct SRandMemberCommand {
+    key: Vec<u8>,
+    count: Option<i64>,
+}
+
+impl Command for SRandMemberCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let count = match args.len() {
+            2 => None,
+            3 => match parse_int::<i64>(&args[2])? {
+                count if count < 0 && count.unsigned_abs() > MAX_REPEATS => return Err(Reply::error("ERR value is out of range")),
+                count => Some(count),
+            },
+            _ => return Err(syntax_error()),
+        };
+        Ok(SRandMemberCommand { key: args[1].clone(), count })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let set = match get_set(&mut db, &self.key) {
+            Ok(Some(set)) => set,
+            Ok(None) if self.count.is_some() => return Reply::Array(Vec::new()),
+            Ok(None) => return Reply::Null,
+            Err(reply) => return reply,
+        };
+        let mut random = Random::new();
+        match self.count {
+            None => set.random_member(|n| random.below(n)).map_or(Reply::Null, Reply::Bulk),
+            Some(count) if count < 0 => Reply::Array(
+                (0..count.unsigned_abs())
+                    .filter_map(|_| set.random_member(|n| random.below(n)))
+                    .map(Reply::Bulk)
+                    .collect(),
+            ),
+            Some(count) => Reply::Array(bulks(distinct_members(set, count as usize, &mut random))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SetOp {
+    Inter,
+    Union,
+    Diff,
+}
+
+/// Applies `op` to the sets at `keys`; missing keys count as empty sets.
+fn combine(db: &mut Keyspace, op: SetOp, keys: &[Vec<u8>]) -> Result<Vec<Vec<u8>>, Reply> {
+    let mut result = get_set(db, &keys[0])?.map_or_else(Vec::new, |set| set.members());
+    let mut seen: HashSet<Vec<u8>> = HashSet::new();
+    if op == SetOp::Union {
+        seen.extend(result.iter().cloned());
+    }
+    for key in &keys[1..] {
+        let set = get_set(db, key)?;
+        match (op, set) {
+            (SetOp::Inter, Some(set)) => result.retain(|member| set.contains(member)),
+            (SetOp::Inter, None) => result.clear(),
+            (SetOp::Diff, Some(set)) => result.retain(|member| !set.contains(member)),
+            (SetOp::Union, Some(set)) => {
+                result.extend(set.members().into_iter().filter(|member| seen.insert(member.clone())));
+            }
+            (SetOp::Diff | SetOp::Union, None) => {}
+        }
+    }
+    Ok(result)
+}
+
+/// `SINTER`/`SUNION`/`SDIFF key [key ...]` and their `*STORE destination
+/// key [key ...]` variants.
+pub struct SetOpCommand {
+    op: SetOp,
+    destination: Option<Vec<u8>>,
+    keys: Vec<Vec<u8>>,
+}
+
+impl Command for SetOpCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let name = args[0].to_ascii_lowercase();
+        let op = match &name[1..5] {
+            b"inte" => SetOp::Inter,
+            b"unio" => SetOp::Union,
+            _ => SetOp::Diff,
+        };
+        if name.ends_with(b"store") {
+            Ok(SetOpCommand { op, destination: Some(args[1].clone()), keys: args[2..].to_vec() })
+        } else {
+            Ok(SetOpCommand { op, destination: None, keys: args[1..].to_vec() })
+        }
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let result = match combine(&mut db, self.op, &self.keys) {
+            Ok(result) => result,
+            Err(reply) => return reply,
+        };
+        let Some(destination) = &self.destination else {
+            return Reply::Set(bulks(result));
+        };
+        let len = result.len();
+        db.remove(destination);
+        if len > 0 {
+            db.insert(destination.clone(), Entry::new(Value::Set(result.into_iter().collect())));
+        }
+        Reply::Integer(len as i64)
+    }
+}
+
+/// `SINTERCARD numkeys key [key ...] [LIMIT limit]`
+pub struct SInterCardCommand {
+    keys: Vec<Vec<u8>>,
+    limit: usize,
+}
+
+impl Command for SInterCardCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let numkeys = match parse_int::<i64>(&args[1]) {
+            Ok(n) if n > 0 => n as usize,
+            _ => return Err(Reply::error("ERR numkeys should be greater than 0")),
+        };
+        if numkeys > args.len() - 2 {
+            return Err(Reply::error("ERR Number of keys can't be greater than number of args"));
+        }
+        let keys = args[2..2 + numkeys].to_vec();
+        let limit = match &args[2 + numkeys..] {
+            [] => 0,
+            [option, limit] if option.eq_ignore_ascii_case(b"LIMIT") => match parse_int::<i64>(limit) {
+                Ok(limit) if limit >= 0 => limit as usize,
+                _ => return Err(Reply::error("ERR LIMIT can't be negative")),
+            },
+            _ => return Err(syntax_error()),
+        };
+        Ok(SInterCardCommand { keys, limit })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        match combine(&mut db, SetOp::Inter, &self.keys) {
+            // A limit of 0 means no limit.
+            Ok(result) if self.limit > 0 => Reply::Integer(result.len().min(self.limit) as i64),
+            Ok(result) => Reply::Integer(result.len() as i64),
+            Err(reply) => reply,
+        }
+    }
+}
+
+/// `SSCAN key cursor [MATCH pattern] [COUNT count]`
+pub struct SScanCommand {
+    key: Vec<u8>,
+    options: ScanOptions,
+}
+
+impl Command for SScanCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(SScanCommand { key: args[1].clone(), options: ScanOptions::parse(&args[2..], &[])? })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let (cursor, page) = match get_set(&mut db, &self.key) {
+            Ok(Some(set)) => set.scan(self.options.cursor, self.options.count),
+            Ok(None) => return scan::reply(0, Vec::new()),
+            Err(reply) => return reply,
+        };
+        let elements = page.into_iter().filter(|member| self.options.matches(member)).map(Reply::Bulk).collect();
+        scan::reply(cursor, elements)
+    }
+}
@@ -0,0 +1,1281 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::cmd::{parse_int, syntax_error, BlockingCommand, Command};
+use crate::db::{ConsumerGroup, Db, Entry, Keyspace, Stream, StreamId, Value, STREAM_NODE_ENTRIES};
+use crate::reply::Reply;
+
+/// Returns the stream stored at `key`, or WRONGTYPE if the key holds another type.
+pub fn get_stream<'a>(db: &'a mut Keyspace, key: &[u8]) -> Result<Option<&'a Stream>, Reply> {
+    match db.get(key) {
+        Some(Entry { value: Value::Stream(stream), .. }) => Ok(Some(stream)),
+        Some(_) => Err(Reply::wrong_type()),
+        None => Ok(None),
+    }
+}
+
+/// Like [`get_stream`], for commands that modify the value; the key counts as
+/// written.
+pub fn get_stream_mut<'a>(db: &'a mut Keyspace, key: &[u8]) -> Result<Option<&'a mut Stream>, Reply> {
+    match db.get_mut(key) {
+        Some(Entry { value: Value::Stream(stream), .. }) => Ok(Some(stream)),
+        Some(_) => Err(Reply::wrong_type()),
+        None => Ok(None),
+    }
+}
+
+pub fn invalid_id() -> Reply {
+    Reply::error("ERR Invalid stream ID specified as stream command argument")
+}
+
+/// Parses an explicit ID; a bare `ms` gets the sequence `default_seq`.
+pub fn parse_id(arg: &[u8], default_seq: u64) -> Result<StreamId, Reply> {
+    StreamId::parse(arg, default_seq).ok_or_else(invalid_id)
+}
+
+/// `[id, [field, value, ...]]`, the reply shape of a single entry.
+pub fn entry_reply(id: StreamId, fields: &[Vec<u8>]) -> Reply {
+    Reply::Array(vec![
+        Reply::Bulk(id.to_bytes()),
+        Reply::Array(fields.iter().map(|field| Reply::Bulk(field.clone())).collect()),
+    ])
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_millis() as u64)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TrimStrategy {
+    MaxLen(usize),
+    MinId(StreamId),
+}
+
+/// `MAXLEN|MINID [=|~] threshold [LIMIT count]` of XADD and XTRIM.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Trim {
+    strategy: TrimStrategy,
+    approximate: bool,
+    limit: Option<usize>,
+}
+
+impl Trim {
+    /// Parses a trim clause starting at `args[0]` (MAXLEN or MINID) and
+    /// returns it with the number of arguments consumed.
+    fn parse(args: &[Vec<u8>]) -> Result<(Self, usize), Reply> {
+        let mut i = 1;
+        let approximate = match args.get(i).map(|arg| arg.as_slice()) {
+            Some(b"~") => true,
+            Some(b"=") => false,
+            _ => {
+                i -= 1;
+                false
+            }
+        };
+        i += 1;
+        let threshold = args.get(i).ok_or_else(syntax_error)?;
+        let strategy = if args[0].eq_ignore_ascii_case(b"MAXLEN") {
+            match parse_int::<i64>(threshold)? {
+                max_len if max_len >= 0 => TrimStrategy::MaxLen(max_len as usize),
+                _ => return Err(Reply::error("ERR The MAXLEN argument must be >= 0.")),
+            }
+        } else {
+            TrimStrategy::MinId(parse_id(threshold, 0)?)
+        };
+        i += 1;
+
+        let mut limit = approximate.then_some(100 * STREAM_NODE_ENTRIES);
+        if args.get(i).is_some_and(|arg| arg.eq_ignore_ascii_case(b"LIMIT")) {
+            if !approximate {
+                return Err(Reply::error("ERR syntax error, LIMIT cannot be used without the special ~ option"));
+            }
+            let count = args.get(i + 1).ok_or_else(syntax_error)?;
+            limit = match parse_int::<i64>(count)? {
+                0 => None,
+                count if count > 0 => Some(count as usize),
+                _ => return Err(Reply::error("ERR The LIMIT argument must be >= 0.")),
+            };
+            i += 2;
+        }
+        Ok((Trim { strategy, approximate, limit }, i))
+    }
+
+    fn apply(&self, stream: &mut Stream) -> usize {
+        match self.strategy {
+            TrimStrategy::MaxLen(max_len) => stream.trim_max_len(max_len, self.approximate, self.limit),
+            TrimStrategy::MinId(min_id) => stream.trim_min_id(min_id, self.approximate, self.limit),
+        }
+    }
+}
+
+/// The ID argument of XADD.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum NewId {
+    /// `*`
+    Auto,
+    /// `ms-*`
+    AutoSeq(u64),
+    Explicit(StreamId),
+}
+
+/// `XADD key [NOMKSTREAM] [MAXLEN|MINID [=|~] threshold [LIMIT count]] *|id field value [field value ...]`
+pub struct XAddCommand {
+    key: Vec<u8>,
+    no_mkstream: bool,
+    trim: Option<Trim>,
+    id: NewId,
+    fields: Vec<Vec<u8>>,
+}
+
+impl Command for XAddCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let mut no_mkstream = false;
+        let mut trim = None;
+        let mut i = 2;
+        loop {
+            let option = args.get(i).ok_or_else(syntax_error)?.to_ascii_uppercase();
+            match option.as_slice() {
+                b"NOMKSTREAM" => {
+                    no_mkstream = true;
+                    i += 1;
+                }
+                b"MAXLEN" | b"MINID" => {
+                    let (parsed, consumed) = Trim::parse(&args[i..])?;
+                    trim = Some(parsed);
+                    i += consumed;
+                }
+                _ => break,
+            }
+        }
+
+        let id = match args[i].as_slice() {
+            b"*" => NewId::Auto,
+            id => match id.strip_suffix(b"-*") {
+                Some(ms) => NewId::AutoSeq(std::str::from_utf8(ms).ok().and_then(|ms| ms.parse().ok()).ok_or_else(invalid_id)?),
+                None => NewId::Explicit(parse_id(id, 0)?),
+            },
+        };
+        let fields = args[i + 1..].to_vec();
+        if fields.is_empty() || !fields.len().is_multiple_of(2) {
+            return Err(Reply::error("ERR wrong number of arguments for 'xadd' command"));
+        }
+        Ok(XAddCommand { key: args[1].clone(), no_mkstream, trim, id, fields })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let existing = match get_stream(&mut db, &self.key) {
+            Ok(stream) => stream,
+            Err(reply) => return reply,
+        };
+        if existing.is_none() && self.no_mkstream {
+            return Reply::Null;
+        }
+
+        // Validate against the stream's top ID before creating it, so a
+        // rejected XADD leaves no empty stream behind.
+        let last_id = existing.map_or(StreamId::MIN, |stream| stream.last_id);
+        let id = match self.id {
+            NewId::Auto => last_id.next_auto(now_ms()),
+            NewId::AutoSeq(ms) if ms == last_id.ms => last_id.next(),
+            NewId::AutoSeq(ms) if ms > last_id.ms => Some(StreamId::new(ms, 0)),
+            NewId::AutoSeq(_) => None,
+            NewId::Explicit(StreamId::MIN) => {
+                return Reply::error("ERR The ID specified in XADD must be greater than 0-0");
+            }
+            NewId::Explicit(id) => Some(id).filter(|id| *id > last_id),
+        };
+        let Some(id) = id else {
+            return Reply::error("ERR The ID specified in XADD is equal or smaller than the target stream top item");
+        };
+
+        if !db.contains_key(&self.key) {
+            db.insert(self.key.clone(), Entry::new(Value::Stream(Stream::new())));
+        }
+        let Ok(Some(stream)) = get_stream_mut(&mut db, &self.key) else {
+            unreachable!("checked to be a stream above");
+        };
+        stream.add(id, self.fields.clone());
+        if let Some(trim) = &self.trim {
+            trim.apply(stream);
+        }
+        db.signal_ready(&self.key);
+        Reply::Bulk(id.to_bytes())
+    }
+}
+
+/// Parses an XRANGE bound: `-`, `+`, an ID, or `(ID` for an exclusive bound.
+/// A bare `ms` covers the whole millisecond. `None` means an empty range.
+fn parse_range_bound(arg: &[u8], start: bool) -> Result<Option<StreamId>, Reply> {
+    match arg {
+        b"-" => Ok(Some(StreamId::MIN)),
+        b"+" => Ok(Some(StreamId::MAX)),
+        _ => {
+            let default_seq = if start { 0 } else { u64::MAX };
+            match arg.strip_prefix(b"(") {
+                Some(id) if start => Ok(parse_id(id, default_seq)?.next()),
+                Some(id) => Ok(parse_id(id, default_seq)?.previous()),
+                None => parse_id(arg, default_seq).map(Some),
+            }
+        }
+    }
+}
+
+/// `XRANGE key start end [COUNT count]` and `XREVRANGE key end start [COUNT count]`
+pub struct XRangeCommand {
+    key: Vec<u8>,
+    range: Option<(StreamId, StreamId)>,
+    rev: bool,
+    count: Option<usize>,
+}
+
+impl Command for XRangeCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let rev = args[0].eq_ignore_ascii_case(b"xrevrange");
+        let (start, end) = if rev { (&args[3], &args[2]) } else { (&args[2], &args[3]) };
+        let range = match (parse_range_bound(start, true)?, parse_range_bound(end, false)?) {
+            (Some(start), Some(end)) if start <= end => Some((start, end)),
+            _ => None,
+        };
+        let count = match &args[4..] {
+            [] => None,
+            [option, count] if option.eq_ignore_ascii_case(b"COUNT") => Some(parse_int::<i64>(count)?.max(0) as usize),
+            _ => return Err(syntax_error()),
+        };
+        Ok(XRangeCommand { key: args[1].clone(), range, rev, count })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let stream = match get_stream(&mut db, &self.key) {
+            Ok(Some(stream)) => stream,
+            Ok(None) => return Reply::Array(Vec::new()),
+            Err(reply) => return reply,
+        };
+        let Some((start, end)) = self.range else {
+            return Reply::Array(Vec::new());
+        };
+        let count = self.count.unwrap_or(usize::MAX);
+        let entries = stream.range(start..=end);
+        let entries: Vec<Reply> = if self.rev {
+            entries.rev().take(count).map(|(id, fields)| entry_reply(*id, fields)).collect()
+        } else {
+            entries.take(count).map(|(id, fields)| entry_reply(*id, fields)).collect()
+        };
+        Reply::Array(entries)
+    }
+}
+
+/// `XLEN key`
+pub struct XLenCommand {
+    key: Vec<u8>,
+}
+
+impl Command for XLenCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(XLenCommand { key: args[1].clone() })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        match get_stream(&mut db, &self.key) {
+            Ok(stream) => Reply::Integer(stream.map_or(0, |stream| stream.len() as i64)),
+            Err(reply) => reply,
+        }
+    }
+}
+
+/// `XTRIM key MAXLEN|MINID [=|~] threshold [LIMIT count]`
+pub struct XTrimCommand {
+    key: Vec<u8>,
+    trim: Trim,
+}
+
+impl Command for XTrimCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        if !args[2].eq_ignore_ascii_case(b"MAXLEN") && !args[2].eq_ignore_ascii_case(b"MINID") {
+            return Err(syntax_error());
+        }
+        let (trim, consumed) = Trim::parse(&args[2..])?;
+        if 2 + consumed != args.len() {
+            return Err(syntax_error());
+        }
+        Ok(XTrimCommand { key: args[1].clone(), trim })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        match get_stream_mut(&mut db, &self.key) {
+            Ok(Some(stream)) => Reply::Integer(self.trim.apply(stream) as i64),
+            Ok(None) => Reply::Integer(0),
+            Err(reply) => reply,
+        }
+    }
+}
+
+/// `XDEL key id [id ...]`
+pub struct XDelCommand {
+    key: Vec<u8>,
+    ids: Vec<StreamId>,
+}
+
+impl Command for XDelCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let ids = args[2..].iter().map(|id| parse_id(id, 0)).collect::<Result<_, _>>()?;
+        Ok(XDelCommand { key: args[1].clone(), ids })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        match get_stream_mut(&mut db, &self.key) {
+            Ok(Some(stream)) => Reply::Integer(self.ids.iter().filter(|id| stream.remove(**id)).count() as i64),
+            Ok(None) => Reply::Integer(0),
+            Err(reply) => reply,
+        }
+    }
+}
+
+/// Where XREAD starts reading a stream.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ReadFrom {
+    /// Entries after this ID.
+    After(StreamId),
+    /// `$`: only entries added from now on.
+    New,
+    /// `+`: the last entry.
+    LastEntry,
+}
+
+/// Parses the `[BLOCK milliseconds]` option shared by XREAD and XREADGROUP.
+pub fn parse_block(arg: &[u8]) -> Result<Option<Duration>, Reply> {
+    match std::str::from_utf8(arg).ok().and_then(|s| s.parse::<i64>().ok()) {
+        Some(ms) if ms < 0 => Err(Reply::error("ERR timeout is negative")),
+        Some(0) => Ok(None),
+        Some(ms) => Ok(Some(Duration::from_millis(ms as u64))),
+        None => Err(Reply::error("ERR timeout is not an integer or out of range")),
+    }
+}
+
+/// The options and `STREAMS` list shared by XREAD and XREADGROUP.
+struct ReadArgs {
+    count: Option<usize>,
+    block: Option<Option<Duration>>,
+    noack: bool,
+    keys: Vec<Vec<u8>>,
+    ids: Vec<Vec<u8>>,
+}
+
+impl ReadArgs {
+    /// Parses the options from `args[start..]`; NOACK is only accepted for groups.
+    fn parse(args: &[Vec<u8>], start: usize, group: bool) -> Result<Self, Reply> {
+        let mut count = None;
+        let mut block = None;
+        let mut noack = false;
+        let mut i = start;
+        while i < args.len() {
+            match args[i].to_ascii_uppercase().as_slice() {
+                b"COUNT" if i + 1 < args.len() => {
+                    count = Some(parse_int::<i64>(&args[i + 1])?.max(0) as usize).filter(|count| *count > 0);
+                    i += 2;
+                }
+                b"BLOCK" if i + 1 < args.len() => {
+                    block = Some(parse_block(&args[i + 1])?);
+                    i += 2;
+                }
+                b"NOACK" if group => {
+                    noack = true;
+                    i += 1;
+                }
+                b"STREAMS" => break,
+                _ => return Err(syntax_error()),
+            }
+        }
+
+        let streams = args.get(i + 1..).unwrap_or_default();
+        if streams.is_empty() || !streams.len().is_multiple_of(2) {
+            let name = if group { "xreadgroup" } else { "xread" };
+            return Err(Reply::error(format!(
+                "ERR Unbalanced '{name}' list of streams: for each stream key an ID or '$' must be specified."
+            )));
+        }
+        let (keys, ids) = streams.split_at(streams.len() / 2);
+        Ok(ReadArgs { count, block, noack, keys: keys.to_vec(), ids: ids.to_vec() })
+    }
+}
+
+/// `XREAD [COUNT count] [BLOCK milliseconds] STREAMS key [key ...] id [id ...]`
+pub struct XReadCommand {
+    keys: Vec<Vec<u8>>,
+    from: Vec<ReadFrom>,
+    count: Option<usize>,
+    block: Option<Option<Duration>>,
+}
+
+impl XReadCommand {
+    /// Entries past each stream's read position; `None` if there are none.
+    fn read(&self, db: &mut Keyspace) -> Result<Option<Reply>, Reply> {
+        let mut results = Vec::new();
+        for (key, from) in self.keys.iter().zip(&self.from) {
+            let Some(stream) = get_stream(db, key)? else {
+                continue;
+            };
+            let start = match from {
+                ReadFrom::After(id) => id.next(),
+                ReadFrom::New => stream.last_id.next(),
+                ReadFrom::LastEntry => stream.last_entry_id(),
+            };
+            let Some(start) = start else {
+                continue;
+            };
+            let take = if *from == ReadFrom::LastEntry { 1 } else { self.count.unwrap_or(usize::MAX) };
+            let entries: Vec<Reply> =
+                stream.range(start..=StreamId::MAX).take(take).map(|(id, fields)| entry_reply(*id, fields)).collect();
+            if !entries.is_empty() {
+                results.push((Reply::Bulk(key.clone()), Reply::Array(entries)));
+            }
+        }
+        Ok((!results.is_empty()).then_some(Reply::NestedMap(results)))
+    }
+}
+
+impl BlockingCommand for XReadCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let read = ReadArgs::parse(args, 1, false)?;
+        let from = read
+            .ids
+            .iter()
+            .map(|id| match id.as_slice() {
+                b"$" => Ok(ReadFrom::New),
+                b"+" => Ok(ReadFrom::LastEntry),
+                id => parse_id(id, 0).map(ReadFrom::After),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(XReadCommand { keys: read.keys, from, count: read.count, block: read.block })
+    }
+
+    fn keys(&self) -> &[Vec<u8>] {
+        &self.keys
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.block.flatten()
+    }
+
+    fn blocks(&self) -> bool {
+        self.block.is_some()
+    }
+
+    fn try_now(&mut self, db: &mut Keyspace) -> Result<Option<Reply>, Reply> {
+        // `$` and `+` are relative to the stream as it is now; pin them so a
+        // parked client sees exactly the entries added after this point.
+        for (key, from) in self.keys.iter().zip(self.from.iter_mut()) {
+            let stream = get_stream(db, key)?;
+            match (*from, stream) {
+                (ReadFrom::LastEntry, Some(stream)) if stream.len() > 0 => return self.read(db),
+                (ReadFrom::New | ReadFrom::LastEntry, stream) => {
+                    *from = ReadFrom::After(stream.map_or(StreamId::MIN, |stream| stream.last_id));
+                }
+                (ReadFrom::After(_), _) => {}
+            }
+        }
+        self.read(db)
+    }
+
+    fn try_serve(&self, db: &mut Keyspace, _key: &[u8]) -> Result<Option<Reply>, Reply> {
+        self.read(db)
+    }
+}
+
+fn no_group(key: &[u8], group: &[u8]) -> Reply {
+    Reply::error(format!(
+        "NOGROUP No such key '{}' or consumer group '{}'",
+        String::from_utf8_lossy(key),
+        String::from_utf8_lossy(group)
+    ))
+}
+
+/// Returns the stream at `key` if it has the group `group`, or NOGROUP.
+fn get_group_stream<'a>(db: &'a mut Keyspace, key: &[u8], group: &[u8]) -> Result<&'a mut Stream, Reply> {
+    match get_stream_mut(db, key)? {
+        Some(stream) if stream.groups.contains_key(group) => Ok(stream),
+        _ => Err(no_group(key, group)),
+    }
+}
+
+fn group_mut<'a>(stream: &'a mut Stream, group: &[u8]) -> &'a mut ConsumerGroup {
+    stream.groups.get_mut(group).expect("group was looked up before")
+}
+
+/// Parses the `ENTRIESREAD` argument of XGROUP; `-1` means unknown.
+fn parse_entries_read(arg: &[u8]) -> Result<Option<u64>, Reply> {
+    match parse_int::<i64>(arg)? {
+        -1 => Ok(None),
+        read if read >= 0 => Ok(Some(read as u64)),
+        _ => Err(Reply::error("ERR value for ENTRIESREAD must be positive or -1")),
+    }
+}
+
+/// Parses `id|$ [MKSTREAM] [ENTRIESREAD entries-read]` of XGROUP CREATE and SETID.
+fn parse_group_position(args: &[Vec<u8>], create: bool) -> Result<(Option<StreamId>, bool, Option<u64>), Reply> {
+    let id = match args[0].as_slice() {
+        b"$" => None,
+        id => Some(parse_id(id, 0)?),
+    };
+    let mut mkstream = false;
+    let mut entries_read = None;
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].to_ascii_uppercase().as_slice() {
+            b"MKSTREAM" if create => {
+                mkstream = true;
+                i += 1;
+            }
+            b"ENTRIESREAD" if i + 1 < args.len() => {
+                entries_read = parse_entries_read(&args[i + 1])?;
+                i += 2;
+            }
+            _ => return Err(syntax_error()),
+        }
+    }
+    Ok((id, mkstream, entries_read))
+}
+
+/// `XGROUP CREATE|SETID|DESTROY|CREATECONSUMER|DELCONSUMER key group ...`
+pub enum XGroupCommand {
+    Create { key: Vec<u8>, group: Vec<u8>, id: Option<StreamId>, mkstream: bool, entries_read: Option<u64> },
+    SetId { key: Vec<u8>, group: Vec<u8>, id: Option<StreamId>, entries_read: Option<u64> },
+    Destroy { key: Vec<u8>, group: Vec<u8> },
+    CreateConsumer { key: Vec<u8>, group: Vec<u8>, consumer: Vec<u8> },
+    DelConsumer { key: Vec<u8>, group: Vec<u8>, consumer: Vec<u8> },
+}
+
+impl Command for XGroupCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let sub = String::from_utf8_lossy(&args[1]).to_uppercase();
+        let (key, group) = match (args.get(2), args.get(3)) {
+            (Some(key), Some(group)) => (key.clone(), group.clone()),
+            _ => return Err(Reply::error(format!("ERR wrong number of arguments for 'xgroup|{}' command", sub.to_lowercase()))),
+        };
+        match sub.as_str() {
+            "CREATE" if args.len() >= 5 => {
+                let (id, mkstream, entries_read) = parse_group_position(&args[4..], true)?;
+                Ok(XGroupCommand::Create { key, group, id, mkstream, entries_read })
+            }
+            "SETID" if args.len() >= 5 => {
+                let (id, _, entries_read) = parse_group_position(&args[4..], false)?;
+                Ok(XGroupCommand::SetId { key, group, id, entries_read })
+            }
+            "DESTROY" if args.len() == 4 => Ok(XGroupCommand::Destroy { key, group }),
+            "CREATECONSUMER" if args.len() == 5 => {
+                Ok(XGroupCommand::CreateConsumer { key, group, consumer: args[4].clone() })
+            }
+            "DELCONSUMER" if args.len() == 5 => Ok(XGroupCommand::DelConsumer { key, group, consumer: args[4].clone() }),
+            "CREATE" | "SETID" | "DESTROY" | "CREATECONSUMER" | "DELCONSUMER" => {
+                Err(Reply::error(format!("ERR wrong number of arguments for 'xgroup|{}' command", sub.to_lowercase())))
+            }
+            _ => Err(Reply::error(format!(
+                "ERR unknown subcommand '{}'. Try XGROUP HELP.",
+                String::from_utf8_lossy(&args[1])
+            ))),
+        }
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let (key, group) = match self {
+            XGroupCommand::Create { key, group, .. }
+            | XGroupCommand::SetId { key, group, .. }
+            | XGroupCommand::Destroy { key, group }
+            | XGroupCommand::CreateConsumer { key, group, .. }
+            | XGroupCommand::DelConsumer { key, group, .. } => (key, group),
+        };
+        if let XGroupCommand::Create { mkstream: true, .. } = self {
+            match get_stream(&mut db, key) {
+                Ok(Some(_)) => {}
+                Ok(None) => {
+                    db.insert(key.clone(), Entry::new(Value::Stream(Stream::new())));
+                }
+                Err(reply) => return reply,
+            }
+        }
+        let stream = match get_stream_mut(&mut db, key) {
+            Ok(Some(stream)) => stream,
+            Ok(None) => {
+                return Reply::error(
+                    "ERR The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically.",
+                )
+            }
+            Err(reply) => return reply,
+        };
+
+        if let XGroupCommand::Create { id, entries_read, .. } = self {
+            if stream.groups.contains_key(group) {
+                return Reply::error("BUSYGROUP Consumer Group name already exists");
+            }
+            let id = id.unwrap_or(stream.last_id);
+            stream.groups.insert(group.clone(), ConsumerGroup::new(id, *entries_read));
+            return Reply::ok();
+        }
+        if let XGroupCommand::Destroy { .. } = self {
+            let destroyed = stream.groups.remove(group).is_some();
+            if destroyed {
+                // Wakes XREADGROUP clients parked on the group, which then get NOGROUP.
+                db.signal_ready(key);
+            }
+            return Reply::Integer(destroyed as i64);
+        }
+        let last_id = stream.last_id;
+        let Some(consumer_group) = stream.groups.get_mut(group.as_slice()) else {
+            return Reply::error(format!(
+                "NOGROUP No such consumer group '{}' for key name '{}'",
+                String::from_utf8_lossy(group),
+                String::from_utf8_lossy(key)
+            ));
+        };
+        match self {
+            XGroupCommand::SetId { id, entries_read, .. } => {
+                consumer_group.last_delivered = id.unwrap_or(last_id);
+                consumer_group.entries_read = *entries_read;
+                Reply::ok()
+            }
+            XGroupCommand::CreateConsumer { consumer, .. } => {
+                Reply::Integer(consumer_group.create_consumer(consumer, now_ms()) as i64)
+            }
+            XGroupCommand::DelConsumer { consumer, .. } => {
+                Reply::Integer(consumer_group.remove_consumer(consumer).unwrap_or(0) as i64)
+            }
+            XGroupCommand::Create { .. } | XGroupCommand::Destroy { .. } => unreachable!("handled above"),
+        }
+    }
+}
+
+/// `[id, [field, value, ...]]`, or `[id, nil]` for an entry deleted while pending.
+fn pending_entry_reply(id: StreamId, fields: Option<&Vec<Vec<u8>>>) -> Reply {
+    match fields {
+        Some(fields) => entry_reply(id, fields),
+        None => Reply::Array(vec![Reply::Bulk(id.to_bytes()), Reply::NullArray]),
+    }
+}
+
+/// Where XREADGROUP reads a stream from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum GroupReadFrom {
+    /// `>`: entries never delivered to the group.
+    New,
+    /// The consumer's own pending entries after this ID.
+    History(StreamId),
+}
+
+/// `XREADGROUP GROUP group consumer [COUNT count] [BLOCK milliseconds] [NOACK] STREAMS key [key ...] id [id ...]`
+pub struct XReadGroupCommand {
+    group: Vec<u8>,
+    consumer: Vec<u8>,
+    keys: Vec<Vec<u8>>,
+    from: Vec<GroupReadFrom>,
+    count: Option<usize>,
+    block: Option<Option<Duration>>,
+    noack: bool,
+}
+
+impl XReadGroupCommand {
+    /// Delivers new entries or replays the consumer's history for every
+    /// stream; `None` if there was nothing new to deliver.
+    fn read(&self, db: &mut Keyspace) -> Result<Option<Reply>, Reply> {
+        for key in &self.keys {
+            get_group_stream(db, key, &self.group)?;
+        }
+        let now = now_ms();
+        let count = self.count.unwrap_or(usize::MAX);
+        let mut results = Vec::new();
+        for (key, from) in self.keys.iter().zip(&self.from) {
+            let stream = get_group_stream(db, key, &self.group)?;
+            group_mut(stream, &self.group).consumer(&self.consumer, now);
+            let entries = match *from {
+                GroupReadFrom::New => {
+                    let start = group_mut(stream, &self.group).last_delivered.next();
+                    let delivered: Vec<(StreamId, Vec<Vec<u8>>)> = match start {
+                        Some(start) => stream.range(start..=StreamId::MAX).take(count).map(|(id, fields)| (*id, fields.clone())).collect(),
+                        None => Vec::new(),
+                    };
+                    for (id, _) in &delivered {
+                        stream.advance_group(&self.group, *id);
+                        if !self.noack {
+                            group_mut(stream, &self.group).assign(*id, &self.consumer, now, now, 1);
+                        }
+                    }
+                    if delivered.is_empty() {
+                        continue;
+                    }
+                    delivered.iter().map(|(id, fields)| entry_reply(*id, fields)).collect()
+                }
+                GroupReadFrom::History(after) => {
+                    let group = group_mut(stream, &self.group);
+                    let ids: Vec<StreamId> = group.consumers[&self.consumer]
+                        .pending
+                        .iter()
+                        .filter(|id| **id > after)
+                        .take(count)
+                        .copied()
+                        .collect();
+                    for id in &ids {
+                        let pending = group.pending.get_mut(id).expect("consumer and group PEL agree");
+                        pending.delivery_time = now;
+                        pending.delivery_count += 1;
+                    }
+                    ids.iter().map(|id| pending_entry_reply(*id, stream.get(*id))).collect()
+                }
+            };
+            results.push((Reply::Bulk(key.clone()), Reply::Array(entries)));
+        }
+        Ok((!results.is_empty()).then_some(Reply::NestedMap(results)))
+    }
+}
+
+impl BlockingCommand for XReadGroupCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        if !args[1].eq_ignore_ascii_case(b"GROUP") {
+            return Err(syntax_error());
+        }
+        let read = ReadArgs::parse(args, 4, true)?;
+        let from = read
+            .ids
+            .iter()
+            .map(|id| match id.as_slice() {
+                b">" => Ok(GroupReadFrom::New),
+                b"$" => Err(Reply::error(
+                    "ERR The $ ID is meaningless in the context of XREADGROUP: you want to read the history of this consumer by specifying a proper ID, or use the > ID to get new messages. The $ ID would just return an empty result set.",
+                )),
+                id => parse_id(id, 0).map(GroupReadFrom::History),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(XReadGroupCommand {
+            group: args[2].clone(),
+            consumer: args[3].clone(),
+            keys: read.keys,
+            from,
+            count: read.count,
+            block: read.block,
+            noack: read.noack,
+        })
+    }
+
+    fn keys(&self) -> &[Vec<u8>] {
+        &self.keys
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.block.flatten()
+    }
+
+    fn blocks(&self) -> bool {
+        self.block.is_some()
+    }
+
+    fn try_now(&mut self, db: &mut Keyspace) -> Result<Option<Reply>, Reply> {
+        self.read(db)
+    }
+
+    fn try_serve(&self, db: &mut Keyspace, _key: &[u8]) -> Result<Option<Reply>, Reply> {
+        self.read(db)
+    }
+}
+
+/// `XACK key group id [id ...]`
+pub struct XAckCommand {
+    key: Vec<u8>,
+    group: Vec<u8>,
+    ids: Vec<StreamId>,
+}
+
+impl Command for XAckCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let ids = args[3..].iter().map(|id| parse_id(id, 0)).collect::<Result<_, _>>()?;
+        Ok(XAckCommand { key: args[1].clone(), group: args[2].clone(), ids })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let stream = match get_stream_mut(&mut db, &self.key) {
+            Ok(stream) => stream,
+            Err(reply) => return reply,
+        };
+        match stream.and_then(|stream| stream.groups.get_mut(&self.group)) {
+            Some(group) => Reply::Integer(self.ids.iter().filter(|id| group.acknowledge(**id)).count() as i64),
+            None => Reply::Integer(0),
+        }
+    }
+}
+
+/// The extended form of XPENDING.
+struct PendingRange {
+    min_idle: u64,
+    range: Option<(StreamId, StreamId)>,
+    count: usize,
+    consumer: Option<Vec<u8>>,
+}
+
+/// `XPENDING key group [[IDLE min-idle-time] start end count [consumer]]`
+pub struct XPendingCommand {
+    key: Vec<u8>,
+    group: Vec<u8>,
+    extended: Option<PendingRange>,
+}
+
+impl Command for XPendingCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let (key, group) = (args[1].clone(), args[2].clone());
+        if args.len() == 3 {
+            return Ok(XPendingCommand { key, group, extended: None });
+        }
+        let mut i = 3;
+        let mut min_idle = 0;
+        if args[i].eq_ignore_ascii_case(b"IDLE") {
+            min_idle = parse_int::<i64>(args.get(i + 1).ok_or_else(syntax_error)?)?.max(0) as u64;
+            i += 2;
+        }
+        let rest = &args[i..];
+        if rest.len() != 3 && rest.len() != 4 {
+            return Err(syntax_error());
+        }
+        let range = match (parse_range_bound(&rest[0], true)?, parse_range_bound(&rest[1], false)?) {
+            (Some(start), Some(end)) if start <= end => Some((start, end)),
+            _ => None,
+        };
+        let count = parse_int::<i64>(&rest[2])?.max(0) as usize;
+        let consumer = rest.get(3).cloned();
+        Ok(XPendingCommand { key, group, extended: Some(PendingRange { min_idle, range, count, consumer }) })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let stream = match get_group_stream(&mut db, &self.key, &self.group) {
+            Ok(stream) => stream,
+            Err(reply) => return reply,
+        };
+        let group = group_mut(stream, &self.group);
+
+        let Some(extended) = &self.extended else {
+            let (Some(first), Some(last)) = (group.pending.keys().next(), group.pending.keys().next_back()) else {
+                return Reply::Array(vec![Reply::Integer(0), Reply::Null, Reply::Null, Reply::NullArray]);
+            };
+            let consumers = group
+                .consumers
+                .iter()
+                .filter(|(_, consumer)| !consumer.pending.is_empty())
+                .map(|(name, consumer)| {
+                    Reply::Array(vec![Reply::Bulk(name.clone()), Reply::Bulk(consumer.pending.len().to_string().into_bytes())])
+                })
+                .collect();
+            return Reply::Array(vec![
+                Reply::Integer(group.pending.len() as i64),
+                Reply::Bulk(first.to_bytes()),
+                Reply::Bulk(last.to_bytes()),
+                Reply::Array(consumers),
+            ]);
+        };
+
+        let Some((start, end)) = extended.range else {
+            return Reply::Array(Vec::new());
+        };
+        let now = now_ms();
+        let entries = group
+            .pending
+            .range(start..=end)
+            .filter(|(_, pending)| extended.consumer.as_ref().is_none_or(|consumer| pending.consumer == *consumer))
+            .filter(|(_, pending)| now.saturating_sub(pending.delivery_time) >= extended.min_idle)
+            .take(extended.count)
+            .map(|(id, pending)| {
+                Reply::Array(vec![
+                    Reply::Bulk(id.to_bytes()),
+                    Reply::Bulk(pending.consumer.clone()),
+                    Reply::Integer(now.saturating_sub(pending.delivery_time) as i64),
+                    Reply::Integer(pending.delivery_count as i64),
+                ])
+            })
+            .collect();
+        Reply::Array(entries)
+    }
+}
+
+fn parse_min_idle(arg: &[u8]) -> Result<u64, Reply> {
+    match std::str::from_utf8(arg).ok().and_then(|s| s.parse::<i64>().ok()) {
+        Some(min_idle) => Ok(min_idle.max(0) as u64),
+        None => Err(Reply::error("ERR Invalid min-idle-time argument for XCLAIM")),
+    }
+}
+
+/// `XCLAIM key group consumer min-idle-time id [id ...] [IDLE ms] [TIME unix-time-milliseconds]
+/// [RETRYCOUNT count] [FORCE] [JUSTID] [LASTID lastid]`
+pub struct XClaimCommand {
+    key: Vec<u8>,
+    group: Vec<u8>,
+    consumer: Vec<u8>,
+    min_idle: u64,
+    ids: Vec<StreamId>,
+    idle: Option<u64>,
+    time: Option<u64>,
+    retry_count: Option<u64>,
+    force: bool,
+    just_id: bool,
+    last_id: Option<StreamId>,
+}
+
+impl Command for XClaimCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let min_idle = parse_min_idle(&args[4])?;
+        let mut i = 5;
+        let mut ids = Vec::new();
+        while let Some(id) = args.get(i).and_then(|arg| StreamId::parse(arg, 0)) {
+            ids.push(id);
+            i += 1;
+        }
+        if ids.is_empty() {
+            return Err(invalid_id());
+        }
+
+        let mut command = XClaimCommand {
+            key: args[1].clone(),
+            group: args[2].clone(),
+            consumer: args[3].clone(),
+            min_idle,
+            ids,
+            idle: None,
+            time: None,
+            retry_count: None,
+            force: false,
+            just_id: false,
+            last_id: None,
+        };
+        while i < args.len() {
+            let option = args[i].to_ascii_uppercase();
+            match (option.as_slice(), args.get(i + 1)) {
+                (b"FORCE", _) => command.force = true,
+                (b"JUSTID", _) => command.just_id = true,
+                (b"IDLE", Some(value)) => {
+                    command.idle = Some(parse_int::<i64>(value)?.max(0) as u64);
+                    i += 1;
+                }
+                (b"TIME", Some(value)) => {
+                    command.time = Some(parse_int::<i64>(value)?.max(0) as u64);
+                    i += 1;
+                }
+                (b"RETRYCOUNT", Some(value)) => {
+                    command.retry_count = Some(parse_int::<i64>(value)?.max(0) as u64);
+                    i += 1;
+                }
+                (b"LASTID", Some(value)) => {
+                    command.last_id = Some(parse_id(value, 0)?);
+                    i += 1;
+                }
+                _ => return Err(Reply::error(format!("ERR Unrecognized XCLAIM option '{}'", String::from_utf8_lossy(&args[i])))),
+            }
+            i += 1;
+        }
+        Ok(command)
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let stream = match get_group_stream(&mut db, &self.key, &self.group) {
+            Ok(stream) => stream,
+            Err(reply) => return reply,
+        };
+        let now = now_ms();
+        let delivery_time = match (self.time, self.idle) {
+            (Some(time), _) => time,
+            (None, Some(idle)) => now.saturating_sub(idle),
+            (None, None) => now,
+        };
+        let existing: Vec<Option<Vec<Vec<u8>>>> = self.ids.iter().map(|id| stream.get(*id).cloned()).collect();
+        let group = group_mut(stream, &self.group);
+        if let Some(last_id) = self.last_id.filter(|last_id| *last_id > group.last_delivered) {
+            group.last_delivered = last_id;
+        }
+        group.consumer(&self.consumer, now);
+
+        let mut claimed = Vec::new();
+        for (id, fields) in self.ids.iter().zip(existing) {
+            let Some(fields) = fields else {
+                // Entries deleted from the stream can't be claimed any more.
+                group.acknowledge(*id);
+                continue;
+            };
+            let delivery_count = match group.pending.get(id) {
+                Some(pending) if now.saturating_sub(pending.delivery_time) < self.min_idle => continue,
+                Some(pending) => pending.delivery_count,
+                None if self.force => 0,
+                None => continue,
+            };
+            let delivery_count = match self.retry_count {
+                Some(retry_count) => retry_count,
+                None if self.just_id => delivery_count,
+                None => delivery_count + 1,
+            };
+            group.assign(*id, &self.consumer, now, delivery_time, delivery_count);
+            claimed.push(if self.just_id { Reply::Bulk(id.to_bytes()) } else { entry_reply(*id, &fields) });
+        }
+        Reply::Array(claimed)
+    }
+}
+
+/// `XAUTOCLAIM key group consumer min-idle-time start [COUNT count] [JUSTID]`
+pub struct XAutoClaimCommand {
+    key: Vec<u8>,
+    group: Vec<u8>,
+    consumer: Vec<u8>,
+    min_idle: u64,
+    start: StreamId,
+    count: usize,
+    just_id: bool,
+}
+
+impl Command for XAutoClaimCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let min_idle = parse_min_idle(&args[4])?;
+        let start = match parse_range_bound(&args[5], true)? {
+            Some(start) => start,
+            None => return Err(invalid_id()),
+        };
+        let mut count = 100;
+        let mut just_id = false;
+        let mut i = 6;
+        while i < args.len() {
+            match args[i].to_ascii_uppercase().as_slice() {
+                b"COUNT" if i + 1 < args.len() => {
+                    count = match parse_int::<i64>(&args[i + 1])? {
+                        count if (1..=i64::MAX / 10).contains(&count) => count as usize,
+                        _ => return Err(Reply::error("ERR COUNT must be > 0")),
+                    };
+                    i += 2;
+                }
+                b"JUSTID" => {
+                    just_id = true;
+                    i += 1;
+                }
+                _ => return Err(syntax_error()),
+            }
+        }
+        Ok(XAutoClaimCommand { key: args[1].clone(), group: args[2].clone(), consumer: args[3].clone(), min_idle, start, count, just_id })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let stream = match get_group_stream(&mut db, &self.key, &self.group) {
+            Ok(stream) => stream,
+            Err(reply) => return reply,
+        };
+        let now = now_ms();
+
+        // Scan at most ten times COUNT pending entries, so a PEL full of
+        // fresh deliveries can't make a single call arbitrarily slow.
+        let mut attempts = self.count * 10;
+        let mut cursor = StreamId::MIN;
+        let mut candidates = Vec::new();
+        for (id, pending) in stream.groups[&self.group].pending.range(self.start..) {
+            if attempts == 0 || candidates.len() == self.count {
+                cursor = *id;
+                break;
+            }
+            attempts -= 1;
+            if now.saturating_sub(pending.delivery_time) >= self.min_idle {
+                candidates.push(*id);
+            }
+        }
+
+        let mut claimed = Vec::new();
+        let mut deleted = Vec::new();
+        let existing: Vec<Option<Vec<Vec<u8>>>> = candidates.iter().map(|id| stream.get(*id).cloned()).collect();
+        let group = group_mut(stream, &self.group);
+        group.consumer(&self.consumer, now);
+        for (id, fields) in candidates.into_iter().zip(existing) {
+            let Some(fields) = fields else {
+                group.acknowledge(id);
+                deleted.push(Reply::Bulk(id.to_bytes()));
+                continue;
+            };
+            let delivery_count = group.pending[&id].delivery_count + u64::from(!self.just_id);
+            group.assign(id, &self.consumer, now, now, delivery_count);
+            claimed.push(if self.just_id { Reply::Bulk(id.to_bytes()) } else { entry_reply(id, &fields) });
+        }
+        Reply::Array(vec![Reply::Bulk(cursor.to_bytes()), Reply::Array(claimed), Reply::Array(deleted)])
+    }
+}
+
+fn bulk_id(id: StreamId) -> Reply {
+    Reply::Bulk(id.to_bytes())
+}
+
+fn field(name: &str, value: Reply) -> (Reply, Reply) {
+    (Reply::Bulk(name.as_bytes().to_vec()), value)
+}
+
+/// `XINFO STREAM key [FULL [COUNT count]]`, `XINFO GROUPS key` and `XINFO CONSUMERS key group`
+pub enum XInfoCommand {
+    Stream { key: Vec<u8>, full: Option<usize> },
+    Groups { key: Vec<u8> },
+    Consumers { key: Vec<u8>, group: Vec<u8> },
+}
+
+impl Command for XInfoCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let sub = String::from_utf8_lossy(&args[1]).to_uppercase();
+        let wrong_arity = || Reply::error(format!("ERR wrong number of arguments for 'xinfo|{}' command", sub.to_lowercase()));
+        match sub.as_str() {
+            "STREAM" => {
+                let key = args.get(2).ok_or_else(wrong_arity)?.clone();
+                let full = match &args[3..] {
+                    [] => None,
+                    [full] if full.eq_ignore_ascii_case(b"FULL") => Some(10),
+                    [full, option, count] if full.eq_ignore_ascii_case(b"FULL") && option.eq_ignore_ascii_case(b"COUNT") => {
+                        Some(parse_int::<i64>(count)?.max(0) as usize)
+                    }
+                    _ => return Err(syntax_error()),
+                };
+                Ok(XInfoCommand::Stream { key, full })
+            }
+            "GROUPS" if args.len() == 3 => Ok(XInfoCommand::Groups { key: args[2].clone() }),
+            "CONSUMERS" if args.len() == 4 => Ok(XInfoCommand::Consumers { key: args[2].clone(), group: args[3].clone() }),
+            "GROUPS" | "CONSUMERS" => Err(wrong_arity()),
+            _ => Err(Reply::error(format!(
+                "ERR unknown subcommand '{}'. Try XINFO HELP.",
+                String::from_utf8_lossy(&args[1])
+            ))),
+        }
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let key = match self {
+            XInfoCommand::Stream { key, .. } | XInfoCommand::Groups { key } | XInfoCommand::Consumers { key, .. } => key,
+        };
+        let stream = match get_stream(&mut db, key) {
+            Ok(Some(stream)) => stream,
+            Ok(None) => return Reply::error("ERR no such key"),
+            Err(reply) => return reply,
+        };
+        let now = now_ms();
+        match self {
+            XInfoCommand::Stream { full: None, .. } => stream_info(stream),
+            XInfoCommand::Stream { full: Some(count), .. } => stream_info_full(stream, *count),
+            XInfoCommand::Groups { .. } => Reply::Array(
+                stream
+                    .groups
+                    .iter()
+                    .map(|(name, group)| {
+                        Reply::Map(vec![
+                            field("name", Reply::Bulk(name.clone())),
+                            field("consumers", Reply::Integer(group.consumers.len() as i64)),
+                            field("pending", Reply::Integer(group.pending.len() as i64)),
+                            field("last-delivered-id", bulk_id(group.last_delivered)),
+                            field("entries-read", group.entries_read.map_or(Reply::Null, |read| Reply::Integer(read as i64))),
+                            field("lag", stream.lag(group).map_or(Reply::Null, |lag| Reply::Integer(lag as i64))),
+                        ])
+                    })
+                    .collect(),
+            ),
+            XInfoCommand::Consumers { group, .. } => {
+                let Some(group) = stream.groups.get(group) else {
+                    return no_group(key, group);
+                };
+                Reply::Array(
+                    group
+                        .consumers
+                        .iter()
+                        .map(|(name, consumer)| {
+                            let inactive = consumer.active_time.map_or(-1, |active| now.saturating_sub(active) as i64);
+                            Reply::Map(vec![
+                                field("name", Reply::Bulk(name.clone())),
+                                field("pending", Reply::Integer(consumer.pending.len() as i64)),
+                                field("idle", Reply::Integer(now.saturating_sub(consumer.seen_time) as i64)),
+                                field("inactive", Reply::Integer(inactive)),
+                            ])
+                        })
+                        .collect(),
+                )
+            }
+        }
+    }
+}
+
+/// The fields XINFO STREAM reports in both its short and FULL forms.
+fn stream_summary(stream: &Stream) -> Vec<(Reply, Reply)> {
+    let nodes = stream.len().div_ceil(STREAM_NODE_ENTRIES) as i64;
+    vec![
+        field("length", Reply::Integer(stream.len() as i64)),
+        field("radix-tree-keys", Reply::Integer(nodes)),
+        field("radix-tree-nodes", Reply::Integer(nodes + 1)),
+        field("last-generated-id", bulk_id(stream.last_id)),
+        field("max-deleted-entry-id", bulk_id(stream.max_deleted_id)),
+        field("entries-added", Reply::Integer(stream.entries_added as i64)),
+        field("recorded-first-entry-id", bulk_id(stream.first_id().unwrap_or(StreamId::MIN))),
+    ]
+}
+
+fn stream_info(stream: &Stream) -> Reply {
+    let entry = |id: Option<StreamId>| id.and_then(|id| stream.get(id).map(|fields| entry_reply(id, fields)));
+    let mut info = stream_summary(stream);
+    info.push(field("groups", Reply::Integer(stream.groups.len() as i64)));
+    info.push(field("first-entry", entry(stream.first_id()).unwrap_or(Reply::Null)));
+    info.push(field("last-entry", entry(stream.last_entry_id()).unwrap_or(Reply::Null)));
+    Reply::Map(info)
+}
+
+/// XINFO STREAM FULL; `count` limits entries and PELs, 0 means all.
+fn stream_info_full(stream: &Stream, count: usize) -> Reply {
+    let count = if count == 0 { usize::MAX } else { count };
+    let mut info = stream_summary(stream);
+    info.push(field(
+        "entries",
+        Reply::Array(stream.range(StreamId::MIN..=StreamId::MAX).take(count).map(|(id, fields)| entry_reply(*id, fields)).collect()),
+    ));
+    let groups = stream
+        .groups
+        .iter()
+        .map(|(name, group)| {
+            let pending = group
+                .pending
+                .iter()
+                .take(count)
+                .map(|(id, pending)| {
+                    Reply::Array(vec![
+                        bulk_id(*id),
+                        Reply::Bulk(pending.consumer.clone()),
+                        Reply::Integer(pending.delivery_time as i64),
+                        Reply::Integer(pending.delivery_count as i64),
+                    ])
+                })
+                .collect();
+            let consumers = group
+                .consumers
+                .iter()
+                .map(|(name, consumer)| {
+                    let pending = consumer
+                        .pending
+                        .iter()
+                        .take(count)
+                        .map(|id| {
+                            let entry = &group.pending[id];
+                            Reply::Array(vec![bulk_id(*id), Reply::Integer(entry.delivery_time as i64), Reply::Integer(entry.delivery_count as i64)])
+                        })
+                        .collect();
+                    Reply::Map(vec![
+                        field("name", Reply::Bulk(name.clone())),
+                        field("seen-time", Reply::Integer(consumer.seen_time as i64)),
+                        field("active-time", Reply::Integer(consumer.active_time.map_or(-1, |active| active as i64))),
+                        field("pel-count", Reply::Integer(consumer.pending.len() as i64)),
+                        field("pending", Reply::Array(pending)),
+                    ])
+                })
+                .collect();
+            Reply::Map(vec![
+                field("name", Reply::Bulk(name.clone())),
+                field("last-delivered-id", bulk_id(group.last_delivered)),
+                field("entries-read", group.entries_read.map_or(Reply::Null, |read| Reply::Integer(read as i64))),
+                field("lag", stream.lag(group).map_or(Reply::Null, |lag| Reply::Integer(lag as i64))),
+                field("pel-count", Reply::Integer(group.pending.len() as i64)),
+                field("pending", Reply::Array(pending)),
+                field("consumers", Reply::Array(consumers)),
+            ])
+        })
+        .collect();
+    info.push(field("groups", Reply::Array(groups)));
+    Reply::Map(info)
+}
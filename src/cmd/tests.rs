@@ -4,194 +4,1819 @@ use super::incr::*;
 use super::decr::*;
 use super::ttl::*;
 use super::expire::*;
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use super::list::*;
+use super::hash::*;
+use super::sets::*;
+use super::zset::*;
+use super::stream::*;
+use super::json::*;
+use super::keyspace::*;
+use super::exists::*;
+use super::search::*;
+use super::scan::{self, glob_match, ScanOptions};
+use super::{lookup, run_blocking, BlockingCommand, Command, Flag, Outcome, Parked};
+use crate::db::{Db, Entry, Set, Value};
+use crate::reply::Reply;
+use std::collections::VecDeque;
 use std::time::{Duration, Instant};
 
-type Db = Arc<Mutex<HashMap<String, DbValue>>>;
-type DbValue = (String, Option<Instant>);
+fn new_db() -> Db {
+    Db::new()
+}
+
+/// Parses and executes a command given as plain strings.
+fn run<C: Command>(db: &Db, args: &[&str]) -> Reply {
+    let args: Vec<Vec<u8>> = args.iter().map(|arg| arg.as_bytes().to_vec()).collect();
+    match C::parse(&args) {
+        Ok(command) => command.execute(db),
+        Err(reply) => reply,
+    }
+}
+
+/// Runs a blocking command and returns the parked client, if it blocked.
+fn block<C: BlockingCommand>(db: &Db, args: &[&str]) -> Result<Parked, Reply> {
+    let args: Vec<Vec<u8>> = args.iter().map(|arg| arg.as_bytes().to_vec()).collect();
+    match run_blocking::<C>(&args, db) {
+        Outcome::Blocked(parked) => Ok(parked),
+        Outcome::Reply(reply) => Err(reply),
+    }
+}
+
+fn bulks(items: &[&str]) -> Reply {
+    Reply::Array(items.iter().map(|item| Reply::Bulk(item.as_bytes().to_vec())).collect())
+}
 
 // Tests für den GET-Befehl
 #[test]
 fn test_get_existing_key() {
-    let db: Db = Arc::new(Mutex::new(HashMap::new()));
-    db.lock().unwrap().insert("key".to_string(), ("value".to_string(), None));
+    let db = Db::new();
+    db.lock().unwrap().insert(b"key".to_vec(), Entry::with_expiry(Value::String(b"value".to_vec()), None));
     
-    let get_cmd = GetCommand::new("key");
+    let get_cmd = GetCommand::new(b"key");
     let result = get_cmd.execute(&db);
     
-    assert_eq!(result, "$5\r\nvalue\r\n");
+    assert_eq!(result, Reply::Bulk(b"value".to_vec()));
 }
 
 #[test]
 fn test_get_non_existing_key() {
-    let db: Db = Arc::new(Mutex::new(HashMap::new()));
+    let db = Db::new();
     
-    let get_cmd = GetCommand::new("missing_key");
+    let get_cmd = GetCommand::new(b"missing_key");
     let result = get_cmd.execute(&db);
     
-    assert_eq!(result, "$-1\r\n");
+    assert_eq!(result, Reply::Null);
 }
 
 #[test]
 fn test_get_expired_key() {
-    let db: Db = Arc::new(Mutex::new(HashMap::new()));
+    let db = Db::new();
     let past_time = Instant::now() - Duration::from_secs(10);
-    db.lock().unwrap().insert("key".to_string(), ("value".to_string(), Some(past_time)));
+    db.lock().unwrap().insert(b"key".to_vec(), Entry::with_expiry(Value::String(b"value".to_vec()), Some(past_time)));
     
-    let get_cmd = GetCommand::new("key");
+    let get_cmd = GetCommand::new(b"key");
     let result = get_cmd.execute(&db);
     
-    assert_eq!(result, "$-1\r\n");
-    assert!(db.lock().unwrap().get("key").is_none());
+    assert_eq!(result, Reply::Null);
+    assert!(db.lock().unwrap().get(b"key".as_slice()).is_none());
 }
 
 #[test]
 fn test_get_key_with_future_expiration() {
-    let db: Db = Arc::new(Mutex::new(HashMap::new()));
+    let db = Db::new();
     let future_time = Instant::now() + Duration::from_secs(10);
-    db.lock().unwrap().insert("key".to_string(), ("value".to_string(), Some(future_time)));
+    db.lock().unwrap().insert(b"key".to_vec(), Entry::with_expiry(Value::String(b"value".to_vec()), Some(future_time)));
     
-    let get_cmd = GetCommand::new("key");
+    let get_cmd = GetCommand::new(b"key");
     let result = get_cmd.execute(&db);
     
-    assert_eq!(result, "$5\r\nvalue\r\n");
+    assert_eq!(result, Reply::Bulk(b"value".to_vec()));
+}
+
+#[test]
+fn test_get_wrong_type() {
+    let db = Db::new();
+    db.lock().unwrap().insert(b"list".to_vec(), Entry::new(Value::List(VecDeque::from([b"a".to_vec()]))));
+
+    assert_eq!(GetCommand::new(b"list").execute(&db), Reply::wrong_type());
+    assert_eq!(IncrCommand::new(b"list").execute(&db), Reply::wrong_type());
 }
 
 // Tests für den SET-Befehl
 #[test]
 fn test_set_command() {
-    let db: Db = Arc::new(Mutex::new(HashMap::new()));
-    let set_cmd = SetCommand::new("key", "value", None, None);
+    let db = Db::new();
+    let set_cmd = SetCommand::new(b"key", b"value", None, None);
     let result = set_cmd.execute(&db);
     
-    assert_eq!(result, "+OK\r\n");
-    assert_eq!(db.lock().unwrap().get("key").unwrap().0, "value");
+    assert_eq!(result, Reply::ok());
+    assert_eq!(db.lock().unwrap().get(b"key".as_slice()).unwrap().value, Value::String(b"value".to_vec()));
 }
 
 #[test]
 fn test_set_with_expiration() {
-    let db: Db = Arc::new(Mutex::new(HashMap::new()));
+    let db = Db::new();
     let future_time = Instant::now() + Duration::from_secs(10);
     let future_time_ms = future_time.duration_since(Instant::now()).as_millis() as u64;
-    let set_cmd = SetCommand::new("key", "value", Some(future_time_ms), None);
+    let set_cmd = SetCommand::new(b"key", b"value", Some(future_time_ms), None);
     let result = set_cmd.execute(&db);
     
-    assert_eq!(result, "+OK\r\n");
-    let binding = db.lock().unwrap();
-    let (value, expire_time) = binding.get("key").unwrap();
-    assert_eq!(value, "value");
-    assert!(expire_time.is_some());
+    assert_eq!(result, Reply::ok());
+    let mut binding = db.lock().unwrap();
+    let entry = binding.get(b"key".as_slice()).unwrap();
+    assert_eq!(entry.value, Value::String(b"value".to_vec()));
+    assert!(entry.expires_at.is_some());
 }
 
 // Tests für den INCR-Befehl
 #[test]
 fn test_incr_command() {
-    let db: Db = Arc::new(Mutex::new(HashMap::new()));
-    db.lock().unwrap().insert("counter".to_string(), ("1".to_string(), None));
+    let db = Db::new();
+    db.lock().unwrap().insert(b"counter".to_vec(), Entry::with_expiry(Value::String(b"1".to_vec()), None));
     
-    let incr_cmd = IncrCommand::new("counter");
+    let incr_cmd = IncrCommand::new(b"counter");
     let result = incr_cmd.execute(&db);
     
-    assert_eq!(result, ":2\r\n");
-    assert_eq!(db.lock().unwrap().get("counter").unwrap().0, "2");
+    assert_eq!(result, Reply::Integer(2));
+    assert_eq!(db.lock().unwrap().get(b"counter".as_slice()).unwrap().value, Value::String(b"2".to_vec()));
 }
 
 #[test]
 fn test_incr_non_existing_key() {
-    let db: Db = Arc::new(Mutex::new(HashMap::new()));
+    let db = Db::new();
     
-    let incr_cmd = IncrCommand::new("counter");
+    let incr_cmd = IncrCommand::new(b"counter");
     let result = incr_cmd.execute(&db);
     
-    assert_eq!(result, ":1\r\n");
-    assert_eq!(db.lock().unwrap().get("counter").unwrap().0, "1");
+    assert_eq!(result, Reply::Integer(1));
+    assert_eq!(db.lock().unwrap().get(b"counter".as_slice()).unwrap().value, Value::String(b"1".to_vec()));
 }
 
 // Tests für den DECR-Befehl
 #[test]
 fn test_decr_command() {
-    let db: Db = Arc::new(Mutex::new(HashMap::new()));
-    db.lock().unwrap().insert("counter".to_string(), ("2".to_string(), None));
+    let db = Db::new();
+    db.lock().unwrap().insert(b"counter".to_vec(), Entry::with_expiry(Value::String(b"2".to_vec()), None));
     
-    let decr_cmd = DecrCommand::new("counter");
+    let decr_cmd = DecrCommand::new(b"counter");
     let result = decr_cmd.execute(&db);
     
-    assert_eq!(result, ":1\r\n");
-    assert_eq!(db.lock().unwrap().get("counter").unwrap().0, "1");
+    assert_eq!(result, Reply::Integer(1));
+    assert_eq!(db.lock().unwrap().get(b"counter".as_slice()).unwrap().value, Value::String(b"1".to_vec()));
 }
 
 #[test]
 fn test_decr_non_existing_key() {
-    let db: Db = Arc::new(Mutex::new(HashMap::new()));
+    let db = Db::new();
     
-    let decr_cmd = DecrCommand::new("counter");
+    let decr_cmd = DecrCommand::new(b"counter");
     let result = decr_cmd.execute(&db);
     
-    assert_eq!(result, ":-1\r\n"); // Erwarteter Wert angepasst
-    assert_eq!(db.lock().unwrap().get("counter").unwrap().0, "-1");
+    assert_eq!(result, Reply::Integer(-1)); // Erwarteter Wert angepasst
+    assert_eq!(db.lock().unwrap().get(b"counter".as_slice()).unwrap().value, Value::String(b"-1".to_vec()));
 }
 
 // Tests für den EXPIRE-Befehl
 #[test]
 fn test_expire_command() {
-    let db: Db = Arc::new(Mutex::new(HashMap::new()));
-    db.lock().unwrap().insert("key".to_string(), ("value".to_string(), None));
+    let db = Db::new();
+    db.lock().unwrap().insert(b"key".to_vec(), Entry::with_expiry(Value::String(b"value".to_vec()), None));
     
-    let expire_cmd = ExpireCommand::new("key", 10);
+    let expire_cmd = ExpireCommand::new(b"key", 10);
     let result = expire_cmd.execute(&db);
     
-    assert_eq!(result, "+OK\r\n"); // Erwarteter Wert angepasst
+    assert_eq!(result, Reply::ok()); // Erwarteter Wert angepasst
     
-    let binding = db.lock().unwrap();
-    let (_, expire_time) = binding.get("key").unwrap();
+    let mut binding = db.lock().unwrap();
+    let expire_time = binding.get(b"key".as_slice()).unwrap().expires_at;
     assert!(expire_time.is_some());
     assert!(expire_time.unwrap() > Instant::now());
 }
 
+#[test]
+fn test_active_expiry() {
+    let db = new_db();
+    for i in 0..200 {
+        run::<SetCommand>(&db, &["SET", &format!("short:{i}"), "v", "PX", "1"]);
+    }
+    std::thread::sleep(Duration::from_millis(5));
+    // Most of each round expired, so the cycle keeps going until all are gone.
+    assert_eq!(db.lock().unwrap().expire_cycle(Instant::now()), 200);
+    assert_eq!(db.lock().unwrap().key_count(), 0);
+
+    for i in 0..100 {
+        run::<SetCommand>(&db, &["SET", &format!("long:{i}"), "v", "EX", "100"]);
+        run::<SetCommand>(&db, &["SET", &format!("plain:{i}"), "v"]);
+    }
+    run::<SetCommand>(&db, &["SET", "short", "v", "PX", "1"]);
+    std::thread::sleep(Duration::from_millis(5));
+    // Rounds that find almost nothing expired end the cycle, so reaching the
+    // one expired key may take a cycle per round of volatile keys.
+    let mut cycles = 0;
+    while db.lock().unwrap().key_count() == 201 {
+        db.lock().unwrap().expire_cycle(Instant::now());
+        cycles += 1;
+        assert!(cycles <= 6, "the expired key was never reached");
+    }
+    assert_eq!(db.lock().unwrap().key_count(), 200);
+}
+
 #[test]
 fn test_expire_non_existing_key() {
-    let db: Db = Arc::new(Mutex::new(HashMap::new()));
+    let db = Db::new();
     
-    let expire_cmd = ExpireCommand::new("missing_key", 10);
+    let expire_cmd = ExpireCommand::new(b"missing_key", 10);
     let result = expire_cmd.execute(&db);
     
-    assert_eq!(result, "-ERR no such key\r\n"); // Erwarteter Wert angepasst
+    assert_eq!(result, Reply::error("ERR no such key")); // Erwarteter Wert angepasst
 }
 
 // Tests für den TTL-Befehl
 #[test]
 fn test_ttl_command_with_expiration() {
-    let db: Db = Arc::new(Mutex::new(HashMap::new()));
+    let db = Db::new();
     let future_time = Instant::now() + Duration::from_secs(10);
-    db.lock().unwrap().insert("key".to_string(), ("value".to_string(), Some(future_time)));
+    db.lock().unwrap().insert(b"key".to_vec(), Entry::with_expiry(Value::String(b"value".to_vec()), Some(future_time)));
     
-    let ttl_cmd = TTLCommand::new("key");
+    let ttl_cmd = TTLCommand::new(b"key");
     let result = ttl_cmd.execute(&db);
     
     // Prüfe, ob die TTL zwischen 0 und 10 Sekunden liegt
-    let ttl = result.trim_start_matches(':').trim_end_matches("\r\n").parse::<u64>().unwrap();
+    let Reply::Integer(ttl) = result else { panic!("expected integer reply, got {:?}", result) };
     assert!(ttl <= 10);
     assert!(ttl > 0);
 }
 
 #[test]
 fn test_ttl_command_no_expiration() {
-    let db: Db = Arc::new(Mutex::new(HashMap::new()));
-    db.lock().unwrap().insert("key".to_string(), ("value".to_string(), None));
+    let db = Db::new();
+    db.lock().unwrap().insert(b"key".to_vec(), Entry::with_expiry(Value::String(b"value".to_vec()), None));
     
-    let ttl_cmd = TTLCommand::new("key");
+    let ttl_cmd = TTLCommand::new(b"key");
     let result = ttl_cmd.execute(&db);
     
-    assert_eq!(result, ":-1\r\n");
+    assert_eq!(result, Reply::Integer(-1));
 }
 
 #[test]
 fn test_ttl_non_existing_key() {
-    let db: Db = Arc::new(Mutex::new(HashMap::new()));
+    let db = Db::new();
     
-    let ttl_cmd = TTLCommand::new("missing_key");
+    let ttl_cmd = TTLCommand::new(b"missing_key");
     let result = ttl_cmd.execute(&db);
     
-    assert_eq!(result, ":-1\r\n"); // Erwarteter Wert angepasst
-}
\ No newline at end of file
+    assert_eq!(result, Reply::Integer(-1)); // Erwarteter Wert angepasst
+}
+#[test]
+fn test_set_and_get_binary_value() {
+    let db = Db::new();
+    let value = [0u8, 159, 146, 150, 255, b'\r', b'\n'];
+    SetCommand::new(b"\xffkey", &value, None, None).execute(&db);
+
+    let result = GetCommand::new(b"\xffkey").execute(&db);
+
+    assert_eq!(result, Reply::Bulk(value.to_vec()));
+}
+
+// Tests für die Schlüsselraum-Befehle
+#[test]
+fn test_del_and_unlink() {
+    let db = new_db();
+    run::<SetCommand>(&db, &["SET", "a", "1"]);
+    run::<SetCommand>(&db, &["SET", "b", "2"]);
+    let members: Vec<String> = (0..100).map(|i| i.to_string()).collect();
+    let mut push = vec!["RPUSH", "big"];
+    push.extend(members.iter().map(String::as_str));
+    run::<PushCommand>(&db, &push);
+
+    assert_eq!(run::<DelCommand>(&db, &["DEL", "a", "missing", "a"]), Reply::Integer(1));
+    assert_eq!(run::<DelCommand>(&db, &["UNLINK", "big", "b"]), Reply::Integer(2));
+    assert_eq!(run::<DbSizeCommand>(&db, &["DBSIZE"]), Reply::Integer(0));
+    assert_eq!(run::<RandomKeyCommand>(&db, &["RANDOMKEY"]), Reply::Null);
+}
+
+#[tokio::test]
+async fn test_unlink_frees_in_background() {
+    let db = new_db();
+    let members: Vec<String> = (0..1000).map(|i| i.to_string()).collect();
+    let mut add = vec!["SADD", "big"];
+    add.extend(members.iter().map(String::as_str));
+    run::<SAddCommand>(&db, &add);
+    assert_eq!(run::<DelCommand>(&db, &["UNLINK", "big"]), Reply::Integer(1));
+    assert_eq!(run::<ExistsCommand>(&db, &["EXISTS", "big"]), Reply::Integer(0));
+}
+
+#[test]
+fn test_type_and_touch() {
+    let db = new_db();
+    run::<SetCommand>(&db, &["SET", "s", "v"]);
+    run::<HSetCommand>(&db, &["HSET", "h", "f", "v"]);
+    run::<SetJsonCommand>(&db, &["JSON.SET", "j", "$", "{}"]);
+
+    assert_eq!(run::<TypeCommand>(&db, &["TYPE", "s"]), Reply::Simple("string".to_string()));
+    assert_eq!(run::<TypeCommand>(&db, &["TYPE", "h"]), Reply::Simple("hash".to_string()));
+    assert_eq!(run::<TypeCommand>(&db, &["TYPE", "j"]), Reply::Simple("ReJSON-RL".to_string()));
+    assert_eq!(run::<TypeCommand>(&db, &["TYPE", "missing"]), Reply::Simple("none".to_string()));
+    assert_eq!(run::<ExistsCommand>(&db, &["TOUCH", "s", "h", "missing"]), Reply::Integer(2));
+    assert_eq!(run::<DbSizeCommand>(&db, &["DBSIZE"]), Reply::Integer(3));
+    let Reply::Bulk(key) = run::<RandomKeyCommand>(&db, &["RANDOMKEY"]) else {
+        panic!("expected a key");
+    };
+    assert!([b"s".as_slice(), b"h", b"j"].contains(&key.as_slice()));
+}
+
+#[test]
+fn test_rename() {
+    let db = new_db();
+    run::<SetCommand>(&db, &["SET", "a", "1", "EX", "100"]);
+    run::<SetCommand>(&db, &["SET", "b", "2"]);
+
+    assert_eq!(run::<RenameCommand>(&db, &["RENAMENX", "a", "b"]), Reply::Integer(0));
+    assert_eq!(run::<RenameCommand>(&db, &["RENAME", "a", "b"]), Reply::ok());
+    assert_eq!(run::<GetCommand>(&db, &["GET", "a"]), Reply::Null);
+    assert_eq!(run::<GetCommand>(&db, &["GET", "b"]), Reply::Bulk(b"1".to_vec()));
+    // The deadline moves with the value.
+    assert_eq!(run::<TTLCommand>(&db, &["TTL", "b"]), Reply::Integer(99));
+    assert_eq!(run::<RenameCommand>(&db, &["RENAMENX", "b", "c"]), Reply::Integer(1));
+    assert_eq!(run::<RenameCommand>(&db, &["RENAME", "c", "c"]), Reply::ok());
+    assert_eq!(run::<RenameCommand>(&db, &["RENAME", "missing", "d"]), Reply::error("ERR no such key"));
+}
+
+#[test]
+fn test_copy() {
+    let db = new_db();
+    run::<PushCommand>(&db, &["RPUSH", "list", "x", "y"]);
+    run::<SetCommand>(&db, &["SET", "other", "v"]);
+
+    assert_eq!(run::<CopyCommand>(&db, &["COPY", "list", "copy"]), Reply::Integer(1));
+    run::<PushCommand>(&db, &["RPUSH", "list", "z"]);
+    assert_eq!(run::<LRangeCommand>(&db, &["LRANGE", "copy", "0", "-1"]), bulks(&["x", "y"]));
+    assert_eq!(run::<CopyCommand>(&db, &["COPY", "list", "other"]), Reply::Integer(0));
+    assert_eq!(run::<CopyCommand>(&db, &["COPY", "list", "other", "DB", "0", "REPLACE"]), Reply::Integer(1));
+    assert_eq!(run::<TypeCommand>(&db, &["TYPE", "other"]), Reply::Simple("list".to_string()));
+    assert_eq!(run::<CopyCommand>(&db, &["COPY", "missing", "x"]), Reply::Integer(0));
+    assert_eq!(run::<CopyCommand>(&db, &["COPY", "list", "x", "DB", "1"]), Reply::error("ERR DB index is out of range"));
+    assert_eq!(run::<CopyCommand>(&db, &["COPY", "list", "list"]), Reply::error("ERR source and destination objects are the same"));
+}
+
+#[test]
+fn test_keys() {
+    let db = new_db();
+    for key in ["user:1", "user:2", "user:10", "order:1", "u*"] {
+        run::<SetCommand>(&db, &["SET", key, "v"]);
+    }
+    let keys = |pattern: &str| {
+        let Reply::Array(mut keys) = run::<KeysCommand>(&db, &["KEYS", pattern]) else {
+            panic!("expected an array");
+        };
+        keys.sort_by_key(|key| format!("{key:?}"));
+        keys
+    };
+    assert_eq!(Reply::Array(keys("user:?")), bulks(&["user:1", "user:2"]));
+    assert_eq!(Reply::Array(keys("[^u]*")), bulks(&["order:1"]));
+    assert_eq!(Reply::Array(keys("u\\*")), bulks(&["u*"]));
+    assert_eq!(keys("*").len(), 5);
+}
+
+#[test]
+fn test_scan_survives_growth() {
+    let db = new_db();
+    for i in 0..50 {
+        run::<SetCommand>(&db, &["SET", &format!("key:{i}"), "v"]);
+    }
+    run::<PushCommand>(&db, &["RPUSH", "list", "x"]);
+
+    let mut seen = std::collections::HashSet::new();
+    let mut cursor = "0".to_string();
+    let mut added = 0;
+    loop {
+        let Reply::Array(reply) = run::<ScanCommand>(&db, &["SCAN", &cursor, "MATCH", "key:*", "COUNT", "7"]) else {
+            panic!("expected an array");
+        };
+        let [Reply::Bulk(next), Reply::Array(keys)] = reply.as_slice() else {
+            panic!("unexpected reply");
+        };
+        seen.extend(keys.iter().filter_map(|key| match key {
+            Reply::Bulk(key) => Some(key.clone()),
+            _ => None,
+        }));
+        // Grow the keyspace well past its size while iterating.
+        for _ in 0..20 {
+            run::<SetCommand>(&db, &["SET", &format!("new:{added}"), "v"]);
+            added += 1;
+        }
+        cursor = String::from_utf8(next.clone()).unwrap();
+        if cursor == "0" {
+            break;
+        }
+    }
+    for i in 0..50 {
+        assert!(seen.contains(format!("key:{i}").as_bytes()), "key:{i} was not returned");
+    }
+    assert_eq!(seen.len(), 50);
+
+    assert_eq!(run::<ScanCommand>(&db, &["SCAN", "0", "COUNT", "1000", "TYPE", "LIST"]), scan::reply(0, vec![Reply::Bulk(b"list".to_vec())]));
+    assert_eq!(run::<ScanCommand>(&db, &["SCAN", "0", "NOVALUES"]), Reply::error("ERR syntax error"));
+}
+
+#[test]
+fn test_scan_survives_shrinking() {
+    let db = new_db();
+    for i in 0..500 {
+        run::<SetCommand>(&db, &["SET", &format!("key:{i}"), "v"]);
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    let mut cursor = "0".to_string();
+    let mut deleted = 0;
+    loop {
+        let Reply::Array(reply) = run::<ScanCommand>(&db, &["SCAN", &cursor, "COUNT", "10"]) else {
+            panic!("expected an array");
+        };
+        let [Reply::Bulk(next), Reply::Array(keys)] = reply.as_slice() else {
+            panic!("unexpected reply");
+        };
+        seen.extend(keys.iter().filter_map(|key| match key {
+            Reply::Bulk(key) => Some(key.clone()),
+            _ => None,
+        }));
+        // Delete most keys while iterating, so the table shrinks under the cursor.
+        while deleted < 480 && deleted < seen.len() * 4 {
+            run::<DelCommand>(&db, &["DEL", &format!("key:{}", 20 + deleted)]);
+            deleted += 1;
+        }
+        cursor = String::from_utf8(next.clone()).unwrap();
+        if cursor == "0" {
+            break;
+        }
+    }
+    for i in 0..20 {
+        assert!(seen.contains(format!("key:{i}").as_bytes()), "key:{i} was not returned");
+    }
+}
+
+// Tests für die JSON-Befehle
+#[test]
+fn test_json_set_and_get() {
+    let db = Db::new();
+    let result = super::json::SetJsonCommand::new(b"doc", "$", br#"{"a":1,"b":[true]}"#).execute(&db);
+    assert_eq!(result, Reply::ok());
+
+    let result = super::json::GetJsonCommand::new(b"doc", &[]).execute(&db);
+
+    assert_eq!(result, Reply::Bulk(br#"{"a":1,"b":[true]}"#.to_vec()));
+}
+
+#[test]
+fn test_json_get_paths() {
+    let db = new_db();
+    run::<SetJsonCommand>(&db, &["JSON.SET", "doc", "$", r#"{"a":{"b":1},"c":[{"b":2},{"b":3}]}"#]);
+
+    assert_eq!(run::<GetJsonCommand>(&db, &["JSON.GET", "doc", "$..b"]), Reply::Bulk(b"[1,2,3]".to_vec()));
+    assert_eq!(run::<GetJsonCommand>(&db, &["JSON.GET", "doc", ".c[1].b"]), Reply::Bulk(b"3".to_vec()));
+    assert_eq!(run::<GetJsonCommand>(&db, &["JSON.GET", "doc", "$.c[?(@.b > 2)]"]), Reply::Bulk(br#"[{"b":3}]"#.to_vec()));
+    assert_eq!(run::<GetJsonCommand>(&db, &["JSON.GET", "doc", "$.nope"]), Reply::Bulk(b"[]".to_vec()));
+    assert_eq!(run::<GetJsonCommand>(&db, &["JSON.GET", "doc", ".nope"]), Reply::error("ERR Path '.nope' does not exist"));
+    assert_eq!(
+        run::<GetJsonCommand>(&db, &["JSON.GET", "doc", "$.a.b", "$.c[0]"]),
+        Reply::Bulk(br#"{"$.a.b":[1],"$.c[0]":[{"b":2}]}"#.to_vec())
+    );
+    assert_eq!(run::<GetJsonCommand>(&db, &["JSON.GET", "doc", ".a", "c[0].b"]), Reply::Bulk(br#"{".a":{"b":1},"c[0].b":2}"#.to_vec()));
+    assert_eq!(
+        run::<GetJsonCommand>(&db, &["JSON.GET", "doc", "INDENT", "  ", "NEWLINE", "\n", "SPACE", " ", ".a"]),
+        Reply::Bulk(b"{\n  \"b\": 1\n}".to_vec())
+    );
+    assert_eq!(run::<GetJsonCommand>(&db, &["JSON.GET", "missing"]), Reply::Null);
+}
+
+#[test]
+fn test_json_set_paths() {
+    let db = new_db();
+    assert_eq!(run::<SetJsonCommand>(&db, &["JSON.SET", "doc", "$.a", "1"]), Reply::error("ERR new objects must be created at the root"));
+    assert_eq!(run::<SetJsonCommand>(&db, &["JSON.SET", "doc", "$", "{bad"]), Reply::error("ERR key must be a string at line 1 column 2"));
+    assert_eq!(run::<SetJsonCommand>(&db, &["JSON.SET", "doc", ".", r#"{"a":[1,2],"o":{"x":1}}"#]), Reply::ok());
+
+    assert_eq!(run::<SetJsonCommand>(&db, &["JSON.SET", "doc", "$.a[*]", "0"]), Reply::ok());
+    assert_eq!(run::<SetJsonCommand>(&db, &["JSON.SET", "doc", "$.o.y", r#""new""#]), Reply::ok());
+    assert_eq!(run::<SetJsonCommand>(&db, &["JSON.SET", "doc", "$.o.y", "2", "NX"]), Reply::Null);
+    assert_eq!(run::<SetJsonCommand>(&db, &["JSON.SET", "doc", "$.o.z", "2", "XX"]), Reply::Null);
+    assert_eq!(run::<SetJsonCommand>(&db, &["JSON.SET", "doc", "$.o.x", "2", "XX"]), Reply::ok());
+    assert_eq!(run::<SetJsonCommand>(&db, &["JSON.SET", "doc", "$.nope.deep", "2"]), Reply::Null);
+    assert_eq!(run::<SetJsonCommand>(&db, &["JSON.SET", "doc", "$", "1", "NX"]), Reply::Null);
+    assert_eq!(
+        run::<GetJsonCommand>(&db, &["JSON.GET", "doc"]),
+        Reply::Bulk(br#"{"a":[0,0],"o":{"x":2,"y":"new"}}"#.to_vec())
+    );
+    assert_eq!(run::<SetJsonCommand>(&db, &["JSON.SET", "doc", "$", "1", "FOO"]), Reply::error("ERR syntax error"));
+}
+
+#[test]
+fn test_json_del_and_type() {
+    let db = new_db();
+    run::<SetJsonCommand>(&db, &["JSON.SET", "doc", "$", r#"{"a":[1,2,3,4],"b":{"a":true},"c":"x"}"#]);
+
+    assert_eq!(run::<TypeJsonCommand>(&db, &["JSON.TYPE", "doc", "$..a"]), bulks(&["array", "boolean"]));
+    assert_eq!(run::<TypeJsonCommand>(&db, &["JSON.TYPE", "doc", ".c"]), Reply::Simple("string".to_string()));
+    assert_eq!(run::<DelJsonCommand>(&db, &["JSON.DEL", "doc", "$.a[1:3]"]), Reply::Integer(2));
+    assert_eq!(run::<DelJsonCommand>(&db, &["JSON.FORGET", "doc", "$..a"]), Reply::Integer(2));
+    assert_eq!(run::<DelJsonCommand>(&db, &["JSON.DEL", "doc", "$.nope"]), Reply::Integer(0));
+    assert_eq!(run::<GetJsonCommand>(&db, &["JSON.GET", "doc"]), Reply::Bulk(br#"{"b":{},"c":"x"}"#.to_vec()));
+    assert_eq!(run::<DelJsonCommand>(&db, &["JSON.DEL", "doc"]), Reply::Integer(1));
+    assert_eq!(run::<DelJsonCommand>(&db, &["JSON.DEL", "doc"]), Reply::Integer(0));
+    assert_eq!(run::<TypeJsonCommand>(&db, &["JSON.TYPE", "doc"]), Reply::Null);
+
+    run::<SetJsonCommand>(&db, &["JSON.SET", "nested", "$", r#"{"x":{"y":{"z":1}},"w":[1,[2,{"v":3}]]}"#]);
+    assert_eq!(run::<DelJsonCommand>(&db, &["JSON.DEL", "nested", "$..*"]), Reply::Integer(2));
+    assert_eq!(run::<GetJsonCommand>(&db, &["JSON.GET", "nested"]), Reply::Bulk(b"{}".to_vec()));
+}
+
+#[test]
+fn test_json_mget() {
+    let db = new_db();
+    run::<SetJsonCommand>(&db, &["JSON.SET", "a", "$", r#"{"n":1}"#]);
+    run::<SetJsonCommand>(&db, &["JSON.SET", "b", "$", r#"{"n":2}"#]);
+
+    assert_eq!(
+        run::<MGetJsonCommand>(&db, &["JSON.MGET", "a", "b", "missing", "$.n"]),
+        Reply::Array(vec![Reply::Bulk(b"[1]".to_vec()), Reply::Bulk(b"[2]".to_vec()), Reply::Null])
+    );
+    assert_eq!(run::<MGetJsonCommand>(&db, &["JSON.MGET", "a", ".n"]), Reply::Array(vec![Reply::Bulk(b"1".to_vec())]));
+}
+
+#[test]
+fn test_json_numbers_and_strings() {
+    let db = new_db();
+    run::<SetJsonCommand>(&db, &["JSON.SET", "doc", "$", r#"{"a":2,"b":{"a":"s"},"c":1.5}"#]);
+
+    assert_eq!(run::<NumOpJsonCommand>(&db, &["JSON.NUMINCRBY", "doc", "$..a", "3"]), Reply::Bulk(b"[5,null]".to_vec()));
+    assert_eq!(run::<NumOpJsonCommand>(&db, &["JSON.NUMMULTBY", "doc", ".c", "3"]), Reply::Bulk(b"4.5".to_vec()));
+    assert_eq!(run::<NumOpJsonCommand>(&db, &["JSON.NUMINCRBY", "doc", ".a", "0.5"]), Reply::Bulk(b"5.5".to_vec()));
+    assert_eq!(
+        run::<NumOpJsonCommand>(&db, &["JSON.NUMINCRBY", "doc", ".b.a", "1"]),
+        Reply::error("ERR wrong type of path value - expected number but found string")
+    );
+
+    assert_eq!(run::<StrAppendJsonCommand>(&db, &["JSON.STRAPPEND", "doc", "$..a", r#""tr""#]), Reply::Array(vec![Reply::Null, Reply::Integer(3)]));
+    assert_eq!(run::<LenJsonCommand>(&db, &["JSON.STRLEN", "doc", ".b.a"]), Reply::Integer(3));
+    assert_eq!(run::<StrAppendJsonCommand>(&db, &["JSON.STRAPPEND", "doc", "$.b.a", "x"]), Reply::error("ERR expected value at line 1 column 1"));
+    assert_eq!(run::<NumOpJsonCommand>(&db, &["JSON.NUMINCRBY", "missing", "$", "1"]), Reply::error("ERR could not perform this operation on a key that doesn't exist"));
+}
+
+#[test]
+fn test_json_arrays() {
+    let db = new_db();
+    run::<SetJsonCommand>(&db, &["JSON.SET", "doc", "$", r#"{"a":[1],"b":{"a":[]},"s":"x"}"#]);
+
+    assert_eq!(run::<ArrInsertJsonCommand>(&db, &["JSON.ARRAPPEND", "doc", "$..a", "2", r#""three""#]), Reply::Array(vec![Reply::Integer(3), Reply::Integer(2)]));
+    assert_eq!(run::<ArrInsertJsonCommand>(&db, &["JSON.ARRINSERT", "doc", ".a", "0", "0"]), Reply::Integer(4));
+    assert_eq!(run::<ArrInsertJsonCommand>(&db, &["JSON.ARRINSERT", "doc", ".a", "9", "0"]), Reply::error("ERR index out of bounds"));
+    assert_eq!(run::<GetJsonCommand>(&db, &["JSON.GET", "doc", ".a"]), Reply::Bulk(br#"[0,1,2,"three"]"#.to_vec()));
+    assert_eq!(run::<LenJsonCommand>(&db, &["JSON.ARRLEN", "doc", "$..a"]), ints(&[4, 2]));
+    assert_eq!(run::<LenJsonCommand>(&db, &["JSON.ARRLEN", "doc", "$.s"]), Reply::Array(vec![Reply::Null]));
+
+    assert_eq!(run::<ArrIndexJsonCommand>(&db, &["JSON.ARRINDEX", "doc", ".a", r#""three""#]), Reply::Integer(3));
+    assert_eq!(run::<ArrIndexJsonCommand>(&db, &["JSON.ARRINDEX", "doc", ".a", "1", "2"]), Reply::Integer(-1));
+    assert_eq!(run::<ArrIndexJsonCommand>(&db, &["JSON.ARRINDEX", "doc", "$.a", "2", "-3", "-1"]), ints(&[2]));
+
+    assert_eq!(run::<ArrPopJsonCommand>(&db, &["JSON.ARRPOP", "doc", ".a"]), Reply::Bulk(br#""three""#.to_vec()));
+    assert_eq!(run::<ArrPopJsonCommand>(&db, &["JSON.ARRPOP", "doc", "$.a", "-99"]), Reply::Array(vec![Reply::Bulk(b"0".to_vec())]));
+    assert_eq!(run::<ArrTrimJsonCommand>(&db, &["JSON.ARRTRIM", "doc", "$..a", "1", "-1"]), ints(&[1, 1]));
+    assert_eq!(run::<ArrTrimJsonCommand>(&db, &["JSON.ARRTRIM", "doc", ".a", "5", "9"]), Reply::Integer(0));
+    assert_eq!(run::<ArrPopJsonCommand>(&db, &["JSON.ARRPOP", "doc", "$.a"]), Reply::Array(vec![Reply::Null]));
+    assert_eq!(run::<GetJsonCommand>(&db, &["JSON.GET", "doc"]), Reply::Bulk(br#"{"a":[],"b":{"a":["three"]},"s":"x"}"#.to_vec()));
+}
+
+#[test]
+fn test_json_objects_toggle_and_clear() {
+    let db = new_db();
+    run::<SetJsonCommand>(&db, &["JSON.SET", "doc", "$", r#"{"o":{"x":1,"y":true},"n":7,"f":false,"l":[1]}"#]);
+
+    assert_eq!(run::<ObjKeysJsonCommand>(&db, &["JSON.OBJKEYS", "doc", ".o"]), bulks(&["x", "y"]));
+    assert_eq!(run::<ObjKeysJsonCommand>(&db, &["JSON.OBJKEYS", "doc", "$.*"]), Reply::Array(vec![bulks(&["x", "y"]), Reply::Null, Reply::Null, Reply::Null]));
+    assert_eq!(run::<LenJsonCommand>(&db, &["JSON.OBJLEN", "doc"]), Reply::Integer(4));
+
+    assert_eq!(run::<ToggleJsonCommand>(&db, &["JSON.TOGGLE", "doc", "$..[?(@ == true || @ == false)]"]), ints(&[1, 0]));
+    assert_eq!(run::<ToggleJsonCommand>(&db, &["JSON.TOGGLE", "doc", ".f"]), Reply::Bulk(b"false".to_vec()));
+    assert_eq!(run::<ToggleJsonCommand>(&db, &["JSON.TOGGLE", "doc", "$.n"]), Reply::Array(vec![Reply::Null]));
+
+    assert_eq!(run::<ClearJsonCommand>(&db, &["JSON.CLEAR", "doc", "$.*"]), Reply::Integer(3));
+    assert_eq!(run::<GetJsonCommand>(&db, &["JSON.GET", "doc"]), Reply::Bulk(br#"{"o":{},"n":0,"f":false,"l":[]}"#.to_vec()));
+}
+
+#[test]
+fn test_json_merge() {
+    let db = new_db();
+    assert_eq!(run::<MergeJsonCommand>(&db, &["JSON.MERGE", "doc", "$", r#"{"a":{"b":1,"c":2},"d":[1]}"#]), Reply::ok());
+    assert_eq!(run::<MergeJsonCommand>(&db, &["JSON.MERGE", "doc", "$.a", r#"{"b":null,"e":{"f":3}}"#]), Reply::ok());
+    assert_eq!(run::<MergeJsonCommand>(&db, &["JSON.MERGE", "doc", "$.d", "[2]"]), Reply::ok());
+    assert_eq!(run::<MergeJsonCommand>(&db, &["JSON.MERGE", "doc", "$.g", "true"]), Reply::ok());
+    assert_eq!(run::<MergeJsonCommand>(&db, &["JSON.MERGE", "doc", "$.g", "null"]), Reply::ok());
+    assert_eq!(run::<GetJsonCommand>(&db, &["JSON.GET", "doc"]), Reply::Bulk(br#"{"a":{"c":2,"e":{"f":3}},"d":[2]}"#.to_vec()));
+    assert_eq!(
+        run::<MergeJsonCommand>(&db, &["JSON.MERGE", "missing", "$.a", "1"]),
+        Reply::error("ERR new objects must be created at the root")
+    );
+}
+
+#[test]
+fn test_json_native_type() {
+    let db = new_db();
+    assert_eq!(run::<SetJsonCommand>(&db, &["JSON.SET", "doc", "$", r#"{"a":1}"#]), Reply::ok());
+    assert_eq!(db.lock().unwrap().get(b"doc".as_slice()).unwrap().value.type_name(), "ReJSON-RL");
+    assert!(matches!(run::<SetJsonCommand>(&db, &["JSON.SET", "doc", "$", "{bad"]), Reply::Error(_)));
+    assert_eq!(run::<GetJsonCommand>(&db, &["JSON.GET", "doc"]), Reply::Bulk(br#"{"a":1}"#.to_vec()));
+
+    assert_eq!(GetCommand::new(b"doc").execute(&db), Reply::wrong_type());
+    assert_eq!(IncrCommand::new(b"doc").execute(&db), Reply::wrong_type());
+    assert_eq!(run::<SetCommand>(&db, &["SET", "str", "{}"]), Reply::ok());
+    assert_eq!(run::<GetJsonCommand>(&db, &["JSON.GET", "str"]), Reply::wrong_type());
+    assert_eq!(run::<SetJsonCommand>(&db, &["JSON.SET", "str", "$.a", "1"]), Reply::wrong_type());
+}
+
+// Tests für die Suchbefehle
+fn product_index(db: &Db) {
+    let create = [
+        "FT.CREATE", "products", "ON", "JSON", "PREFIX", "1", "product:", "SCHEMA",
+        "$.name", "AS", "name", "TEXT", "$.tags[*]", "AS", "tags", "TAG", "$.price", "AS", "price", "NUMERIC", "SORTABLE",
+    ];
+    assert_eq!(run::<CreateIndexCommand>(db, &create), Reply::ok());
+}
+
+fn bulks_with_total(total: i64, keys: &[&str]) -> Reply {
+    let mut items = vec![Reply::Integer(total)];
+    items.extend(keys.iter().map(|key| Reply::Bulk(key.as_bytes().to_vec())));
+    Reply::Array(items)
+}
+
+#[test]
+fn test_ft_search_json() {
+    let db = new_db();
+    run::<SetJsonCommand>(&db, &["JSON.SET", "product:1", "$", r#"{"name":"Trail running shoe","tags":["sport"],"price":90}"#]);
+    product_index(&db);
+    run::<SetJsonCommand>(&db, &["JSON.SET", "product:2", "$", r#"{"name":"Road running shoe","tags":["sport","road"],"price":120}"#]);
+    run::<SetJsonCommand>(&db, &["JSON.SET", "product:3", "$", r#"{"name":"Rain jacket","tags":["outdoor"],"price":60}"#]);
+    run::<SetJsonCommand>(&db, &["JSON.SET", "other:1", "$", r#"{"name":"Running socks","price":5}"#]);
+
+    assert_eq!(
+        run::<SearchCommand>(&db, &["FT.SEARCH", "products", "running @price:[100 +inf]"]),
+        Reply::Array(vec![
+            Reply::Integer(1),
+            Reply::Bulk(b"product:2".to_vec()),
+            bulks(&["$", r#"{"name":"Road running shoe","tags":["sport","road"],"price":120}"#]),
+        ])
+    );
+    assert_eq!(
+        run::<SearchCommand>(&db, &["FT.SEARCH", "products", "*", "SORTBY", "price", "DESC", "LIMIT", "1", "2", "RETURN", "2", "name", "$.price"]),
+        Reply::Array(vec![
+            Reply::Integer(3),
+            Reply::Bulk(b"product:1".to_vec()),
+            bulks(&["name", "Trail running shoe", "$.price", "90"]),
+            Reply::Bulk(b"product:3".to_vec()),
+            bulks(&["name", "Rain jacket", "$.price", "60"]),
+        ])
+    );
+    assert_eq!(run::<SearchCommand>(&db, &["FT.SEARCH", "products", "@tags:{sport} -road", "NOCONTENT"]), bulks_with_total(1, &["product:1"]));
+
+    // Writes, deletes and expiry keep the index current.
+    run::<SetJsonCommand>(&db, &["JSON.SET", "product:1", "$.price", "100"]);
+    assert_eq!(run::<SearchCommand>(&db, &["FT.SEARCH", "products", "@price:[100 100]", "NOCONTENT"]), bulks_with_total(1, &["product:1"]));
+    run::<DelJsonCommand>(&db, &["JSON.DEL", "product:2"]);
+    run::<SetCommand>(&db, &["SET", "product:3", "not json"]);
+    assert_eq!(run::<SearchCommand>(&db, &["FT.SEARCH", "products", "*", "NOCONTENT"]), bulks_with_total(1, &["product:1"]));
+    db.lock().unwrap().get_mut(b"product:1").unwrap().expires_at = Some(Instant::now());
+    db.lock().unwrap().expire_cycle(Instant::now());
+    assert_eq!(run::<SearchCommand>(&db, &["FT.SEARCH", "products", "*"]), Reply::Array(vec![Reply::Integer(0)]));
+}
+
+#[test]
+fn test_ft_search_hash() {
+    let db = new_db();
+    let create = ["FT.CREATE", "users", "PREFIX", "1", "user:", "SCHEMA", "name", "TEXT", "city", "TAG", "age", "NUMERIC"];
+    assert_eq!(run::<CreateIndexCommand>(&db, &create), Reply::ok());
+    assert_eq!(run::<CreateIndexCommand>(&db, &create), Reply::error("ERR Index already exists"));
+    run::<HSetCommand>(&db, &["HSET", "user:1", "name", "Ada Lovelace", "city", "London", "age", "36"]);
+    run::<HSetCommand>(&db, &["HSET", "user:2", "name", "Alan Turing", "city", "London", "age", "41"]);
+
+    assert_eq!(
+        run::<SearchCommand>(&db, &["FT.SEARCH", "users", "@city:{london}", "SORTBY", "age", "RETURN", "1", "name"]),
+        Reply::Array(vec![
+            Reply::Integer(2),
+            Reply::Bulk(b"user:1".to_vec()),
+            bulks(&["name", "Ada Lovelace"]),
+            Reply::Bulk(b"user:2".to_vec()),
+            bulks(&["name", "Alan Turing"]),
+        ])
+    );
+    run::<HSetCommand>(&db, &["HSET", "user:2", "city", "Manchester"]);
+    run::<HExpireCommand>(&db, &["HPEXPIRE", "user:1", "1", "FIELDS", "1", "city"]);
+    std::thread::sleep(Duration::from_millis(5));
+    db.lock().unwrap().expire_cycle(Instant::now());
+    assert_eq!(run::<SearchCommand>(&db, &["FT.SEARCH", "users", "@city:{london}"]), Reply::Array(vec![Reply::Integer(0)]));
+    assert_eq!(run::<SearchCommand>(&db, &["FT.SEARCH", "users", "alan", "NOCONTENT"]), bulks_with_total(1, &["user:2"]));
+
+    assert_eq!(run::<SearchCommand>(&db, &["FT.SEARCH", "users", "@nope:x"]), Reply::error("ERR Unknown field at offset 0 near nope"));
+    assert_eq!(run::<SearchCommand>(&db, &["FT.SEARCH", "missing", "*"]), Reply::error("ERR missing: no such index"));
+    assert_eq!(run::<ListIndexesCommand>(&db, &["FT._LIST"]), bulks(&["users"]));
+    assert_eq!(run::<DropIndexCommand>(&db, &["FT.DROPINDEX", "users", "DD"]), Reply::ok());
+    assert_eq!(run::<GetCommand>(&db, &["GET", "user:1"]), Reply::Null);
+    assert_eq!(run::<ListIndexesCommand>(&db, &["FT._LIST"]), Reply::Array(vec![]));
+}
+
+#[test]
+fn test_ft_aggregate() {
+    let db = new_db();
+    for (key, doc) in [
+        ("product:1", r#"{"name":"Trail shoe","tags":["sport"],"price":90}"#),
+        ("product:2", r#"{"name":"Road shoe","tags":["sport"],"price":120}"#),
+        ("product:3", r#"{"name":"Rain jacket","tags":["outdoor"],"price":60}"#),
+    ] {
+        run::<SetJsonCommand>(&db, &["JSON.SET", key, "$", doc]);
+    }
+    product_index(&db);
+
+    let aggregate = [
+        "FT.AGGREGATE", "products", "*",
+        "GROUPBY", "1", "@tags", "REDUCE", "COUNT", "0", "AS", "count", "REDUCE", "AVG", "1", "@price",
+        "APPLY", "@__generated_aliasavgprice * 1.1", "AS", "gross",
+        "SORTBY", "2", "@count", "DESC",
+    ];
+    assert_eq!(
+        run::<AggregateCommand>(&db, &aggregate),
+        Reply::Array(vec![
+            Reply::Integer(2),
+            bulks(&["tags", "sport", "count", "2", "__generated_aliasavgprice", "105", "gross", "115.50000000000001"]),
+            bulks(&["tags", "outdoor", "count", "1", "__generated_aliasavgprice", "60", "gross", "66"]),
+        ])
+    );
+    assert_eq!(
+        run::<AggregateCommand>(&db, &["FT.AGGREGATE", "products", "shoe", "LOAD", "1", "@name", "FILTER", "@price < 100", "LIMIT", "0", "5"]),
+        Reply::Array(vec![Reply::Integer(1), bulks(&["name", "Trail shoe"])])
+    );
+    assert_eq!(
+        run::<AggregateCommand>(&db, &["FT.AGGREGATE", "products", "*", "GROUPBY", "1", "tags"]),
+        Reply::error("ERR Bad arguments for GROUPBY: Unknown property `tags`. Did you mean `@tags`?")
+    );
+    assert!(matches!(run::<AggregateCommand>(&db, &["FT.AGGREGATE", "products", "*", "APPLY", "@price +", "AS", "x"]), Reply::Error(_)));
+    assert_eq!(run::<AggregateCommand>(&db, &["FT.AGGREGATE", "products", "*", "APPLY", "1"]), Reply::error("ERR Missing AS for APPLY"));
+    assert_eq!(
+        run::<AggregateCommand>(&db, &["FT.AGGREGATE", "products", "*", "LOAD", "18446744073709551615", "@name"]),
+        Reply::error("ERR syntax error")
+    );
+}
+
+#[test]
+fn test_ft_search_vectors() {
+    let db = new_db();
+    let create = [
+        "FT.CREATE", "docs", "ON", "JSON", "SCHEMA", "$.embedding", "AS", "embedding", "VECTOR", "HNSW", "10",
+        "TYPE", "FLOAT32", "DIM", "2", "DISTANCE_METRIC", "L2", "M", "4", "EF_RUNTIME", "20", "$.kind", "AS", "kind", "TAG",
+    ];
+    assert_eq!(run::<CreateIndexCommand>(&db, &create), Reply::ok());
+    for (key, doc) in [
+        ("doc:1", r#"{"embedding":[1,0],"kind":"a"}"#),
+        ("doc:2", r#"{"embedding":[0,1],"kind":"b"}"#),
+        ("doc:3", r#"{"embedding":[3,0.5],"kind":"a"}"#),
+    ] {
+        run::<SetJsonCommand>(&db, &["JSON.SET", key, "$", doc]);
+    }
+    // The query vector is a binary blob, so the arguments can't be strings.
+    let search = |query: &str, rest: &[&str]| {
+        let blob: Vec<u8> = [1.0f32, 0.5].iter().flat_map(|n| n.to_le_bytes()).collect();
+        let mut args = vec![b"FT.SEARCH".to_vec(), b"docs".to_vec(), query.as_bytes().to_vec()];
+        args.extend(rest.iter().map(|arg| arg.as_bytes().to_vec()));
+        args.extend([b"PARAMS".to_vec(), b"2".to_vec(), b"vec".to_vec(), blob, b"DIALECT".to_vec(), b"2".to_vec()]);
+        match SearchCommand::parse(&args) {
+            Ok(command) => command.execute(&db),
+            Err(reply) => reply,
+        }
+    };
+
+    assert_eq!(
+        search("*=>[KNN 2 @embedding $vec]", &["RETURN", "2", "__embedding_score", "kind"]),
+        Reply::Array(vec![
+            Reply::Integer(2),
+            Reply::Bulk(b"doc:1".to_vec()),
+            bulks(&["__embedding_score", "0.25", "kind", "a"]),
+            Reply::Bulk(b"doc:2".to_vec()),
+            bulks(&["__embedding_score", "1.25", "kind", "b"]),
+        ])
+    );
+    assert_eq!(
+        search("@kind:{a}=>[KNN 5 @embedding $vec AS dist]", &["SORTBY", "dist", "DESC", "RETURN", "1", "dist"]),
+        Reply::Array(vec![
+            Reply::Integer(2),
+            Reply::Bulk(b"doc:3".to_vec()),
+            bulks(&["dist", "4"]),
+            Reply::Bulk(b"doc:1".to_vec()),
+            bulks(&["dist", "0.25"]),
+        ])
+    );
+    run::<DelJsonCommand>(&db, &["JSON.DEL", "doc:1"]);
+    assert_eq!(search("*=>[KNN 1 @embedding $vec]", &["NOCONTENT"]), bulks_with_total(1, &["doc:2"]));
+    assert_eq!(search("*=>[KNN 1 @kind $vec]", &[]), Reply::error("ERR Unknown vector field `kind`"));
+}
+
+#[test]
+fn test_ft_create_errors() {
+    let db = new_db();
+    assert_eq!(run::<CreateIndexCommand>(&db, &["FT.CREATE", "i", "SCHEMA", "a", "GEO"]), Reply::error("ERR Invalid field type for field `a`"));
+    assert_eq!(
+        run::<CreateIndexCommand>(&db, &["FT.CREATE", "i", "SCHEMA", "v", "VECTOR", "FLAT", "4", "TYPE", "FLOAT32", "DIM", "2"]),
+        Reply::error("ERR Missing mandatory parameter: cannot create FLAT index without specifying DISTANCE_METRIC argument")
+    );
+    assert_eq!(
+        run::<CreateIndexCommand>(&db, &["FT.CREATE", "i", "SCHEMA", "v", "VECTOR", "FLAT", "2", "M", "4"]),
+        Reply::error("ERR Bad arguments for vector similarity FLAT index M")
+    );
+    assert_eq!(
+        run::<CreateIndexCommand>(&db, &["FT.CREATE", "i", "SCHEMA", "v", "VECTOR", "FLAT", "6", "TYPE", "FLOAT32", "DIM", "4611686018427387904", "DISTANCE_METRIC", "L2"]),
+        Reply::error("ERR Bad arguments for vector similarity FLAT index DIM")
+    );
+    assert_eq!(
+        run::<CreateIndexCommand>(&db, &["FT.CREATE", "i", "SCHEMA", "v", "VECTOR", "HNSW", "8", "TYPE", "FLOAT32", "DIM", "2", "DISTANCE_METRIC", "L2", "M", "9223372036854775808"]),
+        Reply::error("ERR Bad arguments for vector similarity HNSW index M")
+    );
+    assert_eq!(run::<CreateIndexCommand>(&db, &["FT.CREATE", "i", "SCHEMA", "a", "TEXT", "a", "TAG"]), Reply::error("ERR Duplicate field in schema - a"));
+    assert!(matches!(run::<CreateIndexCommand>(&db, &["FT.CREATE", "i", "ON", "JSON", "SCHEMA", "$.", "TEXT"]), Reply::Error(_)));
+    assert_eq!(run::<CreateIndexCommand>(&db, &["FT.CREATE", "i", "SCHEMA"]), Reply::error("ERR Fields arguments are missing"));
+    assert_eq!(
+        run::<CreateIndexCommand>(&db, &["FT.CREATE", "i", "PREFIX", "18446744073709551615", "a:", "SCHEMA", "a", "TEXT"]),
+        Reply::error("ERR syntax error")
+    );
+    assert_eq!(
+        run::<SearchCommand>(&db, &["FT.SEARCH", "i", "*", "RETURN", "18446744073709551615", "a"]),
+        Reply::error("ERR syntax error")
+    );
+}
+
+// Tests für die Befehlsregistrierung
+#[test]
+fn test_lookup_is_case_insensitive() {
+    let spec = lookup(b"GeT").unwrap();
+
+    assert_eq!(spec.name, "get");
+    assert!(spec.has_flag(Flag::ReadOnly));
+    assert!(lookup(b"nonexistent").is_none());
+}
+
+#[test]
+fn test_arity_check() {
+    let get = lookup(b"get").unwrap();
+    let exists = lookup(b"exists").unwrap();
+
+    assert!(get.accepts(2));
+    assert!(!get.accepts(3));
+    assert!(!exists.accepts(1));
+    assert!(exists.accepts(4));
+}
+
+#[test]
+fn test_set_parse_options() {
+    let args = |list: &[&str]| list.iter().map(|s| s.as_bytes().to_vec()).collect::<Vec<_>>();
+
+    assert!(SetCommand::parse(&args(&["SET", "k", "v", "ex", "10"])).is_ok());
+    assert_eq!(SetCommand::parse(&args(&["SET", "k", "v", "EX"])).err(), Some(Reply::error("ERR syntax error")));
+    assert_eq!(SetCommand::parse(&args(&["SET", "k", "v", "EX", "1", "PX", "1"])).err(), Some(Reply::error("ERR syntax error")));
+    assert_eq!(
+        SetCommand::parse(&args(&["SET", "k", "v", "PX", "abc"])).err(),
+        Some(Reply::error("ERR value is not an integer or out of range"))
+    );
+}
+
+// Tests für die Listen-Befehle
+#[test]
+fn test_push_and_range() {
+    let db = new_db();
+
+    assert_eq!(run::<PushCommand>(&db, &["RPUSH", "list", "a", "b"]), Reply::Integer(2));
+    assert_eq!(run::<PushCommand>(&db, &["LPUSH", "list", "x", "y"]), Reply::Integer(4));
+    assert_eq!(run::<LRangeCommand>(&db, &["LRANGE", "list", "0", "-1"]), bulks(&["y", "x", "a", "b"]));
+    assert_eq!(run::<LRangeCommand>(&db, &["LRANGE", "list", "-2", "100"]), bulks(&["a", "b"]));
+    assert_eq!(run::<LRangeCommand>(&db, &["LRANGE", "list", "3", "1"]), bulks(&[]));
+    assert_eq!(run::<LIndexCommand>(&db, &["LINDEX", "list", "-1"]), Reply::Bulk(b"b".to_vec()));
+    assert_eq!(run::<LIndexCommand>(&db, &["LINDEX", "list", "4"]), Reply::Null);
+}
+
+#[test]
+fn test_pop_with_count_deletes_empty_list() {
+    let db = new_db();
+    run::<PushCommand>(&db, &["RPUSH", "list", "a", "b", "c"]);
+
+    assert_eq!(run::<PopCommand>(&db, &["RPOP", "list", "2"]), bulks(&["c", "b"]));
+    assert_eq!(run::<PopCommand>(&db, &["LPOP", "list"]), Reply::Bulk(b"a".to_vec()));
+    assert!(!db.lock().unwrap().contains_key(b"list"));
+    assert_eq!(run::<PopCommand>(&db, &["LPOP", "list"]), Reply::Null);
+    assert_eq!(run::<PopCommand>(&db, &["LPOP", "list", "2"]), Reply::NullArray);
+    assert_eq!(
+        run::<PopCommand>(&db, &["LPOP", "list", "-1"]),
+        Reply::error("ERR value is out of range, must be positive")
+    );
+}
+
+#[test]
+fn test_lrem_ltrim_lset_linsert() {
+    let db = new_db();
+    run::<PushCommand>(&db, &["RPUSH", "list", "a", "b", "a", "c", "a"]);
+
+    assert_eq!(run::<LRemCommand>(&db, &["LREM", "list", "-2", "a"]), Reply::Integer(2));
+    assert_eq!(run::<LRangeCommand>(&db, &["LRANGE", "list", "0", "-1"]), bulks(&["a", "b", "c"]));
+    run::<PushCommand>(&db, &["RPUSH", "other", "a", "b", "a"]);
+    assert_eq!(run::<LRemCommand>(&db, &["LREM", "other", "-5", "a"]), Reply::Integer(2));
+    assert_eq!(run::<LRangeCommand>(&db, &["LRANGE", "other", "0", "-1"]), bulks(&["b"]));
+    assert_eq!(run::<LInsertCommand>(&db, &["LINSERT", "list", "AFTER", "b", "x"]), Reply::Integer(4));
+    assert_eq!(run::<LInsertCommand>(&db, &["LINSERT", "list", "BEFORE", "zz", "x"]), Reply::Integer(-1));
+    assert_eq!(run::<LSetCommand>(&db, &["LSET", "list", "-1", "z"]), Reply::ok());
+    assert_eq!(run::<LSetCommand>(&db, &["LSET", "list", "9", "z"]), Reply::error("ERR index out of range"));
+    assert_eq!(run::<LTrimCommand>(&db, &["LTRIM", "list", "1", "-2"]), Reply::ok());
+    assert_eq!(run::<LRangeCommand>(&db, &["LRANGE", "list", "0", "-1"]), bulks(&["b", "x"]));
+    assert_eq!(run::<LTrimCommand>(&db, &["LTRIM", "list", "5", "10"]), Reply::ok());
+    assert_eq!(run::<LLenCommand>(&db, &["LLEN", "list"]), Reply::Integer(0));
+}
+
+#[test]
+fn test_lmove() {
+    let db = new_db();
+    run::<PushCommand>(&db, &["RPUSH", "src", "a", "b"]);
+    run::<SetCommand>(&db, &["SET", "str", "v"]);
+
+    assert_eq!(run::<LMoveCommand>(&db, &["LMOVE", "src", "dst", "RIGHT", "LEFT"]), Reply::Bulk(b"b".to_vec()));
+    assert_eq!(run::<LMoveCommand>(&db, &["LMOVE", "src", "str", "LEFT", "LEFT"]), Reply::wrong_type());
+    assert_eq!(run::<LMoveCommand>(&db, &["LMOVE", "src", "src", "LEFT", "RIGHT"]), Reply::Bulk(b"a".to_vec()));
+    assert_eq!(run::<LRangeCommand>(&db, &["LRANGE", "src", "0", "-1"]), bulks(&["a"]));
+    assert_eq!(run::<LMoveCommand>(&db, &["LMOVE", "missing", "dst", "LEFT", "LEFT"]), Reply::Null);
+}
+
+#[test]
+fn test_blpop_serves_available_data_immediately() {
+    let db = new_db();
+    run::<PushCommand>(&db, &["RPUSH", "second", "x"]);
+    run::<SetCommand>(&db, &["SET", "str", "v"]);
+
+    assert_eq!(block::<BPopCommand>(&db, &["BLPOP", "first", "second", "0"]).err(), Some(bulks(&["second", "x"])));
+    assert_eq!(block::<BPopCommand>(&db, &["BLPOP", "str", "0"]).err(), Some(Reply::wrong_type()));
+    assert_eq!(
+        block::<BPopCommand>(&db, &["BLPOP", "first", "-1"]).err(),
+        Some(Reply::error("ERR timeout is negative"))
+    );
+}
+
+#[test]
+fn test_blocking_timeout_range() {
+    let db = new_db();
+    for timeout in ["1e20", "1e19"] {
+        assert_eq!(
+            block::<BPopCommand>(&db, &["BLPOP", "k", timeout]).err(),
+            Some(Reply::error("ERR timeout is out of range"))
+        );
+    }
+    assert!(block::<BPopCommand>(&db, &["BLPOP", "k", "1e15"]).is_ok());
+}
+
+#[test]
+fn test_blocked_clients_are_served_in_fifo_order() {
+    let db = new_db();
+    let mut first = block::<BPopCommand>(&db, &["BRPOP", "queue", "0"]).unwrap();
+    let mut second = block::<BPopCommand>(&db, &["BLPOP", "other", "queue", "0"]).unwrap();
+    assert_eq!(first.timeout, None);
+
+    assert_eq!(run::<PushCommand>(&db, &["RPUSH", "queue", "a"]), Reply::Integer(1));
+    assert_eq!(first.reply.try_recv().unwrap(), bulks(&["queue", "a"]));
+    assert!(second.reply.try_recv().is_err());
+
+    run::<PushCommand>(&db, &["RPUSH", "queue", "b", "c"]);
+    assert_eq!(second.reply.try_recv().unwrap(), bulks(&["queue", "b"]));
+    assert_eq!(run::<LRangeCommand>(&db, &["LRANGE", "queue", "0", "-1"]), bulks(&["c"]));
+}
+
+#[test]
+fn test_blmove_wakes_clients_blocked_on_destination() {
+    let db = new_db();
+    let mut mover = block::<BLMoveCommand>(&db, &["BLMOVE", "src", "dst", "LEFT", "RIGHT", "0.5"]).unwrap();
+    let mut popper = block::<BPopCommand>(&db, &["BLPOP", "dst", "0"]).unwrap();
+    assert_eq!(mover.timeout, Some(Duration::from_millis(500)));
+    assert_eq!(mover.timeout_reply, Reply::Null);
+
+    run::<PushCommand>(&db, &["LPUSH", "src", "job"]);
+    assert_eq!(mover.reply.try_recv().unwrap(), Reply::Bulk(b"job".to_vec()));
+    assert_eq!(popper.reply.try_recv().unwrap(), bulks(&["dst", "job"]));
+    assert_eq!(run::<LLenCommand>(&db, &["LLEN", "dst"]), Reply::Integer(0));
+}
+
+#[test]
+fn test_blmove_reports_destination_of_wrong_type() {
+    let db = new_db();
+    let mut mover = block::<BLMoveCommand>(&db, &["BLMOVE", "src", "dst", "LEFT", "RIGHT", "0"]).unwrap();
+    run::<SetCommand>(&db, &["SET", "dst", "v"]);
+
+    run::<PushCommand>(&db, &["LPUSH", "src", "job"]);
+    assert_eq!(mover.reply.try_recv().unwrap(), Reply::wrong_type());
+    assert_eq!(run::<LLenCommand>(&db, &["LLEN", "src"]), Reply::Integer(1));
+    assert!(!db.lock().unwrap().unblock(mover.id));
+}
+
+#[test]
+fn test_unblocked_client_is_not_served() {
+    let db = new_db();
+    let parked = block::<BPopCommand>(&db, &["BLPOP", "queue", "1"]).unwrap();
+    assert!(db.lock().unwrap().unblock(parked.id));
+
+    run::<PushCommand>(&db, &["RPUSH", "queue", "a"]);
+    assert_eq!(run::<LLenCommand>(&db, &["LLEN", "queue"]), Reply::Integer(1));
+    assert!(!db.lock().unwrap().unblock(parked.id));
+}
+
+// Tests für die Hash-Befehle
+#[test]
+fn test_hset_hget_hdel() {
+    let db = new_db();
+    assert_eq!(run::<HSetCommand>(&db, &["HSET", "user", "name", "ada", "age", "36"]), Reply::Integer(2));
+    assert_eq!(run::<HSetCommand>(&db, &["HSET", "user", "name", "grace"]), Reply::Integer(0));
+    assert_eq!(
+        run::<HSetCommand>(&db, &["HSET", "user", "name"]),
+        Reply::error("ERR wrong number of arguments for 'hset' command")
+    );
+    assert_eq!(run::<HSetNxCommand>(&db, &["HSETNX", "user", "name", "x"]), Reply::Integer(0));
+    assert_eq!(run::<HGetCommand>(&db, &["HGET", "user", "name"]), Reply::Bulk(b"grace".to_vec()));
+    assert_eq!(
+        run::<HMGetCommand>(&db, &["HMGET", "user", "age", "missing"]),
+        Reply::Array(vec![Reply::Bulk(b"36".to_vec()), Reply::Null])
+    );
+    assert_eq!(run::<HExistsCommand>(&db, &["HEXISTS", "user", "age"]), Reply::Integer(1));
+    assert_eq!(run::<HDelCommand>(&db, &["HDEL", "user", "name", "age", "nope"]), Reply::Integer(2));
+    assert!(!db.lock().unwrap().contains_key(b"user"));
+    assert_eq!(run::<HGetAllCommand>(&db, &["HGETALL", "user"]), Reply::Map(Vec::new()));
+}
+
+#[test]
+fn test_hash_increments() {
+    let db = new_db();
+    assert_eq!(run::<HIncrByCommand>(&db, &["HINCRBY", "h", "n", "5"]), Reply::Integer(5));
+    assert_eq!(run::<HIncrByCommand>(&db, &["HINCRBY", "h", "n", "-7"]), Reply::Integer(-2));
+    run::<HSetCommand>(&db, &["HSET", "h", "s", "abc", "max", &i64::MAX.to_string()]);
+    assert_eq!(run::<HIncrByCommand>(&db, &["HINCRBY", "h", "s", "1"]), Reply::error("ERR hash value is not an integer"));
+    assert_eq!(
+        run::<HIncrByCommand>(&db, &["HINCRBY", "h", "max", "1"]),
+        Reply::error("ERR increment or decrement would overflow")
+    );
+
+    assert_eq!(run::<HIncrByFloatCommand>(&db, &["HINCRBYFLOAT", "h", "f", "10.5"]), Reply::Bulk(b"10.5".to_vec()));
+    assert_eq!(run::<HIncrByFloatCommand>(&db, &["HINCRBYFLOAT", "h", "n", "0.5"]), Reply::Bulk(b"-1.5".to_vec()));
+    assert_eq!(run::<HIncrByFloatCommand>(&db, &["HINCRBYFLOAT", "h", "s", "1"]), Reply::error("ERR hash value is not a float"));
+    assert_eq!(run::<HLenCommand>(&db, &["HLEN", "h"]), Reply::Integer(4));
+}
+
+#[test]
+fn test_hscan_visits_every_field() {
+    let db = new_db();
+    for i in 0..25 {
+        run::<HSetCommand>(&db, &["HSET", "h", &format!("field:{}", i), "v"]);
+    }
+
+    let mut cursor = "0".to_string();
+    let mut seen = Vec::new();
+    let mut pages = 0;
+    loop {
+        let Reply::Array(reply) = run::<HScanCommand>(&db, &["HSCAN", "h", &cursor, "COUNT", "7", "NOVALUES"]) else {
+            panic!("HSCAN must reply with an array");
+        };
+        let [Reply::Bulk(next), Reply::Array(fields)] = &reply[..] else {
+            panic!("unexpected HSCAN reply");
+        };
+        // COUNT is a hint: a page ends with the bucket that reached it.
+        pages += 1;
+        seen.extend(fields.iter().cloned());
+        cursor = String::from_utf8(next.clone()).unwrap();
+        if cursor == "0" {
+            break;
+        }
+    }
+    seen.sort_by_key(|field| format!("{:?}", field));
+    seen.dedup();
+    assert_eq!(seen.len(), 25);
+    assert!(pages > 1);
+
+    let Reply::Array(reply) = run::<HScanCommand>(&db, &["HSCAN", "h", "0", "MATCH", "field:1?", "COUNT", "100"]) else {
+        panic!("HSCAN must reply with an array");
+    };
+    assert_eq!(reply[0], Reply::Bulk(b"0".to_vec()));
+    assert!(matches!(&reply[1], Reply::Array(pairs) if pairs.len() == 20));
+    assert_eq!(run::<HScanCommand>(&db, &["HSCAN", "h", "0", "TYPE", "hash"]), Reply::error("ERR syntax error"));
+}
+
+fn ints(values: &[i64]) -> Reply {
+    Reply::Array(values.iter().map(|value| Reply::Integer(*value)).collect())
+}
+
+#[test]
+fn test_hash_field_expiry() {
+    let db = new_db();
+    run::<HSetCommand>(&db, &["HSET", "sessions", "phone", "t1", "laptop", "t2"]);
+
+    assert_eq!(
+        run::<HExpireCommand>(&db, &["HEXPIRE", "sessions", "100", "FIELDS", "2", "phone", "tablet"]),
+        ints(&[1, -2])
+    );
+    assert_eq!(run::<HExpireCommand>(&db, &["HEXPIRE", "sessions", "50", "GT", "FIELDS", "1", "phone"]), ints(&[0]));
+    assert_eq!(run::<HExpireCommand>(&db, &["HEXPIRE", "sessions", "50", "NX", "FIELDS", "1", "laptop"]), ints(&[1]));
+    assert_eq!(
+        run::<HTtlCommand>(&db, &["HTTL", "sessions", "FIELDS", "3", "phone", "laptop", "tablet"]),
+        ints(&[99, 49, -2])
+    );
+    assert_eq!(run::<HPersistCommand>(&db, &["HPERSIST", "sessions", "FIELDS", "1", "laptop"]), ints(&[1]));
+    assert_eq!(run::<HTtlCommand>(&db, &["HPTTL", "sessions", "FIELDS", "1", "laptop"]), ints(&[-1]));
+    assert_eq!(
+        run::<HTtlCommand>(&db, &["HTTL", "sessions", "FIELDS", "2", "phone"]),
+        Reply::error("ERR The `numfields` parameter must match the number of arguments")
+    );
+
+    // Overwriting a field clears its deadline, incrementing keeps it.
+    run::<HSetCommand>(&db, &["HSET", "sessions", "phone", "t3"]);
+    assert_eq!(run::<HTtlCommand>(&db, &["HTTL", "sessions", "FIELDS", "1", "phone"]), ints(&[-1]));
+    run::<HSetCommand>(&db, &["HSET", "sessions", "count", "1"]);
+    run::<HExpireCommand>(&db, &["HEXPIRE", "sessions", "100", "FIELDS", "1", "count"]);
+    run::<HIncrByCommand>(&db, &["HINCRBY", "sessions", "count", "1"]);
+    assert_eq!(run::<HTtlCommand>(&db, &["HTTL", "sessions", "FIELDS", "1", "count"]), ints(&[99]));
+
+    assert_eq!(run::<HExpireCommand>(&db, &["HEXPIRE", "sessions", "0", "FIELDS", "1", "count"]), ints(&[2]));
+    assert_eq!(run::<HLenCommand>(&db, &["HLEN", "sessions"]), Reply::Integer(2));
+}
+
+#[test]
+fn test_hexpire_rejects_overflowing_deadline() {
+    let db = new_db();
+    run::<HSetCommand>(&db, &["HSET", "h", "f", "v"]);
+    assert_eq!(
+        run::<HExpireCommand>(&db, &["HEXPIRE", "h", "9223372036854775807", "FIELDS", "1", "f"]),
+        Reply::error("ERR invalid expire time in 'hexpire' command")
+    );
+    // The keyspace lock is still usable afterwards.
+    assert_eq!(run::<HTtlCommand>(&db, &["HTTL", "h", "FIELDS", "1", "f"]), ints(&[-1]));
+}
+
+#[test]
+fn test_expired_fields_are_purged() {
+    let db = new_db();
+    run::<HSetCommand>(&db, &["HSET", "h", "a", "1", "b", "2"]);
+    run::<HExpireCommand>(&db, &["HPEXPIRE", "h", "1", "FIELDS", "1", "a"]);
+    std::thread::sleep(Duration::from_millis(5));
+    assert_eq!(run::<HGetCommand>(&db, &["HGET", "h", "a"]), Reply::Null);
+    assert_eq!(run::<HLenCommand>(&db, &["HLEN", "h"]), Reply::Integer(1));
+
+    // Active expiry removes the key once its last field is gone.
+    run::<HExpireCommand>(&db, &["HPEXPIRE", "h", "1", "FIELDS", "1", "b"]);
+    std::thread::sleep(Duration::from_millis(5));
+    assert_eq!(db.lock().unwrap().expire_cycle(Instant::now()), 1);
+    assert_eq!(run::<HGetAllCommand>(&db, &["HGETALL", "h"]), Reply::Map(Vec::new()));
+}
+
+#[test]
+fn test_scan_options_and_glob() {
+    let args: Vec<Vec<u8>> = ["0", "match", "a*", "COUNT", "5", "type", "hash"].iter().map(|arg| arg.as_bytes().to_vec()).collect();
+    assert_eq!(
+        ScanOptions::parse(&args, &["TYPE"]),
+        Ok(ScanOptions { cursor: 0, pattern: Some(b"a*".to_vec()), count: 5, novalues: false, type_name: Some(b"hash".to_vec()) })
+    );
+    assert_eq!(ScanOptions::parse(&args, &["NOVALUES"]), Err(Reply::error("ERR syntax error")));
+    assert_eq!(ScanOptions::parse(&[b"x".to_vec()], &[]), Err(Reply::error("ERR invalid cursor")));
+
+    assert!(glob_match(b"h*llo", b"heeello"));
+    assert!(glob_match(b"h?llo", b"hallo"));
+    assert!(glob_match(b"h[ae]llo", b"hello"));
+    assert!(!glob_match(b"h[^e]llo", b"hello"));
+    assert!(glob_match(b"h[a-c]llo", b"hbllo"));
+    assert!(glob_match(b"user:\\*", b"user:*"));
+    assert!(glob_match(b"*", b""));
+    assert!(!glob_match(b"a*b", b"acbd"));
+}
+
+// Tests für die Set-Befehle
+fn sorted_members(reply: Reply) -> Vec<Vec<u8>> {
+    let (Reply::Set(items) | Reply::Array(items)) = reply else {
+        panic!("expected a collection, got {:?}", reply);
+    };
+    let mut members: Vec<Vec<u8>> = items
+        .into_iter()
+        .map(|item| match item {
+            Reply::Bulk(member) => member,
+            other => panic!("expected a bulk string, got {:?}", other),
+        })
+        .collect();
+    members.sort();
+    members
+}
+
+fn members(items: &[&str]) -> Vec<Vec<u8>> {
+    items.iter().map(|item| item.as_bytes().to_vec()).collect()
+}
+
+#[test]
+fn test_set_membership() {
+    let db = new_db();
+    assert_eq!(run::<SAddCommand>(&db, &["SADD", "tags", "a", "b", "a"]), Reply::Integer(2));
+    assert_eq!(run::<SIsMemberCommand>(&db, &["SISMEMBER", "tags", "a"]), Reply::Integer(1));
+    assert_eq!(run::<SIsMemberCommand>(&db, &["SMISMEMBER", "tags", "a", "z"]), ints(&[1, 0]));
+    assert_eq!(run::<SCardCommand>(&db, &["SCARD", "tags"]), Reply::Integer(2));
+    assert_eq!(sorted_members(run::<SMembersCommand>(&db, &["SMEMBERS", "tags"])), members(&["a", "b"]));
+    assert_eq!(run::<SRemCommand>(&db, &["SREM", "tags", "a", "b", "c"]), Reply::Integer(2));
+    assert!(!db.lock().unwrap().contains_key(b"tags"));
+
+    run::<SAddCommand>(&db, &["SADD", "s", "1", "2", "3"]);
+    assert_eq!(sorted_members(run::<SRandMemberCommand>(&db, &["SRANDMEMBER", "s", "10"])), members(&["1", "2", "3"]));
+    let Reply::Array(repeated) = run::<SRandMemberCommand>(&db, &["SRANDMEMBER", "s", "-5"]) else { panic!() };
+    assert_eq!(repeated.len(), 5);
+    assert!(matches!(run::<SRandMemberCommand>(&db, &["SRANDMEMBER", "s"]), Reply::Bulk(member) if ["1", "2", "3"].contains(&std::str::from_utf8(&member).unwrap())));
+    assert_eq!(
+        run::<SRandMemberCommand>(&db, &["SRANDMEMBER", "s", "-1000000000000"]),
+        Reply::error("ERR value is out of range")
+    );
+    run::<SAddCommand>(&db, &["SADD", "words", "a", "b"]);
+    let Reply::Array(repeated) = run::<SRandMemberCommand>(&db, &["SRANDMEMBER", "words", "-3"]) else { panic!() };
+    assert!(repeated.iter().all(|member| matches!(member, Reply::Bulk(m) if m == b"a" || m == b"b")));
+    let words: Vec<String> = (0..600).map(|i| format!("word:{i}")).collect();
+    run::<SAddCommand>(&db, &[&["SADD", "many"], &words.iter().map(String::as_str).collect::<Vec<_>>()[..]].concat());
+    let mut drawn = sorted_members(run::<SRandMemberCommand>(&db, &["SRANDMEMBER", "many", "50"]));
+    drawn.dedup();
+    assert_eq!(drawn.len(), 50);
+    let mut popped = sorted_members(run::<SPopCommand>(&db, &["SPOP", "many", "10"]));
+    popped.dedup();
+    assert_eq!(popped.len(), 10);
+    assert!(matches!(run::<SPopCommand>(&db, &["SPOP", "many"]), Reply::Bulk(_)));
+    assert_eq!(run::<SCardCommand>(&db, &["SCARD", "many"]), Reply::Integer(589));
+    assert_eq!(sorted_members(run::<SPopCommand>(&db, &["SPOP", "many", "1000"])).len(), 589);
+    assert!(!db.lock().unwrap().contains_key(b"many"));
+
+    let popped = sorted_members(run::<SPopCommand>(&db, &["SPOP", "s", "2"]));
+    assert_eq!(popped.len(), 2);
+    assert_eq!(run::<SCardCommand>(&db, &["SCARD", "s"]), Reply::Integer(1));
+    assert_eq!(run::<SPopCommand>(&db, &["SPOP", "missing"]), Reply::Null);
+}
+
+#[test]
+fn test_set_algebra() {
+    let db = new_db();
+    run::<SAddCommand>(&db, &["SADD", "a", "x", "y", "z"]);
+    run::<SAddCommand>(&db, &["SADD", "b", "y", "z", "w"]);
+    run::<SetCommand>(&db, &["SET", "str", "v"]);
+
+    assert_eq!(sorted_members(run::<SetOpCommand>(&db, &["SINTER", "a", "b"])), members(&["y", "z"]));
+    assert_eq!(sorted_members(run::<SetOpCommand>(&db, &["SUNION", "a", "b", "missing"])), members(&["w", "x", "y", "z"]));
+    assert_eq!(sorted_members(run::<SetOpCommand>(&db, &["SDIFF", "a", "b"])), members(&["x"]));
+    assert_eq!(run::<SetOpCommand>(&db, &["SINTER", "a", "str"]), Reply::wrong_type());
+
+    assert_eq!(run::<SetOpCommand>(&db, &["SINTERSTORE", "dst", "a", "b"]), Reply::Integer(2));
+    assert_eq!(sorted_members(run::<SMembersCommand>(&db, &["SMEMBERS", "dst"])), members(&["y", "z"]));
+    assert_eq!(run::<SetOpCommand>(&db, &["SDIFFSTORE", "dst", "a", "a"]), Reply::Integer(0));
+    assert!(!db.lock().unwrap().contains_key(b"dst"));
+
+    assert_eq!(run::<SInterCardCommand>(&db, &["SINTERCARD", "2", "a", "b"]), Reply::Integer(2));
+    assert_eq!(run::<SInterCardCommand>(&db, &["SINTERCARD", "2", "a", "b", "LIMIT", "1"]), Reply::Integer(1));
+    assert_eq!(
+        run::<SInterCardCommand>(&db, &["SINTERCARD", "3", "a", "b"]),
+        Reply::error("ERR Number of keys can't be greater than number of args")
+    );
+}
+
+#[test]
+fn test_intset_encoding() {
+    let mut set: Set = ["3", "1", "2"].into_iter().collect();
+    assert_eq!(set, Set::IntSet(vec![1, 2, 3]));
+    assert!(set.contains(b"2"));
+    // Non-canonical integers are kept verbatim and force a hash table.
+    assert!(!set.contains(b"02"));
+    assert!(set.insert(b"02"));
+    assert_eq!(set.encoding(), "hashtable");
+    assert!(set.contains(b"2") && set.contains(b"02"));
+
+    let large: Set = (0..600).map(|n: i32| n.to_string()).collect();
+    assert_eq!(large.encoding(), "hashtable");
+    assert_eq!(large.len(), 600);
+}
+
+#[test]
+fn test_sscan() {
+    let db = new_db();
+    run::<SAddCommand>(&db, &["SADD", "s", "a", "b", "c"]);
+    assert_eq!(
+        sorted_members(match run::<SScanCommand>(&db, &["SSCAN", "s", "0", "COUNT", "100"]) {
+            Reply::Array(mut reply) => reply.pop().unwrap(),
+            other => other,
+        }),
+        members(&["a", "b", "c"])
+    );
+    assert_eq!(run::<SScanCommand>(&db, &["SSCAN", "s", "0", "NOVALUES"]), Reply::error("ERR syntax error"));
+    assert_eq!(run::<SScanCommand>(&db, &["SSCAN", "s", "0", "TYPE", "set"]), Reply::error("ERR syntax error"));
+
+    let words: Vec<String> = (0..40).map(|i| format!("word:{i}")).collect();
+    run::<SAddCommand>(&db, &[&["SADD", "words"], &words.iter().map(String::as_str).collect::<Vec<_>>()[..]].concat());
+    for word in &words[..10] {
+        run::<SRemCommand>(&db, &["SREM", "words", word]);
+    }
+    let mut seen = Vec::new();
+    let mut cursor = "0".to_string();
+    loop {
+        let Reply::Array(mut reply) = run::<SScanCommand>(&db, &["SSCAN", "words", &cursor, "COUNT", "5"]) else {
+            panic!("SSCAN must reply with an array");
+        };
+        seen.extend(sorted_members(reply.pop().unwrap()));
+        let Some(Reply::Bulk(next)) = reply.pop() else { panic!("unexpected SSCAN reply") };
+        cursor = String::from_utf8(next).unwrap();
+        if cursor == "0" {
+            break;
+        }
+    }
+    seen.sort();
+    assert_eq!(seen, members(&words[10..].iter().map(String::as_str).collect::<Vec<_>>()));
+}
+
+// Tests für die Sorted-Set-Befehle
+fn scored(pairs: &[(&str, f64)]) -> Reply {
+    Reply::Pairs(pairs.iter().map(|(member, score)| (Reply::Bulk(member.as_bytes().to_vec()), Reply::Double(*score))).collect())
+}
+
+#[test]
+fn test_zadd_options() {
+    let db = new_db();
+    assert_eq!(run::<ZAddCommand>(&db, &["ZADD", "z", "1", "a", "2", "b"]), Reply::Integer(2));
+    assert_eq!(run::<ZAddCommand>(&db, &["ZADD", "z", "CH", "5", "a", "3", "c"]), Reply::Integer(2));
+    assert_eq!(run::<ZAddCommand>(&db, &["ZADD", "z", "XX", "9", "d"]), Reply::Integer(0));
+    assert_eq!(run::<ZAddCommand>(&db, &["ZADD", "z", "NX", "9", "a"]), Reply::Integer(0));
+    assert_eq!(run::<ZAddCommand>(&db, &["ZADD", "z", "GT", "CH", "4", "a"]), Reply::Integer(0));
+    assert_eq!(run::<ZAddCommand>(&db, &["ZADD", "z", "LT", "CH", "4", "a"]), Reply::Integer(1));
+    assert_eq!(run::<ZAddCommand>(&db, &["ZADD", "z", "INCR", "1.5", "a"]), Reply::Double(5.5));
+    assert_eq!(run::<ZAddCommand>(&db, &["ZADD", "z", "NX", "INCR", "1", "a"]), Reply::Null);
+    assert_eq!(
+        run::<ZAddCommand>(&db, &["ZADD", "z", "NX", "XX", "1", "a"]),
+        Reply::error("ERR XX and NX options at the same time are not compatible")
+    );
+    assert_eq!(run::<ZAddCommand>(&db, &["ZADD", "z", "x", "a"]), Reply::error("ERR value is not a valid float"));
+    assert_eq!(run::<ZIncrByCommand>(&db, &["ZINCRBY", "z", "-1", "b"]), Reply::Double(1.0));
+    assert_eq!(
+        run::<ZScoreCommand>(&db, &["ZMSCORE", "z", "a", "b", "nope"]),
+        Reply::Array(vec![Reply::Double(5.5), Reply::Double(1.0), Reply::Null])
+    );
+    assert_eq!(run::<ZCardCommand>(&db, &["ZCARD", "z"]), Reply::Integer(3));
+}
+
+#[test]
+fn test_zrange_variants() {
+    let db = new_db();
+    run::<ZAddCommand>(&db, &["ZADD", "z", "1", "a", "2", "b", "3", "c", "4", "d"]);
+
+    assert_eq!(run::<ZRangeCommand>(&db, &["ZRANGE", "z", "1", "-2"]), bulks(&["b", "c"]));
+    assert_eq!(run::<ZRangeCommand>(&db, &["ZRANGE", "z", "0", "1", "REV", "WITHSCORES"]), scored(&[("d", 4.0), ("c", 3.0)]));
+    assert_eq!(run::<ZRangeCommand>(&db, &["ZRANGE", "z", "(1", "3", "BYSCORE"]), bulks(&["b", "c"]));
+    assert_eq!(
+        run::<ZRangeCommand>(&db, &["ZRANGE", "z", "+inf", "-inf", "BYSCORE", "REV", "LIMIT", "1", "2"]),
+        bulks(&["c", "b"])
+    );
+    assert_eq!(run::<ZRangeCommand>(&db, &["ZRANGEBYSCORE", "z", "2", "(4", "LIMIT", "1", "-1"]), bulks(&["c"]));
+    assert_eq!(run::<ZRangeCommand>(&db, &["ZRANGE", "z", "[b", "(d", "BYLEX"]), bulks(&["b", "c"]));
+    assert_eq!(run::<ZRangeCommand>(&db, &["ZREVRANGEBYLEX", "z", "+", "[c"]), bulks(&["d", "c"]));
+    assert_eq!(
+        run::<ZRangeCommand>(&db, &["ZRANGE", "z", "0", "1", "LIMIT", "0", "1"]),
+        Reply::error("ERR syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX")
+    );
+    assert_eq!(run::<ZCountCommand>(&db, &["ZCOUNT", "z", "-inf", "(3"]), Reply::Integer(2));
+    assert_eq!(run::<ZCountCommand>(&db, &["ZLEXCOUNT", "z", "-", "+"]), Reply::Integer(4));
+    assert_eq!(run::<ZRankCommand>(&db, &["ZRANK", "z", "c"]), Reply::Integer(2));
+    assert_eq!(
+        run::<ZRankCommand>(&db, &["ZREVRANK", "z", "c", "WITHSCORE"]),
+        Reply::Array(vec![Reply::Integer(1), Reply::Double(3.0)])
+    );
+}
+
+#[test]
+fn test_zset_removal_and_pops() {
+    let db = new_db();
+    run::<ZAddCommand>(&db, &["ZADD", "z", "1", "a", "2", "b", "3", "c", "4", "d", "5", "e"]);
+
+    assert_eq!(run::<ZRemCommand>(&db, &["ZREM", "z", "a", "x"]), Reply::Integer(1));
+    assert_eq!(run::<ZRemRangeCommand>(&db, &["ZREMRANGEBYSCORE", "z", "4", "+inf"]), Reply::Integer(2));
+    assert_eq!(run::<ZPopCommand>(&db, &["ZPOPMAX", "z"]), Reply::Array(vec![Reply::Bulk(b"c".to_vec()), Reply::Double(3.0)]));
+    assert_eq!(run::<ZPopCommand>(&db, &["ZPOPMIN", "z", "5"]), scored(&[("b", 2.0)]));
+    assert!(!db.lock().unwrap().contains_key(b"z"));
+    assert_eq!(run::<ZPopCommand>(&db, &["ZPOPMIN", "z"]), Reply::Array(Vec::new()));
+}
+
+#[test]
+fn test_zunionstore_and_zinterstore() {
+    let db = new_db();
+    run::<ZAddCommand>(&db, &["ZADD", "a", "1", "x", "2", "y"]);
+    run::<ZAddCommand>(&db, &["ZADD", "b", "10", "y", "20", "z"]);
+    run::<SAddCommand>(&db, &["SADD", "s", "y"]);
+
+    assert_eq!(run::<ZStoreCommand>(&db, &["ZUNIONSTORE", "out", "2", "a", "b", "WEIGHTS", "2", "1"]), Reply::Integer(3));
+    assert_eq!(
+        run::<ZRangeCommand>(&db, &["ZRANGE", "out", "0", "-1", "WITHSCORES"]),
+        scored(&[("x", 2.0), ("y", 14.0), ("z", 20.0)])
+    );
+    assert_eq!(run::<ZStoreCommand>(&db, &["ZINTERSTORE", "out", "3", "a", "b", "s", "AGGREGATE", "MAX"]), Reply::Integer(1));
+    assert_eq!(run::<ZRangeCommand>(&db, &["ZRANGE", "out", "0", "-1", "WITHSCORES"]), scored(&[("y", 10.0)]));
+    assert_eq!(
+        run::<ZStoreCommand>(&db, &["ZINTERSTORE", "out", "0", "a"]),
+        Reply::error("ERR at least 1 input key is needed for 'zinterstore' command")
+    );
+}
+
+#[test]
+fn test_zset_ranks_stay_consistent() {
+    let db = new_db();
+    let mut expected: Vec<(i64, String)> = Vec::new();
+    for i in 0..300i64 {
+        let member = format!("m{}", i);
+        let score = (i * 7919) % 101;
+        run::<ZAddCommand>(&db, &["ZADD", "z", &score.to_string(), &member]);
+        expected.push((score, member));
+    }
+    for i in (0..300).step_by(3) {
+        run::<ZRemCommand>(&db, &["ZREM", "z", &format!("m{}", i)]);
+    }
+    expected.retain(|(_, member)| member[1..].parse::<usize>().unwrap() % 3 != 0);
+    expected.sort();
+
+    let members: Vec<&str> = expected.iter().map(|(_, member)| member.as_str()).collect();
+    assert_eq!(run::<ZRangeCommand>(&db, &["ZRANGE", "z", "0", "-1"]), bulks(&members));
+    for (rank, member) in members.iter().enumerate().step_by(17) {
+        assert_eq!(run::<ZRankCommand>(&db, &["ZRANK", "z", member]), Reply::Integer(rank as i64));
+        assert_eq!(run::<ZRangeCommand>(&db, &["ZRANGE", "z", &rank.to_string(), &rank.to_string()]), bulks(&[member]));
+    }
+}
+
+#[test]
+fn test_zmpop() {
+    let db = new_db();
+    run::<ZAddCommand>(&db, &["ZADD", "z", "1", "a", "2", "b", "3", "c"]);
+    let element = |member: &str, score: f64| Reply::Array(vec![Reply::Bulk(member.as_bytes().to_vec()), Reply::Double(score)]);
+
+    assert_eq!(
+        run::<ZMPopCommand>(&db, &["ZMPOP", "2", "missing", "z", "MAX", "COUNT", "2"]),
+        Reply::Array(vec![Reply::Bulk(b"z".to_vec()), Reply::Array(vec![element("c", 3.0), element("b", 2.0)])])
+    );
+    assert_eq!(run::<ZMPopCommand>(&db, &["ZMPOP", "1", "missing", "MIN"]), Reply::NullArray);
+    assert_eq!(run::<ZMPopCommand>(&db, &["ZMPOP", "1", "z", "MIN", "COUNT", "0"]), Reply::error("ERR count should be greater than 0"));
+    assert_eq!(run::<ZMPopCommand>(&db, &["ZMPOP", "3", "z", "MIN"]), Reply::error("ERR syntax error"));
+}
+
+#[test]
+fn test_bzpop_wakes_on_zadd() {
+    let db = new_db();
+    let mut first = block::<BZPopCommand>(&db, &["BZPOPMIN", "jobs", "0"]).unwrap();
+    let mut second = block::<BZMPopCommand>(&db, &["BZMPOP", "1", "1", "jobs", "MAX"]).unwrap();
+    assert_eq!(second.timeout, Some(Duration::from_secs(1)));
+
+    run::<ZAddCommand>(&db, &["ZADD", "jobs", "5", "later", "1", "soon"]);
+    assert_eq!(
+        first.reply.try_recv().unwrap(),
+        Reply::Array(vec![Reply::Bulk(b"jobs".to_vec()), Reply::Bulk(b"soon".to_vec()), Reply::Double(1.0)])
+    );
+    assert_eq!(
+        second.reply.try_recv().unwrap(),
+        Reply::Array(vec![
+            Reply::Bulk(b"jobs".to_vec()),
+            Reply::Array(vec![Reply::Array(vec![Reply::Bulk(b"later".to_vec()), Reply::Double(5.0)])]),
+        ])
+    );
+    assert!(!db.lock().unwrap().contains_key(b"jobs"));
+
+    run::<ZAddCommand>(&db, &["ZADD", "jobs", "1", "now"]);
+    assert!(block::<BZPopCommand>(&db, &["BZPOPMAX", "other", "jobs", "0"]).is_err());
+}
+
+// Tests für die Stream-Befehle
+fn entry(id: &str, fields: &[&str]) -> Reply {
+    Reply::Array(vec![Reply::Bulk(id.as_bytes().to_vec()), bulks(fields)])
+}
+
+#[test]
+fn test_xadd_ids() {
+    let db = new_db();
+    assert_eq!(run::<XAddCommand>(&db, &["XADD", "s", "1-1", "f", "v"]), Reply::Bulk(b"1-1".to_vec()));
+    assert_eq!(run::<XAddCommand>(&db, &["XADD", "s", "1-*", "f", "v"]), Reply::Bulk(b"1-2".to_vec()));
+    assert_eq!(run::<XAddCommand>(&db, &["XADD", "s", "5-*", "f", "v"]), Reply::Bulk(b"5-0".to_vec()));
+    assert_eq!(
+        run::<XAddCommand>(&db, &["XADD", "s", "5-0", "f", "v"]),
+        Reply::error("ERR The ID specified in XADD is equal or smaller than the target stream top item")
+    );
+    let Reply::Bulk(auto) = run::<XAddCommand>(&db, &["XADD", "s", "*", "f", "v"]) else {
+        panic!("expected an ID");
+    };
+    assert!(String::from_utf8(auto).unwrap().ends_with("-0"));
+    assert_eq!(run::<XLenCommand>(&db, &["XLEN", "s"]), Reply::Integer(4));
+
+    assert_eq!(run::<XAddCommand>(&db, &["XADD", "new", "0-0", "f", "v"]), Reply::error("ERR The ID specified in XADD must be greater than 0-0"));
+    assert!(!db.lock().unwrap().contains_key(b"new"));
+    assert_eq!(run::<XAddCommand>(&db, &["XADD", "new", "NOMKSTREAM", "*", "f", "v"]), Reply::Null);
+    assert_eq!(run::<XAddCommand>(&db, &["XADD", "new", "*", "f"]), Reply::error("ERR wrong number of arguments for 'xadd' command"));
+    assert_eq!(run::<XAddCommand>(&db, &["XADD", "new", "1-x", "f", "v"]), Reply::error("ERR Invalid stream ID specified as stream command argument"));
+}
+
+#[test]
+fn test_xrange_and_xrevrange() {
+    let db = new_db();
+    for id in ["1-0", "1-1", "2-0", "3-0"] {
+        run::<XAddCommand>(&db, &["XADD", "s", id, "id", id]);
+    }
+    assert_eq!(
+        run::<XRangeCommand>(&db, &["XRANGE", "s", "-", "+", "COUNT", "2"]),
+        Reply::Array(vec![entry("1-0", &["id", "1-0"]), entry("1-1", &["id", "1-1"])])
+    );
+    assert_eq!(
+        run::<XRangeCommand>(&db, &["XRANGE", "s", "1", "2"]),
+        Reply::Array(vec![entry("1-0", &["id", "1-0"]), entry("1-1", &["id", "1-1"]), entry("2-0", &["id", "2-0"])])
+    );
+    assert_eq!(run::<XRangeCommand>(&db, &["XRANGE", "s", "(1-1", "(3-0"]), Reply::Array(vec![entry("2-0", &["id", "2-0"])]));
+    assert_eq!(
+        run::<XRangeCommand>(&db, &["XREVRANGE", "s", "+", "2", "COUNT", "5"]),
+        Reply::Array(vec![entry("3-0", &["id", "3-0"]), entry("2-0", &["id", "2-0"])])
+    );
+    assert_eq!(run::<XRangeCommand>(&db, &["XRANGE", "s", "3", "1"]), Reply::Array(vec![]));
+    assert_eq!(run::<XRangeCommand>(&db, &["XRANGE", "missing", "-", "+"]), Reply::Array(vec![]));
+}
+
+#[test]
+fn test_xtrim_and_xdel() {
+    let db = new_db();
+    for i in 1..=250 {
+        run::<XAddCommand>(&db, &["XADD", "s", &format!("{i}-0"), "f", "v"]);
+    }
+    assert_eq!(run::<XTrimCommand>(&db, &["XTRIM", "s", "MAXLEN", "~", "120"]), Reply::Integer(100));
+    assert_eq!(run::<XTrimCommand>(&db, &["XTRIM", "s", "MAXLEN", "120"]), Reply::Integer(30));
+    assert_eq!(run::<XTrimCommand>(&db, &["XTRIM", "s", "MINID", "200"]), Reply::Integer(69));
+    assert_eq!(run::<XLenCommand>(&db, &["XLEN", "s"]), Reply::Integer(51));
+    assert_eq!(
+        run::<XTrimCommand>(&db, &["XTRIM", "s", "MAXLEN", "10", "LIMIT", "5"]),
+        Reply::error("ERR syntax error, LIMIT cannot be used without the special ~ option")
+    );
+    assert_eq!(run::<XTrimCommand>(&db, &["XTRIM", "s", "MAXLEN", "-1"]), Reply::error("ERR The MAXLEN argument must be >= 0."));
+
+    run::<XAddCommand>(&db, &["XADD", "s", "MAXLEN", "=", "3", "300-0", "f", "v"]);
+    assert_eq!(run::<XLenCommand>(&db, &["XLEN", "s"]), Reply::Integer(3));
+    assert_eq!(run::<XDelCommand>(&db, &["XDEL", "s", "300-0", "300-0", "1-0"]), Reply::Integer(1));
+    assert_eq!(run::<XLenCommand>(&db, &["XLEN", "s"]), Reply::Integer(2));
+    assert_eq!(
+        run::<XAddCommand>(&db, &["XADD", "s", "300-0", "f", "v"]),
+        Reply::error("ERR The ID specified in XADD is equal or smaller than the target stream top item")
+    );
+}
+
+#[test]
+fn test_xread() {
+    let db = new_db();
+    run::<XAddCommand>(&db, &["XADD", "a", "1-0", "f", "1"]);
+    run::<XAddCommand>(&db, &["XADD", "a", "2-0", "f", "2"]);
+    run::<XAddCommand>(&db, &["XADD", "b", "1-0", "f", "3"]);
+
+    assert_eq!(
+        block::<XReadCommand>(&db, &["XREAD", "COUNT", "1", "STREAMS", "a", "b", "c", "1-0", "0", "0"]).err().unwrap(),
+        Reply::NestedMap(vec![
+            (Reply::Bulk(b"a".to_vec()), Reply::Array(vec![entry("2-0", &["f", "2"])])),
+            (Reply::Bulk(b"b".to_vec()), Reply::Array(vec![entry("1-0", &["f", "3"])])),
+        ])
+    );
+    assert_eq!(
+        block::<XReadCommand>(&db, &["XREAD", "STREAMS", "a", "+"]).err(),
+        Some(Reply::NestedMap(vec![(Reply::Bulk(b"a".to_vec()), Reply::Array(vec![entry("2-0", &["f", "2"])]))]))
+    );
+    assert_eq!(block::<XReadCommand>(&db, &["XREAD", "STREAMS", "a", "$"]).err(), Some(Reply::NullArray));
+    assert_eq!(
+        block::<XReadCommand>(&db, &["XREAD", "STREAMS", "a", "b", "$"]).err(),
+        Some(Reply::error("ERR Unbalanced 'xread' list of streams: for each stream key an ID or '$' must be specified."))
+    );
+}
+
+#[test]
+fn test_xread_block_wakes_on_xadd() {
+    let db = new_db();
+    run::<XAddCommand>(&db, &["XADD", "s", "1-0", "f", "old"]);
+    let mut parked = block::<XReadCommand>(&db, &["XREAD", "BLOCK", "0", "STREAMS", "s", "$"]).unwrap();
+    assert_eq!(parked.timeout, None);
+    assert!(parked.reply.try_recv().is_err());
+
+    run::<XAddCommand>(&db, &["XADD", "s", "2-0", "f", "new"]);
+    assert_eq!(
+        parked.reply.try_recv().unwrap(),
+        Reply::NestedMap(vec![(Reply::Bulk(b"s".to_vec()), Reply::Array(vec![entry("2-0", &["f", "new"])]))])
+    );
+
+    let parked = block::<XReadCommand>(&db, &["XREAD", "BLOCK", "1500", "STREAMS", "missing", "$"]).unwrap();
+    assert_eq!(parked.timeout, Some(Duration::from_millis(1500)));
+}
+
+fn read_group(db: &Db, args: &[&str]) -> Reply {
+    block::<XReadGroupCommand>(db, args).err().expect("XREADGROUP should not block")
+}
+
+fn stream_with_group(db: &Db) {
+    for id in ["1-0", "2-0", "3-0"] {
+        run::<XAddCommand>(db, &["XADD", "s", id, "n", id]);
+    }
+    assert_eq!(run::<XGroupCommand>(db, &["XGROUP", "CREATE", "s", "g", "0"]), Reply::ok());
+}
+
+#[test]
+fn test_xgroup() {
+    let db = new_db();
+    assert_eq!(
+        run::<XGroupCommand>(&db, &["XGROUP", "CREATE", "s", "g", "$"]),
+        Reply::error("ERR The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically.")
+    );
+    assert_eq!(run::<XGroupCommand>(&db, &["XGROUP", "CREATE", "s", "g", "$", "MKSTREAM"]), Reply::ok());
+    assert_eq!(run::<XLenCommand>(&db, &["XLEN", "s"]), Reply::Integer(0));
+    assert_eq!(
+        run::<XGroupCommand>(&db, &["XGROUP", "CREATE", "s", "g", "0"]),
+        Reply::error("BUSYGROUP Consumer Group name already exists")
+    );
+    assert_eq!(run::<XGroupCommand>(&db, &["XGROUP", "CREATECONSUMER", "s", "g", "alice"]), Reply::Integer(1));
+    assert_eq!(run::<XGroupCommand>(&db, &["XGROUP", "CREATECONSUMER", "s", "g", "alice"]), Reply::Integer(0));
+    assert_eq!(run::<XGroupCommand>(&db, &["XGROUP", "DELCONSUMER", "s", "g", "alice"]), Reply::Integer(0));
+    assert_eq!(
+        run::<XGroupCommand>(&db, &["XGROUP", "SETID", "s", "missing", "0"]),
+        Reply::error("NOGROUP No such consumer group 'missing' for key name 's'")
+    );
+    assert_eq!(run::<XGroupCommand>(&db, &["XGROUP", "DESTROY", "s", "g"]), Reply::Integer(1));
+    assert_eq!(run::<XGroupCommand>(&db, &["XGROUP", "DESTROY", "s", "g"]), Reply::Integer(0));
+}
+
+#[test]
+fn test_xreadgroup_and_xack() {
+    let db = new_db();
+    stream_with_group(&db);
+    assert_eq!(
+        read_group(&db, &["XREADGROUP", "GROUP", "g", "alice", "COUNT", "2", "STREAMS", "s", ">"]),
+        Reply::NestedMap(vec![(Reply::Bulk(b"s".to_vec()), Reply::Array(vec![entry("1-0", &["n", "1-0"]), entry("2-0", &["n", "2-0"])]))])
+    );
+    assert_eq!(
+        read_group(&db, &["XREADGROUP", "GROUP", "g", "bob", "STREAMS", "s", ">"]),
+        Reply::NestedMap(vec![(Reply::Bulk(b"s".to_vec()), Reply::Array(vec![entry("3-0", &["n", "3-0"])]))])
+    );
+    assert_eq!(read_group(&db, &["XREADGROUP", "GROUP", "g", "bob", "STREAMS", "s", ">"]), Reply::NullArray);
+
+    // Alice's history is her own pending entries; deleted ones come back as nil.
+    run::<XDelCommand>(&db, &["XDEL", "s", "2-0"]);
+    assert_eq!(
+        read_group(&db, &["XREADGROUP", "GROUP", "g", "alice", "STREAMS", "s", "0"]),
+        Reply::NestedMap(vec![(
+            Reply::Bulk(b"s".to_vec()),
+            Reply::Array(vec![entry("1-0", &["n", "1-0"]), Reply::Array(vec![Reply::Bulk(b"2-0".to_vec()), Reply::NullArray])])
+        )])
+    );
+    assert_eq!(run::<XAckCommand>(&db, &["XACK", "s", "g", "1-0", "2-0", "3-0", "9-0"]), Reply::Integer(3));
+    assert_eq!(
+        read_group(&db, &["XREADGROUP", "GROUP", "g", "alice", "STREAMS", "s", "0"]),
+        Reply::NestedMap(vec![(Reply::Bulk(b"s".to_vec()), Reply::Array(vec![]))])
+    );
+    assert_eq!(run::<XAckCommand>(&db, &["XACK", "s", "missing", "1-0"]), Reply::Integer(0));
+
+    run::<XAddCommand>(&db, &["XADD", "s", "4-0", "n", "4-0"]);
+    read_group(&db, &["XREADGROUP", "GROUP", "g", "alice", "NOACK", "STREAMS", "s", ">"]);
+    assert_eq!(
+        run::<XPendingCommand>(&db, &["XPENDING", "s", "g"]),
+        Reply::Array(vec![Reply::Integer(0), Reply::Null, Reply::Null, Reply::NullArray])
+    );
+    assert_eq!(
+        read_group(&db, &["XREADGROUP", "GROUP", "missing", "alice", "STREAMS", "s", ">"]),
+        Reply::error("NOGROUP No such key 's' or consumer group 'missing'")
+    );
+}
+
+#[test]
+fn test_xreadgroup_block_wakes_on_xadd() {
+    let db = new_db();
+    run::<XGroupCommand>(&db, &["XGROUP", "CREATE", "s", "g", "$", "MKSTREAM"]);
+    let mut parked = block::<XReadGroupCommand>(&db, &["XREADGROUP", "GROUP", "g", "c", "BLOCK", "0", "STREAMS", "s", ">"]).unwrap();
+    run::<XAddCommand>(&db, &["XADD", "s", "1-0", "f", "v"]);
+    assert_eq!(
+        parked.reply.try_recv().unwrap(),
+        Reply::NestedMap(vec![(Reply::Bulk(b"s".to_vec()), Reply::Array(vec![entry("1-0", &["f", "v"])]))])
+    );
+    assert_eq!(
+        run::<XPendingCommand>(&db, &["XPENDING", "s", "g"]),
+        Reply::Array(vec![
+            Reply::Integer(1),
+            Reply::Bulk(b"1-0".to_vec()),
+            Reply::Bulk(b"1-0".to_vec()),
+            Reply::Array(vec![bulks(&["c", "1"])]),
+        ])
+    );
+}
+
+#[test]
+fn test_xreadgroup_block_fails_when_group_is_destroyed() {
+    let db = new_db();
+    run::<XGroupCommand>(&db, &["XGROUP", "CREATE", "s", "g", "$", "MKSTREAM"]);
+    let mut parked = block::<XReadGroupCommand>(&db, &["XREADGROUP", "GROUP", "g", "c", "BLOCK", "0", "STREAMS", "s", ">"]).unwrap();
+    assert_eq!(run::<XGroupCommand>(&db, &["XGROUP", "DESTROY", "s", "g"]), Reply::Integer(1));
+    assert_eq!(
+        parked.reply.try_recv().unwrap(),
+        Reply::error("NOGROUP No such key 's' or consumer group 'g'")
+    );
+}
+
+#[test]
+fn test_xpending_and_xclaim() {
+    let db = new_db();
+    stream_with_group(&db);
+    read_group(&db, &["XREADGROUP", "GROUP", "g", "alice", "STREAMS", "s", ">"]);
+
+    let Reply::Array(pending) = run::<XPendingCommand>(&db, &["XPENDING", "s", "g", "-", "+", "10", "alice"]) else {
+        panic!("expected an array");
+    };
+    assert_eq!(pending.len(), 3);
+    assert_eq!(run::<XPendingCommand>(&db, &["XPENDING", "s", "g", "IDLE", "60000", "-", "+", "10"]), Reply::Array(vec![]));
+
+    // Nothing is idle long enough yet, until IDLE backdates the delivery.
+    assert_eq!(run::<XClaimCommand>(&db, &["XCLAIM", "s", "g", "bob", "60000", "1-0"]), Reply::Array(vec![]));
+    assert_eq!(
+        run::<XClaimCommand>(&db, &["XCLAIM", "s", "g", "bob", "0", "1-0", "2-0", "IDLE", "120000", "JUSTID"]),
+        bulks(&["1-0", "2-0"])
+    );
+    assert_eq!(
+        run::<XClaimCommand>(&db, &["XCLAIM", "s", "g", "carol", "60000", "1-0"]),
+        Reply::Array(vec![entry("1-0", &["n", "1-0"])])
+    );
+    let Reply::Array(pending) = run::<XPendingCommand>(&db, &["XPENDING", "s", "g", "-", "+", "10"]) else {
+        panic!("expected an array");
+    };
+    let owners: Vec<(Reply, Reply)> = pending
+        .into_iter()
+        .map(|entry| match entry {
+            Reply::Array(fields) => (fields[1].clone(), fields[3].clone()),
+            _ => panic!("expected an array"),
+        })
+        .collect();
+    assert_eq!(
+        owners,
+        vec![
+            (Reply::Bulk(b"carol".to_vec()), Reply::Integer(2)),
+            (Reply::Bulk(b"bob".to_vec()), Reply::Integer(1)),
+            (Reply::Bulk(b"alice".to_vec()), Reply::Integer(1)),
+        ]
+    );
+
+    // A deleted entry is dropped from the PEL instead of being claimed.
+    run::<XDelCommand>(&db, &["XDEL", "s", "3-0"]);
+    assert_eq!(run::<XClaimCommand>(&db, &["XCLAIM", "s", "g", "bob", "0", "3-0"]), Reply::Array(vec![]));
+    assert_eq!(run::<XPendingCommand>(&db, &["XPENDING", "s", "g", "-", "+", "10", "alice"]), Reply::Array(vec![]));
+    assert_eq!(
+        run::<XClaimCommand>(&db, &["XCLAIM", "s", "g", "bob", "0", "1-0", "BOGUS"]),
+        Reply::error("ERR Unrecognized XCLAIM option 'BOGUS'")
+    );
+}
+
+#[test]
+fn test_xautoclaim() {
+    let db = new_db();
+    stream_with_group(&db);
+    read_group(&db, &["XREADGROUP", "GROUP", "g", "alice", "STREAMS", "s", ">"]);
+    run::<XDelCommand>(&db, &["XDEL", "s", "2-0"]);
+
+    assert_eq!(
+        run::<XAutoClaimCommand>(&db, &["XAUTOCLAIM", "s", "g", "bob", "0", "0", "COUNT", "1"]),
+        Reply::Array(vec![Reply::Bulk(b"2-0".to_vec()), Reply::Array(vec![entry("1-0", &["n", "1-0"])]), Reply::Array(vec![])])
+    );
+    assert_eq!(
+        run::<XAutoClaimCommand>(&db, &["XAUTOCLAIM", "s", "g", "bob", "0", "2-0", "JUSTID"]),
+        Reply::Array(vec![Reply::Bulk(b"0-0".to_vec()), bulks(&["3-0"]), bulks(&["2-0"])])
+    );
+    assert_eq!(
+        run::<XAutoClaimCommand>(&db, &["XAUTOCLAIM", "s", "g", "bob", "60000", "0"]),
+        Reply::Array(vec![Reply::Bulk(b"0-0".to_vec()), Reply::Array(vec![]), Reply::Array(vec![])])
+    );
+    assert_eq!(
+        run::<XAutoClaimCommand>(&db, &["XAUTOCLAIM", "s", "g", "bob", "0", "0", "COUNT", "0"]),
+        Reply::error("ERR COUNT must be > 0")
+    );
+}
+
+#[test]
+fn test_xinfo() {
+    let db = new_db();
+    stream_with_group(&db);
+    read_group(&db, &["XREADGROUP", "GROUP", "g", "alice", "COUNT", "1", "STREAMS", "s", ">"]);
+
+    let Reply::Array(groups) = run::<XInfoCommand>(&db, &["XINFO", "GROUPS", "s"]) else {
+        panic!("expected an array");
+    };
+    assert_eq!(
+        groups,
+        vec![Reply::Map(vec![
+            (Reply::Bulk(b"name".to_vec()), Reply::Bulk(b"g".to_vec())),
+            (Reply::Bulk(b"consumers".to_vec()), Reply::Integer(1)),
+            (Reply::Bulk(b"pending".to_vec()), Reply::Integer(1)),
+            (Reply::Bulk(b"last-delivered-id".to_vec()), Reply::Bulk(b"1-0".to_vec())),
+            (Reply::Bulk(b"entries-read".to_vec()), Reply::Integer(1)),
+            (Reply::Bulk(b"lag".to_vec()), Reply::Integer(2)),
+        ])]
+    );
+
+    let Reply::Map(info) = run::<XInfoCommand>(&db, &["XINFO", "STREAM", "s"]) else {
+        panic!("expected a map");
+    };
+    assert_eq!(info[0], (Reply::Bulk(b"length".to_vec()), Reply::Integer(3)));
+    assert_eq!(info[8], (Reply::Bulk(b"first-entry".to_vec()), entry("1-0", &["n", "1-0"])));
+    assert_eq!(run::<XInfoCommand>(&db, &["XINFO", "STREAM", "missing"]), Reply::error("ERR no such key"));
+    assert_eq!(
+        run::<XInfoCommand>(&db, &["XINFO", "CONSUMERS", "s", "nope"]),
+        Reply::error("NOGROUP No such key 's' or consumer group 'nope'")
+    );
+}
@@ -1,26 +1,30 @@
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
 use std::time::{Instant};
+use crate::cmd::Command;
+use crate::db::{Db, Entry};
+use crate::reply::Reply;
 
-type Db = Arc<Mutex<HashMap<String, DbValue>>>;
-type DbValue = (String, Option<Instant>);
+pub struct TTLCommand {
+    key: Vec<u8>,
+}
 
-pub struct TTLCommand<'a> {
-    key: &'a str,
+impl TTLCommand {
+    pub fn new(key: &[u8]) -> Self {
+        TTLCommand { key: key.to_vec() }
+    }
 }
 
-impl<'a> TTLCommand<'a> {
-    pub fn new(key: &'a str) -> Self {
-        TTLCommand { key }
+impl Command for TTLCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(TTLCommand::new(&args[1]))
     }
 
-    pub fn execute(&self, db: &Db) -> String {
-        let db = db.lock().unwrap();
-        if let Some((_, Some(expire_time))) = db.get(self.key) {
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        if let Some(Entry { expires_at: Some(expire_time), .. }) = db.get(&self.key) {
             let ttl = expire_time.saturating_duration_since(Instant::now()).as_secs();
-            format!(":{}\r\n", ttl)
+            Reply::Integer(ttl as i64)
         } else {
-            ":-1\r\n".to_string()
+            Reply::Integer(-1)
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,841 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use crate::cmd::{list, parse_int, parse_timeout, syntax_error, BlockingCommand, Command};
+use crate::db::{Db, Entry, Keyspace, SortedSet, Value};
+use crate::reply::Reply;
+
+/// A member with its score.
+pub type Element = (Vec<u8>, f64);
+
+/// Returns the sorted set stored at `key`, or WRONGTYPE if the key holds another type.
+fn get_zset<'a>(db: &'a mut Keyspace, key: &[u8]) -> Result<Option<&'a SortedSet>, Reply> {
+    match db.get(key) {
+        Some(Entry { value: Value::ZSet(zset), .. }) => Ok(Some(zset)),
+        Some(_) => Err(Reply::wrong_type()),
+        None => Ok(None),
+    }
+}
+
+/// Like [`get_zset`], for commands that modify the value; the key counts as
+/// written.
+fn get_zset_mut<'a>(db: &'a mut Keyspace, key: &[u8]) -> Result<Option<&'a mut SortedSet>, Reply> {
+    match db.get_mut(key) {
+        Some(Entry { value: Value::ZSet(zset), .. }) => Ok(Some(zset)),
+        Some(_) => Err(Reply::wrong_type()),
+        None => Ok(None),
+    }
+}
+
+/// Parses a score; `inf`, `+inf` and `-inf` are accepted, NaN is not.
+fn parse_score(arg: &[u8]) -> Option<f64> {
+    let score: f64 = std::str::from_utf8(arg).ok()?.parse().ok()?;
+    // Adding zero turns -0 into 0, so both sort as the same score.
+    (!score.is_nan()).then_some(score + 0.0)
+}
+
+fn float_error() -> Reply {
+    Reply::error("ERR value is not a valid float")
+}
+
+/// One end of a `BYSCORE` range: `1.5`, `(1.5` (exclusive), `-inf`, `+inf`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ScoreBound {
+    score: f64,
+    exclusive: bool,
+}
+
+impl ScoreBound {
+    fn parse(arg: &[u8]) -> Result<Self, Reply> {
+        let (exclusive, score) = match arg.strip_prefix(b"(") {
+            Some(rest) => (true, rest),
+            None => (false, arg),
+        };
+        let score = parse_score(score).ok_or_else(|| Reply::error("ERR min or max is not a float"))?;
+        Ok(ScoreBound { score, exclusive })
+    }
+}
+
+/// One end of a `BYLEX` range: `[a` (inclusive), `(a` (exclusive), `-`, `+`.
+#[derive(Debug, Clone, PartialEq)]
+enum LexBound {
+    Min,
+    Max,
+    Inclusive(Vec<u8>),
+    Exclusive(Vec<u8>),
+}
+
+impl LexBound {
+    fn parse(arg: &[u8]) -> Result<Self, Reply> {
+        match arg.split_first() {
+            Some((b'-', [])) => Ok(LexBound::Min),
+            Some((b'+', [])) => Ok(LexBound::Max),
+            Some((b'[', member)) => Ok(LexBound::Inclusive(member.to_vec())),
+            Some((b'(', member)) => Ok(LexBound::Exclusive(member.to_vec())),
+            _ => Err(Reply::error("ERR min or max not valid string range item")),
+        }
+    }
+
+    /// Whether `member` sorts before this bound used as a lower bound.
+    fn below_min(&self, member: &[u8]) -> bool {
+        match self {
+            LexBound::Min => false,
+            LexBound::Max => true,
+            LexBound::Inclusive(bound) => member < bound.as_slice(),
+            LexBound::Exclusive(bound) => member <= bound.as_slice(),
+        }
+    }
+
+    /// Whether `member` is within this bound used as an upper bound.
+    fn within_max(&self, member: &[u8]) -> bool {
+        match self {
+            LexBound::Min => false,
+            LexBound::Max => true,
+            LexBound::Inclusive(bound) => member <= bound.as_slice(),
+            LexBound::Exclusive(bound) => member < bound.as_slice(),
+        }
+    }
+}
+
+/// A range of elements selected by rank, score or member.
+#[derive(Debug, Clone, PartialEq)]
+enum Bounds {
+    Rank(i64, i64),
+    Score(ScoreBound, ScoreBound),
+    Lex(LexBound, LexBound),
+}
+
+impl Bounds {
+    /// The ranks `lo..hi` the bounds select in ascending order. `rev` only
+    /// matters for rank bounds, which then count from the highest score.
+    fn ranks(&self, zset: &SortedSet, rev: bool) -> (usize, usize) {
+        let len = zset.len();
+        match self {
+            Bounds::Rank(start, stop) => match list::range(*start, *stop, len) {
+                Some((start, stop)) if rev => (len - 1 - stop, len - start),
+                Some((start, stop)) => (start, stop + 1),
+                None => (0, 0),
+            },
+            Bounds::Score(min, max) => {
+                let lo = zset.count_below(|score, _| score < min.score || (min.exclusive && score == min.score));
+                let hi = zset.count_below(|score, _| score < max.score || (!max.exclusive && score == max.score));
+                (lo, hi.max(lo))
+            }
+            Bounds::Lex(min, max) => {
+                let lo = zset.count_below(|_, member| min.below_min(member));
+                let hi = zset.count_below(|_, member| max.within_max(member));
+                (lo, hi.max(lo))
+            }
+        }
+    }
+}
+
+fn elements_reply(elements: Vec<Element>, with_scores: bool) -> Reply {
+    if with_scores {
+        Reply::Pairs(elements.into_iter().map(|(member, score)| (Reply::Bulk(member), Reply::Double(score))).collect())
+    } else {
+        Reply::Array(elements.into_iter().map(|(member, _)| Reply::Bulk(member)).collect())
+    }
+}
+
+/// `ZADD key [NX|XX] [GT|LT] [CH] [INCR] score member [score member ...]`
+pub struct ZAddCommand {
+    key: Vec<u8>,
+    nx: bool,
+    xx: bool,
+    gt: bool,
+    lt: bool,
+    ch: bool,
+    incr: bool,
+    elements: Vec<(f64, Vec<u8>)>,
+}
+
+impl Command for ZAddCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let mut command = ZAddCommand {
+            key: args[1].clone(),
+            nx: false,
+            xx: false,
+            gt: false,
+            lt: false,
+            ch: false,
+            incr: false,
+            elements: Vec::new(),
+        };
+        let mut i = 2;
+        while let Some(arg) = args.get(i) {
+            match arg.to_ascii_uppercase().as_slice() {
+                b"NX" => command.nx = true,
+                b"XX" => command.xx = true,
+                b"GT" => command.gt = true,
+                b"LT" => command.lt = true,
+                b"CH" => command.ch = true,
+                b"INCR" => command.incr = true,
+                _ => break,
+            }
+            i += 1;
+        }
+
+        let pairs = &args[i..];
+        if pairs.is_empty() || !pairs.len().is_multiple_of(2) {
+            return Err(syntax_error());
+        }
+        if command.nx && command.xx {
+            return Err(Reply::error("ERR XX and NX options at the same time are not compatible"));
+        }
+        if [command.nx, command.gt, command.lt].iter().filter(|set| **set).count() > 1 {
+            return Err(Reply::error("ERR GT, LT, and/or NX options at the same time are not compatible"));
+        }
+        if command.incr && pairs.len() > 2 {
+            return Err(Reply::error("ERR INCR option supports a single increment-element pair"));
+        }
+        for pair in pairs.chunks(2) {
+            command.elements.push((parse_score(&pair[0]).ok_or_else(float_error)?, pair[1].clone()));
+        }
+        Ok(command)
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let zset = match get_zset_mut(&mut db, &self.key) {
+            Ok(Some(zset)) => zset,
+            Ok(None) if self.xx => return if self.incr { Reply::Null } else { Reply::Integer(0) },
+            Ok(None) => {
+                db.insert(self.key.clone(), Entry::new(Value::ZSet(SortedSet::new())));
+                get_zset_mut(&mut db, &self.key).unwrap().expect("sorted set was just inserted")
+            }
+            Err(reply) => return reply,
+        };
+
+        let (mut added, mut changed) = (0, 0);
+        let mut result = None;
+        let mut error = None;
+        for (score, member) in &self.elements {
+            let current = zset.score(member);
+            let new = match current {
+                None if self.xx => continue,
+                Some(_) if self.nx => continue,
+                Some(current) if self.incr => current + score,
+                _ => *score,
+            };
+            if new.is_nan() {
+                error = Some(Reply::error("ERR resulting score is not a number (NaN)"));
+                break;
+            }
+            if let Some(current) = current {
+                if (self.gt && new <= current) || (self.lt && new >= current) {
+                    continue;
+                }
+                if new != current {
+                    changed += 1;
+                }
+            } else {
+                added += 1;
+            }
+            zset.insert(member, new);
+            result = Some(new);
+        }
+
+        if result.is_some() {
+            db.signal_ready(&self.key);
+        }
+        if let Some(error) = error {
+            db.remove_if_empty(&self.key);
+            error
+        } else if self.incr {
+            result.map_or(Reply::Null, Reply::Double)
+        } else if self.ch {
+            Reply::Integer(added + changed)
+        } else {
+            Reply::Integer(added)
+        }
+    }
+}
+
+/// `ZINCRBY key increment member`
+pub struct ZIncrByCommand {
+    key: Vec<u8>,
+    increment: f64,
+    member: Vec<u8>,
+}
+
+impl Command for ZIncrByCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(ZIncrByCommand { key: args[1].clone(), increment: parse_score(&args[2]).ok_or_else(float_error)?, member: args[3].clone() })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let zset = match get_zset_mut(&mut db, &self.key) {
+            Ok(Some(zset)) => zset,
+            Ok(None) => {
+                db.insert(self.key.clone(), Entry::new(Value::ZSet(SortedSet::new())));
+                get_zset_mut(&mut db, &self.key).unwrap().expect("sorted set was just inserted")
+            }
+            Err(reply) => return reply,
+        };
+        let score = zset.score(&self.member).unwrap_or(0.0) + self.increment;
+        if score.is_nan() {
+            db.remove_if_empty(&self.key);
+            return Reply::error("ERR resulting score is not a number (NaN)");
+        }
+        zset.insert(&self.member, score);
+        db.signal_ready(&self.key);
+        Reply::Double(score)
+    }
+}
+
+/// `ZRANGE key start stop [BYSCORE|BYLEX] [REV] [LIMIT offset count] [WITHSCORES]`,
+/// plus the older ZREVRANGE, Z[REV]RANGEBYSCORE and Z[REV]RANGEBYLEX forms.
+pub struct ZRangeCommand {
+    key: Vec<u8>,
+    bounds: Bounds,
+    rev: bool,
+    limit: Option<(i64, i64)>,
+    with_scores: bool,
+}
+
+impl Command for ZRangeCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let name = args[0].to_ascii_lowercase();
+        let mut rev = name.starts_with(b"zrev");
+        let (mut by_score, mut by_lex) = (name.ends_with(b"byscore"), name.ends_with(b"bylex"));
+        let modern = name == b"zrange";
+
+        let mut limit = None;
+        let mut with_scores = false;
+        let mut i = 4;
+        while let Some(option) = args.get(i) {
+            match option.to_ascii_uppercase().as_slice() {
+                b"BYSCORE" if modern => by_score = true,
+                b"BYLEX" if modern => by_lex = true,
+                b"REV" if modern => rev = true,
+                b"WITHSCORES" => with_scores = true,
+                b"LIMIT" if i + 2 < args.len() => {
+                    limit = Some((parse_int(&args[i + 1])?, parse_int(&args[i + 2])?));
+                    i += 2;
+                }
+                _ => return Err(syntax_error()),
+            }
+            i += 1;
+        }
+        if by_score && by_lex {
+            return Err(syntax_error());
+        }
+        if limit.is_some() && !by_score && !by_lex {
+            return Err(Reply::error("ERR syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX"));
+        }
+        if with_scores && by_lex {
+            return Err(Reply::error("ERR syntax error, WITHSCORES not supported in combination with BYLEX"));
+        }
+
+        // Score and lex ranges are given as max..min when reversed.
+        let (start, stop) = if rev && (by_score || by_lex) { (&args[3], &args[2]) } else { (&args[2], &args[3]) };
+        let bounds = if by_score {
+            Bounds::Score(ScoreBound::parse(start)?, ScoreBound::parse(stop)?)
+        } else if by_lex {
+            Bounds::Lex(LexBound::parse(start)?, LexBound::parse(stop)?)
+        } else {
+            Bounds::Rank(parse_int(start)?, parse_int(stop)?)
+        };
+        Ok(ZRangeCommand { key: args[1].clone(), bounds, rev, limit, with_scores })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let zset = match get_zset(&mut db, &self.key) {
+            Ok(Some(zset)) => zset,
+            Ok(None) => return Reply::Array(Vec::new()),
+            Err(reply) => return reply,
+        };
+        let (mut lo, mut hi) = self.bounds.ranks(zset, self.rev);
+
+        if let Some((offset, count)) = self.limit {
+            if offset < 0 {
+                return Reply::Array(Vec::new());
+            }
+            let skip = (offset as usize).min(hi - lo);
+            let take = if count < 0 { hi - lo - skip } else { (count as usize).min(hi - lo - skip) };
+            (lo, hi) = if self.rev { (hi - skip - take, hi - skip) } else { (lo + skip, lo + skip + take) };
+        }
+
+        let mut elements = zset.range(lo, hi);
+        if self.rev {
+            elements.reverse();
+        }
+        elements_reply(elements, self.with_scores)
+    }
+}
+
+/// `ZCOUNT key min max` and `ZLEXCOUNT key min max`
+pub struct ZCountCommand {
+    key: Vec<u8>,
+    bounds: Bounds,
+}
+
+impl Command for ZCountCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let bounds = if args[0].eq_ignore_ascii_case(b"zlexcount") {
+            Bounds::Lex(LexBound::parse(&args[2])?, LexBound::parse(&args[3])?)
+        } else {
+            Bounds::Score(ScoreBound::parse(&args[2])?, ScoreBound::parse(&args[3])?)
+        };
+        Ok(ZCountCommand { key: args[1].clone(), bounds })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        match get_zset(&mut db, &self.key) {
+            Ok(Some(zset)) => {
+                let (lo, hi) = self.bounds.ranks(zset, false);
+                Reply::Integer((hi - lo) as i64)
+            }
+            Ok(None) => Reply::Integer(0),
+            Err(reply) => reply,
+        }
+    }
+}
+
+/// `ZREMRANGEBYRANK key start stop`, `ZREMRANGEBYSCORE key min max` and
+/// `ZREMRANGEBYLEX key min max`
+pub struct ZRemRangeCommand {
+    key: Vec<u8>,
+    bounds: Bounds,
+}
+
+impl Command for ZRemRangeCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let name = args[0].to_ascii_lowercase();
+        let bounds = if name.ends_with(b"byscore") {
+            Bounds::Score(ScoreBound::parse(&args[2])?, ScoreBound::parse(&args[3])?)
+        } else if name.ends_with(b"bylex") {
+            Bounds::Lex(LexBound::parse(&args[2])?, LexBound::parse(&args[3])?)
+        } else {
+            Bounds::Rank(parse_int(&args[2])?, parse_int(&args[3])?)
+        };
+        Ok(ZRemRangeCommand { key: args[1].clone(), bounds })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let removed = match get_zset_mut(&mut db, &self.key) {
+            Ok(Some(zset)) => {
+                let (lo, hi) = self.bounds.ranks(zset, false);
+                zset.remove_range(lo, hi).len()
+            }
+            Ok(None) => 0,
+            Err(reply) => return reply,
+        };
+        db.remove_if_empty(&self.key);
+        Reply::Integer(removed as i64)
+    }
+}
+
+/// `ZRANK key member [WITHSCORE]` and `ZREVRANK key member [WITHSCORE]`
+pub struct ZRankCommand {
+    key: Vec<u8>,
+    member: Vec<u8>,
+    rev: bool,
+    with_score: bool,
+}
+
+impl Command for ZRankCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let with_score = match args.get(3) {
+            None => false,
+            Some(option) if option.eq_ignore_ascii_case(b"WITHSCORE") && args.len() == 4 => true,
+            Some(_) => return Err(syntax_error()),
+        };
+        Ok(ZRankCommand {
+            key: args[1].clone(),
+            member: args[2].clone(),
+            rev: args[0].eq_ignore_ascii_case(b"zrevrank"),
+            with_score,
+        })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let zset = match get_zset(&mut db, &self.key) {
+            Ok(Some(zset)) => zset,
+            Ok(None) => return Reply::Null,
+            Err(reply) => return reply,
+        };
+        let (Some(rank), Some(score)) = (zset.rank(&self.member), zset.score(&self.member)) else {
+            return Reply::Null;
+        };
+        let rank = if self.rev { zset.len() - 1 - rank } else { rank };
+        if self.with_score {
+            Reply::Array(vec![Reply::Integer(rank as i64), Reply::Double(score)])
+        } else {
+            Reply::Integer(rank as i64)
+        }
+    }
+}
+
+/// `ZSCORE key member` and `ZMSCORE key member [member ...]`
+pub struct ZScoreCommand {
+    key: Vec<u8>,
+    members: Vec<Vec<u8>>,
+    multiple: bool,
+}
+
+impl Command for ZScoreCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(ZScoreCommand {
+            key: args[1].clone(),
+            members: args[2..].to_vec(),
+            multiple: args[0].eq_ignore_ascii_case(b"zmscore"),
+        })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let zset = match get_zset(&mut db, &self.key) {
+            Ok(zset) => zset,
+            Err(reply) => return reply,
+        };
+        let mut scores = self
+            .members
+            .iter()
+            .map(|member| zset.as_ref().and_then(|zset| zset.score(member)).map_or(Reply::Null, Reply::Double));
+        if self.multiple {
+            Reply::Array(scores.collect())
+        } else {
+            scores.next().expect("arity guarantees a member")
+        }
+    }
+}
+
+/// `ZCARD key`
+pub struct ZCardCommand {
+    key: Vec<u8>,
+}
+
+impl Command for ZCardCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(ZCardCommand { key: args[1].clone() })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        match get_zset(&mut db, &self.key) {
+            Ok(zset) => Reply::Integer(zset.map_or(0, |zset| zset.len() as i64)),
+            Err(reply) => reply,
+        }
+    }
+}
+
+/// `ZREM key member [member ...]`
+pub struct ZRemCommand {
+    key: Vec<u8>,
+    members: Vec<Vec<u8>>,
+}
+
+impl Command for ZRemCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(ZRemCommand { key: args[1].clone(), members: args[2..].to_vec() })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let removed = match get_zset_mut(&mut db, &self.key) {
+            Ok(Some(zset)) => self.members.iter().filter(|member| zset.remove(member)).count(),
+            Ok(None) => 0,
+            Err(reply) => return reply,
+        };
+        db.remove_if_empty(&self.key);
+        Reply::Integer(removed as i64)
+    }
+}
+
+/// Removes up to `count` elements with the lowest (or highest, if `max`)
+/// scores, deleting the key once it is empty. `None` if the key is missing.
+pub fn pop(db: &mut Keyspace, key: &[u8], max: bool, count: usize) -> Result<Option<Vec<Element>>, Reply> {
+    let Some(zset) = get_zset_mut(db, key)? else {
+        return Ok(None);
+    };
+    let len = zset.len();
+    let popped = if max {
+        let mut popped = zset.remove_range(len - count.min(len), len);
+        popped.reverse();
+        popped
+    } else {
+        zset.remove_range(0, count.min(len))
+    };
+    db.remove_if_empty(key);
+    Ok(Some(popped))
+}
+
+/// `ZPOPMIN key [count]` and `ZPOPMAX key [count]`
+pub struct ZPopCommand {
+    key: Vec<u8>,
+    max: bool,
+    count: Option<usize>,
+}
+
+impl Command for ZPopCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let count = match args.len() {
+            2 => None,
+            3 => match parse_int::<i64>(&args[2]) {
+                Ok(count) if count >= 0 => Some(count as usize),
+                _ => return Err(Reply::error("ERR value is out of range, must be positive")),
+            },
+            _ => return Err(syntax_error()),
+        };
+        Ok(ZPopCommand { key: args[1].clone(), max: args[0].eq_ignore_ascii_case(b"zpopmax"), count })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let popped = match pop(&mut db, &self.key, self.max, self.count.unwrap_or(1)) {
+            Ok(popped) => popped.unwrap_or_default(),
+            Err(reply) => return reply,
+        };
+        match self.count {
+            Some(_) => elements_reply(popped, true),
+            None => Reply::Array(
+                popped.into_iter().flat_map(|(member, score)| [Reply::Bulk(member), Reply::Double(score)]).collect(),
+            ),
+        }
+    }
+}
+
+/// `BZPOPMIN key [key ...] timeout` and `BZPOPMAX key [key ...] timeout`
+pub struct BZPopCommand {
+    keys: Vec<Vec<u8>>,
+    max: bool,
+    timeout: Option<Duration>,
+}
+
+impl BlockingCommand for BZPopCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let (timeout, keys) = args[1..].split_last().expect("arity is checked");
+        Ok(BZPopCommand { keys: keys.to_vec(), max: args[0].eq_ignore_ascii_case(b"bzpopmax"), timeout: parse_timeout(timeout)? })
+    }
+
+    fn keys(&self) -> &[Vec<u8>] {
+        &self.keys
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    fn try_serve(&self, db: &mut Keyspace, key: &[u8]) -> Result<Option<Reply>, Reply> {
+        Ok(pop(db, key, self.max, 1)?
+            .and_then(|mut popped| popped.pop())
+            .map(|(member, score)| Reply::Array(vec![Reply::Bulk(key.to_vec()), Reply::Bulk(member), Reply::Double(score)])))
+    }
+}
+
+/// Arguments shared by ZMPOP and BZMPOP: `numkeys key [key ...] MIN|MAX [COUNT count]`.
+struct MPopArgs {
+    keys: Vec<Vec<u8>>,
+    max: bool,
+    count: usize,
+}
+
+impl MPopArgs {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let numkeys = match parse_int::<i64>(&args[0]) {
+            Ok(n) if n > 0 => n as usize,
+            _ => return Err(Reply::error("ERR numkeys should be greater than 0")),
+        };
+        if numkeys + 1 >= args.len() {
+            return Err(syntax_error());
+        }
+        let keys = args[1..=numkeys].to_vec();
+        let max = match args[numkeys + 1].to_ascii_uppercase().as_slice() {
+            b"MIN" => false,
+            b"MAX" => true,
+            _ => return Err(syntax_error()),
+        };
+        let count = match &args[numkeys + 2..] {
+            [] => 1,
+            [option, count] if option.eq_ignore_ascii_case(b"COUNT") => match parse_int::<i64>(count) {
+                Ok(count) if count > 0 => count as usize,
+                _ => return Err(Reply::error("ERR count should be greater than 0")),
+            },
+            _ => return Err(syntax_error()),
+        };
+        Ok(MPopArgs { keys, max, count })
+    }
+
+    /// Pops from `key`, replying `[key, [[member, score], ...]]`.
+    fn pop(&self, db: &mut Keyspace, key: &[u8]) -> Result<Option<Reply>, Reply> {
+        Ok(pop(db, key, self.max, self.count)?.map(|popped| {
+            let elements = popped
+                .into_iter()
+                .map(|(member, score)| Reply::Array(vec![Reply::Bulk(member), Reply::Double(score)]))
+                .collect();
+            Reply::Array(vec![Reply::Bulk(key.to_vec()), Reply::Array(elements)])
+        }))
+    }
+}
+
+/// `ZMPOP numkeys key [key ...] MIN|MAX [COUNT count]`
+pub struct ZMPopCommand {
+    args: MPopArgs,
+}
+
+impl Command for ZMPopCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(ZMPopCommand { args: MPopArgs::parse(&args[1..])? })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        for key in &self.args.keys {
+            match self.args.pop(&mut db, key) {
+                Ok(Some(reply)) | Err(reply) => return reply,
+                Ok(None) => {}
+            }
+        }
+        Reply::NullArray
+    }
+}
+
+/// `BZMPOP timeout numkeys key [key ...] MIN|MAX [COUNT count]`
+pub struct BZMPopCommand {
+    args: MPopArgs,
+    timeout: Option<Duration>,
+}
+
+impl BlockingCommand for BZMPopCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        Ok(BZMPopCommand { timeout: parse_timeout(&args[1])?, args: MPopArgs::parse(&args[2..])? })
+    }
+
+    fn keys(&self) -> &[Vec<u8>] {
+        &self.args.keys
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    fn try_serve(&self, db: &mut Keyspace, key: &[u8]) -> Result<Option<Reply>, Reply> {
+        self.args.pop(db, key)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Aggregate {
+    Sum,
+    Min,
+    Max,
+}
+
+impl Aggregate {
+    fn apply(self, a: f64, b: f64) -> f64 {
+        match self {
+            // inf + -inf is NaN, which Redis reports as 0.
+            Aggregate::Sum => Some(a + b).filter(|sum| !sum.is_nan()).unwrap_or(0.0),
+            Aggregate::Min => a.min(b),
+            Aggregate::Max => a.max(b),
+        }
+    }
+}
+
+/// Members and scores of a ZUNIONSTORE/ZINTERSTORE input; plain sets count
+/// as sorted sets with all scores 1.
+fn load_scores(db: &mut Keyspace, key: &[u8]) -> Result<HashMap<Vec<u8>, f64>, Reply> {
+    match db.get(key) {
+        Some(Entry { value: Value::ZSet(zset), .. }) => Ok(zset.iter().map(|(member, score)| (member.clone(), score)).collect()),
+        Some(Entry { value: Value::Set(set), .. }) => Ok(set.members().into_iter().map(|member| (member, 1.0)).collect()),
+        Some(_) => Err(Reply::wrong_type()),
+        None => Ok(HashMap::new()),
+    }
+}
+
+/// `ZUNIONSTORE|ZINTERSTORE destination numkeys key [key ...]
+/// [WEIGHTS weight [weight ...]] [AGGREGATE SUM|MIN|MAX]`
+pub struct ZStoreCommand {
+    destination: Vec<u8>,
+    keys: Vec<Vec<u8>>,
+    weights: Vec<f64>,
+    aggregate: Aggregate,
+    union: bool,
+}
+
+impl Command for ZStoreCommand {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, Reply> {
+        let union = args[0].eq_ignore_ascii_case(b"zunionstore");
+        let numkeys = match parse_int::<i64>(&args[2])? {
+            n if n > 0 => n as usize,
+            _ => {
+                let name = String::from_utf8_lossy(&args[0]).to_lowercase();
+                return Err(Reply::error(format!("ERR at least 1 input key is needed for '{}' command", name)));
+            }
+        };
+        if numkeys > args.len() - 3 {
+            return Err(syntax_error());
+        }
+        let keys = args[3..3 + numkeys].to_vec();
+
+        let mut weights = vec![1.0; numkeys];
+        let mut aggregate = Aggregate::Sum;
+        let mut i = 3 + numkeys;
+        while let Some(option) = args.get(i) {
+            match option.to_ascii_uppercase().as_slice() {
+                b"WEIGHTS" if i + numkeys < args.len() => {
+                    for (weight, arg) in weights.iter_mut().zip(&args[i + 1..=i + numkeys]) {
+                        *weight = parse_score(arg).ok_or_else(|| Reply::error("ERR weight value is not a float"))?;
+                    }
+                    i += numkeys + 1;
+                }
+                b"AGGREGATE" if i + 1 < args.len() => {
+                    aggregate = match args[i + 1].to_ascii_uppercase().as_slice() {
+                        b"SUM" => Aggregate::Sum,
+                        b"MIN" => Aggregate::Min,
+                        b"MAX" => Aggregate::Max,
+                        _ => return Err(syntax_error()),
+                    };
+                    i += 2;
+                }
+                _ => return Err(syntax_error()),
+            }
+        }
+        Ok(ZStoreCommand { destination: args[1].clone(), keys, weights, aggregate, union })
+    }
+
+    fn execute(&self, db: &Db) -> Reply {
+        let mut db = db.lock().unwrap();
+        let mut result: Option<HashMap<Vec<u8>, f64>> = None;
+        for (key, weight) in self.keys.iter().zip(&self.weights) {
+            let scores = match load_scores(&mut db, key) {
+                Ok(scores) => scores,
+                Err(reply) => return reply,
+            };
+            let weighted = scores.into_iter().map(|(member, score)| {
+                let score = score * weight;
+                (member, if score.is_nan() { 0.0 } else { score })
+            });
+            result = Some(match result {
+                None => weighted.collect(),
+                Some(mut acc) if self.union => {
+                    for (member, score) in weighted {
+                        acc.entry(member).and_modify(|current| *current = self.aggregate.apply(*current, score)).or_insert(score);
+                    }
+                    acc
+                }
+                Some(acc) => {
+                    let weighted: HashMap<Vec<u8>, f64> = weighted.collect();
+                    acc.into_iter()
+                        .filter_map(|(member, score)| weighted.get(&member).map(|other| (member, self.aggregate.apply(score, *other))))
+                        .collect()
+                }
+            });
+        }
+
+        let result: SortedSet = result.unwrap_or_default().into_iter().collect();
+        let len = result.len();
+        db.remove(&self.destination);
+        if len > 0 {
+            db.insert(self.destination.clone(), Entry::new(Value::ZSet(result)));
+            db.signal_ready(&self.destination);
+        }
+        Reply::Integer(len as i64)
+    }
+}
@@ -0,0 +1,102 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use tokio::sync::oneshot;
+use crate::db::Keyspace;
+use crate::reply::Reply;
+
+/// Retries a parked command against a key that just received data. Returns
+/// `Ok(None)` while the key still cannot serve it, which keeps the client
+/// blocked; an error reply unblocks the client with that error.
+pub type Serve = Box<dyn FnMut(&mut Keyspace, &[u8]) -> Result<Option<Reply>, Reply> + Send>;
+
+struct Waiter {
+    keys: Vec<Vec<u8>>,
+    serve: Serve,
+    reply: oneshot::Sender<Reply>,
+}
+
+/// Clients parked by blocking commands, queued per key in arrival order.
+#[derive(Default)]
+pub struct Blocked {
+    next_id: u64,
+    waiters: HashMap<u64, Waiter>,
+    queues: HashMap<Vec<u8>, VecDeque<u64>>,
+    ready: VecDeque<Vec<u8>>,
+    ready_set: HashSet<Vec<u8>>,
+}
+
+impl Keyspace {
+    /// Parks a client on `keys`. The reply is sent through `reply` once a
+    /// write makes one of the keys serviceable.
+    pub fn block(&mut self, keys: Vec<Vec<u8>>, serve: Serve, reply: oneshot::Sender<Reply>) -> u64 {
+        let blocked = &mut self.blocked;
+        blocked.next_id += 1;
+        let id = blocked.next_id;
+        for key in &keys {
+            let queue = blocked.queues.entry(key.clone()).or_default();
+            if !queue.contains(&id) {
+                queue.push_back(id);
+            }
+        }
+        blocked.waiters.insert(id, Waiter { keys, serve, reply });
+        id
+    }
+
+    /// Removes a parked client, e.g. on timeout or disconnect. Returns `false`
+    /// if it was already served.
+    pub fn unblock(&mut self, id: u64) -> bool {
+        let Some(waiter) = self.blocked.waiters.remove(&id) else {
+            return false;
+        };
+        self.dequeue(id, &waiter.keys);
+        true
+    }
+
+    /// Marks `key` as having received data that a parked client may be waiting for.
+    pub fn signal_ready(&mut self, key: &[u8]) {
+        let blocked = &mut self.blocked;
+        if blocked.queues.contains_key(key) && blocked.ready_set.insert(key.to_vec()) {
+            blocked.ready.push_back(key.to_vec());
+        }
+    }
+
+    /// Hands data on ready keys to the clients parked on them, oldest first.
+    /// Serving a client may write to other keys (BLMOVE), which are then
+    /// served in turn.
+    pub fn serve_blocked(&mut self) {
+        while let Some(key) = self.blocked.ready.pop_front() {
+            self.blocked.ready_set.remove(&key);
+            let mut position = 0;
+            while let Some(id) = self.blocked.queues.get(&key).and_then(|queue| queue.get(position)).copied() {
+                let mut waiter = self.blocked.waiters.remove(&id).expect("queued waiter is registered");
+                if waiter.reply.is_closed() {
+                    self.dequeue(id, &waiter.keys);
+                    continue;
+                }
+                match (waiter.serve)(self, &key) {
+                    Ok(Some(reply)) | Err(reply) => {
+                        self.dequeue(id, &waiter.keys);
+                        let _ = waiter.reply.send(reply);
+                    }
+                    Ok(None) => {
+                        self.blocked.waiters.insert(id, waiter);
+                        if !self.contains_key(&key) {
+                            break;
+                        }
+                        position += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    fn dequeue(&mut self, id: u64, keys: &[Vec<u8>]) {
+        for key in keys {
+            if let Some(queue) = self.blocked.queues.get_mut(key) {
+                queue.retain(|queued| *queued != id);
+                if queue.is_empty() {
+                    self.blocked.queues.remove(key);
+                }
+            }
+        }
+    }
+}
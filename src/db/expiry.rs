@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use crate::db::{Db, Keyspace};
+
+/// How often [`active_expiry`] runs an expire cycle.
+const ACTIVE_EXPIRE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Volatile keys looked at per round of an expire cycle.
+const KEYS_PER_ROUND: usize = 20;
+
+/// Time after which an expire cycle leaves the remaining keys to the next
+/// one, a quarter of the interval as in Redis.
+const CYCLE_BUDGET: Duration = Duration::from_millis(25);
+
+/// Keys with a deadline of their own or on hash fields, in a sequence an
+/// expire cycle walks a few at a time.
+#[derive(Default)]
+pub(super) struct Volatile {
+    keys: Vec<Vec<u8>>,
+    slots: HashMap<Vec<u8>, usize>,
+    /// Slot the next round starts at.
+    cursor: usize,
+}
+
+impl Volatile {
+    fn insert(&mut self, key: &[u8]) {
+        if !self.slots.contains_key(key) {
+            self.slots.insert(key.to_vec(), self.keys.len());
+            self.keys.push(key.to_vec());
+        }
+    }
+
+    /// Removes `key`; the last key moves into its slot.
+    fn remove(&mut self, key: &[u8]) {
+        let Some(slot) = self.slots.remove(key) else {
+            return;
+        };
+        self.keys.swap_remove(slot);
+        if let Some(moved) = self.keys.get(slot) {
+            *self.slots.get_mut(moved).expect("every key has a slot") = slot;
+        }
+    }
+}
+
+impl Keyspace {
+    /// Adds the keys written since the last call to the volatile keys if
+    /// they carry a deadline, and drops them otherwise.
+    pub(super) fn track_deadlines(&mut self) {
+        for key in std::mem::take(&mut self.written) {
+            match self.entries.get(&key) {
+                Some(entry) if entry.has_deadline() => self.volatile.insert(&key),
+                _ => self.volatile.remove(&key),
+            }
+        }
+    }
+
+    /// Evicts expired keys and hash fields that nobody looked up, the way
+    /// Redis does: volatile keys are checked a round at a time, and another
+    /// round follows only while more than a quarter of the last one had
+    /// expired and the cycle is within its time budget. Returns the number
+    /// of keys removed.
+    pub fn expire_cycle(&mut self, now: Instant) -> usize {
+        let started = Instant::now();
+        self.track_deadlines();
+        let mut removed = 0;
+        loop {
+            let round = KEYS_PER_ROUND.min(self.volatile.keys.len());
+            let mut expired = 0;
+            for _ in 0..round {
+                let slot = self.volatile.cursor % self.volatile.keys.len();
+                let key = self.volatile.keys[slot].clone();
+                let Some(entry) = self.entries.get_mut(&key) else {
+                    self.volatile.cursor = slot;
+                    self.volatile.remove(&key);
+                    continue;
+                };
+                let (gone, purged) = entry.purge_expired(now);
+                let keeps_deadline = !gone && entry.has_deadline();
+                if gone {
+                    self.entries.remove(&key);
+                    removed += 1;
+                }
+                if gone || purged > 0 {
+                    expired += 1;
+                    self.touch(&key);
+                }
+                if keeps_deadline {
+                    self.volatile.cursor = slot + 1;
+                } else {
+                    // Another key moved into the slot, so it is looked at next.
+                    self.volatile.cursor = slot;
+                    self.volatile.remove(&key);
+                }
+            }
+            if round == 0 || expired * 4 <= round || started.elapsed() >= CYCLE_BUDGET {
+                return removed;
+            }
+        }
+    }
+}
+
+/// Background task that reclaims expired keys and hash fields, so memory is
+/// freed even for data that is never accessed again.
+pub async fn active_expiry(db: Db) {
+    let mut interval = tokio::time::interval(ACTIVE_EXPIRE_INTERVAL);
+    loop {
+        interval.tick().await;
+        db.lock().unwrap().expire_cycle(Instant::now());
+    }
+}
@@ -0,0 +1,54 @@
+use std::time::Instant;
+use crate::db::{Entry, Keyspace};
+use crate::search::Index;
+
+impl Keyspace {
+    /// Adds an index and fills it with the documents already stored. Returns
+    /// `false` if an index of that name exists.
+    pub fn create_index(&mut self, name: &str, mut index: Index) -> bool {
+        if self.indexes.contains_key(name) {
+            return false;
+        }
+        let now = Instant::now();
+        for (key, entry) in self.entries.iter() {
+            if !entry.is_expired(now) {
+                index.update(key, Some(&entry.value));
+            }
+        }
+        self.indexes.insert(name.to_string(), index);
+        true
+    }
+
+    pub fn drop_index(&mut self, name: &str) -> Option<Index> {
+        self.indexes.remove(name)
+    }
+
+    pub fn index(&self, name: &str) -> Option<&Index> {
+        self.indexes.get(name)
+    }
+
+    pub fn index_names(&self) -> impl Iterator<Item = &String> {
+        self.indexes.keys()
+    }
+
+    /// Looks up an entry without evicting it, for readers that only hold a
+    /// shared borrow such as a search over an index.
+    pub fn peek(&self, key: &[u8]) -> Option<&Entry> {
+        self.entries.get(key).filter(|entry| !entry.is_expired(Instant::now()))
+    }
+
+    /// Brings the indexes up to date with the keys touched since the last
+    /// call. Keys whose value no longer fits an index drop out of it.
+    pub fn sync_indexes(&mut self) {
+        if self.stale.is_empty() {
+            return;
+        }
+        let now = Instant::now();
+        for key in std::mem::take(&mut self.stale) {
+            let value = self.entries.get(&key).filter(|entry| !entry.is_expired(now)).map(|entry| &entry.value);
+            for index in self.indexes.values_mut() {
+                index.update(&key, value);
+            }
+        }
+    }
+}
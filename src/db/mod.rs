@@ -1 +1,221 @@
-pub mod connection;
\ No newline at end of file
+mod blocking;
+mod expiry;
+mod indexing;
+pub mod connection;
+mod table;
+mod stream;
+mod value;
+mod zset;
+
+use std::collections::{BTreeMap, HashSet};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, LockResult, Mutex, MutexGuard, PoisonError};
+use std::time::Instant;
+use crate::search::Index;
+
+pub use expiry::active_expiry;
+pub use table::Table;
+pub use value::{Entry, Hash, Set, Value};
+pub use stream::{ConsumerGroup, Stream, StreamId, STREAM_NODE_ENTRIES};
+pub use zset::SortedSet;
+
+/// Shared handle to the keyspace.
+#[derive(Clone, Default)]
+pub struct Db {
+    inner: Arc<Mutex<Keyspace>>,
+}
+
+impl Db {
+    pub fn new() -> Self {
+        Db::default()
+    }
+
+    pub fn lock(&self) -> LockResult<KeyspaceGuard<'_>> {
+        match self.inner.lock() {
+            Ok(guard) => Ok(KeyspaceGuard(guard)),
+            Err(poisoned) => Err(PoisonError::new(KeyspaceGuard(poisoned.into_inner()))),
+        }
+    }
+}
+
+/// Exclusive access to the keyspace. Clients blocked on keys written while
+/// the guard was held are served before it is released, so no other
+/// connection can take the data first, and search indexes catch up with
+/// the writes.
+pub struct KeyspaceGuard<'a>(MutexGuard<'a, Keyspace>);
+
+impl Deref for KeyspaceGuard<'_> {
+    type Target = Keyspace;
+
+    fn deref(&self) -> &Keyspace {
+        &self.0
+    }
+}
+
+impl DerefMut for KeyspaceGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Keyspace {
+        &mut self.0
+    }
+}
+
+impl Drop for KeyspaceGuard<'_> {
+    fn drop(&mut self) {
+        self.0.serve_blocked();
+        self.0.track_deadlines();
+        self.0.sync_indexes();
+    }
+}
+
+/// The key/value map. Expired entries are evicted lazily whenever they are
+/// looked up, so callers never observe them.
+#[derive(Default)]
+pub struct Keyspace {
+    entries: Table<Entry>,
+    blocked: blocking::Blocked,
+    /// Search indexes by name.
+    indexes: BTreeMap<String, Index>,
+    /// Keys that may have changed since the indexes last saw them.
+    stale: HashSet<Vec<u8>>,
+    /// Keys written, deleted or expired since deadlines were last tracked.
+    written: HashSet<Vec<u8>>,
+    /// Keys an expire cycle has to look at.
+    volatile: expiry::Volatile,
+}
+
+impl Keyspace {
+    pub fn new() -> Self {
+        Keyspace::default()
+    }
+
+    pub fn get(&mut self, key: &[u8]) -> Option<&Entry> {
+        self.evict_expired(key);
+        self.entries.get(key)
+    }
+
+    /// Mutable access to an entry. The key is assumed to be written and is
+    /// reindexed once the guard is released.
+    pub fn get_mut(&mut self, key: &[u8]) -> Option<&mut Entry> {
+        if !self.evict_expired(key) {
+            return None;
+        }
+        self.touch(key);
+        self.entries.get_mut(key)
+    }
+
+    /// Removes `key` if it expired. Returns `true` if the key still exists.
+    fn evict_expired(&mut self, key: &[u8]) -> bool {
+        let now = Instant::now();
+        let Some(entry) = self.entries.get_mut(key) else {
+            return false;
+        };
+        let (expired, purged) = entry.purge_expired(now);
+        if expired {
+            self.entries.remove(key);
+        }
+        if expired || purged > 0 {
+            self.touch(key);
+        }
+        !expired
+    }
+
+    pub fn contains_key(&mut self, key: &[u8]) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn insert(&mut self, key: Vec<u8>, entry: Entry) -> Option<Entry> {
+        self.touch(&key);
+        self.entries.insert(key, entry)
+    }
+
+    /// Deletes `key` if it holds an aggregate that became empty, the way
+    /// Redis never keeps empty lists, hashes or sets around.
+    pub fn remove_if_empty(&mut self, key: &[u8]) {
+        if self.entries.get(key).is_some_and(|entry| entry.value.is_empty_collection()) {
+            self.entries.remove(key);
+            self.touch(key);
+        }
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<Entry> {
+        let now = Instant::now();
+        let mut entry = self.entries.remove(key)?;
+        self.touch(key);
+        (!entry.purge_expired(now).0).then_some(entry)
+    }
+
+    /// Removes `key` like [`remove`](Self::remove), but frees a large value
+    /// on a blocking task instead of while the keyspace is locked. Returns
+    /// whether the key existed.
+    pub fn unlink(&mut self, key: &[u8]) -> bool {
+        let Some(entry) = self.remove(key) else {
+            return false;
+        };
+        if entry.value.free_effort() > LAZY_FREE_THRESHOLD {
+            if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+                runtime.spawn_blocking(move || drop(entry));
+            }
+        }
+        true
+    }
+
+    /// Moves the entry at `from`, deadline included, to `to`, replacing
+    /// whatever `to` held. Returns `false` if `from` doesn't exist.
+    pub fn rename(&mut self, from: &[u8], to: &[u8]) -> bool {
+        let Some(entry) = self.remove(from) else {
+            return false;
+        };
+        self.unlink(to);
+        self.insert(to.to_vec(), entry);
+        self.signal_ready(to);
+        true
+    }
+
+    /// A random live key; `pick(n)` chooses an index below `n`.
+    pub fn random_key(&mut self, mut pick: impl FnMut(usize) -> usize) -> Option<Vec<u8>> {
+        loop {
+            if self.entries.is_empty() {
+                return None;
+            }
+            let key = self.entries.random(&mut pick)?.0.clone();
+            // An expired key is evicted, so this terminates.
+            if self.evict_expired(&key) {
+                return Some(key);
+            }
+        }
+    }
+
+    /// Every key that hasn't expired, with its entry.
+    pub fn live_entries(&self) -> impl Iterator<Item = (&[u8], &Entry)> {
+        let now = Instant::now();
+        self.entries.iter().filter(move |(_, entry)| !entry.is_expired(now)).map(|(key, entry)| (key.as_slice(), entry))
+    }
+
+    /// A page of SCAN over the live keys, with the cursor to continue from;
+    /// see [`Table::scan`].
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<(&[u8], &Entry)>) {
+        let now = Instant::now();
+        let (cursor, keys) = self.entries.scan(cursor, count);
+        let entries = keys
+            .into_iter()
+            .filter_map(|key| Some((key, self.entries.get(key).filter(|entry| !entry.is_expired(now))?)))
+            .collect();
+        (cursor, entries)
+    }
+
+    /// The number of keys, including expired ones not evicted yet.
+    pub fn key_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Marks `key` as written, deleted or expired.
+    fn touch(&mut self, key: &[u8]) {
+        self.written.insert(key.to_vec());
+        if !self.indexes.is_empty() {
+            self.stale.insert(key.to_vec());
+        }
+    }
+}
+
+/// Values that take more allocations than this to drop are freed in the
+/// background by [`Keyspace::unlink`].
+const LAZY_FREE_THRESHOLD: usize = 64;
@@ -0,0 +1,299 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// Entry ID of the form `<milliseconds>-<sequence>`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId { ms: u64::MAX, seq: u64::MAX };
+
+    pub fn new(ms: u64, seq: u64) -> Self {
+        StreamId { ms, seq }
+    }
+
+    /// Parses `ms-seq`, or a bare `ms` whose sequence is `default_seq`.
+    pub fn parse(arg: &[u8], default_seq: u64) -> Option<Self> {
+        let text = std::str::from_utf8(arg).ok()?;
+        match text.split_once('-') {
+            Some((ms, seq)) => Some(StreamId::new(ms.parse().ok()?, seq.parse().ok()?)),
+            None => Some(StreamId::new(text.parse().ok()?, default_seq)),
+        }
+    }
+
+    /// The smallest ID greater than this one.
+    pub fn next(self) -> Option<Self> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId::new(self.ms, seq)),
+            None => Some(StreamId::new(self.ms.checked_add(1)?, 0)),
+        }
+    }
+
+    /// The largest ID smaller than this one.
+    pub fn previous(self) -> Option<Self> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(StreamId::new(self.ms, seq)),
+            None => Some(StreamId::new(self.ms.checked_sub(1)?, u64::MAX)),
+        }
+    }
+
+    /// The ID XADD assigns after this one: the current time, or one past
+    /// this ID if the clock went backwards. `None` once IDs are exhausted.
+    pub fn next_auto(self, now_ms: u64) -> Option<Self> {
+        if now_ms > self.ms {
+            Some(StreamId::new(now_ms, 0))
+        } else {
+            self.next()
+        }
+    }
+
+    pub fn to_bytes(self) -> Vec<u8> {
+        self.to_string().into_bytes()
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+/// Entries per node in Redis' radix tree; approximate trimming (`~`) only
+/// evicts whole nodes' worth of entries.
+pub const STREAM_NODE_ENTRIES: usize = 100;
+
+/// An append-only log of field/value entries ordered by ID.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stream {
+    entries: BTreeMap<StreamId, Vec<Vec<u8>>>,
+    /// Highest ID ever added, even if that entry was deleted since.
+    pub last_id: StreamId,
+    pub max_deleted_id: StreamId,
+    pub entries_added: u64,
+    pub groups: BTreeMap<Vec<u8>, ConsumerGroup>,
+}
+
+impl Stream {
+    pub fn new() -> Self {
+        Stream::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Appends an entry; `id` must be greater than [`Stream::last_id`].
+    pub fn add(&mut self, id: StreamId, fields: Vec<Vec<u8>>) {
+        self.entries.insert(id, fields);
+        self.last_id = id;
+        self.entries_added += 1;
+    }
+
+    pub fn get(&self, id: StreamId) -> Option<&Vec<Vec<u8>>> {
+        self.entries.get(&id)
+    }
+
+    pub fn range(&self, range: RangeInclusive<StreamId>) -> impl DoubleEndedIterator<Item = (&StreamId, &Vec<Vec<u8>>)> {
+        self.entries.range(range)
+    }
+
+    pub fn first_id(&self) -> Option<StreamId> {
+        self.entries.keys().next().copied()
+    }
+
+    pub fn last_entry_id(&self) -> Option<StreamId> {
+        self.entries.keys().next_back().copied()
+    }
+
+    pub fn remove(&mut self, id: StreamId) -> bool {
+        if self.entries.remove(&id).is_none() {
+            return false;
+        }
+        self.max_deleted_id = self.max_deleted_id.max(id);
+        true
+    }
+
+    /// Whether entries at or after `from` may have been deleted, in which case
+    /// a group's read counter can no longer be derived by counting.
+    pub fn has_tombstones_from(&self, from: StreamId) -> bool {
+        self.len() > 0 && self.max_deleted_id != StreamId::MIN && self.max_deleted_id >= from
+    }
+
+    /// Number of entries added up to and including `id`, if that can be told
+    /// without walking the stream; used to keep a group's `entries-read` valid.
+    pub fn entries_up_to(&self, id: StreamId) -> Option<u64> {
+        if self.entries_added == 0 {
+            return Some(0);
+        }
+        if self.len() == 0 && id <= self.last_id {
+            return Some(self.entries_added);
+        }
+        match id.cmp(&self.last_id) {
+            std::cmp::Ordering::Equal => return Some(self.entries_added),
+            std::cmp::Ordering::Greater => return None,
+            std::cmp::Ordering::Less => {}
+        }
+        let first_id = self.first_id().unwrap_or(StreamId::MIN);
+        if self.max_deleted_id == StreamId::MIN || self.max_deleted_id < first_id {
+            let before_first = self.entries_added - self.len() as u64;
+            match id.cmp(&first_id) {
+                std::cmp::Ordering::Less => return Some(before_first),
+                std::cmp::Ordering::Equal => return Some(before_first + 1),
+                std::cmp::Ordering::Greater => {}
+            }
+        }
+        None
+    }
+
+    /// Entries added to the stream but not yet read by `group`, if known.
+    pub fn lag(&self, group: &ConsumerGroup) -> Option<u64> {
+        let entries_read = match group.entries_read {
+            Some(read) if !self.has_tombstones_from(group.last_delivered) => read,
+            _ => self.entries_up_to(group.last_delivered)?,
+        };
+        Some(self.entries_added.saturating_sub(entries_read))
+    }
+
+    /// Moves `group` past `id` after delivering it, keeping its read counter.
+    pub fn advance_group(&mut self, name: &[u8], id: StreamId) {
+        let tombstones = self.has_tombstones_from(id);
+        let estimate = self.entries_up_to(id);
+        let entries_added = self.entries_added;
+        let Some(group) = self.groups.get_mut(name) else {
+            return;
+        };
+        if id <= group.last_delivered {
+            return;
+        }
+        match group.entries_read {
+            Some(read) if !tombstones => group.entries_read = Some(read + 1),
+            _ if entries_added > 0 => group.entries_read = estimate,
+            _ => {}
+        }
+        group.last_delivered = id;
+    }
+
+    /// Evicts the oldest entries, at most `count`; approximate trimming
+    /// rounds down to whole nodes. Returns the number of entries removed.
+    fn evict(&mut self, count: usize, approximate: bool, limit: Option<usize>) -> usize {
+        let mut count = limit.map_or(count, |limit| count.min(limit));
+        if approximate {
+            count -= count % STREAM_NODE_ENTRIES;
+        }
+        for _ in 0..count {
+            if let Some((id, _)) = self.entries.pop_first() {
+                self.max_deleted_id = self.max_deleted_id.max(id);
+            }
+        }
+        count
+    }
+
+    /// Trims to at most `max_len` entries (at least `max_len` if approximate).
+    pub fn trim_max_len(&mut self, max_len: usize, approximate: bool, limit: Option<usize>) -> usize {
+        self.evict(self.len().saturating_sub(max_len), approximate, limit)
+    }
+
+    /// Evicts entries with an ID below `min_id`.
+    pub fn trim_min_id(&mut self, min_id: StreamId, approximate: bool, limit: Option<usize>) -> usize {
+        let below = self.entries.range(..min_id).count();
+        self.evict(below, approximate, limit)
+    }
+}
+
+/// An entry delivered to a consumer but not acknowledged yet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingEntry {
+    pub consumer: Vec<u8>,
+    /// Unix time of the last delivery in milliseconds.
+    pub delivery_time: u64,
+    pub delivery_count: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Consumer {
+    /// Last time the consumer issued a command, in Unix milliseconds.
+    pub seen_time: u64,
+    /// Last time the consumer read or claimed an entry; `None` if it never did.
+    pub active_time: Option<u64>,
+    pub pending: BTreeSet<StreamId>,
+}
+
+impl Consumer {
+    fn new(now_ms: u64) -> Self {
+        Consumer { seen_time: now_ms, active_time: None, pending: BTreeSet::new() }
+    }
+}
+
+/// Named cursor into a stream that hands each entry to one of its consumers
+/// and tracks deliveries until they are acknowledged.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConsumerGroup {
+    pub last_delivered: StreamId,
+    /// Entries the group has read so far; `None` when it can't be known.
+    pub entries_read: Option<u64>,
+    /// The pending entries list (PEL) of the whole group.
+    pub pending: BTreeMap<StreamId, PendingEntry>,
+    pub consumers: BTreeMap<Vec<u8>, Consumer>,
+}
+
+impl ConsumerGroup {
+    pub fn new(last_delivered: StreamId, entries_read: Option<u64>) -> Self {
+        ConsumerGroup { last_delivered, entries_read, ..ConsumerGroup::default() }
+    }
+
+    /// Returns the consumer named `name`, creating it if needed, and marks it as seen.
+    pub fn consumer(&mut self, name: &[u8], now_ms: u64) -> &mut Consumer {
+        let consumer = self.consumers.entry(name.to_vec()).or_insert_with(|| Consumer::new(now_ms));
+        consumer.seen_time = now_ms;
+        consumer
+    }
+
+    /// Adds a consumer without touching an existing one. Returns `true` if it was created.
+    pub fn create_consumer(&mut self, name: &[u8], now_ms: u64) -> bool {
+        if self.consumers.contains_key(name) {
+            return false;
+        }
+        self.consumers.insert(name.to_vec(), Consumer::new(now_ms));
+        true
+    }
+
+    /// Removes a consumer along with its pending entries and returns how
+    /// many it had, or `None` if there is no such consumer.
+    pub fn remove_consumer(&mut self, name: &[u8]) -> Option<usize> {
+        let consumer = self.consumers.remove(name)?;
+        for id in &consumer.pending {
+            self.pending.remove(id);
+        }
+        Some(consumer.pending.len())
+    }
+
+    /// Makes `consumer` the owner of the pending entry `id`, taking it from
+    /// its previous owner if there was one.
+    pub fn assign(&mut self, id: StreamId, consumer: &[u8], now_ms: u64, delivery_time: u64, delivery_count: u64) {
+        if let Some(previous) = self.pending.remove(&id) {
+            if let Some(owner) = self.consumers.get_mut(&previous.consumer) {
+                owner.pending.remove(&id);
+            }
+        }
+        self.pending.insert(id, PendingEntry { consumer: consumer.to_vec(), delivery_time, delivery_count });
+        let consumer = self.consumer(consumer, now_ms);
+        consumer.pending.insert(id);
+        consumer.active_time = Some(now_ms);
+    }
+
+    /// Removes `id` from the pending entries. Returns `true` if it was pending.
+    pub fn acknowledge(&mut self, id: StreamId) -> bool {
+        let Some(entry) = self.pending.remove(&id) else {
+            return false;
+        };
+        if let Some(consumer) = self.consumers.get_mut(&entry.consumer) {
+            consumer.pending.remove(&id);
+        }
+        true
+    }
+}
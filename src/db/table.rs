@@ -0,0 +1,199 @@
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::BuildHasher;
+use std::iter;
+
+/// Tables never shrink below this many buckets once allocated.
+const MIN_BUCKETS: usize = 4;
+
+/// A hash table with chained buckets, like Redis' dict. Unlike the standard
+/// map its buckets can be addressed, so SCAN resumes from a cursor without
+/// any extra bookkeeping and a random element is found without walking the
+/// table.
+#[derive(Clone)]
+pub struct Table<V> {
+    /// A power of two in length, or empty before the first insert.
+    buckets: Vec<Option<Box<Node<V>>>>,
+    len: usize,
+    hasher: RandomState,
+}
+
+#[derive(Clone)]
+struct Node<V> {
+    key: Vec<u8>,
+    value: V,
+    next: Option<Box<Node<V>>>,
+}
+
+impl<V> Default for Table<V> {
+    fn default() -> Self {
+        Table { buckets: Vec::new(), len: 0, hasher: RandomState::new() }
+    }
+}
+
+impl<V> Table<V> {
+    pub fn new() -> Self {
+        Table::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn bucket(&self, key: &[u8]) -> Option<usize> {
+        if self.buckets.is_empty() {
+            return None;
+        }
+        Some(self.hasher.hash_one(key) as usize & (self.buckets.len() - 1))
+    }
+
+    fn chain(&self, bucket: usize) -> impl Iterator<Item = &Node<V>> {
+        iter::successors(self.buckets[bucket].as_deref(), |node| node.next.as_deref())
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&V> {
+        let bucket = self.bucket(key)?;
+        self.chain(bucket).find(|node| node.key == key).map(|node| &node.value)
+    }
+
+    pub fn get_mut(&mut self, key: &[u8]) -> Option<&mut V> {
+        let bucket = self.bucket(key)?;
+        let mut node = self.buckets[bucket].as_deref_mut();
+        while let Some(current) = node {
+            if current.key == key {
+                return Some(&mut current.value);
+            }
+            node = current.next.as_deref_mut();
+        }
+        None
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Sets `key` to `value`, returning the value it replaced.
+    pub fn insert(&mut self, key: Vec<u8>, value: V) -> Option<V> {
+        if let Some(current) = self.get_mut(&key) {
+            return Some(std::mem::replace(current, value));
+        }
+        if self.len >= self.buckets.len() {
+            self.resize((self.buckets.len() * 2).max(MIN_BUCKETS));
+        }
+        let bucket = self.bucket(&key).expect("table has buckets");
+        let next = self.buckets[bucket].take();
+        self.buckets[bucket] = Some(Box::new(Node { key, value, next }));
+        self.len += 1;
+        None
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<V> {
+        let bucket = self.bucket(key)?;
+        let mut link = &mut self.buckets[bucket];
+        while link.as_ref().is_some_and(|node| node.key != key) {
+            link = &mut link.as_mut().expect("checked above").next;
+        }
+        let Node { value, next, .. } = *link.take()?;
+        *link = next;
+        self.len -= 1;
+        // Shrink once mostly empty, so scans and random picks don't have to
+        // wade through empty buckets.
+        if self.len * 8 < self.buckets.len() && self.buckets.len() > MIN_BUCKETS {
+            self.resize(self.len.next_power_of_two().max(MIN_BUCKETS));
+        }
+        Some(value)
+    }
+
+    /// Rehashes into `size` buckets, moving the nodes rather than copying.
+    fn resize(&mut self, size: usize) {
+        let old = std::mem::replace(&mut self.buckets, iter::repeat_with(|| None).take(size).collect());
+        for mut chain in old {
+            while let Some(mut node) = chain {
+                chain = node.next.take();
+                let bucket = self.bucket(&node.key).expect("table has buckets");
+                node.next = self.buckets[bucket].take();
+                self.buckets[bucket] = Some(node);
+            }
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Vec<u8>, &V)> {
+        (0..self.buckets.len()).flat_map(|bucket| self.chain(bucket)).map(|node| (&node.key, &node.value))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &Vec<u8>> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, value)| value)
+    }
+
+    /// Returns the keys of the buckets from `cursor` on, stopping once about
+    /// `count` keys were found, and the cursor to continue from (0 once every
+    /// bucket was visited). Keys present for the whole iteration are
+    /// returned at least once, however the table is resized in between.
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<&[u8]>) {
+        if self.buckets.is_empty() {
+            return (0, Vec::new());
+        }
+        let mask = self.buckets.len() as u64 - 1;
+        let mut cursor = cursor;
+        let mut keys = Vec::new();
+        // Bounds the work on a sparse table, as Redis does.
+        let mut visits = count.saturating_mul(10);
+        loop {
+            keys.extend(self.chain((cursor & mask) as usize).map(|node| node.key.as_slice()));
+            // Counting with the bits reversed visits a bucket before the
+            // ones it splits into when the table grows, and after the ones
+            // merged into it when it shrinks.
+            cursor = (cursor | !mask).reverse_bits().wrapping_add(1).reverse_bits();
+            visits -= 1;
+            if cursor == 0 || keys.len() >= count || visits == 0 {
+                return (cursor, keys);
+            }
+        }
+    }
+
+    /// A random element; `pick(n)` chooses an index below `n`. Elements in
+    /// crowded buckets are slightly less likely to be chosen, as in Redis.
+    pub fn random(&self, mut pick: impl FnMut(usize) -> usize) -> Option<(&Vec<u8>, &V)> {
+        if self.len == 0 {
+            return None;
+        }
+        loop {
+            let bucket = pick(self.buckets.len());
+            let length = self.chain(bucket).count();
+            if length > 0 {
+                let node = self.chain(bucket).nth(pick(length))?;
+                return Some((&node.key, &node.value));
+            }
+        }
+    }
+}
+
+impl<V: PartialEq> PartialEq for Table<V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().all(|(key, value)| other.get(key) == Some(value))
+    }
+}
+
+impl<V: fmt::Debug> fmt::Debug for Table<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<V> FromIterator<(Vec<u8>, V)> for Table<V> {
+    fn from_iter<I: IntoIterator<Item = (Vec<u8>, V)>>(iter: I) -> Self {
+        let mut table = Table::new();
+        for (key, value) in iter {
+            table.insert(key, value);
+        }
+        table
+    }
+}
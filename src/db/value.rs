@@ -0,0 +1,344 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::Instant;
+use crate::db::stream::Stream;
+use crate::db::table::Table;
+use crate::db::zset::SortedSet;
+
+/// A value stored under a key together with its optional deadline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub value: Value,
+    pub expires_at: Option<Instant>,
+}
+
+impl Entry {
+    pub fn new(value: Value) -> Self {
+        Entry { value, expires_at: None }
+    }
+
+    pub fn with_expiry(value: Value, expires_at: Option<Instant>) -> Self {
+        Entry { value, expires_at }
+    }
+
+    pub fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|deadline| now >= deadline)
+    }
+
+    /// Whether the entry or any of its hash fields has a deadline.
+    pub fn has_deadline(&self) -> bool {
+        self.expires_at.is_some() || matches!(&self.value, Value::Hash(hash) if hash.has_expiring_fields())
+    }
+
+    /// Drops expired hash fields. Returns whether the whole entry expired,
+    /// either by its own deadline or because it lost its last field, and
+    /// how many fields were dropped.
+    pub fn purge_expired(&mut self, now: Instant) -> (bool, usize) {
+        if self.is_expired(now) {
+            return (true, 0);
+        }
+        let purged = self.value.purge_expired(now);
+        (purged > 0 && self.value.is_empty_collection(), purged)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(Vec<u8>),
+    List(VecDeque<Vec<u8>>),
+    Hash(Hash),
+    Set(Set),
+    ZSet(SortedSet),
+    Stream(Stream),
+    /// A RedisJSON document, parsed once when it is written.
+    Json(serde_json::Value),
+}
+
+impl Value {
+    /// The name reported by TYPE.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Hash(_) => "hash",
+            Value::Set(_) => "set",
+            Value::ZSet(_) => "zset",
+            Value::Stream(_) => "stream",
+            Value::Json(_) => "ReJSON-RL",
+        }
+    }
+
+    /// Drops hash fields whose deadline passed and returns how many. A hash
+    /// left empty by this means the key itself has expired.
+    pub fn purge_expired(&mut self, now: Instant) -> usize {
+        match self {
+            Value::Hash(hash) => hash.purge_expired(now),
+            _ => 0,
+        }
+    }
+
+    /// Roughly how many allocations dropping the value frees: one per
+    /// element of a collection.
+    pub fn free_effort(&self) -> usize {
+        match self {
+            Value::String(_) => 1,
+            Value::List(list) => list.len(),
+            Value::Hash(hash) => hash.len(),
+            Value::Set(set) => set.len(),
+            Value::ZSet(zset) => zset.len(),
+            Value::Stream(stream) => stream.len(),
+            Value::Json(serde_json::Value::Array(items)) => items.len(),
+            Value::Json(serde_json::Value::Object(members)) => members.len(),
+            Value::Json(_) => 1,
+        }
+    }
+
+    pub fn is_empty_collection(&self) -> bool {
+        match self {
+            // Streams keep existing when empty, as in Redis.
+            Value::String(_) | Value::Stream(_) | Value::Json(_) => false,
+            Value::List(list) => list.is_empty(),
+            Value::Hash(hash) => hash.is_empty(),
+            Value::Set(set) => set.is_empty(),
+            Value::ZSet(zset) => zset.is_empty(),
+        }
+    }
+}
+
+/// Field/value pairs where each field may carry its own deadline. Expired
+/// fields are purged by the keyspace before a hash is handed out, so lookups
+/// here do not check deadlines themselves.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Hash {
+    fields: Table<Vec<u8>>,
+    expires: HashMap<Vec<u8>, Instant>,
+}
+
+impl Hash {
+    pub fn new() -> Self {
+        Hash::default()
+    }
+
+    pub fn get(&self, field: &[u8]) -> Option<&Vec<u8>> {
+        self.fields.get(field)
+    }
+
+    /// Mutable access to a value; unlike [`Hash::insert`] this keeps the
+    /// field's deadline.
+    pub fn get_mut(&mut self, field: &[u8]) -> Option<&mut Vec<u8>> {
+        self.fields.get_mut(field)
+    }
+
+    pub fn contains_key(&self, field: &[u8]) -> bool {
+        self.fields.contains_key(field)
+    }
+
+    /// Sets a field, clearing any deadline it had. Returns the old value.
+    pub fn insert(&mut self, field: Vec<u8>, value: Vec<u8>) -> Option<Vec<u8>> {
+        self.expires.remove(&field);
+        self.fields.insert(field, value)
+    }
+
+    pub fn remove(&mut self, field: &[u8]) -> Option<Vec<u8>> {
+        self.expires.remove(field);
+        self.fields.remove(field)
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Vec<u8>, &Vec<u8>)> {
+        self.fields.iter()
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &Vec<u8>> {
+        self.fields.keys()
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Vec<u8>> {
+        self.fields.values()
+    }
+
+    /// A page of field names for HSCAN, with the cursor to continue from;
+    /// see [`Table::scan`].
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<&[u8]>) {
+        self.fields.scan(cursor, count)
+    }
+
+    pub fn expires_at(&self, field: &[u8]) -> Option<Instant> {
+        self.expires.get(field).copied()
+    }
+
+    pub fn has_expiring_fields(&self) -> bool {
+        !self.expires.is_empty()
+    }
+
+    /// Sets or clears the deadline of an existing field.
+    pub fn set_expiry(&mut self, field: &[u8], expires_at: Option<Instant>) {
+        match expires_at {
+            Some(deadline) if self.fields.contains_key(field) => {
+                self.expires.insert(field.to_vec(), deadline);
+            }
+            _ => {
+                self.expires.remove(field);
+            }
+        }
+    }
+
+    /// Removes every field whose deadline passed and returns how many.
+    pub fn purge_expired(&mut self, now: Instant) -> usize {
+        if self.expires.is_empty() {
+            return 0;
+        }
+        let expired: Vec<Vec<u8>> = self.expires.iter().filter(|(_, deadline)| now >= **deadline).map(|(field, _)| field.clone()).collect();
+        for field in &expired {
+            self.remove(field);
+        }
+        expired.len()
+    }
+}
+
+impl FromIterator<(Vec<u8>, Vec<u8>)> for Hash {
+    fn from_iter<I: IntoIterator<Item = (Vec<u8>, Vec<u8>)>>(iter: I) -> Self {
+        Hash { fields: iter.into_iter().collect(), expires: HashMap::new() }
+    }
+}
+
+/// Sets of integers up to this size are kept in the compact encoding.
+const MAX_INTSET_ENTRIES: usize = 512;
+
+/// A set of members. Small sets whose members are all canonical integers are
+/// stored as a sorted `Vec<i64>`, like Redis' intset; anything else converts
+/// the set to a hash table for good.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Set {
+    IntSet(Vec<i64>),
+    HashTable(Table<()>),
+}
+
+impl Default for Set {
+    fn default() -> Self {
+        Set::IntSet(Vec::new())
+    }
+}
+
+/// Parses `member` as an integer only if it prints back identically, so
+/// members such as `007` or `+1` keep their exact bytes.
+fn as_int(member: &[u8]) -> Option<i64> {
+    let n: i64 = std::str::from_utf8(member).ok()?.parse().ok()?;
+    (n.to_string().as_bytes() == member).then_some(n)
+}
+
+impl Set {
+    pub fn new() -> Self {
+        Set::default()
+    }
+
+    /// Name of the internal representation, as OBJECT ENCODING reports it.
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Set::IntSet(_) => "intset",
+            Set::HashTable(_) => "hashtable",
+        }
+    }
+
+    pub fn contains(&self, member: &[u8]) -> bool {
+        match self {
+            Set::IntSet(ints) => as_int(member).is_some_and(|n| ints.binary_search(&n).is_ok()),
+            Set::HashTable(members) => members.contains_key(member),
+        }
+    }
+
+    /// Adds `member`, returning `false` if it was already present.
+    pub fn insert(&mut self, member: &[u8]) -> bool {
+        if let Set::IntSet(ints) = self {
+            match as_int(member) {
+                Some(n) => match ints.binary_search(&n) {
+                    Ok(_) => return false,
+                    Err(position) if ints.len() < MAX_INTSET_ENTRIES => {
+                        ints.insert(position, n);
+                        return true;
+                    }
+                    Err(_) => self.convert(),
+                },
+                None => self.convert(),
+            }
+        }
+        match self {
+            Set::HashTable(members) => members.insert(member.to_vec(), ()).is_none(),
+            Set::IntSet(_) => unreachable!("set was converted"),
+        }
+    }
+
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self {
+            Set::IntSet(ints) => match as_int(member).map(|n| ints.binary_search(&n)) {
+                Some(Ok(position)) => {
+                    ints.remove(position);
+                    true
+                }
+                _ => false,
+            },
+            Set::HashTable(members) => members.remove(member).is_some(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Set::IntSet(ints) => ints.len(),
+            Set::HashTable(members) => members.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn members(&self) -> Vec<Vec<u8>> {
+        match self {
+            Set::IntSet(ints) => ints.iter().map(|n| n.to_string().into_bytes()).collect(),
+            Set::HashTable(members) => members.keys().cloned().collect(),
+        }
+    }
+
+    /// A random member; `pick(n)` chooses an index below `n`.
+    pub fn random_member(&self, mut pick: impl FnMut(usize) -> usize) -> Option<Vec<u8>> {
+        match self {
+            Set::IntSet(ints) if ints.is_empty() => None,
+            Set::IntSet(ints) => Some(ints[pick(ints.len())].to_string().into_bytes()),
+            Set::HashTable(members) => members.random(pick).map(|(member, _)| member.clone()),
+        }
+    }
+
+    /// A page of SSCAN, with the cursor to continue from; see
+    /// [`Table::scan`]. An intset is small enough to return whole.
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<Vec<u8>>) {
+        match self {
+            Set::IntSet(_) => (0, self.members()),
+            Set::HashTable(members) => {
+                let (cursor, page) = members.scan(cursor, count);
+                (cursor, page.into_iter().map(<[u8]>::to_vec).collect())
+            }
+        }
+    }
+
+    fn convert(&mut self) {
+        if let Set::IntSet(ints) = self {
+            *self = Set::HashTable(ints.iter().map(|n| (n.to_string().into_bytes(), ())).collect());
+        }
+    }
+}
+
+impl<M: AsRef<[u8]>> FromIterator<M> for Set {
+    fn from_iter<I: IntoIterator<Item = M>>(iter: I) -> Self {
+        let mut set = Set::new();
+        for member in iter {
+            set.insert(member.as_ref());
+        }
+        set
+    }
+}
@@ -0,0 +1,283 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// Members ordered by `(score, member)`, with O(log n) lookups by score,
+/// member order and rank.
+#[derive(Debug, Clone, Default)]
+pub struct SortedSet {
+    scores: HashMap<Vec<u8>, f64>,
+    list: SkipList,
+}
+
+impl PartialEq for SortedSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.scores == other.scores
+    }
+}
+
+impl SortedSet {
+    pub fn new() -> Self {
+        SortedSet::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// Adds `member` or moves it to `score`. Returns `true` if it was added.
+    pub fn insert(&mut self, member: &[u8], score: f64) -> bool {
+        match self.scores.get_mut(member) {
+            Some(current) if *current == score => false,
+            Some(current) => {
+                self.list.remove(*current, member);
+                self.list.insert(score, member.to_vec());
+                *current = score;
+                false
+            }
+            None => {
+                self.list.insert(score, member.to_vec());
+                self.scores.insert(member.to_vec(), score);
+                true
+            }
+        }
+    }
+
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self.scores.remove(member) {
+            Some(score) => self.list.remove(score, member),
+            None => false,
+        }
+    }
+
+    /// Zero-based position of `member` in ascending order.
+    pub fn rank(&self, member: &[u8]) -> Option<usize> {
+        let score = self.score(member)?;
+        Some(self.count_below(|s, m| compare(s, m, score, member) == Ordering::Less))
+    }
+
+    /// Number of leading elements for which `below` holds. `below` must be
+    /// true for a prefix of the ascending order and false afterwards, e.g.
+    /// "score is less than the lower bound".
+    pub fn count_below(&self, below: impl Fn(f64, &[u8]) -> bool) -> usize {
+        self.list.count_below(below)
+    }
+
+    /// Elements with a rank in `start..end`, in ascending order.
+    pub fn range(&self, start: usize, end: usize) -> Vec<(Vec<u8>, f64)> {
+        let mut elements = Vec::with_capacity(end.saturating_sub(start));
+        let mut node = self.list.node_at(start);
+        while let Some(index) = node.filter(|_| elements.len() < end.saturating_sub(start)) {
+            let node_ref = &self.list.nodes[index];
+            elements.push((node_ref.member.clone(), node_ref.score));
+            node = node_ref.levels[0].forward;
+        }
+        elements
+    }
+
+    /// Removes the elements with a rank in `start..end` and returns them.
+    pub fn remove_range(&mut self, start: usize, end: usize) -> Vec<(Vec<u8>, f64)> {
+        let removed = self.range(start, end);
+        for (member, _) in &removed {
+            self.remove(member);
+        }
+        removed
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Vec<u8>, f64)> {
+        self.scores.iter().map(|(member, score)| (member, *score))
+    }
+}
+
+impl FromIterator<(Vec<u8>, f64)> for SortedSet {
+    fn from_iter<I: IntoIterator<Item = (Vec<u8>, f64)>>(iter: I) -> Self {
+        let mut set = SortedSet::new();
+        for (member, score) in iter {
+            set.insert(&member, score);
+        }
+        set
+    }
+}
+
+fn compare(score: f64, member: &[u8], other_score: f64, other_member: &[u8]) -> Ordering {
+    score.total_cmp(&other_score).then_with(|| member.cmp(other_member))
+}
+
+const MAX_LEVEL: usize = 32;
+const HEAD: usize = 0;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Level {
+    forward: Option<usize>,
+    /// Number of level-0 steps this link skips.
+    span: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    member: Vec<u8>,
+    score: f64,
+    levels: Vec<Level>,
+}
+
+/// Redis-style skiplist with spans, stored in an arena. Node 0 is the head.
+#[derive(Debug, Clone)]
+struct SkipList {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    level: usize,
+    len: usize,
+    seed: u64,
+}
+
+impl Default for SkipList {
+    fn default() -> Self {
+        let head = Node { member: Vec::new(), score: 0.0, levels: vec![Level::default(); MAX_LEVEL] };
+        SkipList { nodes: vec![head], free: Vec::new(), level: 1, len: 0, seed: 0x2545f4914f6cdd1d }
+    }
+}
+
+impl SkipList {
+    /// Each level is kept with probability 1/4, as in Redis.
+    fn random_level(&mut self) -> usize {
+        let mut level = 1;
+        loop {
+            self.seed ^= self.seed << 13;
+            self.seed ^= self.seed >> 7;
+            self.seed ^= self.seed << 17;
+            if level >= MAX_LEVEL || self.seed & 3 != 0 {
+                return level;
+            }
+            level += 1;
+        }
+    }
+
+    fn forward(&self, node: usize, level: usize) -> Option<usize> {
+        self.nodes[node].levels[level].forward
+    }
+
+    fn is_before(&self, node: usize, score: f64, member: &[u8]) -> bool {
+        let node = &self.nodes[node];
+        compare(node.score, &node.member, score, member) == Ordering::Less
+    }
+
+    fn insert(&mut self, score: f64, member: Vec<u8>) {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut rank = [0usize; MAX_LEVEL];
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            rank[i] = if i == self.level - 1 { 0 } else { rank[i + 1] };
+            while let Some(next) = self.forward(x, i).filter(|next| self.is_before(*next, score, &member)) {
+                rank[i] += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            update[i] = x;
+        }
+
+        let level = self.random_level();
+        if level > self.level {
+            for i in self.level..level {
+                rank[i] = 0;
+                update[i] = HEAD;
+                self.nodes[HEAD].levels[i].span = self.len;
+            }
+            self.level = level;
+        }
+
+        let node = Node { member, score, levels: vec![Level::default(); level] };
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.nodes[index] = node;
+                index
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+
+        for i in 0..level {
+            let previous = self.nodes[update[i]].levels[i];
+            self.nodes[index].levels[i] = Level { forward: previous.forward, span: previous.span - (rank[0] - rank[i]) };
+            self.nodes[update[i]].levels[i] = Level { forward: Some(index), span: rank[0] - rank[i] + 1 };
+        }
+        for (i, node) in update.iter().enumerate().take(self.level).skip(level) {
+            self.nodes[*node].levels[i].span += 1;
+        }
+        self.len += 1;
+    }
+
+    fn remove(&mut self, score: f64, member: &[u8]) -> bool {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.forward(x, i).filter(|next| self.is_before(*next, score, member)) {
+                x = next;
+            }
+            update[i] = x;
+        }
+        let Some(target) = self.forward(x, 0) else {
+            return false;
+        };
+        if self.nodes[target].score != score || self.nodes[target].member != member {
+            return false;
+        }
+
+        for (i, node) in update.iter().enumerate().take(self.level) {
+            if self.forward(*node, i) == Some(target) {
+                let removed = self.nodes[target].levels[i];
+                let link = &mut self.nodes[*node].levels[i];
+                link.span += removed.span;
+                link.span -= 1;
+                link.forward = removed.forward;
+            } else {
+                self.nodes[*node].levels[i].span -= 1;
+            }
+        }
+        while self.level > 1 && self.forward(HEAD, self.level - 1).is_none() {
+            self.level -= 1;
+        }
+        self.nodes[target].member = Vec::new();
+        self.free.push(target);
+        self.len -= 1;
+        true
+    }
+
+    fn count_below(&self, below: impl Fn(f64, &[u8]) -> bool) -> usize {
+        let mut count = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.forward(x, i).filter(|next| below(self.nodes[*next].score, &self.nodes[*next].member)) {
+                count += self.nodes[x].levels[i].span;
+                x = next;
+            }
+        }
+        count
+    }
+
+    /// The node at zero-based `rank`.
+    fn node_at(&self, rank: usize) -> Option<usize> {
+        if rank >= self.len {
+            return None;
+        }
+        let mut traversed = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.forward(x, i).filter(|_| traversed + self.nodes[x].levels[i].span <= rank + 1) {
+                traversed += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            if traversed == rank + 1 {
+                return Some(x);
+            }
+        }
+        None
+    }
+}
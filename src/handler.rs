@@ -1,166 +1,254 @@
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use std::time::Instant;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use tokio::net::TcpStream;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::time::Instant;
 use crate::db::connection::DbConnection;
-use crate::cmd::{set, get, expire, ttl, incr, decr, exists, json::{SetJsonCommand, GetJsonCommand, DelJsonCommand}};
+use crate::db::Db;
+use crate::reply::{Protocol, Reply};
+use crate::resp;
+use crate::cmd::{self, Handler, Outcome, Parked};
 
-type Db = Arc<Mutex<HashMap<String, DbValue>>>;
-type DbValue = (String, Option<Instant>);
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
 
-pub fn parse_resp_bulk_string(input: &str) -> Vec<String> {
-    let mut args = Vec::new();
-    let parts: Vec<&str> = input.split("\r\n").collect();
-
-    if parts.is_empty() {
-        return args;
-    }
+/// Per-connection state negotiated by the client.
+pub struct Client {
+    pub id: u64,
+    pub protocol: Protocol,
+    pub name: Option<Vec<u8>>,
+    pub user: Option<String>,
+}
 
-    if let Some(count_str) = parts.first().and_then(|s| s.strip_prefix('*')) {
-        if let Ok(count) = count_str.parse::<usize>() {
-            for i in 0..count {
-                if let (Some(len_str), Some(value)) = (parts.get(i * 2 + 1), parts.get(i * 2 + 2)) {
-                    if let Some(len_str) = len_str.strip_prefix('$') {
-                        if let Ok(len) = len_str.parse::<usize>() {
-                            if value.len() == len {
-                                args.push(value.to_string());
-                            }
-                        }
-                    }
-                }
-            }
+impl Client {
+    pub fn new() -> Self {
+        Client {
+            id: NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed),
+            protocol: Protocol::Resp2,
+            name: None,
+            user: None,
         }
     }
-    args
 }
 
 pub async fn handle_client(mut stream: TcpStream, db: Db, db_conn: Arc<DbConnection>) {
     let peer_addr = stream.peer_addr().unwrap();
     println!("New connection from {}", peer_addr);
-    
-    let mut buffer = [0; 1024];
-    
+
+    let mut client = Client::new();
+    let mut buffer: Vec<u8> = Vec::with_capacity(16 * 1024);
+
     loop {
-        let bytes_read = match stream.read(&mut buffer).await {
+        match stream.read_buf(&mut buffer).await {
             Ok(0) => {
                 println!("Connection closed by {}", peer_addr);
                 return;
             }
-            Ok(n) => n,
+            Ok(_) => {}
             Err(_) => {
                 println!("Error reading from connection {}", peer_addr);
                 return;
             }
-        };
-
-        let input = String::from_utf8_lossy(&buffer[..bytes_read]);
-
-        let args = parse_resp_bulk_string(&input);
-
-        if args.is_empty() {
-            let _ = stream.write_all(b"-ERR no command received\r\n").await;
-            continue;
         }
 
-        let response = match args.first().map(|s| s.to_uppercase()) {
-            Some(command) if command == "SET" => {
-                let key = &args[1];
-                let value = &args[2];
-                let mut expire_seconds = None;
-                let mut expire_milliseconds = None;
-
-                if args.len() > 3 {
-                    let mut i = 3;
-                    while i < args.len() {
-                        match args[i].as_str() {
-                            "EX" => {
-                                if i + 1 < args.len() {
-                                    expire_seconds = args[i + 1].parse::<u64>().ok();
-                                    i += 2;
-                                } else {
-                                    break;
-                                }
+        // Execute every complete command in the buffer and answer them with a
+        // single write; a trailing partial command stays buffered.
+        let mut replies = Vec::new();
+        let mut consumed = 0;
+        loop {
+            match resp::parse_command(&buffer[consumed..]) {
+                Ok(Some((args, len))) => {
+                    consumed += len;
+                    if args.is_empty() {
+                        continue;
+                    }
+                    let reply = match execute_command(&args, &mut client, &db, &db_conn).await {
+                        Outcome::Reply(reply) => reply,
+                        Outcome::Blocked(parked) => {
+                            // Answer what came before, then hold back the rest
+                            // of the pipeline until the blocked command is done.
+                            if !replies.is_empty() {
+                                let _ = stream.write_all(&replies).await;
+                                replies.clear();
                             }
-                            "PX" => {
-                                if i + 1 < args.len() {
-                                    expire_milliseconds = args[i + 1].parse::<u64>().ok();
-                                    i += 2;
-                                } else {
-                                    break;
+                            buffer.drain(..consumed);
+                            consumed = 0;
+                            match wait_blocked(parked, &mut stream, &mut buffer, &db).await {
+                                Some(reply) => reply,
+                                None => {
+                                    println!("Connection closed by {}", peer_addr);
+                                    return;
                                 }
                             }
-                            _ => break,
                         }
-                    }
+                    };
+                    reply.encode(client.protocol, &mut replies);
                 }
-
-                println!("Executing SET with key: '{}' and value: '{}'", key, value);
-                set::SetCommand::new(key, value, expire_seconds, expire_milliseconds).execute(&db)
-            }
-            Some(command) if command == "GET" && args.len() == 2 => {
-                println!("Executing GET with key: '{}'", args[1]);
-                get::GetCommand::new(&args[1]).execute(&db)
-            }
-            Some(command) if command == "EXPIRE" && args.len() == 3 => {
-                if let Ok(seconds) = args[2].parse::<u64>() {
-                    println!("Executing EXPIRE with key: '{}' and seconds: '{}'", args[1], seconds);
-                    expire::ExpireCommand::new(&args[1], seconds).execute(&db)
-                } else {
-                    "-ERR invalid expire time\r\n".to_string()
+                Ok(None) => break,
+                Err(e) => {
+                    println!("Protocol error from {}: {}", peer_addr, e);
+                    replies.extend_from_slice(format!("-ERR {}\r\n", e).as_bytes());
+                    let _ = stream.write_all(&replies).await;
+                    return;
                 }
             }
-            Some(command) if command == "TTL" && args.len() == 2 => {
-                println!("Executing TTL with key: '{}'", args[1]);
-                ttl::TTLCommand::new(&args[1]).execute(&db)
-            }
-            Some(command) if command == "INCR" && args.len() == 2 => {
-                println!("Executing INCR with key: '{}'", args[1]);
-                incr::IncrCommand::new(&args[1]).execute(&db)
-            }
-            Some(command) if command == "DECR" && args.len() == 2 => {
-                println!("Executing DECR with key: '{}'", args[1]);
-                decr::DecrCommand::new(&args[1]).execute(&db)
-            }
-            Some(command) if command == "EXISTS" => {
-                println!("Executing EXISTS with keys: {:?}", &args[1..]);
-                exists::ExistsCommand::new(args[1..].to_vec()).execute(&db)
+        }
+        buffer.drain(..consumed);
+
+        if !replies.is_empty() {
+            let _ = stream.write_all(&replies).await;
+            let _ = stream.flush().await;
+        }
+    }
+}
+
+/// Waits until a parked client is served or times out. Input arriving in the
+/// meantime is buffered; `None` means the client disconnected.
+async fn wait_blocked(parked: Parked, stream: &mut TcpStream, buffer: &mut Vec<u8>, db: &Db) -> Option<Reply> {
+    let Parked { id, mut reply, timeout, timeout_reply } = parked;
+    // A deadline too far out to represent never comes.
+    let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+    let expired = async {
+        match deadline {
+            Some(deadline) => tokio::time::sleep_until(deadline).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(expired);
+
+    loop {
+        tokio::select! {
+            served = &mut reply => return Some(served.unwrap_or(timeout_reply)),
+            _ = &mut expired => {
+                // The client may have been served while the timer fired.
+                if db.lock().unwrap().unblock(id) {
+                    return Some(timeout_reply);
+                }
+                return Some(reply.try_recv().unwrap_or(timeout_reply));
             }
-            Some(command) if command == "USERS" => {
-                println!("Executing USERS command");
-                match db_conn.query_users().await {
-                    Ok(users) => users.into_iter()
-                        .map(|(username, password, role)| format!("{}:{}:{}", username, password, role))
-                        .collect::<Vec<String>>()
-                        .join("\n"),
-                    Err(_) => "-ERR failed to query users\r\n".to_string(),
+            read = stream.read_buf(buffer) => {
+                if !matches!(read, Ok(n) if n > 0) {
+                    db.lock().unwrap().unblock(id);
+                    return None;
                 }
             }
-            Some(command) if command == "JSON.SET" && args.len() == 4 => {
-                println!("Executing JSON.SET command");
-                let key = &args[1];
-                let path = &args[2];
-                let value = &args[3];
-                let json_cmd = SetJsonCommand::new(key, path, value);
-                json_cmd.execute(&db)
+        }
+    }
+}
+
+async fn execute_command(args: &[Vec<u8>], client: &mut Client, db: &Db, db_conn: &DbConnection) -> Outcome {
+    let Some(spec) = cmd::lookup(&args[0]) else {
+        let mut message = format!("ERR unknown command '{}', with args beginning with:", String::from_utf8_lossy(&args[0]));
+        for arg in &args[1..] {
+            message.push_str(&format!(" '{}'", String::from_utf8_lossy(arg)));
+        }
+        return Outcome::Reply(Reply::error(message));
+    };
+    if !spec.accepts(args.len()) {
+        return Outcome::Reply(Reply::error(format!("ERR wrong number of arguments for '{}' command", spec.name)));
+    }
+
+    println!("Executing {} command", spec.name.to_uppercase());
+    match spec.handler {
+        Handler::Db(run) => Outcome::Reply(run(args, db)),
+        Handler::Blocking(run) => run(args, db),
+        Handler::Connection => Outcome::Reply(match spec.name {
+            "hello" => hello(&args[1..], client, db_conn).await,
+            "users" => users(db_conn).await,
+            name => unreachable!("no connection handler for '{}'", name),
+        }),
+    }
+}
+
+async fn users(db_conn: &DbConnection) -> Reply {
+    match db_conn.query_users().await {
+        Ok(users) => Reply::Array(
+            users.into_iter()
+                .map(|(username, password, role)| {
+                    Reply::Array(vec![
+                        Reply::Bulk(username.into_bytes()),
+                        Reply::Bulk(password.into_bytes()),
+                        Reply::Bulk(role.into_bytes()),
+                    ])
+                })
+                .collect(),
+        ),
+        Err(_) => Reply::error("ERR failed to query users"),
+    }
+}
+
+/// `HELLO [protover [AUTH username password] [SETNAME clientname]]`
+async fn hello(args: &[Vec<u8>], client: &mut Client, db_conn: &DbConnection) -> Reply {
+    let mut protocol = client.protocol;
+    let mut auth = None;
+    let mut name = None;
+
+    if let Some(version) = args.first() {
+        protocol = match parse_u64(version) {
+            Some(2) => Protocol::Resp2,
+            Some(3) => Protocol::Resp3,
+            Some(_) => return Reply::Error("NOPROTO unsupported protocol version".to_string()),
+            None => return Reply::Error("ERR Protocol version is not an integer or out of range".to_string()),
+        };
+    }
+
+    let mut i = 1;
+    while i < args.len() {
+        let option = String::from_utf8_lossy(&args[i]).to_uppercase();
+        match option.as_str() {
+            "AUTH" if i + 2 < args.len() => {
+                auth = Some((String::from_utf8_lossy(&args[i + 1]).into_owned(), args[i + 2].clone()));
+                i += 3;
             }
-            Some(command) if command == "JSON.GET" && args.len() >= 2 => {
-                println!("Executing JSON.GET command");
-                let key = &args[1];
-                let paths: Vec<&str> = args[2..].iter().map(|s| s.as_str()).collect();
-                let json_cmd = GetJsonCommand::new(key, &paths);
-                json_cmd.execute(&db)
+            "SETNAME" if i + 1 < args.len() => {
+                if args[i + 1].iter().any(|c| *c <= b' ' || *c > b'~') {
+                    return Reply::Error("ERR Client names cannot contain spaces, newlines or special characters.".to_string());
+                }
+                name = Some(args[i + 1].clone());
+                i += 2;
             }
-            Some(command) if command == "JSON.DEL" && args.len() == 2 => {
-                println!("Executing JSON.DEL command");
-                let key = &args[1];
-                let json_cmd = DelJsonCommand::new(key);
-                json_cmd.execute(&db)
+            _ => {
+                return Reply::Error(format!("ERR Syntax error in HELLO option '{}'", String::from_utf8_lossy(&args[i])));
             }
-            _ => "-ERR unknown command or wrong number of arguments\r\n".to_string(),
-        };
+        }
+    }
 
-        let _ = stream.write_all(response.as_bytes()).await;
-        let _ = stream.flush().await;
+    if let Some((username, password)) = auth {
+        if !authenticate(db_conn, &username, password).await {
+            return Reply::Error("WRONGPASS invalid username-password pair or user is disabled.".to_string());
+        }
+        client.user = Some(username);
+    }
+    if name.is_some() {
+        client.name = name;
     }
-}
\ No newline at end of file
+    client.protocol = protocol;
+
+    Reply::Map(vec![
+        (Reply::Bulk(b"server".to_vec()), Reply::Bulk(b"rustis".to_vec())),
+        (Reply::Bulk(b"version".to_vec()), Reply::Bulk(env!("CARGO_PKG_VERSION").as_bytes().to_vec())),
+        (Reply::Bulk(b"proto".to_vec()), Reply::Integer(protocol.version())),
+        (Reply::Bulk(b"id".to_vec()), Reply::Integer(client.id as i64)),
+        (Reply::Bulk(b"mode".to_vec()), Reply::Bulk(b"standalone".to_vec())),
+        (Reply::Bulk(b"role".to_vec()), Reply::Bulk(b"master".to_vec())),
+        (Reply::Bulk(b"modules".to_vec()), Reply::Array(Vec::new())),
+    ])
+}
+
+/// Checks a password against the bcrypt hash stored for `username`.
+async fn authenticate(db_conn: &DbConnection, username: &str, password: Vec<u8>) -> bool {
+    let hash = match db_conn.query_users().await {
+        Ok(users) => users.into_iter().find(|(name, _, _)| name == username).map(|(_, hash, _)| hash),
+        Err(_) => None,
+    };
+    match hash {
+        // bcrypt is deliberately slow, keep it off the async worker threads.
+        Some(hash) => tokio::task::spawn_blocking(move || bcrypt::verify(password, &hash).unwrap_or(false))
+            .await
+            .unwrap_or(false),
+        None => false,
+    }
+}
+
+fn parse_u64(arg: &[u8]) -> Option<u64> {
+    std::str::from_utf8(arg).ok()?.parse().ok()
+}
@@ -0,0 +1,620 @@
+use std::fmt;
+use serde_json::Value;
+
+/// One step from a JSON value to one of its children.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Key {
+    Field(String),
+    Index(usize),
+}
+
+/// Where a match sits in a document: the keys leading to it from the root.
+pub type Location = Vec<Key>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError(String);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// A parsed path, either JSONPath (`$.a[0]`) or RedisJSON's legacy dot
+/// notation (`.a[0]`, `a.b`), which selects the same values but only ever
+/// reports the first match.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Path {
+    segments: Vec<Segment>,
+    legacy: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Segment {
+    selectors: Vec<Selector>,
+    /// `..`: apply the selectors to the value and all of its descendants.
+    descendants: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Selector {
+    Name(String),
+    Index(i64),
+    Slice { start: Option<i64>, end: Option<i64>, step: i64 },
+    Wildcard,
+    Filter(Filter),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Filter {
+    Or(Box<Filter>, Box<Filter>),
+    And(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+    /// A bare operand, true if a relative or absolute query has a match.
+    Exists(Operand),
+    Compare(Operand, Comparison, Operand),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    /// `@...`, relative to the value under test.
+    Current(Vec<Segment>),
+    /// `$...`, relative to the document root.
+    Root(Vec<Segment>),
+    Literal(Value),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Path {
+    pub fn parse(text: &str) -> Result<Path, ParseError> {
+        if text.starts_with('$') {
+            let mut parser = Parser::new(text);
+            parser.pos = 1;
+            let segments = parser.segments()?;
+            if !parser.at_end() {
+                return Err(parser.error("unexpected character"));
+            }
+            return Ok(Path { segments, legacy: false });
+        }
+
+        // Legacy paths are JSONPath without the leading `$`, and `.` is the root.
+        let normalized = match text {
+            "" | "." => String::from("$"),
+            _ if text.starts_with('.') || text.starts_with('[') => format!("${text}"),
+            _ => format!("$.{text}"),
+        };
+        let mut path = Path::parse(&normalized).map_err(|_| ParseError(format!("invalid path '{text}'")))?;
+        path.legacy = true;
+        Ok(path)
+    }
+
+    pub fn is_legacy(&self) -> bool {
+        self.legacy
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Locations of all matches, in document order per segment.
+    pub fn locate(&self, root: &Value) -> Vec<Location> {
+        locate(&self.segments, root, root, Vec::new())
+    }
+
+    pub fn query<'a>(&self, root: &'a Value) -> Vec<&'a Value> {
+        self.locate(root).iter().filter_map(|location| get(root, location)).collect()
+    }
+
+    /// Splits a path ending in a single member name into the path of the
+    /// parent and that name, which is where JSON.SET may create a member.
+    pub fn split_last_member(&self) -> Option<(Path, &str)> {
+        let (last, parents) = self.segments.split_last()?;
+        match last.selectors.as_slice() {
+            [Selector::Name(name)] if !last.descendants => {
+                Some((Path { segments: parents.to_vec(), legacy: self.legacy }, name))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Follows `location` from `root`.
+pub fn get<'a>(root: &'a Value, location: &[Key]) -> Option<&'a Value> {
+    location.iter().try_fold(root, |value, key| match (key, value) {
+        (Key::Field(name), Value::Object(map)) => map.get(name),
+        (Key::Index(index), Value::Array(items)) => items.get(*index),
+        _ => None,
+    })
+}
+
+pub fn get_mut<'a>(root: &'a mut Value, location: &[Key]) -> Option<&'a mut Value> {
+    location.iter().try_fold(root, |value, key| match (key, value) {
+        (Key::Field(name), Value::Object(map)) => map.get_mut(name),
+        (Key::Index(index), Value::Array(items)) => items.get_mut(*index),
+        _ => None,
+    })
+}
+
+/// Removes the value at `location` from its parent, keeping the order of
+/// the remaining members. The root itself can't be removed.
+pub fn remove(root: &mut Value, location: &[Key]) -> Option<Value> {
+    let (last, parent) = location.split_last()?;
+    match (get_mut(root, parent)?, last) {
+        (Value::Object(map), Key::Field(name)) => map.shift_remove(name),
+        (Value::Array(items), Key::Index(index)) if *index < items.len() => Some(items.remove(*index)),
+        _ => None,
+    }
+}
+
+/// RedisJSON's name for the type of `value`.
+pub fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(number) if number.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn locate(segments: &[Segment], value: &Value, root: &Value, base: Location) -> Vec<Location> {
+    let mut current = vec![base];
+    for segment in segments {
+        let mut next = Vec::new();
+        for location in current {
+            let Some(value) = get(value, &location) else {
+                continue;
+            };
+            if segment.descendants {
+                let mut nodes = Vec::new();
+                descendants(value, location, &mut nodes);
+                for (node, node_location) in nodes {
+                    select(&segment.selectors, node, node_location, root, &mut next);
+                }
+            } else {
+                select(&segment.selectors, value, location, root, &mut next);
+            }
+        }
+        current = next;
+    }
+    current
+}
+
+/// The value and all values nested in it, parents before their children.
+fn descendants<'a>(value: &'a Value, location: Location, out: &mut Vec<(&'a Value, Location)>) {
+    out.push((value, location.clone()));
+    for (key, child) in children(value) {
+        let mut child_location = location.clone();
+        child_location.push(key);
+        descendants(child, child_location, out);
+    }
+}
+
+fn children(value: &Value) -> Vec<(Key, &Value)> {
+    match value {
+        Value::Object(map) => map.iter().map(|(name, child)| (Key::Field(name.clone()), child)).collect(),
+        Value::Array(items) => items.iter().enumerate().map(|(index, child)| (Key::Index(index), child)).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn select(selectors: &[Selector], value: &Value, location: Location, root: &Value, out: &mut Vec<Location>) {
+    let child = |key: Key| {
+        let mut child = location.clone();
+        child.push(key);
+        child
+    };
+    for selector in selectors {
+        match (selector, value) {
+            (Selector::Name(name), Value::Object(map)) if map.contains_key(name) => out.push(child(Key::Field(name.clone()))),
+            (Selector::Index(index), Value::Array(items)) => {
+                let index = if *index < 0 { items.len() as i64 + index } else { *index };
+                if (0..items.len() as i64).contains(&index) {
+                    out.push(child(Key::Index(index as usize)));
+                }
+            }
+            (Selector::Slice { start, end, step }, Value::Array(items)) => {
+                out.extend(slice_indices(items.len(), *start, *end, *step).map(|index| child(Key::Index(index))));
+            }
+            (Selector::Wildcard, _) => out.extend(children(value).into_iter().map(|(key, _)| child(key))),
+            (Selector::Filter(filter), _) => {
+                for (key, candidate) in children(value) {
+                    if filter.matches(candidate, root) {
+                        out.push(child(key));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Indices selected by `[start:end:step]`, with Python's slice semantics.
+fn slice_indices(len: usize, start: Option<i64>, end: Option<i64>, step: i64) -> Box<dyn Iterator<Item = usize>> {
+    let len = len as i64;
+    let normalize = |index: i64| if index < 0 { len + index } else { index };
+    if step > 0 {
+        let start = start.map_or(0, normalize).clamp(0, len);
+        let end = end.map_or(len, normalize).clamp(0, len);
+        Box::new((start..end).step_by(step as usize).map(|index| index as usize))
+    } else if step < 0 {
+        let start = start.map_or(len - 1, normalize).clamp(-1, len - 1);
+        let end = end.map_or(-1, normalize).clamp(-1, len - 1);
+        let mut index = start;
+        Box::new(std::iter::from_fn(move || {
+            if index <= end {
+                return None;
+            }
+            let current = index;
+            index += step;
+            Some(current as usize)
+        }))
+    } else {
+        Box::new(std::iter::empty())
+    }
+}
+
+impl Filter {
+    fn matches(&self, current: &Value, root: &Value) -> bool {
+        match self {
+            Filter::Or(left, right) => left.matches(current, root) || right.matches(current, root),
+            Filter::And(left, right) => left.matches(current, root) && right.matches(current, root),
+            Filter::Not(filter) => !filter.matches(current, root),
+            Filter::Exists(operand) => operand.resolve(current, root).is_some(),
+            Filter::Compare(left, comparison, right) => {
+                match (left.resolve(current, root), right.resolve(current, root)) {
+                    (Some(left), Some(right)) => compare(&left, *comparison, &right),
+                    (None, None) => *comparison == Comparison::Eq,
+                    _ => *comparison == Comparison::Ne,
+                }
+            }
+        }
+    }
+}
+
+impl Operand {
+    /// The operand's value; a query yields its first match.
+    fn resolve(&self, current: &Value, root: &Value) -> Option<Value> {
+        let (segments, base) = match self {
+            Operand::Literal(value) => return Some(value.clone()),
+            Operand::Current(segments) => (segments, current),
+            Operand::Root(segments) => (segments, root),
+        };
+        let location = locate(segments, base, root, Vec::new()).into_iter().next()?;
+        get(base, &location).cloned()
+    }
+}
+
+fn compare(left: &Value, comparison: Comparison, right: &Value) -> bool {
+    use std::cmp::Ordering;
+    let ordering = match (left, right) {
+        (Value::Number(left), Value::Number(right)) => left.as_f64().partial_cmp(&right.as_f64()),
+        (Value::String(left), Value::String(right)) => Some(left.cmp(right)),
+        _ if left == right => Some(Ordering::Equal),
+        _ => None,
+    };
+    match comparison {
+        Comparison::Eq => ordering == Some(Ordering::Equal),
+        Comparison::Ne => ordering != Some(Ordering::Equal),
+        Comparison::Lt => ordering == Some(Ordering::Less),
+        Comparison::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+        Comparison::Gt => ordering == Some(Ordering::Greater),
+        Comparison::Ge => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+    }
+}
+
+/// How deep a filter tree may grow, through `(`, `!`, inner filters and
+/// chained `&&`/`||`, so neither parsing nor evaluating a hostile path can
+/// overflow the stack.
+const MAX_FILTER_DEPTH: usize = 64;
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    /// Filter nesting at the current position.
+    depth: usize,
+}
+
+impl Parser {
+    fn new(text: &str) -> Self {
+        Parser { chars: text.chars().collect(), pos: 0, depth: 0 }
+    }
+
+    fn error(&self, message: &str) -> ParseError {
+        ParseError(format!("{message} at position {} of JSONPath '{}'", self.pos, self.chars.iter().collect::<String>()))
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.chars.len()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, text: &str) -> bool {
+        let matches = text.chars().enumerate().all(|(offset, c)| self.chars.get(self.pos + offset) == Some(&c));
+        if matches {
+            self.pos += text.chars().count();
+        }
+        matches
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), ParseError> {
+        self.skip_whitespace();
+        if self.eat(text) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{text}'")))
+        }
+    }
+
+    /// Segments up to the first character that can't continue a path.
+    fn segments(&mut self) -> Result<Vec<Segment>, ParseError> {
+        let mut segments = Vec::new();
+        loop {
+            let descendants = if self.eat("..") {
+                true
+            } else if self.eat(".") {
+                false
+            } else if self.peek() == Some('[') {
+                segments.push(Segment { selectors: self.bracket()?, descendants: false });
+                continue;
+            } else {
+                return Ok(segments);
+            };
+
+            let selectors = match self.peek() {
+                Some('[') if descendants => self.bracket()?,
+                Some('*') => {
+                    self.pos += 1;
+                    vec![Selector::Wildcard]
+                }
+                _ => vec![Selector::Name(self.member_name()?)],
+            };
+            segments.push(Segment { selectors, descendants });
+        }
+    }
+
+    fn member_name(&mut self) -> Result<String, ParseError> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| !".[]()=<>!&|,'\"".contains(c) && !c.is_whitespace()) {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return Err(self.error("expected a member name"));
+        }
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+
+    /// `[selector, ...]`
+    fn bracket(&mut self) -> Result<Vec<Selector>, ParseError> {
+        self.expect("[")?;
+        let mut selectors = Vec::new();
+        loop {
+            self.skip_whitespace();
+            selectors.push(self.selector()?);
+            self.skip_whitespace();
+            if self.eat("]") {
+                return Ok(selectors);
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn selector(&mut self) -> Result<Selector, ParseError> {
+        match self.peek() {
+            Some('\'' | '"') => Ok(Selector::Name(self.string()?)),
+            Some('*') => {
+                self.pos += 1;
+                Ok(Selector::Wildcard)
+            }
+            Some('?') => {
+                self.pos += 1;
+                Ok(Selector::Filter(self.or()?))
+            }
+            _ => {
+                let start = self.integer()?;
+                self.skip_whitespace();
+                if !self.eat(":") {
+                    return start.map(Selector::Index).ok_or_else(|| self.error("expected an index"));
+                }
+                self.skip_whitespace();
+                let end = self.integer()?;
+                self.skip_whitespace();
+                let step = if self.eat(":") {
+                    self.skip_whitespace();
+                    self.integer()?.unwrap_or(1)
+                } else {
+                    1
+                };
+                Ok(Selector::Slice { start, end, step })
+            }
+        }
+    }
+
+    fn integer(&mut self) -> Result<Option<i64>, ParseError> {
+        let start = self.pos;
+        if self.peek() == Some('-') {
+            self.pos += 1;
+        }
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        match self.pos - start {
+            0 => Ok(None),
+            _ => {
+                let text: String = self.chars[start..self.pos].iter().collect();
+                text.parse().map(Some).map_err(|_| self.error("invalid integer"))
+            }
+        }
+    }
+
+    /// A single- or double-quoted string with JSON-style escapes.
+    fn string(&mut self) -> Result<String, ParseError> {
+        let quote = self.peek().ok_or_else(|| self.error("expected a string"))?;
+        self.pos += 1;
+        let mut text = String::new();
+        loop {
+            match self.peek() {
+                None => return Err(self.error("unterminated string")),
+                Some(c) if c == quote => {
+                    self.pos += 1;
+                    return Ok(text);
+                }
+                Some('\\') => {
+                    self.pos += 1;
+                    let escaped = self.peek().ok_or_else(|| self.error("unterminated string"))?;
+                    self.pos += 1;
+                    text.push(match escaped {
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'u' => {
+                            let hex: String = self.chars.iter().skip(self.pos).take(4).collect();
+                            self.pos += 4;
+                            u32::from_str_radix(&hex, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| self.error("invalid unicode escape"))?
+                        }
+                        other => other,
+                    });
+                }
+                Some(c) => {
+                    self.pos += 1;
+                    text.push(c);
+                }
+            }
+        }
+    }
+
+    /// Goes one level deeper into the filter tree.
+    fn enter(&mut self) -> Result<(), ParseError> {
+        if self.depth >= MAX_FILTER_DEPTH {
+            return Err(self.error("filter nested too deeply"));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn or(&mut self) -> Result<Filter, ParseError> {
+        let depth = self.depth;
+        let mut filter = self.and()?;
+        loop {
+            self.skip_whitespace();
+            if !self.eat("||") {
+                self.depth = depth;
+                return Ok(filter);
+            }
+            self.enter()?;
+            filter = Filter::Or(Box::new(filter), Box::new(self.and()?));
+        }
+    }
+
+    fn and(&mut self) -> Result<Filter, ParseError> {
+        let depth = self.depth;
+        let mut filter = self.unary()?;
+        loop {
+            self.skip_whitespace();
+            if !self.eat("&&") {
+                self.depth = depth;
+                return Ok(filter);
+            }
+            self.enter()?;
+            filter = Filter::And(Box::new(filter), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Filter, ParseError> {
+        self.enter()?;
+        let filter = self.negation_or_comparison();
+        self.depth -= 1;
+        filter
+    }
+
+    fn negation_or_comparison(&mut self) -> Result<Filter, ParseError> {
+        self.skip_whitespace();
+        if self.peek() == Some('!') && self.chars.get(self.pos + 1) != Some(&'=') {
+            self.pos += 1;
+            return Ok(Filter::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("(") {
+            let filter = self.or()?;
+            self.expect(")")?;
+            return Ok(filter);
+        }
+
+        let left = self.operand()?;
+        self.skip_whitespace();
+        let comparison = [
+            ("==", Comparison::Eq),
+            ("!=", Comparison::Ne),
+            ("<=", Comparison::Le),
+            (">=", Comparison::Ge),
+            ("<", Comparison::Lt),
+            (">", Comparison::Gt),
+        ]
+        .into_iter()
+        .find(|(text, _)| self.eat(text));
+        if self.chars[self.pos..].starts_with(&['=', '~']) {
+            return Err(self.error("regular expression filters are not supported"));
+        }
+        match comparison {
+            Some((_, comparison)) => {
+                self.skip_whitespace();
+                Ok(Filter::Compare(left, comparison, self.operand()?))
+            }
+            None if matches!(left, Operand::Literal(_)) => Err(self.error("expected a comparison")),
+            None => Ok(Filter::Exists(left)),
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, ParseError> {
+        match self.peek() {
+            Some('@') => {
+                self.pos += 1;
+                Ok(Operand::Current(self.segments()?))
+            }
+            Some('$') => {
+                self.pos += 1;
+                Ok(Operand::Root(self.segments()?))
+            }
+            Some('\'' | '"') => Ok(Operand::Literal(Value::String(self.string()?))),
+            _ => {
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c.is_ascii_alphanumeric() || "+-.".contains(c)) {
+                    self.pos += 1;
+                }
+                let text: String = self.chars[start..self.pos].iter().collect();
+                serde_json::from_str::<Value>(&text)
+                    .ok()
+                    .filter(|value| !value.is_array() && !value.is_object())
+                    .map(Operand::Literal)
+                    .ok_or_else(|| self.error("expected a filter operand"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,126 @@
+use super::*;
+use serde_json::json;
+
+fn store() -> Value {
+    json!({
+        "store": {
+            "book": [
+                {"category": "reference", "author": "Nigel Rees", "title": "Sayings of the Century", "price": 8.95},
+                {"category": "fiction", "author": "Evelyn Waugh", "title": "Sword of Honour", "price": 12.99},
+                {"category": "fiction", "author": "Herman Melville", "title": "Moby Dick", "isbn": "0-553-21311-3", "price": 8.99},
+                {"category": "fiction", "author": "J. R. R. Tolkien", "title": "The Lord of the Rings", "isbn": "0-395-19395-8", "price": 22.99}
+            ],
+            "bicycle": {"color": "red", "price": 19.95}
+        },
+        "expensive": 10
+    })
+}
+
+fn query(path: &str, value: &Value) -> Vec<Value> {
+    Path::parse(path).unwrap().query(value).into_iter().cloned().collect()
+}
+
+#[test]
+fn test_child_access() {
+    let doc = store();
+
+    assert_eq!(query("$.store.bicycle.color", &doc), vec![json!("red")]);
+    assert_eq!(query("$['store']['bicycle']['price']", &doc), vec![json!(19.95)]);
+    assert_eq!(query("$.store.book[0].author", &doc), vec![json!("Nigel Rees")]);
+    assert_eq!(query("$.store.book[-1].author", &doc), vec![json!("J. R. R. Tolkien")]);
+    assert_eq!(query("$.store.book[9]", &doc), Vec::<Value>::new());
+    assert_eq!(query("$.missing", &doc), Vec::<Value>::new());
+    assert_eq!(query("$", &doc), vec![doc.clone()]);
+}
+
+#[test]
+fn test_slices_and_unions() {
+    let doc = json!({"a": [0, 1, 2, 3, 4, 5]});
+
+    assert_eq!(query("$.a[1:3]", &doc), vec![json!(1), json!(2)]);
+    assert_eq!(query("$.a[:2]", &doc), vec![json!(0), json!(1)]);
+    assert_eq!(query("$.a[-2:]", &doc), vec![json!(4), json!(5)]);
+    assert_eq!(query("$.a[::2]", &doc), vec![json!(0), json!(2), json!(4)]);
+    assert_eq!(query("$.a[::-2]", &doc), vec![json!(5), json!(3), json!(1)]);
+    assert_eq!(query("$.a[0,-1]", &doc), vec![json!(0), json!(5)]);
+    assert_eq!(query("$.a[1:1]", &doc), Vec::<Value>::new());
+}
+
+#[test]
+fn test_wildcards_and_recursive_descent() {
+    let doc = store();
+
+    assert_eq!(query("$.store.bicycle.*", &doc), vec![json!("red"), json!(19.95)]);
+    assert_eq!(query("$.store.book[*].price", &doc), vec![json!(8.95), json!(12.99), json!(8.99), json!(22.99)]);
+    assert_eq!(
+        query("$..price", &doc),
+        vec![json!(8.95), json!(12.99), json!(8.99), json!(22.99), json!(19.95)]
+    );
+    assert_eq!(query("$..book[2].title", &doc), vec![json!("Moby Dick")]);
+    assert_eq!(query("$..*", &json!({"a": {"b": 1}})), vec![json!({"b": 1}), json!(1)]);
+}
+
+#[test]
+fn test_filters() {
+    let doc = store();
+
+    assert_eq!(query("$..book[?(@.price < 10)].title", &doc), vec![json!("Sayings of the Century"), json!("Moby Dick")]);
+    assert_eq!(query("$..book[?(@.isbn)].title", &doc), vec![json!("Moby Dick"), json!("The Lord of the Rings")]);
+    assert_eq!(query("$..book[?(!@.isbn)].price", &doc), vec![json!(8.95), json!(12.99)]);
+    assert_eq!(
+        query("$..book[?(@.category == 'fiction' && @.price > $.expensive)].title", &doc),
+        vec![json!("Sword of Honour"), json!("The Lord of the Rings")]
+    );
+    assert_eq!(
+        query(r#"$..book[?(@.author == "Nigel Rees" || @.price >= 22.99)].price"#, &doc),
+        vec![json!(8.95), json!(22.99)]
+    );
+    assert_eq!(query("$.*[?(@ == true)]", &json!({"a": [true, false, 1]})), vec![json!(true)]);
+}
+
+#[test]
+fn test_legacy_paths() {
+    let doc = store();
+
+    for path in [".store.bicycle.color", "store.bicycle.color", "['store'].bicycle.color"] {
+        let path = Path::parse(path).unwrap();
+        assert!(path.is_legacy());
+        assert_eq!(path.query(&doc), vec![&json!("red")]);
+    }
+    let root = Path::parse(".").unwrap();
+    assert!(root.is_legacy() && root.is_root());
+}
+
+#[test]
+fn test_locate_and_split() {
+    let mut doc = json!({"a": [{"b": 1}, {"b": 2}]});
+    let path = Path::parse("$.a[*].b").unwrap();
+    let locations = path.locate(&doc);
+
+    assert_eq!(locations[1], vec![Key::Field("a".to_string()), Key::Index(1), Key::Field("b".to_string())]);
+    *get_mut(&mut doc, &locations[1]).unwrap() = json!(3);
+    assert_eq!(doc, json!({"a": [{"b": 1}, {"b": 3}]}));
+
+    let (parent, name) = path.split_last_member().unwrap();
+    assert_eq!(name, "b");
+    assert_eq!(parent, Path::parse("$.a[*]").unwrap());
+    assert!(Path::parse("$.a[0]").unwrap().split_last_member().is_none());
+}
+
+#[test]
+fn test_parse_errors() {
+    for path in ["$.", "$[", "$.a[?(@.b ==)]", "$.a[1", "$.a[?(@.b =~ 'x')]", "$a"] {
+        assert!(Path::parse(path).is_err(), "{path} should not parse");
+    }
+}
+
+#[test]
+fn test_deeply_nested_filters_are_rejected() {
+    let nested = |depth: usize| format!("$[?({}@.a{})]", "(".repeat(depth), ")".repeat(depth));
+    assert!(Path::parse(&nested(50)).is_ok());
+    assert!(Path::parse(&nested(200_000)).is_err());
+    assert!(Path::parse(&format!("$[?({}@.a)]", "!".repeat(200_000))).is_err());
+    assert!(Path::parse(&format!("$[?({}@.a{})]", "@.b[?(".repeat(200_000), ")]".repeat(200_000))).is_err());
+    assert!(Path::parse(&format!("$[?(@.a{})]", " && @.a".repeat(200_000))).is_err());
+    assert!(Path::parse(&format!("$[?(@.a{})]", " && @.a".repeat(20))).is_ok());
+}
@@ -2,18 +2,18 @@ pub mod cmd;
 mod config;
 mod db;
 mod handler;
+mod jsonpath;
+mod reply;
+mod resp;
+mod search;
 
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use std::time::Instant;
+use std::sync::Arc;
 use std::net::TcpListener;
 use tokio::net::TcpStream as TokioTcpStream;
 
 use config::Settings;
 use db::connection::DbConnection;
-
-type Db = Arc<Mutex<HashMap<String, DbValue>>>;
-type DbValue = (String, Option<Instant>);
+use db::Db;
 
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
@@ -40,11 +40,12 @@ async fn main() -> std::io::Result<()> {
     let listener = TcpListener::bind(address_listener.clone())?;
     println!("Server is running on {}", address_listener);
 
-    let db: Db = Arc::new(Mutex::new(HashMap::new()));
+    let db = Db::new();
+    tokio::spawn(db::active_expiry(db.clone()));
 
     for stream in listener.incoming() {
         let stream = stream?;
-        let db = Arc::clone(&db);
+        let db = db.clone();
         let db_conn = Arc::clone(&db_conn);
 
         tokio::spawn(async move {
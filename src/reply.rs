@@ -0,0 +1,161 @@
+/// Wire protocol version negotiated by a connection via HELLO.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protocol {
+    Resp2,
+    Resp3,
+}
+
+impl Protocol {
+    pub fn version(self) -> i64 {
+        match self {
+            Protocol::Resp2 => 2,
+            Protocol::Resp3 => 3,
+        }
+    }
+}
+
+/// A protocol-independent reply. RESP3-only types are downgraded to their
+/// closest RESP2 equivalent when encoding for a RESP2 connection.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reply {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Vec<u8>),
+    /// Absent value: `$-1` in RESP2, `_` in RESP3.
+    Null,
+    /// Absent aggregate: `*-1` in RESP2, `_` in RESP3.
+    NullArray,
+    Array(Vec<Reply>),
+    Map(Vec<(Reply, Reply)>),
+    /// Ordered pairs such as member/score: a flat array in RESP2 and an
+    /// array of two-element arrays in RESP3.
+    Pairs(Vec<(Reply, Reply)>),
+    /// A map that RESP2 clients receive as an array of two-element arrays
+    /// rather than a flat array, e.g. the per-stream results of XREAD.
+    NestedMap(Vec<(Reply, Reply)>),
+    Set(Vec<Reply>),
+    Double(f64),
+    Boolean(bool),
+    BigNumber(String),
+    /// Bulk text tagged with a three letter format such as `txt` or `mkd`.
+    Verbatim(&'static str, Vec<u8>),
+    Push(Vec<Reply>),
+}
+
+impl Reply {
+    pub fn ok() -> Reply {
+        Reply::Simple("OK".to_string())
+    }
+
+    pub fn error(message: impl Into<String>) -> Reply {
+        Reply::Error(message.into())
+    }
+
+    pub fn wrong_type() -> Reply {
+        Reply::error("WRONGTYPE Operation against a key holding the wrong kind of value")
+    }
+
+    pub fn encode(&self, protocol: Protocol, out: &mut Vec<u8>) {
+        let resp3 = protocol == Protocol::Resp3;
+        match self {
+            Reply::Simple(s) => {
+                out.push(b'+');
+                out.extend_from_slice(s.as_bytes());
+                out.extend_from_slice(b"\r\n");
+            }
+            Reply::Error(e) => {
+                out.push(b'-');
+                out.extend_from_slice(e.as_bytes());
+                out.extend_from_slice(b"\r\n");
+            }
+            Reply::Integer(n) => out.extend_from_slice(format!(":{}\r\n", n).as_bytes()),
+            Reply::Bulk(data) => encode_blob(b'$', data, out),
+            Reply::Null if resp3 => out.extend_from_slice(b"_\r\n"),
+            Reply::Null => out.extend_from_slice(b"$-1\r\n"),
+            Reply::NullArray if resp3 => out.extend_from_slice(b"_\r\n"),
+            Reply::NullArray => out.extend_from_slice(b"*-1\r\n"),
+            Reply::Array(items) => encode_aggregate(b'*', items, protocol, out),
+            Reply::Map(pairs) => {
+                if resp3 {
+                    out.extend_from_slice(format!("%{}\r\n", pairs.len()).as_bytes());
+                } else {
+                    out.extend_from_slice(format!("*{}\r\n", pairs.len() * 2).as_bytes());
+                }
+                for (key, value) in pairs {
+                    key.encode(protocol, out);
+                    value.encode(protocol, out);
+                }
+            }
+            Reply::Pairs(pairs) => {
+                if resp3 {
+                    out.extend_from_slice(format!("*{}\r\n", pairs.len()).as_bytes());
+                } else {
+                    out.extend_from_slice(format!("*{}\r\n", pairs.len() * 2).as_bytes());
+                }
+                for (first, second) in pairs {
+                    if resp3 {
+                        out.extend_from_slice(b"*2\r\n");
+                    }
+                    first.encode(protocol, out);
+                    second.encode(protocol, out);
+                }
+            }
+            Reply::NestedMap(pairs) if resp3 => Reply::Map(pairs.clone()).encode(protocol, out),
+            Reply::NestedMap(pairs) => {
+                out.extend_from_slice(format!("*{}\r\n", pairs.len()).as_bytes());
+                for (key, value) in pairs {
+                    out.extend_from_slice(b"*2\r\n");
+                    key.encode(protocol, out);
+                    value.encode(protocol, out);
+                }
+            }
+            Reply::Set(items) => encode_aggregate(if resp3 { b'~' } else { b'*' }, items, protocol, out),
+            Reply::Double(d) if resp3 => out.extend_from_slice(format!(",{}\r\n", format_double(*d)).as_bytes()),
+            Reply::Double(d) => encode_blob(b'$', format_double(*d).as_bytes(), out),
+            Reply::Boolean(b) if resp3 => out.extend_from_slice(if *b { b"#t\r\n" } else { b"#f\r\n" }),
+            Reply::Boolean(b) => out.extend_from_slice(if *b { b":1\r\n" } else { b":0\r\n" }),
+            Reply::BigNumber(n) if resp3 => out.extend_from_slice(format!("({}\r\n", n).as_bytes()),
+            Reply::BigNumber(n) => encode_blob(b'$', n.as_bytes(), out),
+            Reply::Verbatim(format, text) if resp3 => {
+                let mut data = format!("{}:", format).into_bytes();
+                data.extend_from_slice(text);
+                encode_blob(b'=', &data, out);
+            }
+            Reply::Verbatim(_, text) => encode_blob(b'$', text, out),
+            Reply::Push(items) => encode_aggregate(if resp3 { b'>' } else { b'*' }, items, protocol, out),
+        }
+    }
+}
+
+fn encode_blob(prefix: u8, data: &[u8], out: &mut Vec<u8>) {
+    out.push(prefix);
+    out.extend_from_slice(data.len().to_string().as_bytes());
+    out.extend_from_slice(b"\r\n");
+    out.extend_from_slice(data);
+    out.extend_from_slice(b"\r\n");
+}
+
+fn encode_aggregate(prefix: u8, items: &[Reply], protocol: Protocol, out: &mut Vec<u8>) {
+    out.push(prefix);
+    out.extend_from_slice(items.len().to_string().as_bytes());
+    out.extend_from_slice(b"\r\n");
+    for item in items {
+        item.encode(protocol, out);
+    }
+}
+
+/// Formats a double the way Redis prints it (`inf`, `-inf`, `nan`, or the
+/// shortest representation that round-trips).
+pub fn format_double(d: f64) -> String {
+    if d.is_nan() {
+        "nan".to_string()
+    } else if d.is_infinite() {
+        if d > 0.0 { "inf" } else { "-inf" }.to_string()
+    } else {
+        d.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,57 @@
+use super::*;
+
+fn encode(reply: &Reply, protocol: Protocol) -> Vec<u8> {
+    let mut out = Vec::new();
+    reply.encode(protocol, &mut out);
+    out
+}
+
+#[test]
+fn test_encode_resp2_types() {
+    assert_eq!(encode(&Reply::Simple("OK".to_string()), Protocol::Resp2), b"+OK\r\n");
+    assert_eq!(encode(&Reply::Integer(-3), Protocol::Resp2), b":-3\r\n");
+    assert_eq!(encode(&Reply::Bulk(b"a\r\nb".to_vec()), Protocol::Resp2), b"$4\r\na\r\nb\r\n");
+    assert_eq!(encode(&Reply::Null, Protocol::Resp2), b"$-1\r\n");
+    assert_eq!(
+        encode(&Reply::Array(vec![Reply::Integer(1), Reply::Array(vec![])]), Protocol::Resp2),
+        b"*2\r\n:1\r\n*0\r\n"
+    );
+}
+
+#[test]
+fn test_encode_resp3_types() {
+    let map = Reply::Map(vec![(Reply::Bulk(b"a".to_vec()), Reply::Double(1.5))]);
+
+    assert_eq!(encode(&map, Protocol::Resp3), b"%1\r\n$1\r\na\r\n,1.5\r\n");
+    assert_eq!(encode(&Reply::Null, Protocol::Resp3), b"_\r\n");
+    assert_eq!(encode(&Reply::Boolean(true), Protocol::Resp3), b"#t\r\n");
+    assert_eq!(encode(&Reply::Set(vec![Reply::Integer(1)]), Protocol::Resp3), b"~1\r\n:1\r\n");
+    assert_eq!(encode(&Reply::Push(vec![]), Protocol::Resp3), b">0\r\n");
+    assert_eq!(encode(&Reply::Verbatim("txt", b"hi".to_vec()), Protocol::Resp3), b"=6\r\ntxt:hi\r\n");
+    assert_eq!(encode(&Reply::Double(f64::NEG_INFINITY), Protocol::Resp3), b",-inf\r\n");
+}
+
+#[test]
+fn test_encode_resp3_types_downgraded_for_resp2() {
+    let map = Reply::Map(vec![(Reply::Bulk(b"a".to_vec()), Reply::Double(1.5))]);
+
+    assert_eq!(encode(&map, Protocol::Resp2), b"*2\r\n$1\r\na\r\n$3\r\n1.5\r\n");
+    assert_eq!(encode(&Reply::Boolean(false), Protocol::Resp2), b":0\r\n");
+    assert_eq!(encode(&Reply::Verbatim("txt", b"hi".to_vec()), Protocol::Resp2), b"$2\r\nhi\r\n");
+}
+
+#[test]
+fn test_encode_pairs() {
+    let pairs = Reply::Pairs(vec![(Reply::Bulk(b"a".to_vec()), Reply::Double(1.0))]);
+
+    assert_eq!(encode(&pairs, Protocol::Resp2), b"*2\r\n$1\r\na\r\n$1\r\n1\r\n");
+    assert_eq!(encode(&pairs, Protocol::Resp3), b"*1\r\n*2\r\n$1\r\na\r\n,1\r\n");
+}
+
+#[test]
+fn test_encode_nested_map() {
+    let map = Reply::NestedMap(vec![(Reply::Bulk(b"k".to_vec()), Reply::Array(vec![]))]);
+
+    assert_eq!(encode(&map, Protocol::Resp2), b"*1\r\n*2\r\n$1\r\nk\r\n*0\r\n");
+    assert_eq!(encode(&map, Protocol::Resp3), b"%1\r\n$1\r\nk\r\n*0\r\n");
+}
@@ -0,0 +1,192 @@
+use std::fmt;
+
+/// Largest number of elements accepted in a single multibulk request.
+const MAX_MULTIBULK_LEN: usize = 1024 * 1024;
+/// Largest bulk string accepted in a request (512 MB, same as Redis).
+const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+/// Longest length header we wait for before giving up on the client.
+const MAX_LINE_LEN: usize = 64 * 1024;
+
+/// The arguments of one decoded command, command name first.
+pub type Args = Vec<Vec<u8>>;
+
+#[derive(Debug, PartialEq)]
+pub struct ProtocolError(String);
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Protocol error: {}", self.0)
+    }
+}
+
+/// Tries to decode one complete command from the front of `buf`, either as a
+/// RESP array or as an inline command (`GET foo` typed into telnet/netcat).
+///
+/// Returns `Ok(None)` when more bytes are needed, otherwise the decoded
+/// arguments together with the number of bytes they occupied so the caller
+/// can drain them from its read buffer.
+pub fn parse_command(buf: &[u8]) -> Result<Option<(Args, usize)>, ProtocolError> {
+    if buf.is_empty() {
+        return Ok(None);
+    }
+    if buf[0] != b'*' {
+        return parse_inline(buf);
+    }
+
+    let (count, mut pos) = match read_len(buf, 1, "invalid multibulk length")? {
+        Some(header) => header,
+        None => return Ok(None),
+    };
+    let count = match count {
+        Some(count) if count > MAX_MULTIBULK_LEN => {
+            return Err(ProtocolError("invalid multibulk length".to_string()))
+        }
+        Some(count) => count,
+        None => return Ok(Some((Vec::new(), pos))),
+    };
+
+    let mut args = Vec::with_capacity(count.min(1024));
+    for _ in 0..count {
+        match buf.get(pos) {
+            None => return Ok(None),
+            Some(b'$') => {}
+            Some(c) => return Err(ProtocolError(format!("expected '$', got '{}'", *c as char))),
+        }
+        let (len, next) = match read_len(buf, pos + 1, "invalid bulk length")? {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+        let len = match len {
+            Some(len) if len <= MAX_BULK_LEN => len,
+            _ => return Err(ProtocolError("invalid bulk length".to_string())),
+        };
+        if buf.len() < next + len + 2 {
+            return Ok(None);
+        }
+        if &buf[next + len..next + len + 2] != b"\r\n" {
+            return Err(ProtocolError("bulk string is not terminated by CRLF".to_string()));
+        }
+        args.push(buf[next..next + len].to_vec());
+        pos = next + len + 2;
+    }
+
+    Ok(Some((args, pos)))
+}
+
+/// Decodes a newline-terminated inline command.
+fn parse_inline(buf: &[u8]) -> Result<Option<(Args, usize)>, ProtocolError> {
+    let end = match buf.iter().position(|&c| c == b'\n') {
+        Some(end) => end,
+        None if buf.len() > MAX_LINE_LEN => return Err(ProtocolError("too big inline request".to_string())),
+        None => return Ok(None),
+    };
+    let line = buf[..end].strip_suffix(b"\r").unwrap_or(&buf[..end]);
+    let args = split_args(line).ok_or_else(|| ProtocolError("unbalanced quotes in request".to_string()))?;
+    Ok(Some((args, end + 1)))
+}
+
+/// Splits an inline command line into arguments the way redis-cli does:
+/// whitespace separated, with "double quotes" supporting escapes such as
+/// `\n` and `\x41`, and 'single quotes' supporting only `\'`.
+/// Returns `None` on unbalanced quotes.
+fn split_args(line: &[u8]) -> Option<Args> {
+    let mut args = Vec::new();
+    let mut i = 0;
+
+    loop {
+        while i < line.len() && line[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i == line.len() {
+            return Some(args);
+        }
+
+        let mut arg = Vec::new();
+        let mut in_double = false;
+        let mut in_single = false;
+        loop {
+            let c = line.get(i).copied();
+            if in_double {
+                match c? {
+                    b'\\' if i + 3 < line.len() && line[i + 1] == b'x' && hex_byte(&line[i + 2..i + 4]).is_some() => {
+                        arg.push(hex_byte(&line[i + 2..i + 4])?);
+                        i += 3;
+                    }
+                    b'\\' if i + 1 < line.len() => {
+                        i += 1;
+                        arg.push(match line[i] {
+                            b'n' => b'\n',
+                            b'r' => b'\r',
+                            b't' => b'\t',
+                            b'b' => 0x08,
+                            b'a' => 0x07,
+                            other => other,
+                        });
+                    }
+                    b'"' => {
+                        // The closing quote must be followed by a space or the end.
+                        if line.get(i + 1).is_some_and(|c| !c.is_ascii_whitespace()) {
+                            return None;
+                        }
+                        in_double = false;
+                    }
+                    other => arg.push(other),
+                }
+            } else if in_single {
+                match c? {
+                    b'\\' if line.get(i + 1) == Some(&b'\'') => {
+                        i += 1;
+                        arg.push(b'\'');
+                    }
+                    b'\'' => {
+                        if line.get(i + 1).is_some_and(|c| !c.is_ascii_whitespace()) {
+                            return None;
+                        }
+                        in_single = false;
+                    }
+                    other => arg.push(other),
+                }
+            } else {
+                match c {
+                    None => break,
+                    Some(c) if c.is_ascii_whitespace() => break,
+                    Some(b'"') => in_double = true,
+                    Some(b'\'') => in_single = true,
+                    Some(other) => arg.push(other),
+                }
+            }
+            i += 1;
+        }
+        args.push(arg);
+    }
+}
+
+fn hex_byte(digits: &[u8]) -> Option<u8> {
+    u8::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()
+}
+
+/// Returns the line starting at `start` (without CRLF) and the offset after it.
+fn read_line(buf: &[u8], start: usize) -> Result<Option<(&[u8], usize)>, ProtocolError> {
+    let rest = &buf[start.min(buf.len())..];
+    match rest.windows(2).position(|w| w == b"\r\n") {
+        Some(end) => Ok(Some((&rest[..end], start + end + 2))),
+        None if rest.len() > MAX_LINE_LEN => Err(ProtocolError("too big request line".to_string())),
+        None => Ok(None),
+    }
+}
+
+/// Reads a `*`/`$` length header. Negative lengths are reported as `None`.
+fn read_len(buf: &[u8], start: usize, err: &str) -> Result<Option<(Option<usize>, usize)>, ProtocolError> {
+    let (line, next) = match read_line(buf, start)? {
+        Some(line) => line,
+        None => return Ok(None),
+    };
+    let len = std::str::from_utf8(line)
+        .ok()
+        .and_then(|text| text.parse::<i64>().ok())
+        .ok_or_else(|| ProtocolError(err.to_string()))?;
+    Ok(Some((usize::try_from(len).ok(), next)))
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,91 @@
+use super::*;
+
+#[test]
+fn test_parse_complete_command() {
+    let input = b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n";
+    let (args, consumed) = parse_command(input).unwrap().unwrap();
+
+    assert_eq!(args, vec![b"GET".to_vec(), b"key".to_vec()]);
+    assert_eq!(consumed, input.len());
+}
+
+#[test]
+fn test_parse_incomplete_command() {
+    let input = b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nval";
+
+    for end in 0..input.len() {
+        assert_eq!(parse_command(&input[..end]), Ok(None));
+    }
+}
+
+#[test]
+fn test_parse_value_larger_than_read_buffer() {
+    let value = "x".repeat(10_000);
+    let input = format!("*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n${}\r\n{}\r\n", value.len(), value);
+
+    assert_eq!(parse_command(&input.as_bytes()[..1024]), Ok(None));
+    let (args, consumed) = parse_command(input.as_bytes()).unwrap().unwrap();
+    assert_eq!(args[2], value.as_bytes());
+    assert_eq!(consumed, input.len());
+}
+
+#[test]
+fn test_parse_value_containing_crlf() {
+    let input = b"*2\r\n$3\r\nGET\r\n$4\r\na\r\nb\r\n";
+    let (args, _) = parse_command(input).unwrap().unwrap();
+
+    assert_eq!(args[1], b"a\r\nb");
+}
+
+#[test]
+fn test_parse_invalid_lengths() {
+    assert!(parse_command(b"*x\r\n").is_err());
+    assert!(parse_command(b"*1\r\n$-5\r\n").is_err());
+    assert!(parse_command(b"*1\r\n$3\r\nabcde\r\n").is_err());
+}
+
+#[test]
+fn test_parse_pipelined_commands() {
+    let input = b"*1\r\n$4\r\nPING\r\n*2\r\n$3\r\nGET\r\n$1\r\na\r\n*2\r\n$3\r\nGET";
+    let mut commands = Vec::new();
+    let mut consumed = 0;
+    while let Some((args, len)) = parse_command(&input[consumed..]).unwrap() {
+        commands.push(args);
+        consumed += len;
+    }
+
+    assert_eq!(commands, vec![vec![b"PING".to_vec()], vec![b"GET".to_vec(), b"a".to_vec()]]);
+    assert_eq!(&input[consumed..], b"*2\r\n$3\r\nGET");
+}
+
+#[test]
+fn test_parse_binary_arguments() {
+    let input = b"*2\r\n$3\r\nGET\r\n$3\r\n\x00\xff\x80\r\n";
+    let (args, _) = parse_command(input).unwrap().unwrap();
+
+    assert_eq!(args[1], [0x00, 0xff, 0x80]);
+}
+
+#[test]
+fn test_parse_inline_command() {
+    let (args, consumed) = parse_command(b"SET  key value\r\nGET").unwrap().unwrap();
+
+    assert_eq!(args, vec![b"SET".to_vec(), b"key".to_vec(), b"value".to_vec()]);
+    assert_eq!(consumed, 16);
+    assert_eq!(parse_command(b"GET key"), Ok(None));
+    assert_eq!(parse_command(b"PING\n").unwrap().unwrap().0, vec![b"PING".to_vec()]);
+}
+
+#[test]
+fn test_parse_inline_quoted_arguments() {
+    let input = b"SET \"hello world\\n\\x41\" 'it\\'s' \"\"\r\n";
+    let (args, _) = parse_command(input).unwrap().unwrap();
+
+    assert_eq!(args, vec![b"SET".to_vec(), b"hello world\nA".to_vec(), b"it's".to_vec(), b"".to_vec()]);
+}
+
+#[test]
+fn test_parse_inline_unbalanced_quotes() {
+    assert!(parse_command(b"SET \"key value\r\n").is_err());
+    assert!(parse_command(b"SET \"key\"value\r\n").is_err());
+}
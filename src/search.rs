@@ -0,0 +1,618 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ops::Bound;
+use serde_json::Value as Json;
+use crate::db::Value;
+use crate::jsonpath::Path;
+use crate::reply::format_double;
+
+mod aggregate;
+mod expression;
+mod query;
+mod vector;
+
+pub use aggregate::{aggregate, ReduceFunction, Reducer, Step};
+pub use expression::Expression;
+pub use query::{Node, Query};
+pub use vector::{Algorithm, Metric};
+use vector::VectorIndex;
+
+/// The type of key an index reads its documents from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source {
+    Hash,
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldKind {
+    /// Full text, split into lowercase terms. Matches are scored by TF-IDF,
+    /// each occurrence counting `weight` times.
+    Text { weight: f64 },
+    /// Exact values, split on `separator` in hashes.
+    Tag { separator: char, case_sensitive: bool },
+    Numeric,
+    /// FLOAT32 vectors of `dim` components: little-endian blobs in hashes,
+    /// arrays of numbers in JSON.
+    Vector { algorithm: Algorithm, dim: usize, metric: Metric },
+}
+
+#[derive(Debug, Clone)]
+pub struct Field {
+    /// The hash field or JSONPath the value is read from.
+    pub identifier: String,
+    /// The name queries refer to the field by: its `AS` alias or the identifier.
+    pub name: String,
+    pub kind: FieldKind,
+    path: Option<Path>,
+}
+
+impl Field {
+    /// Fails if `source` is JSON and `identifier` is not a valid JSONPath.
+    pub fn new(source: Source, identifier: &str, name: Option<&str>, kind: FieldKind) -> Result<Field, String> {
+        let path = match source {
+            Source::Hash => None,
+            Source::Json => Some(Path::parse(identifier).map_err(|error| error.to_string())?),
+        };
+        Ok(Field {
+            identifier: identifier.to_string(),
+            name: name.unwrap_or(identifier).to_string(),
+            kind,
+            path,
+        })
+    }
+
+    /// The JSONPath of a field of a JSON index.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_ref()
+    }
+
+    /// Reads the field from a hash value. `None` means it can't be indexed.
+    fn read_hash(&self, raw: &[u8]) -> Option<FieldValue> {
+        let text = String::from_utf8_lossy(raw);
+        match &self.kind {
+            FieldKind::Text { .. } => Some(FieldValue::Text(vec![text.into_owned()])),
+            FieldKind::Tag { separator, case_sensitive } => {
+                Some(FieldValue::Tags(text.split(*separator).filter_map(|tag| normalize_tag(tag, *case_sensitive)).collect()))
+            }
+            FieldKind::Numeric => text.trim().parse().ok().filter(|n: &f64| !n.is_nan()).map(FieldValue::Numeric),
+            FieldKind::Vector { dim, .. } => read_vector(raw, *dim).map(FieldValue::Vector),
+        }
+    }
+
+    /// Reads the field from the matches of its path, flattening arrays.
+    /// `Some(None)` means the field is missing, `None` that it can't be indexed.
+    fn read_json(&self, matches: Vec<&Json>) -> Option<Option<FieldValue>> {
+        let values: Vec<&Json> = matches
+            .into_iter()
+            .flat_map(|value| match value {
+                Json::Array(items) => items.iter().collect(),
+                Json::Null => Vec::new(),
+                value => vec![value],
+            })
+            .collect();
+        if values.is_empty() {
+            return Some(None);
+        }
+        let value = match &self.kind {
+            FieldKind::Text { .. } => FieldValue::Text(
+                values.iter().map(|value| value.as_str().map(str::to_string)).collect::<Option<_>>()?,
+            ),
+            FieldKind::Tag { case_sensitive, .. } => FieldValue::Tags(
+                values
+                    .iter()
+                    .map(|value| match value {
+                        Json::String(tag) => Some(normalize_tag(tag, *case_sensitive)),
+                        Json::Bool(flag) => Some(Some(flag.to_string())),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>()?
+                    .into_iter()
+                    .flatten()
+                    .collect(),
+            ),
+            FieldKind::Numeric => match values.as_slice() {
+                [Json::Number(n)] => FieldValue::Numeric(n.as_f64()?),
+                _ => return None,
+            },
+            FieldKind::Vector { dim, .. } if values.len() == *dim => FieldValue::Vector(
+                values.iter().map(|value| value.as_f64().map(|n| n as f32)).collect::<Option<_>>()?,
+            ),
+            FieldKind::Vector { .. } => return None,
+        };
+        Some(Some(value))
+    }
+}
+
+/// What an index covers: keys of one type under some prefixes, and the
+/// fields read from them.
+#[derive(Debug, Clone)]
+pub struct Schema {
+    pub source: Source,
+    /// Key prefixes; an empty list covers every key.
+    pub prefixes: Vec<Vec<u8>>,
+    pub fields: Vec<Field>,
+}
+
+impl Schema {
+    /// Looks up a field by the name queries use.
+    pub fn field(&self, name: &str) -> Option<(usize, &Field)> {
+        self.fields.iter().enumerate().find(|(_, field)| field.name == name)
+    }
+
+    /// Reads `name` from a document: the schema field of that name, or else
+    /// the hash field or JSONPath it names.
+    pub fn load(&self, value: &Value, name: &str) -> Option<Property> {
+        let field = self.field(name).map(|(_, field)| field);
+        match value {
+            Value::Hash(hash) => {
+                let identifier = field.map_or(name, |field| field.identifier.as_str());
+                Some(Property::Text(hash.get(identifier.as_bytes())?.clone()))
+            }
+            Value::Json(document) => {
+                let parsed;
+                let path = match field {
+                    Some(field) => field.path()?,
+                    None => {
+                        parsed = Path::parse(name).ok()?;
+                        &parsed
+                    }
+                };
+                match path.query(document).into_iter().next()? {
+                    Json::Null => None,
+                    Json::Number(n) => Some(Property::Number(n.as_f64()?)),
+                    Json::String(text) => Some(Property::Text(text.clone().into_bytes())),
+                    value => Some(Property::Text(value.to_string().into_bytes())),
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// The whole document: every hash field, or the JSON document as `$`.
+    pub fn load_all(&self, value: &Value) -> Vec<(String, Property)> {
+        match value {
+            Value::Hash(hash) => hash
+                .iter()
+                .map(|(field, value)| (String::from_utf8_lossy(field).into_owned(), Property::Text(value.clone())))
+                .collect(),
+            Value::Json(document) => vec![("$".to_string(), Property::Text(document.to_string().into_bytes()))],
+            _ => Vec::new(),
+        }
+    }
+
+    fn covers(&self, key: &[u8]) -> bool {
+        self.prefixes.is_empty() || self.prefixes.iter().any(|prefix| key.starts_with(prefix))
+    }
+
+    /// The indexed values of each field, `None` if the value is of the wrong
+    /// type or a field can't be indexed.
+    fn extract(&self, value: &Value) -> Option<Vec<Option<FieldValue>>> {
+        match (self.source, value) {
+            (Source::Hash, Value::Hash(hash)) => self
+                .fields
+                .iter()
+                .map(|field| match hash.get(field.identifier.as_bytes()) {
+                    Some(raw) => field.read_hash(raw).map(Some),
+                    None => Some(None),
+                })
+                .collect(),
+            (Source::Json, Value::Json(document)) => self
+                .fields
+                .iter()
+                .map(|field| field.read_json(field.path().map_or_else(Vec::new, |path| path.query(document))))
+                .collect(),
+            _ => None,
+        }
+    }
+}
+
+/// Splits text into lowercase terms at anything but letters, digits and `_`.
+pub fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric() && c != '_').filter(|term| !term.is_empty()).map(str::to_lowercase)
+}
+
+/// Reads a blob of `dim` little-endian FLOAT32s.
+fn read_vector(blob: &[u8], dim: usize) -> Option<Vec<f32>> {
+    if Some(blob.len()) != dim.checked_mul(4) {
+        return None;
+    }
+    Some(blob.chunks_exact(4).map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap())).collect())
+}
+
+fn normalize_tag(tag: &str, case_sensitive: bool) -> Option<String> {
+    let tag = tag.trim();
+    match tag {
+        "" => None,
+        _ if case_sensitive => Some(tag.to_string()),
+        _ => Some(tag.to_lowercase()),
+    }
+}
+
+/// A value read from a document, as FT.SEARCH returns it and FT.AGGREGATE
+/// computes with it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Property {
+    Number(f64),
+    Text(Vec<u8>),
+}
+
+impl Property {
+    /// The value as a number; text is parsed.
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Property::Number(n) => Some(*n),
+            Property::Text(text) => std::str::from_utf8(text).ok()?.trim().parse().ok(),
+        }
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        match self {
+            Property::Number(n) => format_double(n).into_bytes(),
+            Property::Text(text) => text,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum FieldValue {
+    Text(Vec<String>),
+    Tags(Vec<String>),
+    Numeric(f64),
+    /// Emptied once the vector is moved into the field's vector index.
+    Vector(Vec<f32>),
+}
+
+/// A float ordered by `total_cmp`, so it can key a `BTreeSet`.
+#[derive(Debug, Clone, Copy)]
+struct Number(f64);
+
+impl PartialEq for Number {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Number {}
+
+impl PartialOrd for Number {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Number {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// Where a term occurs in a document.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Occurrence {
+    field: usize,
+    position: usize,
+}
+
+/// A document matching a query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match<'a> {
+    pub key: &'a [u8],
+    pub score: f64,
+    /// The distance to the query vector of a KNN query.
+    pub distance: Option<f32>,
+}
+
+/// A secondary index over the documents covered by its schema, kept up to
+/// date by the keyspace as keys are written, deleted or expire.
+#[derive(Debug)]
+pub struct Index {
+    pub schema: Schema,
+    documents: HashMap<Vec<u8>, Vec<Option<FieldValue>>>,
+    /// Term -> document -> occurrences. Ordered so prefixes are a range.
+    terms: BTreeMap<String, HashMap<Vec<u8>, Vec<Occurrence>>>,
+    /// Tag field -> tag -> documents.
+    tags: HashMap<usize, HashMap<String, HashSet<Vec<u8>>>>,
+    /// Numeric field -> documents ordered by value.
+    numbers: HashMap<usize, BTreeSet<(Number, Vec<u8>)>>,
+    /// Vector field -> vectors.
+    vectors: HashMap<usize, VectorIndex>,
+}
+
+impl Index {
+    pub fn new(schema: Schema) -> Self {
+        let vectors = schema
+            .fields
+            .iter()
+            .enumerate()
+            .filter_map(|(field, definition)| match definition.kind {
+                FieldKind::Vector { algorithm, metric, .. } => Some((field, VectorIndex::new(algorithm, metric))),
+                _ => None,
+            })
+            .collect();
+        Index {
+            schema,
+            documents: HashMap::new(),
+            terms: BTreeMap::new(),
+            tags: HashMap::new(),
+            numbers: HashMap::new(),
+            vectors,
+        }
+    }
+
+    /// Keys of the indexed documents.
+    pub fn keys(&self) -> impl Iterator<Item = &[u8]> {
+        self.documents.keys().map(Vec::as_slice)
+    }
+
+    /// Reindexes `key`, which now holds `value` or was deleted.
+    pub fn update(&mut self, key: &[u8], value: Option<&Value>) {
+        self.remove(key);
+        if !self.schema.covers(key) {
+            return;
+        }
+        if let Some(values) = value.and_then(|value| self.schema.extract(value)) {
+            self.insert(key, values);
+        }
+    }
+
+    fn insert(&mut self, key: &[u8], mut values: Vec<Option<FieldValue>>) {
+        for (field, value) in values.iter_mut().enumerate() {
+            match value {
+                Some(FieldValue::Text(texts)) => {
+                    let mut position = 0;
+                    for text in texts {
+                        for term in tokenize(text) {
+                            let occurrences = self.terms.entry(term).or_default().entry(key.to_vec()).or_default();
+                            occurrences.push(Occurrence { field, position });
+                            position += 1;
+                        }
+                        // Separate array items, so phrases don't span them.
+                        position += 1;
+                    }
+                }
+                Some(FieldValue::Tags(tags)) => {
+                    let index = self.tags.entry(field).or_default();
+                    for tag in tags {
+                        index.entry(tag.clone()).or_default().insert(key.to_vec());
+                    }
+                }
+                Some(FieldValue::Numeric(n)) => {
+                    self.numbers.entry(field).or_default().insert((Number(*n), key.to_vec()));
+                }
+                Some(FieldValue::Vector(vector)) => {
+                    if let Some(vectors) = self.vectors.get_mut(&field) {
+                        vectors.insert(key, std::mem::take(vector));
+                    }
+                }
+                None => {}
+            }
+        }
+        self.documents.insert(key.to_vec(), values);
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        let Some(values) = self.documents.remove(key) else {
+            return;
+        };
+        for (field, value) in values.into_iter().enumerate() {
+            match value {
+                Some(FieldValue::Text(texts)) => {
+                    for term in texts.iter().flat_map(|text| tokenize(text)) {
+                        if let Some(postings) = self.terms.get_mut(&term) {
+                            postings.remove(key);
+                            if postings.is_empty() {
+                                self.terms.remove(&term);
+                            }
+                        }
+                    }
+                }
+                Some(FieldValue::Tags(tags)) => {
+                    let index = self.tags.entry(field).or_default();
+                    for tag in tags {
+                        if let Some(keys) = index.get_mut(&tag) {
+                            keys.remove(key);
+                            if keys.is_empty() {
+                                index.remove(&tag);
+                            }
+                        }
+                    }
+                }
+                Some(FieldValue::Numeric(n)) => {
+                    if let Some(numbers) = self.numbers.get_mut(&field) {
+                        numbers.remove(&(Number(n), key.to_vec()));
+                    }
+                }
+                Some(FieldValue::Vector(_)) => {
+                    if let Some(vectors) = self.vectors.get_mut(&field) {
+                        vectors.remove(key);
+                    }
+                }
+                None => {}
+            }
+        }
+    }
+
+    /// Runs `query`, ordering matches by `sort_by` (field, descending), by
+    /// distance for KNN queries or else by score, best first. Ties are
+    /// broken by key.
+    pub fn search(&self, query: &Query, sort_by: Option<(usize, bool)>) -> Vec<Match<'_>> {
+        let filter = &query.filter;
+        let mut matches: Vec<Match> = match &query.knn {
+            None => self
+                .evaluate(filter)
+                .into_iter()
+                .map(|key| Match { key, score: self.score(filter, key), distance: None })
+                .collect(),
+            Some(knn) => {
+                let Some(vectors) = self.vectors.get(&knn.field) else {
+                    return Vec::new();
+                };
+                // A filter limits the search to the documents it matches,
+                // which are then compared one by one.
+                let candidates = (*filter != Node::All).then(|| self.evaluate(filter));
+                vectors
+                    .nearest(&knn.vector, knn.k, knn.ef_runtime, candidates.as_ref())
+                    .into_iter()
+                    .map(|(key, distance)| Match { key, score: self.score(filter, key), distance: Some(distance) })
+                    .collect()
+            }
+        };
+        match sort_by {
+            Some((field, descending)) => matches.sort_by(|a, b| {
+                let (a_value, b_value) = (self.sort_value(a.key, field), self.sort_value(b.key, field));
+                let ordering = match (a_value, b_value) {
+                    // Documents without the field come last either way.
+                    (Some(_), None) => Ordering::Less,
+                    (None, Some(_)) => Ordering::Greater,
+                    (None, None) => Ordering::Equal,
+                    (Some(a_value), Some(b_value)) if descending => b_value.cmp(&a_value),
+                    (Some(a_value), Some(b_value)) => a_value.cmp(&b_value),
+                };
+                ordering.then_with(|| a.key.cmp(b.key))
+            }),
+            None => matches.sort_by(|a, b| {
+                let ordering = match (a.distance, b.distance) {
+                    (Some(a_distance), Some(b_distance)) => a_distance.total_cmp(&b_distance),
+                    _ => b.score.total_cmp(&a.score),
+                };
+                ordering.then_with(|| a.key.cmp(b.key))
+            }),
+        }
+        matches
+    }
+
+    fn sort_value(&self, key: &[u8], field: usize) -> Option<SortValue> {
+        match self.documents.get(key)?.get(field)?.as_ref()? {
+            FieldValue::Numeric(n) => Some(SortValue::Number(Number(*n))),
+            FieldValue::Text(texts) => Some(SortValue::Text(texts.join(" ").to_lowercase())),
+            FieldValue::Tags(tags) => Some(SortValue::Text(tags.join(","))),
+            FieldValue::Vector(_) => None,
+        }
+    }
+
+    /// The keys of the documents matching `node`.
+    fn evaluate(&self, node: &Node) -> HashSet<&[u8]> {
+        match node {
+            Node::All => self.documents.keys().map(Vec::as_slice).collect(),
+            Node::Term { fields, term } => self.term_matches(term, fields.as_deref()),
+            Node::Prefix { fields, prefix } => self
+                .terms
+                .range::<str, _>((Bound::Included(prefix.as_str()), Bound::Unbounded))
+                .take_while(|(term, _)| term.starts_with(prefix.as_str()))
+                .flat_map(|(term, _)| self.term_matches(term, fields.as_deref()))
+                .collect(),
+            Node::Phrase { fields, terms } => self
+                .term_matches(&terms[0], fields.as_deref())
+                .into_iter()
+                .filter(|key| self.contains_phrase(key, terms, fields.as_deref()))
+                .collect(),
+            Node::Tag { field, tags } => {
+                let Some(index) = self.tags.get(field) else {
+                    return HashSet::new();
+                };
+                tags.iter().filter_map(|tag| index.get(tag)).flatten().map(Vec::as_slice).collect()
+            }
+            Node::Range { field, min, max } => {
+                let Some(numbers) = self.numbers.get(field) else {
+                    return HashSet::new();
+                };
+                let start = match min {
+                    Bound::Included(n) | Bound::Excluded(n) => Number(*n),
+                    Bound::Unbounded => Number(f64::NEG_INFINITY),
+                };
+                numbers
+                    .range((start, Vec::new())..)
+                    .take_while(|(n, _)| match max {
+                        Bound::Included(max) => n.0 <= *max,
+                        Bound::Excluded(max) => n.0 < *max,
+                        Bound::Unbounded => true,
+                    })
+                    .filter(|(n, _)| !matches!(min, Bound::Excluded(min) if n.0 == *min))
+                    .map(|(_, key)| key.as_slice())
+                    .collect()
+            }
+            Node::And(nodes) => {
+                let mut sets = nodes.iter().map(|node| self.evaluate(node));
+                let first = sets.next().unwrap_or_default();
+                sets.fold(first, |matches, set| matches.intersection(&set).copied().collect())
+            }
+            Node::Or(nodes) => nodes.iter().flat_map(|node| self.evaluate(node)).collect(),
+            Node::Not(node) => {
+                let excluded = self.evaluate(node);
+                self.documents.keys().map(Vec::as_slice).filter(|key| !excluded.contains(key)).collect()
+            }
+        }
+    }
+
+    fn occurrences<'a>(&'a self, term: &str, key: &[u8], fields: Option<&'a [usize]>) -> impl Iterator<Item = Occurrence> + 'a {
+        self.terms
+            .get(term)
+            .and_then(|postings| postings.get(key))
+            .into_iter()
+            .flatten()
+            .copied()
+            .filter(move |occurrence| fields.is_none_or(|fields| fields.contains(&occurrence.field)))
+    }
+
+    fn term_matches(&self, term: &str, fields: Option<&[usize]>) -> HashSet<&[u8]> {
+        let Some(postings) = self.terms.get(term) else {
+            return HashSet::new();
+        };
+        postings
+            .keys()
+            .filter(|key| self.occurrences(term, key, fields).next().is_some())
+            .map(Vec::as_slice)
+            .collect()
+    }
+
+    fn contains_phrase(&self, key: &[u8], terms: &[String], fields: Option<&[usize]>) -> bool {
+        self.occurrences(&terms[0], key, fields).any(|start| {
+            terms[1..].iter().enumerate().all(|(offset, term)| {
+                self.occurrences(term, key, Some(&[start.field]))
+                    .any(|occurrence| occurrence.position == start.position + offset + 1)
+            })
+        })
+    }
+
+    /// TF-IDF of the terms `node` looks for in the document at `key`.
+    fn score(&self, node: &Node, key: &[u8]) -> f64 {
+        match node {
+            Node::Term { fields, term } => self.term_score(term, key, fields.as_deref()),
+            Node::Prefix { fields, prefix } => self
+                .terms
+                .range::<str, _>((Bound::Included(prefix.as_str()), Bound::Unbounded))
+                .take_while(|(term, _)| term.starts_with(prefix.as_str()))
+                .map(|(term, _)| self.term_score(term, key, fields.as_deref()))
+                .sum(),
+            Node::Phrase { fields, terms } => terms.iter().map(|term| self.term_score(term, key, fields.as_deref())).sum(),
+            Node::And(nodes) | Node::Or(nodes) => nodes.iter().map(|node| self.score(node, key)).sum(),
+            Node::All | Node::Tag { .. } | Node::Range { .. } | Node::Not(_) => 0.0,
+        }
+    }
+
+    fn term_score(&self, term: &str, key: &[u8], fields: Option<&[usize]>) -> f64 {
+        let Some(postings) = self.terms.get(term) else {
+            return 0.0;
+        };
+        let frequency: f64 = self
+            .occurrences(term, key, fields)
+            .map(|occurrence| match self.schema.fields[occurrence.field].kind {
+                FieldKind::Text { weight } => weight,
+                _ => 0.0,
+            })
+            .sum();
+        let idf = (1.0 + self.documents.len() as f64 / postings.len() as f64).ln();
+        frequency * idf
+    }
+}
+
+/// The value of a field that SORTBY compares.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum SortValue {
+    Number(Number),
+    Text(String),
+}
+
+#[cfg(test)]
+mod tests;
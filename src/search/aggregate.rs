@@ -0,0 +1,193 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use crate::search::expression::{compare, truthy, Expression};
+use crate::search::Property;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReduceFunction {
+    Count,
+    CountDistinct,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl ReduceFunction {
+    pub fn by_name(name: &str) -> Option<ReduceFunction> {
+        Some(match name.to_uppercase().as_str() {
+            "COUNT" => ReduceFunction::Count,
+            "COUNT_DISTINCT" => ReduceFunction::CountDistinct,
+            "SUM" => ReduceFunction::Sum,
+            "AVG" => ReduceFunction::Avg,
+            "MIN" => ReduceFunction::Min,
+            "MAX" => ReduceFunction::Max,
+            _ => return None,
+        })
+    }
+
+    /// Whether the function reads a property; only COUNT doesn't.
+    pub fn takes_property(self) -> bool {
+        self != ReduceFunction::Count
+    }
+
+    fn reduce(self, values: Vec<Property>, rows: usize) -> Option<Property> {
+        let numbers = || values.iter().filter_map(Property::as_number);
+        let n = match self {
+            ReduceFunction::Count => rows as f64,
+            ReduceFunction::CountDistinct => {
+                values.into_iter().map(Property::into_bytes).collect::<HashSet<_>>().len() as f64
+            }
+            ReduceFunction::Sum => numbers().sum(),
+            ReduceFunction::Avg => {
+                let count = numbers().count();
+                (count > 0).then(|| numbers().sum::<f64>() / count as f64)?
+            }
+            ReduceFunction::Min => numbers().min_by(f64::total_cmp)?,
+            ReduceFunction::Max => numbers().max_by(f64::total_cmp)?,
+        };
+        Some(Property::Number(n))
+    }
+}
+
+/// `REDUCE function nargs [property] [AS name]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reducer {
+    pub function: ReduceFunction,
+    pub property: Option<String>,
+    pub name: String,
+}
+
+/// One step of an FT.AGGREGATE pipeline, applied to all rows in turn.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    GroupBy { properties: Vec<String>, reducers: Vec<Reducer> },
+    Apply { expression: Expression, name: String },
+    Filter(Expression),
+    /// Properties with `true` for descending order, and a maximum row count.
+    SortBy { keys: Vec<(String, bool)>, max: Option<usize> },
+    Limit { offset: usize, count: usize },
+}
+
+/// A result row. Rows for documents read properties not set by the
+/// pipeline from the document itself, until a GROUPBY replaces them.
+struct Row<'a> {
+    key: Option<&'a [u8]>,
+    properties: Vec<(String, Property)>,
+}
+
+impl Row<'_> {
+    fn get(&self, name: &str, load: &dyn Fn(&[u8], &str) -> Option<Property>) -> Option<Property> {
+        match self.properties.iter().find(|(property, _)| property == name) {
+            Some((_, value)) => Some(value.clone()),
+            None => load(self.key?, name),
+        }
+    }
+
+    fn set(&mut self, name: &str, value: Property) {
+        match self.properties.iter_mut().find(|(property, _)| property == name) {
+            Some((_, old)) => *old = value,
+            None => self.properties.push((name.to_string(), value)),
+        }
+    }
+}
+
+/// Runs `steps` over the documents at `keys`, in order. `load` reads a
+/// property of a document. Each row starts out with the `loaded` properties.
+pub fn aggregate<'a>(
+    keys: impl Iterator<Item = &'a [u8]>,
+    loaded: &[String],
+    steps: &[Step],
+    load: &dyn Fn(&[u8], &str) -> Option<Property>,
+) -> Vec<Vec<(String, Property)>> {
+    let mut rows: Vec<Row> = keys
+        .map(|key| Row {
+            key: Some(key),
+            properties: loaded.iter().filter_map(|name| Some((name.clone(), load(key, name)?))).collect(),
+        })
+        .collect();
+
+    for step in steps {
+        rows = match step {
+            Step::GroupBy { properties, reducers } => group(rows, properties, reducers, load),
+            Step::Apply { expression, name } => {
+                for row in &mut rows {
+                    if let Some(value) = expression.evaluate(&mut |property| row.get(property, load)) {
+                        row.set(name, value);
+                    }
+                }
+                rows
+            }
+            Step::Filter(expression) => rows
+                .into_iter()
+                .filter(|row| truthy(expression.evaluate(&mut |property| row.get(property, load)).as_ref()))
+                .collect(),
+            Step::SortBy { keys, max } => {
+                let mut sorted: Vec<(Vec<Option<Property>>, Row)> = rows
+                    .into_iter()
+                    .map(|row| (keys.iter().map(|(name, _)| row.get(name, load)).collect(), row))
+                    .collect();
+                sorted.sort_by(|(a, _), (b, _)| {
+                    keys.iter()
+                        .zip(a.iter().zip(b))
+                        .map(|((_, descending), values)| match values {
+                            // Rows without the property come last either way.
+                            (Some(_), None) => Ordering::Less,
+                            (None, Some(_)) => Ordering::Greater,
+                            (None, None) => Ordering::Equal,
+                            (Some(a), Some(b)) if *descending => compare(b, a),
+                            (Some(a), Some(b)) => compare(a, b),
+                        })
+                        .find(|ordering| *ordering != Ordering::Equal)
+                        .unwrap_or(Ordering::Equal)
+                });
+                sorted.into_iter().map(|(_, row)| row).take(max.unwrap_or(usize::MAX)).collect()
+            }
+            Step::Limit { offset, count } => rows.into_iter().skip(*offset).take(*count).collect(),
+        };
+    }
+    rows.into_iter().map(|row| row.properties).collect()
+}
+
+/// Groups rows by the values of `properties`, in order of first appearance,
+/// and reduces each group to one row.
+fn group<'a>(
+    rows: Vec<Row<'a>>,
+    properties: &[String],
+    reducers: &[Reducer],
+    load: &dyn Fn(&[u8], &str) -> Option<Property>,
+) -> Vec<Row<'a>> {
+    let mut groups: Vec<(Vec<Option<Property>>, Vec<Row>)> = Vec::new();
+    let mut positions: HashMap<Vec<Option<Vec<u8>>>, usize> = HashMap::new();
+    for row in rows {
+        let values: Vec<Option<Property>> = properties.iter().map(|name| row.get(name, load)).collect();
+        let group_key = values.iter().map(|value| value.clone().map(Property::into_bytes)).collect();
+        let position = *positions.entry(group_key).or_insert_with(|| {
+            groups.push((values, Vec::new()));
+            groups.len() - 1
+        });
+        groups[position].1.push(row);
+    }
+
+    groups
+        .into_iter()
+        .map(|(values, members)| {
+            let mut row = Row { key: None, properties: Vec::new() };
+            for (name, value) in properties.iter().zip(values) {
+                if let Some(value) = value {
+                    row.set(name, value);
+                }
+            }
+            for reducer in reducers {
+                let values = match &reducer.property {
+                    Some(property) => members.iter().filter_map(|member| member.get(property, load)).collect(),
+                    None => Vec::new(),
+                };
+                if let Some(value) = reducer.function.reduce(values, members.len()) {
+                    row.set(&reducer.name, value);
+                }
+            }
+            row
+        })
+        .collect()
+}
@@ -0,0 +1,368 @@
+use std::cmp::Ordering;
+use crate::search::query::QueryError;
+use crate::search::Property;
+
+/// An APPLY or FILTER expression: arithmetic, comparisons and logic over
+/// `@property` values, string and number literals, and a few functions.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Literal(Property),
+    Property(String),
+    Negate(Box<Expression>),
+    Not(Box<Expression>),
+    Binary(Box<Expression>, Operator, Box<Expression>),
+    Call(Function, Vec<Expression>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Power,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Function {
+    Abs,
+    Ceil,
+    Floor,
+    Sqrt,
+    Log,
+    Log2,
+    Exp,
+    Upper,
+    Lower,
+    Strlen,
+}
+
+impl Function {
+    fn by_name(name: &str) -> Option<Function> {
+        Some(match name.to_lowercase().as_str() {
+            "abs" => Function::Abs,
+            "ceil" => Function::Ceil,
+            "floor" => Function::Floor,
+            "sqrt" => Function::Sqrt,
+            "log" => Function::Log,
+            "log2" => Function::Log2,
+            "exp" => Function::Exp,
+            "upper" => Function::Upper,
+            "lower" => Function::Lower,
+            "strlen" => Function::Strlen,
+            _ => return None,
+        })
+    }
+
+    fn apply(self, argument: Property) -> Option<Property> {
+        let text = |argument: &Property| match argument {
+            Property::Text(text) => String::from_utf8_lossy(text).into_owned(),
+            number => String::from_utf8_lossy(&number.clone().into_bytes()).into_owned(),
+        };
+        let number = match self {
+            Function::Upper => return Some(Property::Text(text(&argument).to_uppercase().into_bytes())),
+            Function::Lower => return Some(Property::Text(text(&argument).to_lowercase().into_bytes())),
+            Function::Strlen => return Some(Property::Number(text(&argument).chars().count() as f64)),
+            _ => argument.as_number()?,
+        };
+        Some(Property::Number(match self {
+            Function::Abs => number.abs(),
+            Function::Ceil => number.ceil(),
+            Function::Floor => number.floor(),
+            Function::Sqrt => number.sqrt(),
+            Function::Log => number.ln(),
+            Function::Log2 => number.log2(),
+            Function::Exp => number.exp(),
+            Function::Upper | Function::Lower | Function::Strlen => unreachable!("handled above"),
+        }))
+    }
+}
+
+impl Expression {
+    pub fn parse(text: &str) -> Result<Expression, QueryError> {
+        let mut parser = Parser { chars: text.chars().collect(), pos: 0, depth: 0 };
+        let expression = parser.or()?;
+        parser.skip_whitespace();
+        if parser.pos < parser.chars.len() {
+            return Err(parser.error());
+        }
+        Ok(expression)
+    }
+
+    /// Evaluates the expression, reading properties through `get`. `None`
+    /// means the result is undefined, e.g. arithmetic on a missing property.
+    pub fn evaluate(&self, get: &mut dyn FnMut(&str) -> Option<Property>) -> Option<Property> {
+        match self {
+            Expression::Literal(value) => Some(value.clone()),
+            Expression::Property(name) => get(name),
+            Expression::Negate(operand) => Some(Property::Number(-operand.evaluate(get)?.as_number()?)),
+            Expression::Not(operand) => Some(boolean(!truthy(operand.evaluate(get).as_ref()))),
+            Expression::Binary(left, Operator::And, right) => {
+                Some(boolean(truthy(left.evaluate(get).as_ref()) && truthy(right.evaluate(get).as_ref())))
+            }
+            Expression::Binary(left, Operator::Or, right) => {
+                Some(boolean(truthy(left.evaluate(get).as_ref()) || truthy(right.evaluate(get).as_ref())))
+            }
+            Expression::Binary(left, operator, right) => {
+                let (left, right) = (left.evaluate(get)?, right.evaluate(get)?);
+                let ordering = || compare(&left, &right);
+                let number = match operator {
+                    Operator::Eq => return Some(boolean(ordering() == Ordering::Equal)),
+                    Operator::Ne => return Some(boolean(ordering() != Ordering::Equal)),
+                    Operator::Lt => return Some(boolean(ordering() == Ordering::Less)),
+                    Operator::Le => return Some(boolean(ordering() != Ordering::Greater)),
+                    Operator::Gt => return Some(boolean(ordering() == Ordering::Greater)),
+                    Operator::Ge => return Some(boolean(ordering() != Ordering::Less)),
+                    _ => {
+                        let (left, right) = (left.as_number()?, right.as_number()?);
+                        match operator {
+                            Operator::Add => left + right,
+                            Operator::Subtract => left - right,
+                            Operator::Multiply => left * right,
+                            Operator::Divide => left / right,
+                            Operator::Modulo => left % right,
+                            Operator::Power => left.powf(right),
+                            _ => unreachable!("handled above"),
+                        }
+                    }
+                };
+                Some(Property::Number(number))
+            }
+            Expression::Call(function, arguments) => function.apply(arguments[0].evaluate(get)?),
+        }
+    }
+}
+
+fn boolean(value: bool) -> Property {
+    Property::Number(if value { 1.0 } else { 0.0 })
+}
+
+/// Whether a value counts as true in FILTER: non-zero numbers and
+/// non-empty text.
+pub fn truthy(value: Option<&Property>) -> bool {
+    match value {
+        Some(Property::Number(n)) => *n != 0.0,
+        Some(Property::Text(text)) => !text.is_empty(),
+        None => false,
+    }
+}
+
+/// Orders two values numerically if both are numbers, else as text.
+pub fn compare(left: &Property, right: &Property) -> Ordering {
+    match (left.as_number(), right.as_number()) {
+        (Some(left), Some(right)) => left.total_cmp(&right),
+        _ => left.clone().into_bytes().cmp(&right.clone().into_bytes()),
+    }
+}
+
+/// How deep the expression tree may grow, through `(`, signs, `!`, function
+/// calls and chained operators, so neither parsing nor evaluating a hostile
+/// expression can overflow the stack.
+const MAX_DEPTH: usize = 128;
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    /// Expression nesting at the current position.
+    depth: usize,
+}
+
+impl Parser {
+    fn error(&self) -> QueryError {
+        QueryError(format!("Syntax error at offset {} of expression '{}'", self.pos, self.chars.iter().collect::<String>()))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    /// Consumes `text` after any whitespace.
+    fn eat(&mut self, text: &str) -> bool {
+        self.skip_whitespace();
+        let matches = text.chars().enumerate().all(|(offset, c)| self.chars.get(self.pos + offset) == Some(&c));
+        if matches {
+            self.pos += text.chars().count();
+        }
+        matches
+    }
+
+    /// Goes one level deeper into the expression tree.
+    fn enter(&mut self) -> Result<(), QueryError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(QueryError(format!("Expression nested too deeply at offset {}", self.pos)));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn binary(
+        &mut self,
+        operators: &[(&str, Operator)],
+        next: fn(&mut Parser) -> Result<Expression, QueryError>,
+    ) -> Result<Expression, QueryError> {
+        let depth = self.depth;
+        let mut left = next(self)?;
+        'outer: loop {
+            for (text, operator) in operators {
+                if self.eat(text) {
+                    // Each operator nests the chain so far one level deeper.
+                    self.enter()?;
+                    left = Expression::Binary(Box::new(left), *operator, Box::new(next(self)?));
+                    continue 'outer;
+                }
+            }
+            self.depth = depth;
+            return Ok(left);
+        }
+    }
+
+    fn or(&mut self) -> Result<Expression, QueryError> {
+        self.binary(&[("||", Operator::Or)], Parser::and)
+    }
+
+    fn and(&mut self) -> Result<Expression, QueryError> {
+        self.binary(&[("&&", Operator::And)], Parser::comparison)
+    }
+
+    fn comparison(&mut self) -> Result<Expression, QueryError> {
+        // Two-character operators first, so `<=` isn't read as `<`.
+        let operators = [
+            ("==", Operator::Eq),
+            ("!=", Operator::Ne),
+            ("<=", Operator::Le),
+            (">=", Operator::Ge),
+            ("<", Operator::Lt),
+            (">", Operator::Gt),
+        ];
+        self.binary(&operators, Parser::additive)
+    }
+
+    fn additive(&mut self) -> Result<Expression, QueryError> {
+        self.binary(&[("+", Operator::Add), ("-", Operator::Subtract)], Parser::multiplicative)
+    }
+
+    fn multiplicative(&mut self) -> Result<Expression, QueryError> {
+        self.binary(&[("*", Operator::Multiply), ("/", Operator::Divide), ("%", Operator::Modulo)], Parser::unary)
+    }
+
+    /// Every nested expression passes through here, which bounds the
+    /// recursion.
+    fn unary(&mut self) -> Result<Expression, QueryError> {
+        self.enter()?;
+        let expression = self.signed();
+        self.depth -= 1;
+        expression
+    }
+
+    fn signed(&mut self) -> Result<Expression, QueryError> {
+        if self.eat("-") {
+            return Ok(Expression::Negate(Box::new(self.unary()?)));
+        }
+        if self.eat("!") {
+            return Ok(Expression::Not(Box::new(self.unary()?)));
+        }
+        self.power()
+    }
+
+    /// `^` binds tighter than a sign and is right-associative, so `-2 ^ 2`
+    /// is -4 and `2 ^ 3 ^ 2` is 512.
+    fn power(&mut self) -> Result<Expression, QueryError> {
+        let base = self.primary()?;
+        if self.eat("^") {
+            return Ok(Expression::Binary(Box::new(base), Operator::Power, Box::new(self.unary()?)));
+        }
+        Ok(base)
+    }
+
+    fn primary(&mut self) -> Result<Expression, QueryError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                let expression = self.or()?;
+                if !self.eat(")") {
+                    return Err(self.error());
+                }
+                Ok(expression)
+            }
+            Some('@') => {
+                self.pos += 1;
+                Ok(Expression::Property(self.name()?))
+            }
+            Some(quote @ ('"' | '\'')) => {
+                self.pos += 1;
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c != quote) {
+                    self.pos += 1;
+                }
+                if self.peek().is_none() {
+                    return Err(self.error());
+                }
+                let text: String = self.chars[start..self.pos].iter().collect();
+                self.pos += 1;
+                Ok(Expression::Literal(Property::Text(text.into_bytes())))
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c.is_ascii_alphanumeric() || c == '.') {
+                    self.pos += 1;
+                }
+                let text: String = self.chars[start..self.pos].iter().collect();
+                let n = text.parse().map_err(|_| self.error())?;
+                Ok(Expression::Literal(Property::Number(n)))
+            }
+            Some(c) if c.is_alphabetic() => {
+                let start = self.pos;
+                let name = self.name()?;
+                let function = Function::by_name(&name)
+                    .ok_or_else(|| QueryError(format!("Unknown function name '{name}' at offset {start}")))?;
+                let mut arguments = Vec::new();
+                if !self.eat("(") {
+                    return Err(self.error());
+                }
+                if !self.eat(")") {
+                    loop {
+                        arguments.push(self.or()?);
+                        if self.eat(")") {
+                            break;
+                        }
+                        if !self.eat(",") {
+                            return Err(self.error());
+                        }
+                    }
+                }
+                if arguments.len() != 1 {
+                    return Err(QueryError(format!("Function '{name}' takes exactly one argument")));
+                }
+                Ok(Expression::Call(function, arguments))
+            }
+            _ => Err(self.error()),
+        }
+    }
+
+    fn name(&mut self) -> Result<String, QueryError> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return Err(self.error());
+        }
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+}
@@ -0,0 +1,359 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Bound;
+use crate::search::{normalize_tag, read_vector, tokenize, FieldKind, Schema};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryError(pub(super) String);
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// A parsed search query. Text nodes carry the TEXT fields they are limited
+/// to by `@field:`, `None` meaning all of them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    /// `*`
+    All,
+    Term { fields: Option<Vec<usize>>, term: String },
+    /// `hel*`
+    Prefix { fields: Option<Vec<usize>>, prefix: String },
+    /// `"hello world"`: the terms next to each other in one field.
+    Phrase { fields: Option<Vec<usize>>, terms: Vec<String> },
+    /// `@field:{a | b}`
+    Tag { field: usize, tags: Vec<String> },
+    /// `@field:[min max]`
+    Range { field: usize, min: Bound<f64>, max: Bound<f64> },
+    And(Vec<Node>),
+    Or(Vec<Node>),
+    /// `-node`
+    Not(Box<Node>),
+}
+
+impl Node {
+    /// Parses a query in RediSearch syntax. Space-separated clauses must all
+    /// match and `|` binds tighter, so `a|b c` means `(a|b) c`.
+    pub fn parse(text: &str, schema: &Schema) -> Result<Node, QueryError> {
+        let mut parser = Parser { chars: text.chars().collect(), pos: 0, depth: 0, schema };
+        let node = parser.intersection(&None)?;
+        if !parser.at_end() {
+            return Err(parser.error());
+        }
+        Ok(node)
+    }
+}
+
+/// `=>[KNN k @field $vector [EF_RUNTIME ef] [AS name]]`: the `k` documents
+/// whose vectors are nearest to the query vector.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Knn {
+    pub k: usize,
+    pub field: usize,
+    pub vector: Vec<f32>,
+    pub ef_runtime: Option<usize>,
+    /// The name the distance is returned as, `__field_score` by default.
+    pub name: String,
+}
+
+/// A search query: a filter, optionally followed by a KNN clause that picks
+/// the nearest of the documents it matches.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    pub filter: Node,
+    pub knn: Option<Knn>,
+}
+
+impl Query {
+    /// Parses `filter=>[KNN ...]` or a plain filter. `$name` arguments of
+    /// the KNN clause are looked up in `params`.
+    pub fn parse(text: &str, schema: &Schema, params: &HashMap<String, Vec<u8>>) -> Result<Query, QueryError> {
+        let Some((filter, knn)) = text.split_once("=>") else {
+            return Ok(Query { filter: Node::parse(text, schema)?, knn: None });
+        };
+        let filter = Node::parse(filter.trim(), schema)?;
+        let offset = text.len() - knn.len();
+        let syntax_error = || QueryError(format!("Syntax error at offset {offset} near {knn}"));
+        let clause = knn.trim().strip_prefix('[').and_then(|knn| knn.strip_suffix(']')).ok_or_else(syntax_error)?;
+        let param = |arg: &str| -> Result<Vec<u8>, QueryError> {
+            match arg.strip_prefix('$') {
+                Some(name) => params.get(name).cloned().ok_or_else(|| QueryError(format!("No such parameter `{name}`"))),
+                None => Ok(arg.as_bytes().to_vec()),
+            }
+        };
+        let number = |arg: &str| -> Result<usize, QueryError> {
+            String::from_utf8_lossy(&param(arg)?).parse().map_err(|_| syntax_error())
+        };
+
+        let args: Vec<&str> = clause.split_whitespace().collect();
+        let [method, k, field, vector, options @ ..] = args.as_slice() else {
+            return Err(syntax_error());
+        };
+        if !method.eq_ignore_ascii_case("KNN") {
+            return Err(syntax_error());
+        }
+        let name = field.strip_prefix('@').ok_or_else(syntax_error)?;
+        let Some((field, FieldKind::Vector { dim, .. })) = schema.field(name).map(|(index, field)| (index, &field.kind)) else {
+            return Err(QueryError(format!("Unknown vector field `{name}`")));
+        };
+        let blob = param(vector)?;
+        let vector = read_vector(&blob, *dim).ok_or_else(|| {
+            QueryError(format!(
+                "Error parsing vector similarity query: query vector blob size ({}) does not match index's expected size ({}).",
+                blob.len(),
+                dim.saturating_mul(4)
+            ))
+        })?;
+        let mut knn = Knn { k: number(k)?, field, vector, ef_runtime: None, name: format!("__{name}_score") };
+        for option in options.chunks(2) {
+            match option {
+                [option, ef] if option.eq_ignore_ascii_case("EF_RUNTIME") => knn.ef_runtime = Some(number(ef)?),
+                [option, name] if option.eq_ignore_ascii_case("AS") => knn.name = name.to_string(),
+                _ => return Err(syntax_error()),
+            }
+        }
+        Ok(Query { filter, knn: Some(knn) })
+    }
+}
+
+type Scope = Option<Vec<usize>>;
+
+/// How deeply clauses may nest through `(`, `-` and `@field:`, so a hostile
+/// query can't overflow the stack.
+const MAX_DEPTH: usize = 64;
+
+struct Parser<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    /// Clause nesting at the current position.
+    depth: usize,
+    schema: &'a Schema,
+}
+
+impl Parser<'_> {
+    fn error(&self) -> QueryError {
+        let near: String = self.chars[self.pos.min(self.chars.len())..].iter().take(10).collect();
+        QueryError(format!("Syntax error at offset {} near {near}", self.pos))
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.chars.len()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let matches = self.peek() == Some(c);
+        if matches {
+            self.pos += 1;
+        }
+        matches
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), QueryError> {
+        self.skip_whitespace();
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error())
+        }
+    }
+
+    /// Clauses up to the end of the query or a closing parenthesis.
+    fn intersection(&mut self, scope: &Scope) -> Result<Node, QueryError> {
+        let mut nodes = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.at_end() || self.peek() == Some(')') {
+                break;
+            }
+            nodes.push(self.union(scope)?);
+        }
+        match nodes.len() {
+            0 => Err(self.error()),
+            1 => Ok(nodes.remove(0)),
+            _ => Ok(Node::And(nodes)),
+        }
+    }
+
+    fn union(&mut self, scope: &Scope) -> Result<Node, QueryError> {
+        let mut nodes = vec![self.unary(scope)?];
+        loop {
+            self.skip_whitespace();
+            if !self.eat('|') {
+                break;
+            }
+            self.skip_whitespace();
+            nodes.push(self.unary(scope)?);
+        }
+        Ok(if nodes.len() == 1 { nodes.remove(0) } else { Node::Or(nodes) })
+    }
+
+    /// Every nested clause passes through here, which bounds the recursion.
+    fn unary(&mut self, scope: &Scope) -> Result<Node, QueryError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(QueryError(format!("Query nested too deeply at offset {}", self.pos)));
+        }
+        self.depth += 1;
+        let node = self.clause(scope);
+        self.depth -= 1;
+        node
+    }
+
+    fn clause(&mut self, scope: &Scope) -> Result<Node, QueryError> {
+        if self.eat('-') {
+            return Ok(Node::Not(Box::new(self.unary(scope)?)));
+        }
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                let node = self.intersection(scope)?;
+                self.expect(')')?;
+                Ok(node)
+            }
+            Some('@') => self.field_clause(),
+            Some('"') => self.phrase(scope),
+            Some('*') => {
+                self.pos += 1;
+                Ok(Node::All)
+            }
+            _ => {
+                let term = self.word()?.to_lowercase();
+                let fields = scope.clone();
+                Ok(if self.eat('*') { Node::Prefix { fields, prefix: term } } else { Node::Term { fields, term } })
+            }
+        }
+    }
+
+    fn word(&mut self) -> Result<String, QueryError> {
+        let mut word = String::new();
+        while let Some(c) = self.peek() {
+            if c == '\\' && self.pos + 1 < self.chars.len() {
+                word.push(self.chars[self.pos + 1]);
+                self.pos += 2;
+            } else if c.is_alphanumeric() || c == '_' {
+                word.push(c);
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+        if word.is_empty() {
+            return Err(self.error());
+        }
+        Ok(word)
+    }
+
+    /// `@field:...` or `@a|b:...` for several TEXT fields.
+    fn field_clause(&mut self) -> Result<Node, QueryError> {
+        let start = self.pos;
+        self.pos += 1;
+        let mut fields = Vec::new();
+        loop {
+            let name = self.word()?;
+            let Some((index, field)) = self.schema.field(&name) else {
+                return Err(QueryError(format!("Unknown field at offset {start} near {name}")));
+            };
+            fields.push((index, field.kind.clone()));
+            if !self.eat('|') {
+                break;
+            }
+        }
+        if !self.eat(':') {
+            return Err(self.error());
+        }
+        self.skip_whitespace();
+        match fields.as_slice() {
+            [(field, FieldKind::Tag { case_sensitive, .. })] => self.tags(*field, *case_sensitive),
+            [(field, FieldKind::Numeric)] => self.range(*field),
+            _ if fields.iter().all(|(_, kind)| matches!(kind, FieldKind::Text { .. })) => {
+                self.unary(&Some(fields.into_iter().map(|(index, _)| index).collect()))
+            }
+            _ => Err(self.error()),
+        }
+    }
+
+    fn phrase(&mut self, scope: &Scope) -> Result<Node, QueryError> {
+        self.pos += 1;
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c != '"') {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        self.expect('"')?;
+        let mut terms: Vec<String> = tokenize(&text).collect();
+        let fields = scope.clone();
+        match terms.len() {
+            0 => Err(self.error()),
+            1 => Ok(Node::Term { fields, term: terms.remove(0) }),
+            _ => Ok(Node::Phrase { fields, terms }),
+        }
+    }
+
+    /// `{a | b\ c}`
+    fn tags(&mut self, field: usize, case_sensitive: bool) -> Result<Node, QueryError> {
+        self.expect('{')?;
+        let mut tags = Vec::new();
+        let mut tag = String::new();
+        loop {
+            match self.peek() {
+                Some('\\') if self.pos + 1 < self.chars.len() => {
+                    tag.push(self.chars[self.pos + 1]);
+                    self.pos += 2;
+                    continue;
+                }
+                Some(c @ ('|' | '}')) => {
+                    tags.push(normalize_tag(&tag, case_sensitive).ok_or_else(|| self.error())?);
+                    tag.clear();
+                    self.pos += 1;
+                    if c == '}' {
+                        return Ok(Node::Tag { field, tags });
+                    }
+                }
+                Some(c) => {
+                    tag.push(c);
+                    self.pos += 1;
+                }
+                None => return Err(self.error()),
+            }
+        }
+    }
+
+    /// `[min max]`, where either end may be `(`-prefixed to exclude it, or
+    /// `-inf`/`+inf`.
+    fn range(&mut self, field: usize) -> Result<Node, QueryError> {
+        self.expect('[')?;
+        let min = self.bound()?;
+        self.skip_whitespace();
+        self.eat(',');
+        let max = self.bound()?;
+        self.expect(']')?;
+        Ok(Node::Range { field, min, max })
+    }
+
+    fn bound(&mut self) -> Result<Bound<f64>, QueryError> {
+        self.skip_whitespace();
+        let exclusive = self.eat('(');
+        let start = self.pos;
+        while self.peek().is_some_and(|c| !c.is_whitespace() && c != ',' && c != ']') {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        let n: f64 = match text.to_lowercase().as_str() {
+            "-inf" => f64::NEG_INFINITY,
+            "inf" | "+inf" => f64::INFINITY,
+            text => text.parse().ok().filter(|n: &f64| !n.is_nan()).ok_or_else(|| self.error())?,
+        };
+        Ok(if exclusive { Bound::Excluded(n) } else { Bound::Included(n) })
+    }
+}
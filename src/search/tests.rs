@@ -0,0 +1,327 @@
+use super::*;
+use crate::db::Hash;
+use serde_json::json;
+
+fn schema() -> Schema {
+    Schema {
+        source: Source::Hash,
+        prefixes: vec![b"doc:".to_vec()],
+        fields: vec![
+            Field::new(Source::Hash, "title", None, FieldKind::Text { weight: 2.0 }).unwrap(),
+            Field::new(Source::Hash, "body", None, FieldKind::Text { weight: 1.0 }).unwrap(),
+            Field::new(Source::Hash, "tags", None, FieldKind::Tag { separator: ',', case_sensitive: false }).unwrap(),
+            Field::new(Source::Hash, "price", None, FieldKind::Numeric).unwrap(),
+        ],
+    }
+}
+
+fn hash(fields: &[(&str, &str)]) -> Value {
+    Value::Hash(fields.iter().map(|(field, value)| (field.as_bytes().to_vec(), value.as_bytes().to_vec())).collect::<Hash>())
+}
+
+fn index() -> Index {
+    let mut index = Index::new(schema());
+    let documents = [
+        ("doc:1", hash(&[("title", "Red shoes"), ("body", "Comfortable running shoes"), ("tags", "Sport,Outdoor"), ("price", "50")])),
+        ("doc:2", hash(&[("title", "Blue shirt"), ("body", "A red stripe on blue cotton"), ("tags", "casual"), ("price", "25.5")])),
+        ("doc:3", hash(&[("title", "Running jacket"), ("body", "Light and warm"), ("tags", "sport"), ("price", "120")])),
+    ];
+    for (key, value) in &documents {
+        index.update(key.as_bytes(), Some(value));
+    }
+    index
+}
+
+fn search(index: &Index, query: &str) -> Vec<String> {
+    let query = Query::parse(query, &index.schema, &HashMap::new()).unwrap();
+    index.search(&query, None).iter().map(|found| String::from_utf8_lossy(found.key).into_owned()).collect()
+}
+
+#[test]
+fn test_terms_and_boolean_operators() {
+    let index = index();
+
+    assert_eq!(search(&index, "red"), ["doc:1", "doc:2"]);
+    assert_eq!(search(&index, "RED shoes"), ["doc:1"]);
+    assert_eq!(search(&index, "shirt|jacket"), ["doc:2", "doc:3"]);
+    assert_eq!(search(&index, "running -shoes"), ["doc:3"]);
+    assert_eq!(search(&index, "(blue|light) -cotton"), ["doc:3"]);
+    // "Running" is in the title of doc:3, which scores higher.
+    assert_eq!(search(&index, "run*"), ["doc:3", "doc:1"]);
+    assert_eq!(search(&index, "*").len(), 3);
+    assert!(search(&index, "missing").is_empty());
+}
+
+#[test]
+fn test_fields_phrases_tags_and_ranges() {
+    let index = index();
+
+    assert_eq!(search(&index, "@title:red"), ["doc:1"]);
+    assert_eq!(search(&index, "@title|body:(running)"), ["doc:3", "doc:1"]);
+    assert_eq!(search(&index, "\"running shoes\""), ["doc:1"]);
+    assert!(search(&index, "\"shoes running\"").is_empty());
+    assert_eq!(search(&index, "@tags:{sport}"), ["doc:1", "doc:3"]);
+    assert_eq!(search(&index, "@tags:{ Casual | outdoor }"), ["doc:1", "doc:2"]);
+    assert_eq!(search(&index, "@price:[25.5 50]"), ["doc:1", "doc:2"]);
+    assert_eq!(search(&index, "@price:[(25.5 +inf]"), ["doc:1", "doc:3"]);
+    assert_eq!(search(&index, "@price:[-inf (50] @tags:{casual}"), ["doc:2"]);
+}
+
+#[test]
+fn test_scoring_and_sorting() {
+    let index = index();
+    // A match in the title weighs twice as much as one in the body.
+    assert_eq!(search(&index, "red"), ["doc:1", "doc:2"]);
+    let query = Query::parse("red", &index.schema, &HashMap::new()).unwrap();
+    let matches = index.search(&query, None);
+    assert!(matches[0].score > matches[1].score);
+
+    let query = Query::parse("*", &index.schema, &HashMap::new()).unwrap();
+    let keys = |matches: Vec<Match>| matches.iter().map(|found| found.key.to_vec()).collect::<Vec<_>>();
+    assert_eq!(keys(index.search(&query, Some((3, false)))), [b"doc:2".to_vec(), b"doc:1".to_vec(), b"doc:3".to_vec()]);
+    assert_eq!(keys(index.search(&query, Some((0, true)))), [b"doc:3".to_vec(), b"doc:1".to_vec(), b"doc:2".to_vec()]);
+}
+
+#[test]
+fn test_updates_replace_and_remove_documents() {
+    let mut index = index();
+    index.update(b"doc:1", Some(&hash(&[("title", "Green hat"), ("price", "10")])));
+    assert!(search(&index, "shoes").is_empty());
+    assert_eq!(search(&index, "green"), ["doc:1"]);
+    assert_eq!(search(&index, "@price:[0 20]"), ["doc:1"]);
+
+    // Unparsable numbers, other types and other prefixes are not indexed.
+    index.update(b"doc:1", Some(&hash(&[("title", "Green hat"), ("price", "cheap")])));
+    index.update(b"doc:4", Some(&Value::String(b"green".to_vec())));
+    index.update(b"other:1", Some(&hash(&[("title", "Green")])));
+    assert!(search(&index, "green").is_empty());
+
+    index.update(b"doc:2", None);
+    assert_eq!(search(&index, "*"), ["doc:3"]);
+}
+
+#[test]
+fn test_json_documents() {
+    let field = |path: &str, name: &str, kind| Field::new(Source::Json, path, Some(name), kind).unwrap();
+    let mut index = Index::new(Schema {
+        source: Source::Json,
+        prefixes: Vec::new(),
+        fields: vec![
+            field("$.name", "name", FieldKind::Text { weight: 1.0 }),
+            field("$.colors[*]", "colors", FieldKind::Tag { separator: ',', case_sensitive: false }),
+            field("$.stock", "stock", FieldKind::Numeric),
+        ],
+    });
+    index.update(b"p:1", Some(&Value::Json(json!({"name": "Wool socks", "colors": ["Grey", "Black"], "stock": 4}))));
+    index.update(b"p:2", Some(&Value::Json(json!({"name": "Cotton socks", "colors": ["white"]}))));
+    index.update(b"p:3", Some(&Value::Json(json!({"name": "Hat", "stock": "many"}))));
+
+    assert_eq!(search(&index, "socks"), ["p:1", "p:2"]);
+    assert_eq!(search(&index, "@colors:{black}"), ["p:1"]);
+    assert_eq!(search(&index, "@stock:[1 10]"), ["p:1"]);
+    assert!(search(&index, "hat").is_empty());
+}
+
+#[test]
+fn test_query_errors() {
+    let schema = schema();
+    for query in ["", "(red", "@title:", "@price:{a}", "@price:[1 x]", "@tags:{a", "\"\"", "red|"] {
+        assert!(Node::parse(query, &schema).is_err(), "{query} should not parse");
+    }
+    assert_eq!(
+        Node::parse("@color:red", &schema).unwrap_err().to_string(),
+        "Unknown field at offset 0 near color"
+    );
+    for query in [format!("{}a", "-".repeat(500_000)), format!("{}a{}", "(".repeat(500_000), ")".repeat(500_000))] {
+        assert!(Node::parse(&query, &schema).is_err());
+    }
+    assert!(Node::parse(&format!("{}a{}", "(-".repeat(20), ")".repeat(20)), &schema).is_ok());
+}
+
+fn evaluate(expression: &str, row: &[(&str, Property)]) -> Option<Property> {
+    let expression = Expression::parse(expression).unwrap();
+    expression.evaluate(&mut |name| row.iter().find(|(property, _)| *property == name).map(|(_, value)| value.clone()))
+}
+
+#[test]
+fn test_expressions() {
+    let row = [("price", Property::Text(b"12.5".to_vec())), ("name", Property::Text(b"Shoe".to_vec()))];
+    let number = |n| Some(Property::Number(n));
+
+    assert_eq!(evaluate("@price * 2 + 1", &row), number(26.0));
+    assert_eq!(evaluate("(1 + 2) * -3 ^ 2", &row), number(-27.0));
+    assert_eq!(evaluate("2 ^ 3 ^ 2", &row), number(512.0));
+    assert_eq!(evaluate("7 % 4 - 10 / 4", &row), number(0.5));
+    assert_eq!(evaluate("floor(@price) == 12 && @name != 'Boot'", &row), number(1.0));
+    assert_eq!(evaluate("!(@price >= 13) || @missing", &row), number(1.0));
+    assert_eq!(evaluate("upper(@name)", &row), Some(Property::Text(b"SHOE".to_vec())));
+    assert_eq!(evaluate("strlen(@name) < 10", &row), number(1.0));
+    assert_eq!(evaluate("@missing + 1", &row), None);
+    assert_eq!(evaluate("@name * 2", &row), None);
+
+    for expression in ["", "1 +", "(1", "@", "nope(1)", "abs(1, 2)", "'open"] {
+        assert!(Expression::parse(expression).is_err(), "{expression} should not parse");
+    }
+    // Deep nesting is rejected before it can overflow the stack.
+    for expression in [
+        format!("{}1{}", "(".repeat(200_000), ")".repeat(200_000)),
+        format!("{}1", "-!".repeat(200_000)),
+        format!("1{}", " ^ 1".repeat(200_000)),
+        format!("1{}", " + 1".repeat(200_000)),
+        format!("{}1{}", "abs(".repeat(200_000), ")".repeat(200_000)),
+    ] {
+        assert!(Expression::parse(&expression).is_err());
+    }
+    assert_eq!(evaluate(&format!("1{}", " + 1".repeat(50)), &row), number(51.0));
+}
+
+#[test]
+fn test_aggregate_pipeline() {
+    let documents: HashMap<&[u8], Value> = HashMap::from([
+        (b"s:1".as_slice(), hash(&[("city", "Berlin"), ("amount", "10"), ("user", "a")])),
+        (b"s:2".as_slice(), hash(&[("city", "Paris"), ("amount", "5"), ("user", "b")])),
+        (b"s:3".as_slice(), hash(&[("city", "Berlin"), ("amount", "30"), ("user", "a")])),
+        (b"s:4".as_slice(), hash(&[("city", "Rome"), ("user", "c")])),
+    ]);
+    let schema = Schema { source: Source::Hash, prefixes: Vec::new(), fields: Vec::new() };
+    let load = |key: &[u8], name: &str| schema.load(&documents[key], name);
+    let keys = [b"s:1".as_slice(), b"s:2", b"s:3", b"s:4"];
+    let reducer = |function, property: Option<&str>, name: &str| Reducer {
+        function,
+        property: property.map(str::to_string),
+        name: name.to_string(),
+    };
+    let steps = [
+        Step::GroupBy {
+            properties: vec!["city".to_string()],
+            reducers: vec![
+                reducer(ReduceFunction::Count, None, "count"),
+                reducer(ReduceFunction::Sum, Some("amount"), "total"),
+                reducer(ReduceFunction::Avg, Some("amount"), "avg"),
+                reducer(ReduceFunction::Max, Some("amount"), "max"),
+                reducer(ReduceFunction::CountDistinct, Some("user"), "users"),
+            ],
+        },
+        Step::Apply { expression: Expression::parse("@total * 2").unwrap(), name: "double".to_string() },
+        Step::SortBy { keys: vec![("total".to_string(), true)], max: None },
+        Step::Limit { offset: 0, count: 2 },
+    ];
+
+    let number = |n| Property::Number(n);
+    let text = |s: &str| Property::Text(s.as_bytes().to_vec());
+    let row = |pairs: &[(&str, Property)]| pairs.iter().map(|(name, value)| (name.to_string(), value.clone())).collect::<Vec<_>>();
+    assert_eq!(
+        aggregate(keys.into_iter(), &[], &steps, &load),
+        [
+            row(&[("city", text("Berlin")), ("count", number(2.0)), ("total", number(40.0)), ("avg", number(20.0)), ("max", number(30.0)), ("users", number(1.0)), ("double", number(80.0))]),
+            row(&[("city", text("Paris")), ("count", number(1.0)), ("total", number(5.0)), ("avg", number(5.0)), ("max", number(5.0)), ("users", number(1.0)), ("double", number(10.0))]),
+        ]
+    );
+
+    // Without a GROUPBY, rows are documents and read what they need from them.
+    let steps = [Step::Filter(Expression::parse("@amount > 5").unwrap()), Step::SortBy { keys: vec![("amount".to_string(), false)], max: Some(1) }];
+    assert_eq!(aggregate(keys.into_iter(), &["user".to_string()], &steps, &load), [row(&[("user", text("a"))])]);
+}
+
+fn blob(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|n| n.to_le_bytes()).collect()
+}
+
+fn vector_index(algorithm: Algorithm, metric: Metric) -> Index {
+    let mut index = Index::new(Schema {
+        source: Source::Hash,
+        prefixes: Vec::new(),
+        fields: vec![
+            Field::new(Source::Hash, "vec", None, FieldKind::Vector { algorithm, dim: 2, metric }).unwrap(),
+            Field::new(Source::Hash, "tags", None, FieldKind::Tag { separator: ',', case_sensitive: false }).unwrap(),
+        ],
+    });
+    let documents: [(&str, [f32; 2], &str); 4] =
+        [("v:1", [1.0, 0.0], "a"), ("v:2", [0.0, 1.0], "b"), ("v:3", [3.0, 0.5], "a"), ("v:4", [-1.0, -1.0], "b")];
+    for (key, vector, tag) in documents {
+        let value = Value::Hash(Hash::from_iter([(b"vec".to_vec(), blob(&vector)), (b"tags".to_vec(), tag.as_bytes().to_vec())]));
+        index.update(key.as_bytes(), Some(&value));
+    }
+    index
+}
+
+fn knn(index: &Index, query: &str, vector: &[f32]) -> Vec<(String, f32)> {
+    let params = HashMap::from([("v".to_string(), blob(vector))]);
+    let query = Query::parse(query, &index.schema, &params).unwrap();
+    index
+        .search(&query, None)
+        .iter()
+        .map(|found| (String::from_utf8_lossy(found.key).into_owned(), found.distance.unwrap()))
+        .collect()
+}
+
+#[test]
+fn test_vector_knn() {
+    let hnsw = Algorithm::Hnsw { m: 4, ef_construction: 10, ef_runtime: 10 };
+    for algorithm in [Algorithm::Flat, hnsw] {
+        let mut index = vector_index(algorithm, Metric::L2);
+        assert_eq!(knn(&index, "*=>[KNN 2 @vec $v]", &[1.0, 0.5]), [("v:1".to_string(), 0.25), ("v:2".to_string(), 1.25)]);
+        // A filter picks the nearest of the documents it matches.
+        assert_eq!(knn(&index, "@tags:{b}=>[KNN 1 @vec $v]", &[2.0, 0.0]), [("v:2".to_string(), 5.0)]);
+
+        index.update(b"v:1", None);
+        assert_eq!(knn(&index, "*=>[KNN 1 @vec $v]", &[1.0, 0.5]), [("v:2".to_string(), 1.25)]);
+        // Blobs of the wrong size are not indexed.
+        index.update(b"v:2", Some(&hash(&[("vec", "short")])));
+        assert_eq!(knn(&index, "*=>[KNN 10 @vec $v]", &[1.0, 0.5]).len(), 2);
+    }
+
+    let index = vector_index(Algorithm::Flat, Metric::Cosine);
+    assert_eq!(knn(&index, "*=>[KNN 1 @vec $v AS distance]", &[2.0, 0.0]), [("v:1".to_string(), 0.0)]);
+    let index = vector_index(Algorithm::Flat, Metric::Ip);
+    assert_eq!(knn(&index, "*=>[KNN 1 @vec $v]", &[1.0, 0.0]), [("v:3".to_string(), -2.0)]);
+
+    let query = Query::parse("*=>[KNN 3 @vec $v EF_RUNTIME 50 AS d]", &index.schema, &HashMap::from([("v".to_string(), blob(&[0.0, 0.0]))]));
+    let knn = query.unwrap().knn.unwrap();
+    assert_eq!((knn.k, knn.ef_runtime, knn.name.as_str()), (3, Some(50), "d"));
+    let cases = [
+        ("*=>[KNN 1 @vec $v]", vec![]),
+        ("*=>[KNN 1 @tags $v]", vec![blob(&[0.0, 0.0])]),
+        ("*=>[KNN 1 @vec $v]", vec![vec![0; 3]]),
+        ("*=>KNN 1 @vec $v", vec![blob(&[0.0, 0.0])]),
+    ];
+    for (query, params) in cases {
+        let params = params.into_iter().map(|value| ("v".to_string(), value)).collect();
+        assert!(Query::parse(query, &index.schema, &params).is_err(), "{query} should not parse");
+    }
+}
+
+#[test]
+fn test_hnsw_recall() {
+    // Compares HNSW with exact search over pseudo-random vectors.
+    let mut seed = 7u64;
+    let mut random = move || {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (seed >> 40) as f32 / (1u64 << 24) as f32
+    };
+    let mut vectors = Vec::new();
+    for i in 0..500 {
+        let vector: Vec<f32> = (0..8).map(|_| random()).collect();
+        vectors.push((format!("k:{i}").into_bytes(), vector));
+    }
+    let hnsw = Algorithm::Hnsw { m: 8, ef_construction: 100, ef_runtime: 50 };
+    let mut approximate = VectorIndex::new(hnsw, Metric::L2);
+    let mut exact = VectorIndex::new(Algorithm::Flat, Metric::L2);
+    for (key, vector) in &vectors {
+        approximate.insert(key, vector.clone());
+        exact.insert(key, vector.clone());
+    }
+    // Deleting keeps the graph searchable.
+    for (key, _) in vectors.iter().step_by(3) {
+        approximate.remove(key);
+        exact.remove(key);
+    }
+
+    let mut found = 0;
+    for _ in 0..20 {
+        let query: Vec<f32> = (0..8).map(|_| random()).collect();
+        let expected: HashSet<&[u8]> = exact.nearest(&query, 10, None, None).into_iter().map(|(key, _)| key).collect();
+        found += approximate.nearest(&query, 10, None, None).iter().filter(|(key, _)| expected.contains(key)).count();
+    }
+    assert!(found >= 180, "recall too low: {found}/200");
+}
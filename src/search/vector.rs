@@ -0,0 +1,379 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Metric {
+    /// Squared Euclidean distance.
+    L2,
+    /// One minus the inner product.
+    Ip,
+    /// One minus the cosine similarity.
+    Cosine,
+}
+
+impl Metric {
+    pub fn distance(self, a: &[f32], b: &[f32]) -> f32 {
+        match self {
+            Metric::L2 => a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum(),
+            Metric::Ip => 1.0 - dot(a, b),
+            Metric::Cosine => {
+                let norms = (dot(a, a) * dot(b, b)).sqrt();
+                if norms == 0.0 {
+                    1.0
+                } else {
+                    1.0 - dot(a, b) / norms
+                }
+            }
+        }
+    }
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Algorithm {
+    /// Exact search comparing the query with every vector.
+    Flat,
+    /// Approximate search over a Hierarchical Navigable Small World graph.
+    Hnsw { m: usize, ef_construction: usize, ef_runtime: usize },
+}
+
+/// The vectors of one VECTOR field, searchable by distance.
+#[derive(Debug)]
+pub enum VectorIndex {
+    Flat { metric: Metric, vectors: HashMap<Vec<u8>, Vec<f32>> },
+    Hnsw(Hnsw),
+}
+
+impl VectorIndex {
+    pub fn new(algorithm: Algorithm, metric: Metric) -> Self {
+        match algorithm {
+            Algorithm::Flat => VectorIndex::Flat { metric, vectors: HashMap::new() },
+            Algorithm::Hnsw { m, ef_construction, ef_runtime } => {
+                VectorIndex::Hnsw(Hnsw::new(metric, m, ef_construction, ef_runtime))
+            }
+        }
+    }
+
+    pub fn insert(&mut self, key: &[u8], vector: Vec<f32>) {
+        match self {
+            VectorIndex::Flat { vectors, .. } => {
+                vectors.insert(key.to_vec(), vector);
+            }
+            VectorIndex::Hnsw(graph) => graph.insert(key, vector),
+        }
+    }
+
+    pub fn remove(&mut self, key: &[u8]) {
+        match self {
+            VectorIndex::Flat { vectors, .. } => {
+                vectors.remove(key);
+            }
+            VectorIndex::Hnsw(graph) => graph.remove(key),
+        }
+    }
+
+    fn metric(&self) -> Metric {
+        match self {
+            VectorIndex::Flat { metric, .. } => *metric,
+            VectorIndex::Hnsw(graph) => graph.metric,
+        }
+    }
+
+    fn get(&self, key: &[u8]) -> Option<&[f32]> {
+        match self {
+            VectorIndex::Flat { vectors, .. } => vectors.get(key).map(Vec::as_slice),
+            VectorIndex::Hnsw(graph) => graph.get(key),
+        }
+    }
+
+    /// The `k` vectors nearest to `query`, closest first. With `candidates`
+    /// only those keys are considered, by comparing against each of them.
+    pub fn nearest<'a>(
+        &'a self,
+        query: &[f32],
+        k: usize,
+        ef_runtime: Option<usize>,
+        candidates: Option<&HashSet<&'a [u8]>>,
+    ) -> Vec<(&'a [u8], f32)> {
+        let metric = self.metric();
+        let exact = |keys: &mut dyn Iterator<Item = &'a [u8]>| {
+            let mut found: Vec<(&[u8], f32)> = keys
+                .filter_map(|key| Some((key, metric.distance(query, self.get(key)?))))
+                .collect();
+            found.sort_by(|a, b| a.1.total_cmp(&b.1).then_with(|| a.0.cmp(b.0)));
+            found.truncate(k);
+            found
+        };
+        match (self, candidates) {
+            (_, Some(candidates)) => exact(&mut candidates.iter().copied()),
+            (VectorIndex::Flat { vectors, .. }, None) => exact(&mut vectors.keys().map(Vec::as_slice)),
+            (VectorIndex::Hnsw(graph), None) => graph.search(query, k, ef_runtime),
+        }
+    }
+}
+
+/// A node id and its distance to the vector being searched for, ordered by
+/// distance.
+#[derive(Debug, Clone, Copy)]
+struct Candidate {
+    distance: f32,
+    id: usize,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance.total_cmp(&other.distance).then(self.id.cmp(&other.id))
+    }
+}
+
+#[derive(Debug)]
+struct Node {
+    key: Vec<u8>,
+    vector: Vec<f32>,
+    /// Outgoing links per layer, from layer 0 up to the node's level.
+    links: Vec<Vec<usize>>,
+    /// Nodes linking to this one per layer, so deletes can repair them.
+    linked_from: Vec<HashSet<usize>>,
+}
+
+/// A Hierarchical Navigable Small World graph (Malkov & Yashunin). Each
+/// vector is a node on layer 0 and, with exponentially falling probability,
+/// on the layers above; searches descend greedily from the sparse top layer.
+#[derive(Debug)]
+pub struct Hnsw {
+    metric: Metric,
+    /// Links per node on the upper layers; layer 0 allows twice as many.
+    m: usize,
+    ef_construction: usize,
+    ef_runtime: usize,
+    nodes: Vec<Option<Node>>,
+    free: Vec<usize>,
+    ids: HashMap<Vec<u8>, usize>,
+    entry: Option<usize>,
+    /// xorshift state for drawing levels, seeded so graphs are reproducible.
+    seed: u64,
+}
+
+impl Hnsw {
+    pub fn new(metric: Metric, m: usize, ef_construction: usize, ef_runtime: usize) -> Self {
+        Hnsw {
+            metric,
+            m: m.max(2),
+            ef_construction: ef_construction.max(1),
+            ef_runtime: ef_runtime.max(1),
+            nodes: Vec::new(),
+            free: Vec::new(),
+            ids: HashMap::new(),
+            entry: None,
+            seed: 0x2545f4914f6cdd1d,
+        }
+    }
+
+    fn node(&self, id: usize) -> &Node {
+        self.nodes[id].as_ref().expect("linked node exists")
+    }
+
+    fn node_mut(&mut self, id: usize) -> &mut Node {
+        self.nodes[id].as_mut().expect("linked node exists")
+    }
+
+    fn level(&self, id: usize) -> usize {
+        self.node(id).links.len() - 1
+    }
+
+    fn max_links(&self, layer: usize) -> usize {
+        if layer == 0 { 2 * self.m } else { self.m }
+    }
+
+    fn get(&self, key: &[u8]) -> Option<&[f32]> {
+        self.ids.get(key).map(|id| self.node(*id).vector.as_slice())
+    }
+
+    fn distance(&self, query: &[f32], id: usize) -> f32 {
+        self.metric.distance(query, &self.node(id).vector)
+    }
+
+    /// Draws a level with P(level >= l) = (1/m)^l.
+    fn random_level(&mut self) -> usize {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        let uniform = (self.seed >> 11) as f64 / (1u64 << 53) as f64;
+        let level = -(1.0 - uniform).ln() / (self.m as f64).ln();
+        (level as usize).min(16)
+    }
+
+    fn insert(&mut self, key: &[u8], vector: Vec<f32>) {
+        self.remove(key);
+        let level = self.random_level();
+        let node = Node {
+            key: key.to_vec(),
+            vector,
+            links: vec![Vec::new(); level + 1],
+            linked_from: vec![HashSet::new(); level + 1],
+        };
+        let id = match self.free.pop() {
+            Some(id) => {
+                self.nodes[id] = Some(node);
+                id
+            }
+            None => {
+                self.nodes.push(Some(node));
+                self.nodes.len() - 1
+            }
+        };
+        self.ids.insert(key.to_vec(), id);
+
+        let Some(entry) = self.entry else {
+            self.entry = Some(id);
+            return;
+        };
+        let query = self.node(id).vector.clone();
+        let top = self.level(entry);
+        let mut entry_points = vec![Candidate { distance: self.distance(&query, entry), id: entry }];
+        for layer in (level + 1..=top).rev() {
+            entry_points = self.search_layer(&query, entry_points, 1, layer);
+        }
+        for layer in (0..=level.min(top)).rev() {
+            let found = self.search_layer(&query, entry_points, self.ef_construction, layer);
+            for neighbour in found.iter().take(self.max_links(layer)) {
+                self.link(id, neighbour.id, layer);
+                self.link(neighbour.id, id, layer);
+                self.prune(neighbour.id, layer);
+            }
+            entry_points = found;
+        }
+        if level > top {
+            self.entry = Some(id);
+        }
+    }
+
+    fn link(&mut self, from: usize, to: usize, layer: usize) {
+        let links = &mut self.node_mut(from).links[layer];
+        if !links.contains(&to) {
+            links.push(to);
+            self.node_mut(to).linked_from[layer].insert(from);
+        }
+    }
+
+    fn unlink(&mut self, from: usize, to: usize, layer: usize) {
+        self.node_mut(from).links[layer].retain(|id| *id != to);
+        self.node_mut(to).linked_from[layer].remove(&from);
+    }
+
+    /// Keeps only the closest links of `id` on `layer` if it has too many.
+    fn prune(&mut self, id: usize, layer: usize) {
+        let max = self.max_links(layer);
+        if self.node(id).links[layer].len() <= max {
+            return;
+        }
+        let vector = self.node(id).vector.clone();
+        let mut links: Vec<Candidate> = self.node(id).links[layer]
+            .iter()
+            .map(|link| Candidate { distance: self.distance(&vector, *link), id: *link })
+            .collect();
+        links.sort();
+        for dropped in &links[max..] {
+            self.unlink(id, dropped.id, layer);
+        }
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        let Some(id) = self.ids.remove(key) else {
+            return;
+        };
+        let levels = self.node(id).links.len();
+        for layer in 0..levels {
+            let neighbours = self.node(id).links[layer].clone();
+            let referrers: Vec<usize> = self.node(id).linked_from[layer].iter().copied().collect();
+            for neighbour in &neighbours {
+                self.unlink(id, *neighbour, layer);
+            }
+            // Reconnect the nodes that lost a link through the removed node's
+            // neighbours, so the graph stays navigable.
+            for referrer in referrers {
+                self.unlink(referrer, id, layer);
+                for neighbour in &neighbours {
+                    if *neighbour != referrer {
+                        self.link(referrer, *neighbour, layer);
+                    }
+                }
+                self.prune(referrer, layer);
+            }
+        }
+        self.nodes[id] = None;
+        self.free.push(id);
+
+        if self.entry == Some(id) {
+            self.entry = self
+                .nodes
+                .iter()
+                .enumerate()
+                .filter_map(|(id, node)| Some((node.as_ref()?.links.len(), id)))
+                .max()
+                .map(|(_, id)| id);
+        }
+    }
+
+    /// Best-first search of one layer; returns up to `ef` nodes, closest first.
+    fn search_layer(&self, query: &[f32], entry_points: Vec<Candidate>, ef: usize, layer: usize) -> Vec<Candidate> {
+        let mut visited: HashSet<usize> = entry_points.iter().map(|candidate| candidate.id).collect();
+        let mut candidates: BinaryHeap<Reverse<Candidate>> = entry_points.iter().copied().map(Reverse).collect();
+        let mut found: BinaryHeap<Candidate> = entry_points.into_iter().collect();
+        while found.len() > ef {
+            found.pop();
+        }
+        while let Some(Reverse(closest)) = candidates.pop() {
+            if found.len() >= ef && found.peek().is_some_and(|furthest| closest.distance > furthest.distance) {
+                break;
+            }
+            for neighbour in &self.node(closest.id).links[layer] {
+                if !visited.insert(*neighbour) {
+                    continue;
+                }
+                let candidate = Candidate { distance: self.distance(query, *neighbour), id: *neighbour };
+                if found.len() < ef || found.peek().is_some_and(|furthest| candidate.distance < furthest.distance) {
+                    candidates.push(Reverse(candidate));
+                    found.push(candidate);
+                    if found.len() > ef {
+                        found.pop();
+                    }
+                }
+            }
+        }
+        found.into_sorted_vec()
+    }
+
+    fn search(&self, query: &[f32], k: usize, ef_runtime: Option<usize>) -> Vec<(&[u8], f32)> {
+        let Some(entry) = self.entry else {
+            return Vec::new();
+        };
+        let mut entry_points = vec![Candidate { distance: self.distance(query, entry), id: entry }];
+        for layer in (1..=self.level(entry)).rev() {
+            entry_points = self.search_layer(query, entry_points, 1, layer);
+        }
+        let ef = ef_runtime.unwrap_or(self.ef_runtime).max(k);
+        self.search_layer(query, entry_points, ef, 0)
+            .into_iter()
+            .take(k)
+            .map(|candidate| (self.node(candidate.id).key.as_slice(), candidate.distance))
+            .collect()
+    }
+}